@@ -0,0 +1,224 @@
+use crate::convert::{run_ffmpeg_cancellable, ConvertError};
+use crate::probe::{probe_file, ProbeError, ReplayGain};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Bitrate of one time bucket
+#[derive(Debug, Clone, Serialize)]
+pub struct BitratePoint {
+    pub time_secs: f64,
+    pub kbps: f64,
+    /// Keyframes starting in this bucket (GOP boundaries)
+    pub keyframes: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BitrateAnalysis {
+    /// "video" or "audio": which stream was measured
+    pub stream: String,
+    pub interval_secs: f64,
+    pub points: Vec<BitratePoint>,
+    pub average_kbps: f64,
+    pub peak_kbps: f64,
+}
+
+/// Per-interval bitrate of the first video stream (or audio for audio-only files), from
+/// packet sizes. Reads packets only, no decoding, but long files still produce millions of
+/// lines, so `cancel_flag` is checked while reading.
+pub fn analyze_bitrate(
+    input_path: &str,
+    interval_secs: f64,
+    sidecar_path: Option<&std::path::Path>,
+    cancel_flag: &AtomicBool,
+) -> Result<BitrateAnalysis, ProbeError> {
+    let interval_secs = if interval_secs > 0.0 { interval_secs } else { 1.0 };
+    let info = probe_file(input_path, sidecar_path)?;
+    let (stream, selector) = if info.has_video { ("video", "v:0") } else { ("audio", "a:0") };
+    
+    let ffprobe_cmd = sidecar_path.map_or("ffprobe".into(), |p| p.to_string_lossy().to_string());
+    let mut child = Command::new(&ffprobe_cmd)
+        .args([
+            "-v", "error",
+            "-select_streams", selector,
+            "-show_entries", "packet=pts_time,size,flags",
+            "-of", "csv=p=0",
+            input_path,
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| ProbeError::ExecutionFailed(e.to_string()))?;
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| ProbeError::ExecutionFailed("No ffprobe output".to_string()))?;
+    // Drained on its own thread so a damaged file's errors can't fill the pipe and stall ffprobe
+    let stderr = child.stderr.take().map(|mut stderr| {
+        std::thread::spawn(move || {
+            let mut text = String::new();
+            std::io::Read::read_to_string(&mut stderr, &mut text).ok();
+            text
+        })
+    });
+    
+    // Bucket index -> (bytes, keyframes)
+    let mut buckets: Vec<(u64, u32)> = Vec::new();
+    for line in BufReader::new(stdout).lines() {
+        if cancel_flag.load(Ordering::Relaxed) {
+            child.kill().ok();
+            child.wait().ok();
+            return Err(ProbeError::Cancelled);
+        }
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                child.kill().ok();
+                child.wait().ok();
+                return Err(ProbeError::ExecutionFailed(e.to_string()));
+            }
+        };
+        // "pts_time,size,flags", e.g. "12.345000,18234,K__"
+        let mut fields = line.split(',');
+        let (Some(Ok(time)), Some(Ok(size))) = (
+            fields.next().map(str::parse::<f64>),
+            fields.next().map(str::parse::<u64>),
+        ) else {
+            continue; // packets without a timestamp
+        };
+        let keyframe = fields.next().is_some_and(|f| f.starts_with('K'));
+        let bucket = (time.max(0.0) / interval_secs) as usize;
+        if buckets.len() <= bucket {
+            buckets.resize(bucket + 1, (0, 0));
+        }
+        buckets[bucket].0 += size;
+        buckets[bucket].1 += keyframe as u32;
+    }
+    let status = child.wait().map_err(|e| ProbeError::ExecutionFailed(e.to_string()))?;
+    let stderr = stderr.and_then(|t| t.join().ok()).unwrap_or_default();
+    // A failed read would otherwise pass for an empty or truncated graph
+    if !status.success() {
+        return Err(ProbeError::ExecutionFailed(stderr_or_status(&stderr, status)));
+    }
+    if buckets.is_empty() {
+        return Err(ProbeError::ExecutionFailed(format!("ffprobe read no {} packets", stream)));
+    }
+    
+    let points: Vec<BitratePoint> = buckets
+        .iter()
+        .enumerate()
+        .map(|(i, (bytes, keyframes))| BitratePoint {
+            time_secs: i as f64 * interval_secs,
+            kbps: *bytes as f64 * 8.0 / 1000.0 / interval_secs,
+            keyframes: *keyframes,
+        })
+        .collect();
+    let average_kbps = if points.is_empty() {
+        0.0
+    } else {
+        points.iter().map(|p| p.kbps).sum::<f64>() / points.len() as f64
+    };
+    let peak_kbps = points.iter().map(|p| p.kbps).fold(0.0, f64::max);
+    
+    Ok(BitrateAnalysis {
+        stream: stream.to_string(),
+        interval_secs,
+        points,
+        average_kbps,
+        peak_kbps,
+    })
+}
+
+/// ffprobe's error text, or the exit status when it printed none
+fn stderr_or_status(stderr: &str, status: std::process::ExitStatus) -> String {
+    match stderr.trim() {
+        "" => format!("ffprobe exited with {}", status),
+        text => text.to_string(),
+    }
+}
+
+/// ReplayGain 2.0 reference loudness
+const REPLAYGAIN_REFERENCE_LUFS: f64 = -18.0;
+
+/// Measure the first audio stream's integrated loudness and true peak (EBU R128, `ebur128`
+/// filter) and express them as ReplayGain track values. Decodes the whole stream, so
+/// `cancel_flag` stops it.
+pub fn measure_replay_gain(
+    input_path: &str,
+    sidecar_path: Option<&std::path::Path>,
+    cancel_flag: &AtomicBool,
+) -> Result<ReplayGain, ConvertError> {
+    let args: Vec<String> = [
+        "-i", input_path,
+        "-map", "0:a:0",
+        "-af", "ebur128=peak=true",
+        "-f", "null", "-",
+    ]
+    .iter()
+    .map(|a| a.to_string())
+    .collect();
+    let stderr = run_ffmpeg_cancellable(sidecar_path, &args, cancel_flag, |_| {})?;
+    
+    // The summary comes last:
+    //   Integrated loudness:
+    //     I:         -16.9 LUFS
+    //   ...
+    //   True peak:
+    //     Peak:       -0.5 dBFS
+    let summary = stderr
+        .rsplit_once("Summary:")
+        .map(|(_, summary)| summary)
+        .ok_or_else(|| ConvertError::ConversionFailed("No loudness summary in ffmpeg output".to_string()))?;
+    let value = |label: &str| {
+        summary
+            .lines()
+            .find_map(|line| line.trim().strip_prefix(label))
+            .and_then(|rest| rest.split_whitespace().next())
+            .and_then(|v| v.parse::<f64>().ok())
+    };
+    let integrated = value("I:")
+        .filter(|i| i.is_finite())
+        .ok_or_else(|| ConvertError::ConversionFailed("Could not measure loudness (silent audio?)".to_string()))?;
+    
+    Ok(ReplayGain {
+        track_gain_db: Some(REPLAYGAIN_REFERENCE_LUFS - integrated),
+        track_peak: value("Peak:").map(|dbfs| 10f64.powf(dbfs / 20.0)),
+        album_gain_db: None,
+        album_peak: None,
+    })
+}
+
+/// loudnorm's first-pass measurements, as printed (numbers as strings, "-inf" for silence)
+#[derive(Debug, Clone, Deserialize)]
+pub struct LoudnormMeasurement {
+    pub input_i: String,
+    pub input_tp: String,
+    pub input_lra: String,
+    pub input_thresh: String,
+    pub target_offset: String,
+}
+
+/// Run a loudnorm measurement pass (`args` with `print_format=json`) and read the JSON
+/// block loudnorm prints at the end. Decodes the whole input, so `cancel_flag` stops it.
+pub fn measure_loudnorm(
+    args: &[String],
+    sidecar_path: Option<&std::path::Path>,
+    cancel_flag: &AtomicBool,
+) -> Result<LoudnormMeasurement, ConvertError> {
+    let stderr = run_ffmpeg_cancellable(sidecar_path, args, cancel_flag, |_| {})?;
+    let json = stderr
+        .rfind('{')
+        .and_then(|start| stderr[start..].find('}').map(|end| &stderr[start..=start + end]))
+        .ok_or_else(|| ConvertError::ConversionFailed("No loudness measurement in ffmpeg output".to_string()))?;
+    let measured: LoudnormMeasurement =
+        serde_json::from_str(json).map_err(|e| ConvertError::ConversionFailed(e.to_string()))?;
+    let finite = |v: &str| v.trim().parse::<f64>().is_ok_and(f64::is_finite);
+    if ![&measured.input_i, &measured.input_tp, &measured.input_lra, &measured.input_thresh, &measured.target_offset]
+        .iter()
+        .all(|v| finite(v))
+    {
+        return Err(ConvertError::ConversionFailed("Audio is silent; nothing to normalize".to_string()));
+    }
+    Ok(measured)
+}
@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Operation id of the main conversion (what `cancel_convert` stops)
+pub const CONVERSION: &str = "conversion";
+/// Operation id of a graceful stop of the main conversion (what `stop_convert` requests)
+pub const STOP_CONVERSION: &str = "conversion-stop";
+/// Operation kind of a folder probe; several can run at once (see `operation_id`)
+pub const PROBE_FOLDER: &str = "probe-folder";
+/// Operation kind of a bitrate analysis; several can run at once (see `operation_id`)
+pub const BITRATE_ANALYSIS: &str = "bitrate-analysis";
+/// Operation id of a split into fixed-length parts
+pub const SPLIT: &str = "split";
+
+/// Sequence number for generated operation ids
+static NEXT_OPERATION: AtomicU64 = AtomicU64::new(1);
+
+/// Id for one run of an operation that may run several times at once: the caller's own id
+/// when given, otherwise `kind` and a sequence number ("probe-folder-3"), so each run has
+/// its own token and finishing or cancelling one leaves the others alone
+pub fn operation_id(kind: &str, requested: Option<String>) -> String {
+    requested
+        .filter(|id| !id.is_empty())
+        .unwrap_or_else(|| format!("{}-{}", kind, NEXT_OPERATION.fetch_add(1, Ordering::Relaxed)))
+}
+
+/// Cancellation tokens for long-running operations, keyed by operation id,
+/// so cancelling one analysis does not touch an unrelated conversion
+#[derive(Default)]
+pub struct CancelRegistry {
+    tokens: Mutex<HashMap<String, Arc<AtomicBool>>>,
+}
+
+impl CancelRegistry {
+    /// Create a fresh (not cancelled) token for an operation, replacing any previous one
+    pub fn register(&self, id: &str) -> Arc<AtomicBool> {
+        let token = Arc::new(AtomicBool::new(false));
+        self.tokens.lock().unwrap().insert(id.to_string(), token.clone());
+        token
+    }
+
+    /// Signal an operation to stop. Returns false if no such operation is running.
+    pub fn cancel(&self, id: &str) -> bool {
+        match self.tokens.lock().unwrap().get(id) {
+            Some(token) => {
+                token.store(true, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Forget an operation's token once it has finished
+    pub fn remove(&self, id: &str) {
+        self.tokens.lock().unwrap().remove(id);
+    }
+
+    /// Ids of the operations currently registered
+    pub fn active(&self) -> Vec<String> {
+        self.tokens.lock().unwrap().keys().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn concurrent_runs_get_their_own_tokens() {
+        let registry = CancelRegistry::default();
+        let first = operation_id(PROBE_FOLDER, None);
+        let second = operation_id(PROBE_FOLDER, None);
+        assert_ne!(first, second);
+        assert_eq!(operation_id(PROBE_FOLDER, Some("scan-a".to_string())), "scan-a");
+
+        let (first_token, second_token) = (registry.register(&first), registry.register(&second));
+        registry.remove(&first);
+        assert!(registry.cancel(&second));
+        assert!(!first_token.load(Ordering::Relaxed));
+        assert!(second_token.load(Ordering::Relaxed));
+    }
+}
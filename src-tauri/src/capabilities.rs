@@ -0,0 +1,177 @@
+use crate::convert::ffmpeg_program;
+use serde::Serialize;
+use std::process::Command;
+use std::sync::OnceLock;
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CodecKind {
+    Video,
+    Audio,
+    Subtitle,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EncoderInfo {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: CodecKind,
+    pub description: String,
+}
+
+/// Parse the table printed by `ffmpeg -encoders` / `ffmpeg -decoders`:
+///
+/// ```text
+///  V....D libx264              libx264 H.264 / AVC / MPEG-4 AVC (codec h264)
+/// ```
+fn parse_codec_list(output: &str) -> Vec<EncoderInfo> {
+    output
+        .lines()
+        .skip_while(|line| !line.trim_start().starts_with("------"))
+        .skip(1)
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            let flags = parts.next()?;
+            let name = parts.next()?;
+            let kind = match flags.chars().next()? {
+                'V' => CodecKind::Video,
+                'A' => CodecKind::Audio,
+                'S' => CodecKind::Subtitle,
+                _ => return None,
+            };
+            Some(EncoderInfo {
+                name: name.to_string(),
+                kind,
+                description: parts.collect::<Vec<_>>().join(" "),
+            })
+        })
+        .collect()
+}
+
+/// stdout of `ffmpeg -hide_banner <flag>`. A failed run is an error rather than an empty
+/// list, which would read as "this build has nothing".
+fn run_listing(sidecar_path: Option<&std::path::Path>, flag: &str) -> Result<String, String> {
+    let output = Command::new(ffmpeg_program(sidecar_path))
+        .args(["-hide_banner", flag])
+        .output()
+        .map_err(|e| format!("Failed to run ffmpeg: {}", e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(match stderr.trim() {
+            "" => format!("ffmpeg {} exited with {}", flag, output.status),
+            text => format!("ffmpeg {} failed: {}", flag, text),
+        });
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+fn run_codec_list(sidecar_path: Option<&std::path::Path>, flag: &str) -> Result<Vec<EncoderInfo>, String> {
+    Ok(parse_codec_list(&run_listing(sidecar_path, flag)?))
+}
+
+/// List the output formats (muxers) compiled into this ffmpeg build, from `ffmpeg -muxers`:
+///
+/// ```text
+///   E avif            AVIF
+/// ```
+pub fn list_muxers(sidecar_path: Option<&std::path::Path>) -> Result<Vec<String>, String> {
+    Ok(run_listing(sidecar_path, "-muxers")?
+        .lines()
+        .skip_while(|line| !line.trim_start().starts_with("--"))
+        .skip(1)
+        .filter_map(|line| line.split_whitespace().nth(1))
+        .flat_map(|names| names.split(','))
+        .map(str::to_string)
+        .collect())
+}
+
+/// List the encoders compiled into this ffmpeg build
+pub fn list_encoders(sidecar_path: Option<&std::path::Path>) -> Result<Vec<EncoderInfo>, String> {
+    run_codec_list(sidecar_path, "-encoders")
+}
+
+/// List the decoders compiled into this ffmpeg build
+pub fn list_decoders(sidecar_path: Option<&std::path::Path>) -> Result<Vec<EncoderInfo>, String> {
+    run_codec_list(sidecar_path, "-decoders")
+}
+
+/// Whether this ffmpeg build has an encoder called `codec` (e.g. "libx265", "libopus")
+pub fn has_encoder(sidecar_path: Option<&std::path::Path>, codec: &str) -> Result<bool, String> {
+    Ok(list_encoders(sidecar_path)?.iter().any(|e| e.name == codec))
+}
+
+/// Suffixes of encoders that run on a GPU or media engine
+const HARDWARE_SUFFIXES: &[&str] = &["_nvenc", "_qsv", "_videotoolbox", "_vaapi", "_amf", "_mf"];
+
+pub fn is_hardware_encoder(name: &str) -> bool {
+    HARDWARE_SUFFIXES.iter().any(|suffix| name.ends_with(suffix))
+}
+
+/// Result of `detect_hw_encoders`; the hardware doesn't change while the app runs
+static WORKING_HW_ENCODERS: OnceLock<Vec<String>> = OnceLock::new();
+
+/// Hardware video encoders that actually work here. ffmpeg builds list NVENC/QSV/... whether or
+/// not the GPU and driver exist, so each one is tried on a tiny test encode. Slow the first
+/// time (a fraction of a second per encoder), cached afterwards.
+pub fn detect_hw_encoders(sidecar_path: Option<&std::path::Path>) -> Result<Vec<String>, String> {
+    if let Some(working) = WORKING_HW_ENCODERS.get() {
+        return Ok(working.clone());
+    }
+    let working: Vec<String> = list_encoders(sidecar_path)?
+        .into_iter()
+        .filter(|e| e.kind == CodecKind::Video && is_hardware_encoder(&e.name))
+        .filter(|e| {
+            Command::new(ffmpeg_program(sidecar_path))
+                .args([
+                    "-hide_banner", "-v", "error",
+                    "-f", "lavfi", "-i", "color=black:size=256x256:duration=0.1",
+                    "-frames:v", "1",
+                    "-c:v", &e.name,
+                    "-f", "null", "-",
+                ])
+                .output()
+                .is_ok_and(|output| output.status.success())
+        })
+        .map(|e| e.name)
+        .collect();
+    Ok(WORKING_HW_ENCODERS.get_or_init(|| working).clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENCODERS: &str = "Encoders:
+ V..... = Video
+ A..... = Audio
+ S..... = Subtitle
+ .F.... = Frame-level multithreading
+ ..S... = Slice-level multithreading
+ ...X.. = Codec is experimental
+ ....B. = Supports draw_horiz_band
+ .....D = Supports direct rendering method 1
+ ------
+ V....D libx264              libx264 H.264 / AVC / MPEG-4 AVC / MPEG-4 part 10 (codec h264)
+ V....D h264_nvenc           NVIDIA NVENC H.264 encoder (codec h264)
+ A....D aac                  AAC (Advanced Audio Coding)
+ A....D libopus              libopus Opus (codec opus)
+ S..... mov_text             3GPP Timed Text subtitle
+";
+
+    #[test]
+    fn encoder_list_is_read_after_the_legend() {
+        let encoders = parse_codec_list(ENCODERS);
+        let names: Vec<&str> = encoders.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["libx264", "h264_nvenc", "aac", "libopus", "mov_text"]);
+        assert_eq!(encoders[1].kind, CodecKind::Video);
+        assert_eq!(encoders[2].kind, CodecKind::Audio);
+        assert_eq!(encoders[4].kind, CodecKind::Subtitle);
+        assert_eq!(encoders[3].description, "libopus Opus (codec opus)");
+    }
+
+    #[test]
+    fn output_without_a_table_has_no_encoders() {
+        assert!(parse_codec_list("").is_empty());
+        assert!(parse_codec_list("Unrecognized option 'encoders'.").is_empty());
+    }
+}
@@ -0,0 +1,450 @@
+use crate::convert::{run_ffmpeg_cancellable, ConvertError};
+use crate::logger::{ConversionLog, LogLevel};
+use crate::presets::{get_all_presets, PresetCategory};
+use crate::probe::{probe_file, MediaInfo, ProbeError, StreamInfo, StreamType};
+use crate::settings::AppSettings;
+use crate::temp::TempFileSet;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+
+/// Frame rates of clips about to be joined. Mixed rates stutter or drift out of sync
+/// when concatenated, so the clips should first be brought to one rate with the `fps` filter.
+#[derive(Debug, Clone, Serialize)]
+pub struct ConcatFpsReport {
+    /// Video frame rate of each input, in input order (`None` for audio-only or unreadable rates)
+    pub frame_rates: Vec<Option<f64>>,
+    pub mixed: bool,
+    /// Most common rate among the inputs (the higher one on a tie)
+    pub recommended_fps: Option<f64>,
+    pub warning: Option<String>,
+}
+
+/// Rates closer than this are the same rate (29.97 reported as 30000/1001 vs 2997/100)
+const FPS_TOLERANCE: f64 = 0.01;
+
+/// Probe every input's video frame rate and recommend a common target
+pub fn analyze_concat_fps(
+    inputs: &[String],
+    sidecar_path: Option<&std::path::Path>,
+) -> Result<ConcatFpsReport, ProbeError> {
+    let mut frame_rates = Vec::with_capacity(inputs.len());
+    for input in inputs {
+        let info = probe_file(input, sidecar_path)?;
+        frame_rates.push(video_stream(&info).and_then(|s| s.frame_rate_fps));
+    }
+    Ok(fps_report(frame_rates))
+}
+
+fn video_stream(info: &MediaInfo) -> Option<&StreamInfo> {
+    info.streams
+        .iter()
+        .find(|s| s.stream_type == StreamType::Video && !s.has_disposition("attached_pic"))
+}
+
+fn audio_stream(info: &MediaInfo) -> Option<&StreamInfo> {
+    info.streams.iter().find(|s| s.stream_type == StreamType::Audio)
+}
+
+fn fps_report(frame_rates: Vec<Option<f64>>) -> ConcatFpsReport {
+    // Group equal rates and count them
+    let mut counts: Vec<(f64, usize)> = Vec::new();
+    for fps in frame_rates.iter().flatten() {
+        match counts.iter_mut().find(|(rate, _)| (rate - fps).abs() < FPS_TOLERANCE) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((*fps, 1)),
+        }
+    }
+    let recommended_fps = counts
+        .iter()
+        .max_by(|a, b| a.1.cmp(&b.1).then(a.0.total_cmp(&b.0)))
+        .map(|(rate, _)| *rate);
+    let mixed = counts.len() > 1;
+    let warning = mixed.then(|| {
+        let rates: Vec<String> = counts.iter().map(|(rate, _)| format!("{:.3}", rate)).collect();
+        format!(
+            "Clips have different frame rates ({}); normalize to {:.3} fps to avoid stutter",
+            rates.join(", "),
+            recommended_fps.unwrap_or_default()
+        )
+    });
+    
+    ConcatFpsReport {
+        frame_rates,
+        mixed,
+        recommended_fps,
+        warning,
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConcatResult {
+    pub output_path: String,
+    /// False when the streams were copied with the concat demuxer
+    pub reencoded: bool,
+    /// Preset used for re-encoding
+    pub preset_id: Option<String>,
+    /// Differences between the inputs (each one rules out stream copy)
+    pub warnings: Vec<String>,
+}
+
+/// One warning if `values` (one per input) are not all the same
+fn mismatch(what: &str, values: &[String]) -> Option<String> {
+    let mut distinct: Vec<&String> = Vec::new();
+    for value in values {
+        if !distinct.contains(&value) {
+            distinct.push(value);
+        }
+    }
+    (distinct.len() > 1).then(|| {
+        let listed: Vec<&str> = distinct.iter().map(|v| v.as_str()).collect();
+        format!("Inputs have different {} ({})", what, listed.join(", "))
+    })
+}
+
+/// Everything the concat demuxer needs to be identical for a stream-copied join to play
+fn compatibility_warnings(infos: &[MediaInfo]) -> Vec<String> {
+    fn column(
+        infos: &[MediaInfo],
+        stream: fn(&MediaInfo) -> Option<&StreamInfo>,
+        field: fn(&StreamInfo) -> Option<String>,
+    ) -> Vec<String> {
+        infos
+            .iter()
+            .map(|info| match stream(info) {
+                Some(s) => field(s).unwrap_or_else(|| "unknown".to_string()),
+                None => "none".to_string(),
+            })
+            .collect()
+    }
+    let checks = [
+        ("video codecs", column(infos, video_stream, |s| s.codec_name.clone())),
+        ("resolutions", column(infos, video_stream, |s| Some(format!("{}x{}", s.width?, s.height?)))),
+        ("pixel formats", column(infos, video_stream, |s| s.pix_fmt.clone())),
+        ("audio codecs", column(infos, audio_stream, |s| s.codec_name.clone())),
+        ("audio sample rates", column(infos, audio_stream, |s| s.sample_rate.clone())),
+        ("audio channel counts", column(infos, audio_stream, |s| s.channels.map(|c| c.to_string()))),
+    ];
+    checks.iter().filter_map(|(what, values)| mismatch(what, values)).collect()
+}
+
+/// One line of a concat demuxer list. Inside single quotes everything is literal, so a
+/// quote is closed, escaped and reopened.
+fn concat_list_entry(path: &Path) -> String {
+    format!("file '{}'\n", path.to_string_lossy().replace('\'', "'\\''"))
+}
+
+/// The demuxer resolves relative entries against the list's directory, not ours
+fn absolute_path(path: &str) -> PathBuf {
+    let path = PathBuf::from(path);
+    if path.is_absolute() {
+        return path;
+    }
+    std::env::current_dir().map(|dir| dir.join(&path)).unwrap_or(path)
+}
+
+/// Filters a preset applies after the join (its `-vf`/`-af`, e.g. the GIF palette)
+#[derive(Default)]
+struct JoinedFilters<'a> {
+    video: Option<&'a str>,
+    audio: Option<&'a str>,
+}
+
+/// `-filter_complex` joining every input with the concat filter, after bringing the
+/// video to the first input's size (letterboxed) and a common frame rate, and the audio
+/// to one sample rate and layout. `then` runs on the joined streams; the results are
+/// labelled `[v]` and `[a]`.
+fn concat_filter(infos: &[MediaInfo], video: bool, audio: bool, fps: Option<f64>, then: &JoinedFilters) -> String {
+    let size = infos.first().and_then(video_stream).and_then(|s| Some((s.width?, s.height?)));
+    let (width, height) = size.map_or((1280, 720), |(w, h)| (w & !1, h & !1));
+    let mut graph = String::new();
+    let mut joined = String::new();
+    for index in 0..infos.len() {
+        if video {
+            graph.push_str(&format!(
+                "[{i}:v:0]scale={w}:{h}:force_original_aspect_ratio=decrease,pad={w}:{h}:(ow-iw)/2:(oh-ih)/2,setsar=1",
+                i = index,
+                w = width,
+                h = height
+            ));
+            if let Some(fps) = fps {
+                graph.push_str(&format!(",fps={}", fps));
+            }
+            graph.push_str(&format!("[v{}];", index));
+            joined.push_str(&format!("[v{}]", index));
+        }
+        if audio {
+            graph.push_str(&format!(
+                "[{i}:a:0]aresample=48000,aformat=sample_fmts=fltp:channel_layouts=stereo[a{i}];",
+                i = index
+            ));
+            joined.push_str(&format!("[a{}]", index));
+        }
+    }
+    let video_filter = then.video.filter(|_| video);
+    let audio_filter = then.audio.filter(|_| audio);
+    let mut filter = format!(
+        "{}{}concat=n={}:v={}:a={}{}{}",
+        graph,
+        joined,
+        infos.len(),
+        video as u8,
+        audio as u8,
+        match (video, video_filter) {
+            (false, _) => "",
+            (true, Some(_)) => "[joinedv]",
+            (true, None) => "[v]",
+        },
+        match (audio, audio_filter) {
+            (false, _) => "",
+            (true, Some(_)) => "[joineda]",
+            (true, None) => "[a]",
+        }
+    );
+    if let Some(chain) = video_filter {
+        filter.push_str(&format!(";[joinedv]{}[v]", chain));
+    }
+    if let Some(chain) = audio_filter {
+        filter.push_str(&format!(";[joineda]{}[a]", chain));
+    }
+    filter
+}
+
+/// Frame rate to bring every clip to: `normalize_fps` when given, otherwise the most
+/// common rate if the clips are mixed. `None` leaves the rates alone.
+fn target_fps(report: &ConcatFpsReport, normalize_fps: Option<f64>) -> Result<Option<f64>, ConvertError> {
+    match normalize_fps {
+        Some(fps) if !fps.is_finite() || fps <= 0.0 => {
+            Err(ConvertError::ConversionFailed(format!("Invalid frame rate: {}", fps)))
+        }
+        Some(fps) => Ok(Some(fps)),
+        None => Ok(report.recommended_fps.filter(|_| report.mixed)),
+    }
+}
+
+/// What `concat_files` joins and how
+pub struct ConcatJob {
+    pub inputs: Vec<String>,
+    pub output_path: String,
+    /// Re-encode even when the inputs could be stream-copied
+    pub reencode: bool,
+    /// Frame rate to bring every clip to
+    pub normalize_fps: Option<f64>,
+}
+
+/// Join the inputs end to end. Identical inputs are stream-copied with the concat demuxer
+/// (fast, lossless) unless `reencode`; anything else is re-encoded with the preset for
+/// the output's extension, through the concat filter which copes with differing inputs.
+/// The video is brought to `normalize_fps` (default: the most common input rate when
+/// the rates are mixed), which also rules out stream copy if any clip is at another rate.
+/// The command and the input warnings go to `log`; setting `cancel_flag` stops ffmpeg and
+/// deletes the partial output.
+pub fn concat_files(
+    job: &ConcatJob,
+    settings: &AppSettings,
+    ffmpeg_path: Option<&Path>,
+    ffprobe_path: Option<&Path>,
+    cancel_flag: &AtomicBool,
+    log: &mut ConversionLog,
+) -> Result<ConcatResult, ConvertError> {
+    let ConcatJob {
+        inputs,
+        output_path,
+        reencode,
+        normalize_fps,
+    } = job;
+    let (output_path, reencode, normalize_fps) = (output_path.as_str(), *reencode, *normalize_fps);
+    if inputs.len() < 2 {
+        return Err(ConvertError::ConversionFailed("Joining needs at least two inputs".to_string()));
+    }
+    // `-y` would overwrite the input while it is still being read
+    let output = absolute_path(output_path);
+    let output = output.canonicalize().unwrap_or(output);
+    if let Some(input) = inputs.iter().find(|input| {
+        let input = absolute_path(input);
+        input.canonicalize().unwrap_or(input) == output
+    }) {
+        return Err(ConvertError::InvalidOutputPath(format!("The output would overwrite the input {}", input)));
+    }
+    let mut infos = Vec::with_capacity(inputs.len());
+    for input in inputs {
+        infos.push(probe_file(input, ffprobe_path).map_err(|_| ConvertError::InputNotFound(input.clone()))?);
+    }
+    let fps = fps_report(
+        infos
+            .iter()
+            .map(|info| video_stream(info).and_then(|s| s.frame_rate_fps))
+            .collect(),
+    );
+    let target_fps = target_fps(&fps, normalize_fps)?;
+    let changes_fps = target_fps.is_some_and(|target| {
+        fps.frame_rates.iter().flatten().any(|rate| (rate - target).abs() >= FPS_TOLERANCE)
+    });
+    let mut warnings = compatibility_warnings(&infos);
+    warnings.extend(fps.warning.clone());
+    for warning in &warnings {
+        log.add_entry(LogLevel::Warning, warning, None);
+    }
+    
+    if !reencode && !changes_fps && warnings.is_empty() {
+        let list = TempFileSet::new(settings, "concat");
+        let list_path = PathBuf::from(format!("{}.txt", list.prefix_path().to_string_lossy()));
+        let mut contents = String::new();
+        for input in inputs {
+            if input.contains('\n') || input.contains('\r') {
+                return Err(ConvertError::ConversionFailed(format!("Unsupported file name: {}", input)));
+            }
+            contents.push_str(&concat_list_entry(&absolute_path(input)));
+        }
+        std::fs::write(&list_path, contents).map_err(|e| ConvertError::ConversionFailed(e.to_string()))?;
+        let args: Vec<String> = vec![
+            "-f".to_string(), "concat".to_string(),
+            "-safe".to_string(), "0".to_string(),
+            "-i".to_string(), list_path.to_string_lossy().to_string(),
+            "-c".to_string(), "copy".to_string(),
+            "-y".to_string(), output_path.to_string(),
+        ];
+        run_join(&args, output_path, ffmpeg_path, cancel_flag, log)?;
+        return Ok(ConcatResult {
+            output_path: output_path.to_string(),
+            reencoded: false,
+            preset_id: None,
+            warnings,
+        });
+    }
+    
+    let extension = Path::new(output_path)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let preset = get_all_presets()
+        .into_iter()
+        .find(|p| p.extension == extension)
+        .ok_or_else(|| ConvertError::InvalidOutputPath(format!("No preset writes .{} files", extension)))?;
+    let log_warnings = warnings.len();
+    // The preset's -vn/-an decide which stream types the output has; its -vf/-af run on the
+    // joined streams, as filter_complex outputs can't take them
+    let mut preset_args = Vec::new();
+    let mut then = JoinedFilters::default();
+    let built = preset.build_args();
+    let mut built_args = built.iter();
+    while let Some(arg) = built_args.next() {
+        match arg.as_str() {
+            "-vf" => then.video = built_args.next().map(String::as_str),
+            "-af" => then.audio = built_args.next().map(String::as_str),
+            _ => preset_args.push(arg.clone()),
+        }
+    }
+    let keeps_video = preset.category != PresetCategory::Audio && !preset_args.iter().any(|a| a == "-vn");
+    let keeps_audio = !preset_args.iter().any(|a| a == "-an");
+    let video = keeps_video && infos.iter().all(|info| video_stream(info).is_some());
+    let audio = keeps_audio && infos.iter().all(|info| audio_stream(info).is_some());
+    if !video && !audio {
+        return Err(ConvertError::ConversionFailed(
+            "Inputs have no stream type in common to join".to_string(),
+        ));
+    }
+    if !video && keeps_video && infos.iter().any(|info| video_stream(info).is_some()) {
+        warnings.push("Some inputs have no video; only their audio is joined".to_string());
+    }
+    if !audio && keeps_audio && infos.iter().any(|info| audio_stream(info).is_some()) {
+        warnings.push("Some inputs have no audio; the output has none".to_string());
+    }
+    for warning in &warnings[log_warnings..] {
+        log.add_entry(LogLevel::Warning, warning, None);
+    }
+    
+    let mut args = Vec::new();
+    for input in inputs {
+        args.push("-i".to_string());
+        args.push(input.clone());
+    }
+    args.push("-filter_complex".to_string());
+    args.push(concat_filter(&infos, video, audio, target_fps, &then));
+    if video {
+        args.push("-map".to_string());
+        args.push("[v]".to_string());
+    }
+    if audio {
+        args.push("-map".to_string());
+        args.push("[a]".to_string());
+    }
+    args.extend(preset_args);
+    args.push("-y".to_string());
+    args.push(output_path.to_string());
+    log.preset_id = Some(preset.id.clone());
+    run_join(&args, output_path, ffmpeg_path, cancel_flag, log)?;
+    Ok(ConcatResult {
+        output_path: output_path.to_string(),
+        reencoded: true,
+        preset_id: Some(preset.id),
+        warnings,
+    })
+}
+
+/// Run the join's ffmpeg command, recording it in `log`; a cancelled join leaves no output
+fn run_join(
+    args: &[String],
+    output_path: &str,
+    ffmpeg_path: Option<&Path>,
+    cancel_flag: &AtomicBool,
+    log: &mut ConversionLog,
+) -> Result<(), ConvertError> {
+    log.ffmpeg_command = format!("ffmpeg {}", args.join(" "));
+    let result = run_ffmpeg_cancellable(ffmpeg_path, args, cancel_flag, |_| {});
+    if let Err(ConvertError::Cancelled) = result {
+        std::fs::remove_file(output_path).ok();
+    }
+    result.map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mixed_rates_default_to_the_most_common() {
+        let report = fps_report(vec![Some(30.0), Some(60.0), Some(30.0)]);
+        assert_eq!(target_fps(&report, None).unwrap(), Some(30.0));
+        assert_eq!(target_fps(&report, Some(60.0)).unwrap(), Some(60.0));
+    }
+
+    #[test]
+    fn preset_filters_run_on_the_joined_video() {
+        let json = r#"{"streams": [{"index": 0, "codec_type": "video", "codec_name": "h264", "width": 640, "height": 360}],
+            "format": {"duration": "5.0"}}"#;
+        let info = crate::probe::parse_probe_output(json, "a.mp4").unwrap();
+        let infos = vec![info.clone(), info];
+        let then = JoinedFilters {
+            video: Some("fps=10,split[s0][s1];[s0]palettegen[p];[s1][p]paletteuse"),
+            audio: None,
+        };
+        let graph = concat_filter(&infos, true, false, None, &then);
+        assert!(graph.contains("concat=n=2:v=1:a=0[joinedv];[joinedv]fps=10,split[s0][s1]"));
+        assert!(graph.ends_with("paletteuse[v]"));
+        assert!(concat_filter(&infos, true, false, None, &JoinedFilters::default()).ends_with("concat=n=2:v=1:a=0[v]"));
+    }
+
+    #[test]
+    fn joining_into_an_input_is_refused() {
+        let job = ConcatJob {
+            inputs: vec!["clip1.mp4".to_string(), "./clip2.mp4".to_string()],
+            output_path: "clip2.mp4".to_string(),
+            reencode: false,
+            normalize_fps: None,
+        };
+        let mut log = ConversionLog::new("clip1.mp4", "clip2.mp4", None, None, "");
+        let never = AtomicBool::new(false);
+        let result = concat_files(&job, &AppSettings::default(), None, None, &never, &mut log);
+        assert!(matches!(result, Err(ConvertError::InvalidOutputPath(_))));
+    }
+
+    #[test]
+    fn matching_rates_are_left_alone_unless_asked() {
+        let report = fps_report(vec![Some(25.0), Some(25.0)]);
+        assert_eq!(target_fps(&report, None).unwrap(), None);
+        assert_eq!(target_fps(&report, Some(50.0)).unwrap(), Some(50.0));
+        assert!(target_fps(&report, Some(0.0)).is_err());
+        assert!(target_fps(&report, Some(f64::NAN)).is_err());
+    }
+}
@@ -1,7 +1,14 @@
+use crate::capabilities::EncoderInfo;
+use crate::filters::{burn_subtitles_filter, gif_palette_filter, review_overlay_filter, BurnSubtitleOptions, CropRect, GifOptions, ReviewOverlay, RotateMode};
+use crate::pause::PauseControl;
+use crate::preflight::{check_input_exists, check_output_writable, output_space, CheckStatus};
 use crate::presets::find_preset;
+use crate::probe::{MediaInfo, ReplayGain, StreamInfo, StreamType};
+use crate::settings::AppSettings;
 use ffmpeg_sidecar::command::FfmpegCommand;
 use ffmpeg_sidecar::event::{FfmpegEvent, LogLevel};
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use tauri::{AppHandle, Emitter};
@@ -21,6 +28,10 @@ pub enum ConvertError {
     Cancelled,
     #[error("Invalid output path: {0}")]
     InvalidOutputPath(String),
+    #[error("Could not reach streaming endpoint: {0}")]
+    StreamConnectionFailed(String),
+    #[error("Not enough disk space: the output needs about {} MB, {} MB free", .required / 1_000_000, .available / 1_000_000)]
+    InsufficientDiskSpace { required: u64, available: u64 },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -29,6 +40,9 @@ pub struct StreamSelection {
     pub include_audio: bool,
     pub include_subtitles: bool,
     pub include_data: bool,
+    /// Per-stream overrides of the type flags above, by input stream index
+    #[serde(default)]
+    pub stream_map: Vec<StreamMapEntry>,
 }
 
 impl Default for StreamSelection {
@@ -38,16 +52,302 @@ impl Default for StreamSelection {
             include_audio: true,
             include_subtitles: true,
             include_data: true,
+            stream_map: Vec::new(),
         }
     }
 }
 
+impl StreamSelection {
+    /// Whether `stream` goes into the output: its `stream_map` entry if it has one, else
+    /// the flag for its type
+    pub fn includes(&self, stream: &StreamInfo) -> bool {
+        if let Some(entry) = self.stream_map.iter().find(|e| e.stream_index == stream.index) {
+            return entry.include;
+        }
+        match stream.stream_type {
+            StreamType::Video => self.include_video,
+            StreamType::Audio => self.include_audio,
+            StreamType::Subtitle => self.include_subtitles,
+            StreamType::Data => self.include_data,
+            StreamType::Attachment | StreamType::Unknown => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamMapEntry {
+    /// Stream index as reported by `probe_media_file`
+    pub stream_index: u32,
+    pub include: bool,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AdvancedOptions {
     pub format: Option<String>,
     pub video_codec: Option<String>,
     pub audio_codec: Option<String>,
     pub extra_args: Option<String>,
+    /// Write the preset and ffmpeg arguments used into the output's `comment` tag
+    #[serde(default)]
+    pub embed_settings: bool,
+    /// Encoder thread count; picked per codec by `recommend_threads` when unset
+    pub threads: Option<u32>,
+    /// PCM codec for uncompressed audio (e.g. "pcm_s24le", "pcm_f32le"), replaces the audio codec
+    pub pcm_format: Option<String>,
+    /// Keep attachments (subtitle fonts, images) when the output container supports them (MKV)
+    #[serde(default)]
+    pub preserve_attachments: bool,
+    /// After a successful conversion, move the original to `<input>.bak` and put the
+    /// output in its place (same folder and name, new extension)
+    #[serde(default)]
+    pub replace_original: bool,
+    /// Closed GOPs (no references across keyframes), needed for clean HLS/DASH segments.
+    /// Honored by libx264, mpeg2video, mpeg4 (`-flags +cgop`) and libx265 (`open-gop=0`).
+    #[serde(default)]
+    pub closed_gop: bool,
+    /// Only place keyframes on the fixed GOP interval, never on scene cuts.
+    /// Honored by libx264, mpeg2video, mpeg4 (`-sc_threshold 0`) and libx265 (`scenecut=0`).
+    #[serde(default)]
+    pub no_scenecut: bool,
+    /// Best-effort passthrough of Dolby Vision / HDR10+ dynamic metadata. ffmpeg can only
+    /// keep Dolby Vision when the video is stream-copied into MP4/MOV/MKV; HDR10+ survives
+    /// a copy and libx265 re-encodes (when x265 is built with HDR10+ support). Any other
+    /// re-encode drops it, which is logged as a warning.
+    #[serde(default)]
+    pub preserve_dynamic_hdr: bool,
+    /// Fragmented MP4 (fMP4/CMAF) for DASH/HLS and playback while encoding. Unlike
+    /// faststart, which only moves the index, this changes the file structure. MP4/MOV only.
+    #[serde(default)]
+    pub fragmented: bool,
+    /// Retime the output to exactly this many seconds (e.g. a fixed ad slot) by speeding
+    /// up or slowing down video (`setpts`) and audio (`atempo`) by the needed factor
+    pub target_duration_secs: Option<f64>,
+    /// Play the output this many times faster: 4.0 for a timelapse, 0.5 for slow motion.
+    /// Video is retimed with `setpts`, audio with `atempo`. Excludes `target_duration_secs`.
+    pub speed: Option<f64>,
+    /// Per-stream codec overrides for multi-track outputs (e.g. TrueHD to AAC while copying
+    /// an existing AAC track). Every stream is mapped to the output when this is set.
+    #[serde(default)]
+    pub stream_codecs: Vec<StreamCodec>,
+    /// Encoder tune (`-tune`), e.g. film, animation, grain, zerolatency
+    pub tune: Option<String>,
+    /// Encoder profile (`-profile:v`), e.g. baseline/main/high for older TVs and phones
+    pub profile: Option<String>,
+    /// Codec level (`-level`), e.g. "4.1"
+    pub level: Option<String>,
+    /// Image quality from 1 (smallest) to 100 (best) for JPEG, WebP and AVIF output,
+    /// mapped onto the encoder's own scale; replaces the preset's quality setting
+    pub image_quality: Option<u32>,
+    /// Burn a label plus timecode/frame number into the video for review copies
+    pub review_overlay: Option<ReviewOverlay>,
+    /// Explicit channel routing, in the `pan` filter's syntax without the `pan=` prefix:
+    /// an output layout, then `|`-separated `out=in` terms using `c<N>` for input channel N.
+    /// "stereo|c0=c2|c1=c3" routes channels 3/4 to left/right, "mono|c0=c1" keeps only the
+    /// right channel, "stereo|c0=c1|c1=c0" swaps left and right.
+    pub channel_map: Option<String>,
+    /// Named output layout ("mono", "stereo", "5.1", "7.1", ...). Surround to stereo uses
+    /// an explicit downmix (center and surrounds at -3 dB, LFE dropped).
+    pub audio_channel_layout: Option<String>,
+    /// Force keyframes at given points (`-force_key_frames`) for exact seeking and segmenting
+    pub force_key_frames: Option<KeyframePlacement>,
+    /// Video timebase as "1/24000" for tools that insist on one. MP4/MOV store it as the
+    /// track timescale (`-video_track_timescale`, numerator must be 1); re-encodes also set
+    /// the encoder timebase (`-enc_time_base`). Matroska (fixed 1/1000) and MPEG-TS (fixed
+    /// 1/90000) ignore it.
+    pub timebase: Option<String>,
+    /// "Play everywhere" mode, applied on top of everything else: H.264 Main profile level 4.0,
+    /// yuv420p, even width/height, at most 30 fps (standard rates only), stereo AAC and
+    /// faststart. The output must be MP4/MOV.
+    #[serde(default)]
+    pub max_compatibility: bool,
+    /// Measure the audio's loudness and write ReplayGain track gain/peak tags (MP3, FLAC,
+    /// Ogg, M4A) so players normalize playback; the audio itself is left untouched. Measured
+    /// on the input, so filters that change levels (downmixes) make it slightly off.
+    #[serde(default)]
+    pub write_replaygain: bool,
+    /// Convert only part of the input: start time ("00:01:30", "1:30" or seconds)
+    pub trim_start: Option<String>,
+    /// End time of the part to convert, same formats as `trim_start`
+    pub trim_end: Option<String>,
+    /// Length of the part to convert, as an alternative to `trim_end`
+    pub trim_duration: Option<String>,
+    /// Average video bitrate (`-b:v`), e.g. "2500k" or "4M"; replaces the preset's
+    /// quality setting (CRF/CQ/qscale)
+    pub target_bitrate: Option<String>,
+    /// Encode twice: an analysis pass, then the real one, for accurate bitrate (and so size)
+    /// targeting. Needs a video bitrate and a software encoder.
+    #[serde(default)]
+    pub two_pass: bool,
+    /// EBU R128 loudness normalization (`loudnorm`), after any other audio filters
+    #[serde(default)]
+    pub normalize_audio: bool,
+    /// Integrated loudness target in LUFS; -16 (podcasts/streaming) when unset
+    pub loudness_target: Option<f64>,
+    /// Measure the audio first and normalize with the measured values (linear gain, no
+    /// dynamic compression). Decodes the input twice.
+    #[serde(default)]
+    pub loudness_two_pass: bool,
+    /// Render subtitles into the video. Independent of `include_subtitles`, which only
+    /// decides whether subtitle tracks are also kept as (soft) streams.
+    pub burn_subtitles: Option<BurnSubtitleOptions>,
+    /// Keep only this part of the picture (e.g. to remove black bars); applied before `scale`
+    pub crop: Option<CropRect>,
+    /// Output (width, height); -1 for either keeps the aspect ratio
+    pub scale: Option<(i32, i32)>,
+    /// Turn or mirror the picture; applied between `crop` and `scale`
+    pub rotate: Option<RotateMode>,
+    /// Only tag the video stream with the rotation and copy it, which is instant. MP4 and
+    /// MOV only; mirroring needs re-encoding.
+    #[serde(default)]
+    pub rotate_metadata_only: bool,
+    /// Output frame rate; frames are dropped or duplicated to reach it
+    pub fps: Option<f64>,
+    /// Copy the streams instead of re-encoding when the target container takes the input's
+    /// codecs and nothing requested needs encoding. Preset and explicit codecs are then
+    /// ignored; otherwise the conversion re-encodes as usual.
+    #[serde(default)]
+    pub copy_streams: bool,
+    /// Mix surround audio down for speakers/headphones; skipped when the source has no
+    /// more channels than the target
+    pub downmix: Option<ChannelLayout>,
+    /// Audio gain as a multiplier ("1.5") or in decibels ("+6dB", "-3dB"). With
+    /// `normalize_audio` it applies after normalization, as an offset from the target.
+    pub volume: Option<String>,
+    /// Palette settings for animated GIF output, replacing the GIF preset's
+    pub gif: Option<GifOptions>,
+    /// Tone-map HDR (PQ/HLG) video down to BT.709 SDR; skipped for SDR sources. Needs an
+    /// ffmpeg built with zimg (`zscale`).
+    #[serde(default)]
+    pub tonemap_to_sdr: bool,
+    /// Tags to set on the output (title, artist, date, ...); an empty value removes the tag.
+    /// Applied after `copy_metadata`, so they win over the source's.
+    pub metadata: Option<HashMap<String, String>>,
+    /// Carry the source's global tags over to the output
+    #[serde(default)]
+    pub copy_metadata: bool,
+    /// Keep the source's cover art (attached picture) in audio outputs that can store one
+    /// (MP3, M4A, FLAC). On by default; without advanced options the cover is kept too.
+    #[serde(default = "default_embed_cover")]
+    pub embed_cover: bool,
+    /// Write every frame as a numbered image (`frame_00001.png`, ...) instead of a single
+    /// picture. `output_path` names the directory (see `sequence_output_pattern`); `fps`
+    /// subsamples. PNG, JPEG, WebP, BMP and TIFF only.
+    #[serde(default)]
+    pub image_sequence: bool,
+    /// Frame rate to read an image-sequence input (`frame_%04d.png`) at; 25 if not given
+    pub input_frame_rate: Option<f64>,
+}
+
+fn default_embed_cover() -> bool {
+    true
+}
+
+impl Default for AdvancedOptions {
+    fn default() -> Self {
+        Self {
+            format: None,
+            video_codec: None,
+            audio_codec: None,
+            extra_args: None,
+            embed_settings: false,
+            threads: None,
+            pcm_format: None,
+            preserve_attachments: false,
+            replace_original: false,
+            closed_gop: false,
+            no_scenecut: false,
+            preserve_dynamic_hdr: false,
+            fragmented: false,
+            target_duration_secs: None,
+            speed: None,
+            stream_codecs: Vec::new(),
+            tune: None,
+            profile: None,
+            level: None,
+            image_quality: None,
+            review_overlay: None,
+            channel_map: None,
+            audio_channel_layout: None,
+            force_key_frames: None,
+            timebase: None,
+            max_compatibility: false,
+            write_replaygain: false,
+            trim_start: None,
+            trim_end: None,
+            trim_duration: None,
+            target_bitrate: None,
+            two_pass: false,
+            normalize_audio: false,
+            loudness_target: None,
+            loudness_two_pass: false,
+            burn_subtitles: None,
+            crop: None,
+            scale: None,
+            rotate: None,
+            rotate_metadata_only: false,
+            fps: None,
+            copy_streams: false,
+            downmix: None,
+            volume: None,
+            gif: None,
+            tonemap_to_sdr: false,
+            metadata: None,
+            copy_metadata: false,
+            embed_cover: true,
+            image_sequence: false,
+            input_frame_rate: None,
+        }
+    }
+}
+
+/// Downmix target of `downmix`
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChannelLayout {
+    Stereo,
+    Mono,
+}
+
+impl ChannelLayout {
+    /// ffmpeg layout name
+    pub fn name(self) -> &'static str {
+        match self {
+            ChannelLayout::Stereo => "stereo",
+            ChannelLayout::Mono => "mono",
+        }
+    }
+
+    pub fn channels(self) -> u32 {
+        match self {
+            ChannelLayout::Stereo => 2,
+            ChannelLayout::Mono => 1,
+        }
+    }
+}
+
+/// Where `-force_key_frames` puts keyframes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum KeyframePlacement {
+    /// Keyframes at these times in seconds (e.g. chapter starts); must be within the input
+    Timestamps { times: Vec<f64> },
+    /// A keyframe every `seconds`
+    Interval { seconds: f64 },
+    /// A raw ffmpeg expression: `expr:` followed by an expression over `t` (time),
+    /// `n` (frame number), `n_forced` (keyframes forced so far) and `prev_forced_t`,
+    /// e.g. `expr:gte(t,n_forced*5)`. `source` keeps the input's keyframes.
+    Expression { expr: String },
+    /// A keyframe at every chapter start of the input (from probe), so players can jump
+    /// straight to a chapter; shifted along with a trim start
+    Chapters,
+}
+
+/// Codec for one input stream
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamCodec {
+    /// Stream index as reported by `probe_media_file`
+    pub stream_index: u32,
+    pub codec: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -57,204 +357,3727 @@ pub struct ConvertOptions {
     pub preset_id: Option<String>,
     pub advanced: Option<AdvancedOptions>,
     pub stream_selection: Option<StreamSelection>,
+    /// Global settings in effect for this conversion (filled in by the app, not the caller)
+    #[serde(skip)]
+    pub settings: AppSettings,
+    /// Id of the job running this conversion, carried by its events (filled in by the app)
+    #[serde(skip)]
+    pub job_id: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConvertProgress {
+    /// Job the progress belongs to; several run at once in a parallel batch
+    pub job_id: u64,
+    /// `None` for live outputs, and while the total length is unknown (neither a duration
+    /// nor a frame count) and the output has outgrown the input: progress is indeterminate
+    pub percent: Option<f64>,
+    pub time_secs: f64,
+    pub speed: Option<String>,
+    pub bitrate: Option<String>,
+    pub size_kb: Option<u64>,
+    /// Frames written so far
+    pub frame: Option<u64>,
+    /// Expected frame count, known for image-sequence inputs and outputs
+    pub total_frames: Option<u64>,
+    /// Open-ended live stream output: only time/speed/bitrate advance
+    pub live: bool,
+    /// Estimated seconds remaining; `None` while the total length (duration or frame count)
+    /// is unknown, for live outputs, and in the first moments of the encode
+    pub eta_secs: Option<f64>,
+}
+
+/// Payload of the `convert-retry` event, sent before waiting to retry a failed attempt
+#[derive(Debug, Clone, Serialize)]
+pub struct ConvertRetry {
+    pub job_id: u64,
+    /// Retry about to be made, from 1
+    pub attempt: u32,
+    pub max_retries: u32,
+    /// Wait before the retry starts
+    pub delay_secs: u64,
+    /// Error that ended the previous attempt
+    pub reason: String,
+}
+
+/// Remaining-time estimate from wall-clock time and percent done, averaged over the
+/// last few progress events so it doesn't jump around while the encoder speed settles
+#[derive(Default)]
+struct EtaEstimator {
+    /// (elapsed seconds when made, estimate)
+    recent: std::collections::VecDeque<(f64, f64)>,
+}
+
+impl EtaEstimator {
+    /// Estimates averaged together
+    const WINDOW: usize = 8;
+    /// Below this much progress the rate says nothing yet
+    const MIN_PERCENT: f64 = 1.0;
+    
+    fn update(&mut self, elapsed_secs: f64, percent: f64) -> Option<f64> {
+        if !(Self::MIN_PERCENT..100.0).contains(&percent) {
+            return if percent >= 100.0 { Some(0.0) } else { None };
+        }
+        let estimate = elapsed_secs * (100.0 - percent) / percent;
+        if self.recent.len() == Self::WINDOW {
+            self.recent.pop_front();
+        }
+        self.recent.push_back((elapsed_secs, estimate));
+        // Older estimates count down by the time passed since they were made
+        let sum: f64 = self
+            .recent
+            .iter()
+            .map(|(made_at, estimate)| estimate - (elapsed_secs - made_at))
+            .sum();
+        Some((sum / self.recent.len() as f64).max(0.0))
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConvertResult {
+    pub success: bool,
+    pub output_path: String,
+    pub duration_secs: f64,
+    pub message: Option<String>,
+    /// Duration of the converted file, when it was measured (target-duration and speed
+    /// conversions, and stopped ones)
+    pub output_duration_secs: Option<f64>,
+    /// Stopped early with `stop_convert`: `output_path` holds the part encoded until then
+    pub stopped: bool,
+    /// Images written, for image-sequence output (`output_path` is then the file pattern)
+    pub frames_written: Option<u64>,
+}
+
+/// Outcome of one file in `start_batch_convert`
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchItemResult {
+    /// Position in the batch (0-based)
+    pub index: usize,
+    /// Job that converted the file, matching its progress events
+    pub job_id: u64,
+    pub input_path: String,
+    pub result: Option<ConvertResult>,
+    pub error: Option<String>,
+}
+
+/// Payload of the `batch-job-started` event, sent when a batch file starts converting
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchJobStarted {
+    pub index: usize,
+    pub job_id: u64,
+}
+
+/// Payload of the `batch-progress` event, sent after each file
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchProgress {
+    pub index: usize,
+    pub total: usize,
+    pub item: BatchItemResult,
+}
+
+/// Result of `start_batch_convert`. Files after a cancellation (or after a failure with
+/// `stop_on_error`) are not attempted and have no entry.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BatchResult {
+    pub items: Vec<BatchItemResult>,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub cancelled: bool,
+}
+
+/// Result of `quick_remux`: the conversion plus how it was done
+#[derive(Debug, Clone, Serialize)]
+pub struct QuickRemuxResult {
+    pub result: ConvertResult,
+    /// True when the streams were copied; false when the copy failed and the file was re-encoded
+    pub remuxed: bool,
+    /// Why the stream copy failed (ffmpeg's last error line), when it fell back
+    pub remux_error: Option<String>,
+}
+
+/// Result of `convert_for_sharing`: the conversion plus the size it achieved
+#[derive(Debug, Clone, Serialize)]
+pub struct SharingResult {
+    pub result: ConvertResult,
+    pub max_bytes: u64,
+    pub output_bytes: u64,
+    pub within_limit: bool,
+}
+
+impl SharingResult {
+    pub fn new(result: ConvertResult, max_mb: f64) -> Self {
+        let max_bytes = (max_mb * 1024.0 * 1024.0) as u64;
+        let output_bytes = std::fs::metadata(&result.output_path)
+            .map(|m| m.len())
+            .unwrap_or(0);
+        Self {
+            result,
+            max_bytes,
+            output_bytes,
+            within_limit: output_bytes <= max_bytes,
+        }
+    }
+}
+
+/// Check if ffmpeg is available
+pub fn check_ffmpeg(sidecar_path: Option<&std::path::Path>) -> Result<String, ConvertError> {
+    use std::process::Command;
+    
+    let output = Command::new(ffmpeg_program(sidecar_path))
+        .arg("-version")
+        .output()
+        .map_err(|_| ConvertError::FfmpegNotFound)?;
+    
+    if output.status.success() {
+        let version = String::from_utf8_lossy(&output.stdout);
+        let first_line = version.lines().next().unwrap_or("ffmpeg version unknown");
+        Ok(first_line.to_string())
+    } else {
+        Err(ConvertError::FfmpegNotFound)
+    }
+}
+
+/// Resolve the ffmpeg executable: the bundled sidecar if known, otherwise ffmpeg from PATH
+pub fn ffmpeg_program(sidecar_path: Option<&std::path::Path>) -> String {
+    if let Some(path) = sidecar_path {
+        path.to_string_lossy().to_string()
+    } else {
+        "ffmpeg".to_string()
+    }
+}
+
+/// `dir` followed by the entries of `current`, joined with the platform's separator
+/// (`;` on Windows, `:` elsewhere). An entry equal to `dir` is dropped so PATH doesn't
+/// grow with duplicates. `None` if `dir` can't go in PATH (contains the separator).
+fn prepend_to_path(dir: &std::path::Path, current: Option<std::ffi::OsString>) -> Option<std::ffi::OsString> {
+    let existing = current
+        .as_deref()
+        .map(|p| std::env::split_paths(p).filter(|entry| entry != dir).collect::<Vec<_>>())
+        .unwrap_or_default();
+    std::env::join_paths(std::iter::once(dir.to_path_buf()).chain(existing)).ok()
+}
+
+/// An ffmpeg command for the sidecar, spawned by its absolute path. The sidecar's folder is
+/// put first in the child's own PATH (so helpers next to it are found); the app's PATH is
+/// never changed, since conversions run concurrently.
+fn sidecar_command(sidecar_path: Option<&std::path::Path>) -> FfmpegCommand {
+    let mut cmd = FfmpegCommand::new_with_path(ffmpeg_program(sidecar_path));
+    if let Some(parent) = sidecar_path.and_then(|p| p.parent()) {
+        if let Some(path) = prepend_to_path(parent, std::env::var_os("PATH")) {
+            cmd.as_inner_mut().env("PATH", path);
+        }
+    }
+    cmd
+}
+
+/// Quote an argument for pasting into the platform's shell: POSIX single quotes, or
+/// Windows double quotes (backslashes before a quote doubled, as programs parse them)
+fn shell_quote(arg: &str) -> String {
+    let plain = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:=+,@%".contains(c) || (cfg!(windows) && c == '\\'));
+    if plain {
+        return arg.to_string();
+    }
+    if cfg!(windows) {
+        let mut quoted = String::from("\"");
+        let mut backslashes = 0;
+        for c in arg.chars() {
+            match c {
+                '\\' => backslashes += 1,
+                '"' => {
+                    // Double the backslashes already written and escape the quote
+                    quoted.push_str(&"\\".repeat(backslashes + 1));
+                    backslashes = 0;
+                }
+                _ => backslashes = 0,
+            }
+            quoted.push(c);
+        }
+        // Backslashes before the closing quote would escape it
+        quoted.push_str(&"\\".repeat(backslashes));
+        quoted.push('"');
+        quoted
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+/// A command line that runs `program` with `args` when pasted into a terminal
+pub fn shell_command_line(program: &str, args: &[String]) -> String {
+    std::iter::once(program)
+        .chain(args.iter().map(String::as_str))
+        .map(shell_quote)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Run ffmpeg to completion without progress reporting (for short utility jobs).
+/// Returns ffmpeg's stderr output on success, the last error line on failure.
+pub fn run_ffmpeg(sidecar_path: Option<&std::path::Path>, args: &[String]) -> Result<String, ConvertError> {
+    use std::process::Command;
+    
+    let output = Command::new(ffmpeg_program(sidecar_path))
+        .arg("-hide_banner")
+        .args(args)
+        .output()
+        .map_err(|_| ConvertError::FfmpegNotFound)?;
+    
+    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+    if output.status.success() {
+        Ok(stderr)
+    } else {
+        let last_line = stderr
+            .lines()
+            .rev()
+            .find(|l| !l.trim().is_empty())
+            .unwrap_or("Unknown error");
+        Err(ConvertError::ConversionFailed(last_line.trim().to_string()))
+    }
+}
+
+/// Like `run_ffmpeg`, but ffmpeg is killed once `cancel_flag` is set, and `on_progress` gets
+/// the output time in seconds each time ffmpeg reports it (about twice a second)
+pub fn run_ffmpeg_cancellable(
+    sidecar_path: Option<&std::path::Path>,
+    args: &[String],
+    cancel_flag: &AtomicBool,
+    mut on_progress: impl FnMut(f64),
+) -> Result<String, ConvertError> {
+    use std::io::{BufRead, BufReader, Read};
+    use std::process::{Command, Stdio};
+    
+    let mut child = Command::new(ffmpeg_program(sidecar_path))
+        .args(["-hide_banner", "-nostats", "-progress", "pipe:1"])
+        .args(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|_| ConvertError::FfmpegNotFound)?;
+    // Drain stderr on its own thread so a chatty ffmpeg can't block on a full pipe
+    let mut stderr_pipe = child.stderr.take();
+    let stderr_reader = std::thread::spawn(move || {
+        let mut stderr = String::new();
+        if let Some(pipe) = stderr_pipe.as_mut() {
+            pipe.read_to_string(&mut stderr).ok();
+        }
+        stderr
+    });
+    
+    if let Some(stdout) = child.stdout.take() {
+        for line in BufReader::new(stdout).lines() {
+            if cancel_flag.load(Ordering::Relaxed) {
+                break;
+            }
+            let Ok(line) = line else {
+                break;
+            };
+            if let Some(time) = line.strip_prefix("out_time=").and_then(parse_time_str) {
+                on_progress(time);
+            }
+        }
+    }
+    if cancel_flag.load(Ordering::Relaxed) {
+        child.kill().ok();
+        child.wait().ok();
+        stderr_reader.join().ok();
+        return Err(ConvertError::Cancelled);
+    }
+    let status = child.wait().map_err(|e| ConvertError::ConversionFailed(e.to_string()))?;
+    let stderr = stderr_reader.join().unwrap_or_default();
+    if status.success() {
+        Ok(stderr)
+    } else {
+        let last_line = stderr
+            .lines()
+            .rev()
+            .find(|l| !l.trim().is_empty())
+            .unwrap_or("Unknown error");
+        Err(ConvertError::ConversionFailed(last_line.trim().to_string()))
+    }
+}
+
+/// What a stream copy into a container does with a subtitle stream
+#[derive(Debug, PartialEq)]
+enum SubtitleRemux {
+    Copy,
+    /// Text subtitles the container only takes in its own format
+    Convert(&'static str),
+    Drop,
+}
+
+/// How a `codec` subtitle stream (as ffprobe names it) gets into `container` in a remux
+fn subtitle_remux(container: &str, codec: &str) -> SubtitleRemux {
+    let text = matches!(codec, "subrip" | "srt" | "ass" | "ssa" | "webvtt" | "mov_text" | "text");
+    match (container, container_accepts(container, codec)) {
+        // Matroska stores every subtitle codec ffmpeg knows; unknown containers are left to ffmpeg
+        ("matroska" | "mkv", _) | (_, Some(true)) | (_, None) => SubtitleRemux::Copy,
+        ("mp4" | "mov" | "m4v", _) if text => SubtitleRemux::Convert("mov_text"),
+        ("webm", _) if text => SubtitleRemux::Convert("webvtt"),
+        _ => SubtitleRemux::Drop,
+    }
+}
+
+/// Arguments copying video and audio and the subtitle streams `container` can take:
+/// text subtitles it can't copy are converted to its own text format, the rest dropped
+/// (listed in the returned warning)
+fn remux_args(
+    input_path: &str,
+    output_path: &str,
+    subtitles: &[&StreamInfo],
+) -> (Vec<String>, Option<String>) {
+    let container = target_container(&[], output_path);
+    let mut args: Vec<String> = ["-i", input_path, "-map", "0:v?", "-map", "0:a?"]
+        .iter()
+        .map(|a| a.to_string())
+        .collect();
+    let mut conversions = Vec::new();
+    let mut dropped = Vec::new();
+    // Output subtitle index, for `-c:s:N`
+    let mut mapped = 0;
+    for stream in subtitles {
+        let codec = stream.codec_name.as_deref().unwrap_or("unknown");
+        let action = subtitle_remux(&container, codec);
+        if action == SubtitleRemux::Drop {
+            dropped.push(format!("#{} ({})", stream.index, codec));
+            continue;
+        }
+        if let SubtitleRemux::Convert(encoder) = action {
+            conversions.push(format!("-c:s:{}", mapped));
+            conversions.push(encoder.to_string());
+        }
+        args.push("-map".to_string());
+        args.push(format!("0:{}", stream.index));
+        mapped += 1;
+    }
+    args.push("-c".to_string());
+    args.push("copy".to_string());
+    args.extend(conversions);
+    args.push("-y".to_string());
+    args.push(output_path.to_string());
+    let warning = (!dropped.is_empty()).then(|| {
+        format!("Subtitle streams {} dropped: {} cannot store them", dropped.join(", "), container)
+    });
+    (args, warning)
+}
+
+/// Copy all video and audio streams, and the subtitle streams the target container can
+/// take (see `remux_args`), into a new container without re-encoding. Only the stream list
+/// is probed, to pick the subtitles. Fails (leaving no output behind) when the container
+/// can't hold a stream's codec or `cancel_flag` is set.
+pub fn remux_streams(
+    input_path: &str,
+    output_path: &str,
+    sidecar_path: Option<&std::path::Path>,
+    ffprobe_path: Option<&std::path::Path>,
+    cancel_flag: &AtomicBool,
+) -> Result<ConvertResult, ConvertError> {
+    let start = std::time::Instant::now();
+    // An unreadable stream list keeps no subtitles rather than risking the copy
+    let info = crate::probe::probe_file(input_path, ffprobe_path).ok();
+    let subtitles: Vec<&StreamInfo> = info
+        .iter()
+        .flat_map(|info| info.streams.iter())
+        .filter(|s| s.stream_type == StreamType::Subtitle)
+        .collect();
+    let (args, warning) = remux_args(input_path, output_path, &subtitles);
+    if let Err(e) = run_ffmpeg_cancellable(sidecar_path, &args, cancel_flag, |_| {}) {
+        std::fs::remove_file(output_path).ok();
+        return Err(e);
+    }
+    Ok(ConvertResult {
+        success: true,
+        output_path: output_path.to_string(),
+        duration_secs: start.elapsed().as_secs_f64(),
+        message: warning,
+        output_duration_secs: None,
+        stopped: false,
+        frames_written: None,
+    })
+}
+
+/// Standalone file extension that holds `codec` (as ffprobe names it) without re-encoding
+fn raw_audio_extension(codec: &str) -> Option<&'static str> {
+    match codec {
+        "aac" | "alac" => Some("m4a"),
+        "mp3" => Some("mp3"),
+        "opus" => Some("opus"),
+        "vorbis" => Some("ogg"),
+        "flac" => Some("flac"),
+        "ac3" => Some("ac3"),
+        "eac3" => Some("eac3"),
+        "dts" => Some("dts"),
+        "truehd" => Some("mka"),
+        pcm if pcm.starts_with("pcm_") => Some("wav"),
+        _ => None,
+    }
+}
+
+/// Extensions ffmpeg picks a sensible audio encoder for on its own
+const AUDIO_EXTENSIONS: &[&str] = &["m4a", "mp3", "opus", "ogg", "flac", "wav", "aac", "ac3", "mka"];
+
+/// Save the first audio track of `input_path` on its own. Without `reencode` the track is
+/// copied (lossless, near-instant) and `output_path`'s extension is replaced by the one
+/// matching its codec; codecs with no standalone file type, or a failed copy, fall back to
+/// re-encoding, as the result message says.
+pub fn extract_audio(
+    input_path: &str,
+    output_path: &str,
+    reencode: bool,
+    ffmpeg_path: Option<&std::path::Path>,
+    ffprobe_path: Option<&std::path::Path>,
+) -> Result<ConvertResult, ConvertError> {
+    let start = std::time::Instant::now();
+    let info = crate::probe::probe_file(input_path, ffprobe_path)
+        .map_err(|_| ConvertError::InputNotFound(input_path.to_string()))?;
+    let codec = info
+        .streams
+        .iter()
+        .find(|s| s.stream_type == StreamType::Audio)
+        .ok_or_else(|| ConvertError::ConversionFailed("File has no audio".to_string()))?
+        .codec_name
+        .clone()
+        .unwrap_or_default();
+    let extract_args = |codec_args: &[&str], output: &str| -> Vec<String> {
+        let mut args: Vec<String> = ["-i", input_path, "-map", "0:a:0", "-vn", "-sn", "-dn"].map(String::from).to_vec();
+        args.extend(codec_args.iter().map(|a| a.to_string()));
+        args.extend(["-y".to_string(), output.to_string()]);
+        args
+    };
+    
+    let mut message = None;
+    if !reencode {
+        match raw_audio_extension(&codec) {
+            Some(extension) => {
+                let copy_path = std::path::Path::new(output_path)
+                    .with_extension(extension)
+                    .to_string_lossy()
+                    .to_string();
+                match run_ffmpeg(ffmpeg_path, &extract_args(&["-c:a", "copy"], &copy_path)) {
+                    Ok(_) => {
+                        return Ok(ConvertResult {
+                            success: true,
+                            output_path: copy_path,
+                            duration_secs: start.elapsed().as_secs_f64(),
+                            message: None,
+                            output_duration_secs: info.format.duration,
+                            stopped: false,
+                            frames_written: None,
+                        })
+                    }
+                    Err(e) => {
+                        std::fs::remove_file(&copy_path).ok();
+                        message = Some(format!("Copying the {} track failed ({}); re-encoded instead", codec, e));
+                    }
+                }
+            }
+            None => {
+                message = Some(format!("{} audio has no standalone file type; re-encoded instead", codec));
+            }
+        }
+    }
+    
+    // Re-encode with ffmpeg's default encoder for the extension, or AAC in M4A for others
+    let extension = std::path::Path::new(output_path)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let (output_path, codec_args): (String, &[&str]) = if AUDIO_EXTENSIONS.contains(&extension.as_str()) {
+        (output_path.to_string(), &[])
+    } else {
+        let path = std::path::Path::new(output_path).with_extension("m4a");
+        (path.to_string_lossy().to_string(), &["-c:a", "aac", "-b:a", "192k"])
+    };
+    run_ffmpeg(ffmpeg_path, &extract_args(codec_args, &output_path))?;
+    Ok(ConvertResult {
+        success: true,
+        output_path,
+        duration_secs: start.elapsed().as_secs_f64(),
+        message,
+        output_duration_secs: info.format.duration,
+        stopped: false,
+        frames_written: None,
+    })
+}
+
+/// Save the embedded cover art (the attached-picture stream) of `input_path`. The picture is
+/// copied as-is when the output extension matches its format, otherwise re-encoded.
+pub fn extract_cover_art(
+    input_path: &str,
+    output_path: &str,
+    ffmpeg_path: Option<&std::path::Path>,
+    ffprobe_path: Option<&std::path::Path>,
+) -> Result<String, ConvertError> {
+    let info = crate::probe::probe_file(input_path, ffprobe_path)
+        .map_err(|_| ConvertError::InputNotFound(input_path.to_string()))?;
+    let cover = cover_art_stream(&info)
+        .ok_or_else(|| ConvertError::ConversionFailed("File has no cover art".to_string()))?;
+    
+    let extension = std::path::Path::new(output_path)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let target = match extension.as_str() {
+        "jpg" | "jpeg" => "mjpeg",
+        "png" => "png",
+        _ => {
+            return Err(ConvertError::InvalidOutputPath(
+                "Cover art can be saved as .jpg or .png".to_string(),
+            ))
+        }
+    };
+    let codec = if cover.codec_name.as_deref() == Some(target) { "copy" } else { target };
+    
+    let args: Vec<String> = [
+        "-y", "-i", input_path,
+        "-map", &format!("0:{}", cover.index),
+        "-c:v", codec,
+        "-frames:v", "1",
+        output_path,
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect();
+    run_ffmpeg(ffmpeg_path, &args)?;
+    Ok(output_path.to_string())
+}
+
+/// Write the left and right channels of a stereo file as two mono WAV files in `output_dir`
+/// (`<name>_left.wav`, `<name>_right.wav`). Returns the two paths.
+pub fn split_stereo(
+    input_path: &str,
+    output_dir: &str,
+    ffmpeg_path: Option<&std::path::Path>,
+    ffprobe_path: Option<&std::path::Path>,
+) -> Result<Vec<String>, ConvertError> {
+    let info = crate::probe::probe_file(input_path, ffprobe_path)
+        .map_err(|_| ConvertError::InputNotFound(input_path.to_string()))?;
+    let audio = info
+        .streams
+        .iter()
+        .find(|s| s.stream_type == StreamType::Audio)
+        .ok_or_else(|| ConvertError::ConversionFailed("File has no audio".to_string()))?;
+    if audio.channels != Some(2) {
+        return Err(ConvertError::ConversionFailed(format!(
+            "Only stereo audio can be split into left/right (this file has {} channels); \
+             use a channel map to pick channels from other layouts",
+            audio.channels.map_or("an unknown number of".to_string(), |c| c.to_string())
+        )));
+    }
+    let dir = std::path::Path::new(output_dir);
+    if !dir.is_dir() {
+        return Err(ConvertError::InvalidOutputPath(format!(
+            "Output directory does not exist: {}",
+            output_dir
+        )));
+    }
+    
+    // Keep 24-bit precision for anything beyond 16-bit sources
+    let codec = match audio.sample_fmt.as_deref() {
+        Some("u8" | "u8p" | "s16" | "s16p") => "pcm_s16le",
+        _ => "pcm_s24le",
+    };
+    let stem = std::path::Path::new(input_path).file_stem().unwrap_or_default().to_string_lossy();
+    let left = ensure_unique_output_path(&dir.join(format!("{}_left.wav", stem)).to_string_lossy());
+    let right = ensure_unique_output_path(&dir.join(format!("{}_right.wav", stem)).to_string_lossy());
+    
+    let args: Vec<String> = [
+        "-y", "-i", input_path,
+        "-filter_complex", &format!("[0:{}]channelsplit=channel_layout=stereo[l][r]", audio.index),
+        "-map", "[l]", "-c:a", codec, &left,
+        "-map", "[r]", "-c:a", codec, &right,
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect();
+    run_ffmpeg(ffmpeg_path, &args)?;
+    Ok(vec![left, right])
+}
+
+/// Files written by `split_by_duration`
+#[derive(Debug, Clone, Serialize)]
+pub struct SegmentResult {
+    pub count: usize,
+    /// Segment files in order
+    pub paths: Vec<String>,
+    pub message: Option<String>,
+}
+
+/// Output name for the segment muxer. It expands % sequences anywhere in the name, so a
+/// literal % in the file name is doubled.
+fn segment_pattern(dir: &std::path::Path, stem: &str, extension: &str) -> String {
+    dir.join(format!("{}_part%03d.{}", stem.replace('%', "%%"), extension))
+        .to_string_lossy()
+        .to_string()
+}
+
+/// How `split_by_duration` cuts a file
+pub struct SegmentSplit {
+    pub segment_secs: f64,
+    /// Re-encode for exact lengths instead of cutting at the copied stream's keyframes
+    pub reencode: bool,
+}
+
+/// Emitted as `split-progress` while `split_by_duration` runs
+#[derive(Debug, Clone, Serialize)]
+pub struct SplitProgress {
+    /// `None` when the input's duration is unknown
+    pub percent: Option<f64>,
+    pub time_secs: f64,
+}
+
+/// Cut `input_path` into consecutive `segment_secs` long files in `output_dir`, named
+/// `<name>_part001.<ext>`, `<name>_part002.<ext>`, ... with the segment muxer. Stream copy
+/// can only cut at keyframes, so copied segments run up to the next keyframe; re-encoding
+/// forces keyframes at the cut points for exact lengths. Setting `cancel_flag` stops ffmpeg
+/// and deletes the parts written so far.
+pub fn split_by_duration(
+    input_path: &str,
+    output_dir: &str,
+    split: SegmentSplit,
+    ffmpeg_path: Option<&std::path::Path>,
+    ffprobe_path: Option<&std::path::Path>,
+    cancel_flag: &AtomicBool,
+    mut on_progress: impl FnMut(SplitProgress),
+) -> Result<SegmentResult, ConvertError> {
+    let SegmentSplit { segment_secs, reencode } = split;
+    if !segment_secs.is_finite() || segment_secs <= 0.0 {
+        return Err(ConvertError::ConversionFailed(format!(
+            "Invalid segment length: {}",
+            segment_secs
+        )));
+    }
+    let info = crate::probe::probe_file(input_path, ffprobe_path)
+        .map_err(|_| ConvertError::InputNotFound(input_path.to_string()))?;
+    let dir = std::path::Path::new(output_dir);
+    if !dir.is_dir() {
+        return Err(ConvertError::InvalidOutputPath(format!(
+            "Output directory does not exist: {}",
+            output_dir
+        )));
+    }
+    
+    let input = std::path::Path::new(input_path);
+    let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = input.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_else(|| "mp4".to_string());
+    let part_path = |n: usize| dir.join(format!("{}_part{:03}.{}", stem, n, extension));
+    // The segment muxer overwrites silently; don't let it clobber an earlier split
+    if part_path(1).exists() {
+        return Err(ConvertError::InvalidOutputPath(format!(
+            "{} already exists",
+            part_path(1).display()
+        )));
+    }
+    
+    let segment_time = format!("{:.3}", segment_secs);
+    let mut args: Vec<String> = ["-i", input_path, "-map", "0:v?", "-map", "0:a?", "-map", "0:s?"]
+        .map(String::from)
+        .to_vec();
+    if reencode {
+        args.extend(["-force_key_frames".to_string(), format!("expr:gte(t,n_forced*{})", segment_time)]);
+    } else {
+        args.extend(["-c", "copy"].map(String::from));
+    }
+    args.extend(
+        [
+            "-f", "segment",
+            "-segment_time", &segment_time,
+            "-segment_start_number", "1",
+            "-reset_timestamps", "1",
+            "-y", &segment_pattern(dir, &stem, &extension),
+        ]
+        .map(String::from),
+    );
+    let duration = info.format.duration.filter(|d| *d > 0.0);
+    let written = || -> Vec<String> {
+        (1..)
+            .map(part_path)
+            .take_while(|p| p.exists())
+            .map(|p| p.to_string_lossy().to_string())
+            .collect()
+    };
+    let result = run_ffmpeg_cancellable(ffmpeg_path, &args, cancel_flag, |time_secs| {
+        on_progress(SplitProgress {
+            percent: duration.map(|d| (time_secs / d * 100.0).min(100.0)),
+            time_secs,
+        })
+    });
+    if let Err(ConvertError::Cancelled) = result {
+        for path in written() {
+            std::fs::remove_file(path).ok();
+        }
+    }
+    result?;
+    
+    let paths = written();
+    if paths.is_empty() {
+        return Err(ConvertError::ConversionFailed("No segments were written".to_string()));
+    }
+    let message = info
+        .format
+        .duration
+        .filter(|d| *d <= segment_secs)
+        .map(|d| format!("The file is only {:.1}s long, shorter than one segment: written as a single part", d));
+    Ok(SegmentResult {
+        count: paths.len(),
+        paths,
+        message,
+    })
+}
+
+/// Write every attachment of `input_path` (fonts, images, ...) into `output_dir`.
+/// Files are named from the attachment's filename tag. Returns the written paths.
+pub fn extract_attachments(
+    input_path: &str,
+    output_dir: &str,
+    ffmpeg_path: Option<&std::path::Path>,
+    ffprobe_path: Option<&std::path::Path>,
+) -> Result<Vec<String>, ConvertError> {
+    let info = crate::probe::probe_file(input_path, ffprobe_path)
+        .map_err(|_| ConvertError::InputNotFound(input_path.to_string()))?;
+    if info.attachments.is_empty() {
+        return Err(ConvertError::ConversionFailed("File has no attachments".to_string()));
+    }
+    let dir = std::path::Path::new(output_dir);
+    if !dir.is_dir() {
+        return Err(ConvertError::InvalidOutputPath(format!(
+            "Output directory does not exist: {}",
+            output_dir
+        )));
+    }
+    
+    let mut args: Vec<String> = Vec::new();
+    let mut written = Vec::new();
+    for attachment in &info.attachments {
+        let name = attachment
+            .filename
+            .as_deref()
+            .and_then(|f| std::path::Path::new(f).file_name())
+            .map(|f| f.to_string_lossy().to_string())
+            .unwrap_or_else(|| format!("attachment_{}", attachment.index));
+        let path = dir.join(name).to_string_lossy().to_string();
+        args.push(format!("-dump_attachment:{}", attachment.index));
+        args.push(path.clone());
+        written.push(path);
+    }
+    // -dump_attachment is an input option; a zero-length null output keeps ffmpeg from decoding anything
+    args.extend(["-y", "-i", input_path, "-t", "0", "-f", "null", "-"].map(String::from));
+    
+    run_ffmpeg(ffmpeg_path, &args)?;
+    Ok(written
+        .into_iter()
+        .filter(|p| std::path::Path::new(p).exists())
+        .collect())
+}
+
+/// Build ffmpeg arguments from options
+pub fn build_ffmpeg_args(options: &ConvertOptions, input_info: Option<&MediaInfo>) -> Result<Vec<String>, ConvertError> {
+    let mut args: Vec<String> = Vec::new();
+    
+    // Input file; a trim start goes before it so ffmpeg seeks instead of decoding up to it
+    let trim = match options.advanced {
+        Some(ref advanced) => trim_range(advanced, input_info.and_then(|info| info.format.duration))?,
+        None => None,
+    };
+    if let Some((start, _)) = trim.filter(|(start, _)| *start > 0.0) {
+        args.push("-ss".to_string());
+        args.push(format!("{:.3}", start));
+    }
+    if is_sequence_path(&options.input_path) {
+        args.extend(sequence_input_args(options)?);
+    }
+    // The raised scan limits a re-probe needed to find every stream, so ffmpeg finds them too
+    if let Some(limits) = input_info.map(|info| info.probe_limits) {
+        if let Some(us) = limits.analyze_duration_us {
+            args.push("-analyzeduration".to_string());
+            args.push(us.to_string());
+        }
+        if let Some(bytes) = limits.probe_size {
+            args.push("-probesize".to_string());
+            args.push(bytes.to_string());
+        }
+    }
+    args.push("-i".to_string());
+    args.push(options.input_path.clone());
+    // Timestamps restart at 0 after an input seek, so the end is given as a length
+    if let Some((_, Some(length))) = trim {
+        args.push("-t".to_string());
+        args.push(format!("{:.3}", length));
+    }
+    
+    // Stream selection flags
+    let stream_sel = options.stream_selection.clone().unwrap_or_default();
+    let mapped_streams = explicit_stream_maps(&stream_sel, input_info)?;
+    
+    // With explicit maps, a type is only switched off when none of its streams is mapped
+    let type_excluded = |kind: StreamType, flag: bool| match (&mapped_streams, input_info) {
+        (Some(mapped), Some(info)) => !info
+            .streams
+            .iter()
+            .any(|s| s.stream_type == kind && mapped.contains(&s.index)),
+        _ => !flag,
+    };
+    if type_excluded(StreamType::Video, stream_sel.include_video) {
+        args.push("-vn".to_string());
+    }
+    if type_excluded(StreamType::Audio, stream_sel.include_audio) {
+        args.push("-an".to_string());
+    }
+    if type_excluded(StreamType::Subtitle, stream_sel.include_subtitles) {
+        args.push("-sn".to_string());
+    }
+    if type_excluded(StreamType::Data, stream_sel.include_data) {
+        args.push("-dn".to_string());
+    }
+    
+    // Only the container changes: copy everything and skip the encoding options
+    if let (Some(advanced), Some(info)) = (options.advanced.as_ref(), input_info) {
+        if advanced.copy_streams && stream_copy_blocker(options, info).is_none() {
+            if let Some(format) = copy_format(options) {
+                args.push("-f".to_string());
+                args.push(format);
+            }
+            if let Some(ref extra) = advanced.extra_args {
+                let parsed = parse_extra_args(extra);
+                check_extra_args(&parsed).map_err(ConvertError::ConversionFailed)?;
+                args.extend(parsed);
+            }
+            for index in mapped_streams.iter().flatten() {
+                args.push("-map".to_string());
+                args.push(format!("0:{}", index));
+            }
+            args.push("-c".to_string());
+            args.push("copy".to_string());
+            if let Some(mode) = advanced.rotate.filter(|_| advanced.rotate_metadata_only) {
+                apply_rotate_metadata(&mut args, mode, &options.output_path)?;
+            }
+            args.push("-y".to_string());
+            args.push(options.output_path.clone());
+            apply_metadata(&mut args, advanced)?;
+            return Ok(args);
+        }
+    }
+    
+    // Preset or advanced options
+    if let Some(ref preset_id) = options.preset_id {
+        let preset = find_preset(preset_id)
+            .ok_or_else(|| ConvertError::PresetNotFound(preset_id.clone()))?;
+        
+        let preset_args = preset.build_args();
+        args.extend(preset_args);
+        
+        // The WAV preset keeps the source's sample format unless told which PCM format to write
+        let codec_chosen = options
+            .advanced
+            .as_ref()
+            .is_some_and(|a| a.pcm_format.is_some() || a.audio_codec.is_some());
+        if preset.id == "wav" && !codec_chosen {
+            let source = input_info.and_then(|info| info.streams.iter().find(|s| s.stream_type == StreamType::Audio));
+            if let Some(stream) = source {
+                replace_option(&mut args, "-c:a", source_pcm_format(stream));
+            }
+        }
+    }
+    
+    // Advanced options override preset
+    if let Some(ref advanced) = options.advanced {
+        if let Some(ref format) = advanced.format {
+            args.push("-f".to_string());
+            args.push(format.clone());
+        }
+        if let Some(ref vcodec) = advanced.video_codec {
+            // Remove any existing -c:v if present
+            if let Some(pos) = args.iter().position(|a| a == "-c:v") {
+                args.remove(pos);
+                if pos < args.len() {
+                    args.remove(pos);
+                }
+            }
+            args.push("-c:v".to_string());
+            args.push(vcodec.clone());
+        }
+        if let Some(ref acodec) = advanced.audio_codec {
+            // Remove any existing -c:a if present
+            if let Some(pos) = args.iter().position(|a| a == "-c:a") {
+                args.remove(pos);
+                if pos < args.len() {
+                    args.remove(pos);
+                }
+            }
+            args.push("-c:a".to_string());
+            args.push(acodec.clone());
+        }
+        if let Some(ref pcm) = advanced.pcm_format {
+            if !PCM_FORMATS.contains(&pcm.as_str()) {
+                return Err(ConvertError::ConversionFailed(format!(
+                    "Unknown PCM format '{}' (expected one of: {})",
+                    pcm,
+                    PCM_FORMATS.join(", ")
+                )));
+            }
+            if let Some(pos) = args.iter().position(|a| a == "-c:a") {
+                args.remove(pos);
+                if pos < args.len() {
+                    args.remove(pos);
+                }
+            }
+            args.push("-c:a".to_string());
+            args.push(pcm.clone());
+        }
+        if let Some(ref extra) = advanced.extra_args {
+            // Parse extra args (split by whitespace, respecting quotes)
+            let parsed = parse_extra_args(extra);
+            check_extra_args(&parsed).map_err(ConvertError::ConversionFailed)?;
+            push_extra_args(&mut args, parsed);
+        }
+        if !advanced.stream_codecs.is_empty() {
+            let info = input_info.ok_or_else(|| {
+                ConvertError::ConversionFailed("Per-stream codecs need a readable input".to_string())
+            })?;
+            args.extend(stream_codec_args(&advanced.stream_codecs, info, &stream_sel)?);
+        }
+        if let Some(ref gif) = advanced.gif {
+            apply_gif_options(&mut args, gif, &options.output_path)?;
+        }
+    }
+    
+    if options.settings.prefer_software_encoding {
+        force_software_codecs(&mut args);
+    }
+    
+    if let Some(ref advanced) = options.advanced {
+        if advanced.closed_gop || advanced.no_scenecut {
+            apply_gop_options(&mut args, advanced.closed_gop, advanced.no_scenecut)?;
+        }
+        if advanced.fragmented {
+            let container = target_container(&args, &options.output_path);
+            if container != "mp4" && container != "mov" {
+                return Err(ConvertError::ConversionFailed(format!(
+                    "Fragmented output needs an MP4 or MOV container, not '{}'",
+                    container
+                )));
+            }
+            push_movflags(&mut args, "+frag_keyframe+empty_moov+default_base_moof");
+        }
+        if let Some(ref bitrate) = advanced.target_bitrate {
+            apply_target_bitrate(&mut args, bitrate)?;
+        }
+        if let Some(quality) = advanced.image_quality {
+            apply_image_quality(&mut args, quality)?;
+        }
+        if advanced.two_pass {
+            check_two_pass(&args, &options.output_path)?;
+        }
+        if advanced.tune.is_some() || advanced.profile.is_some() || advanced.level.is_some() {
+            apply_encoder_tuning(
+                &mut args,
+                advanced.tune.as_deref(),
+                advanced.profile.as_deref(),
+                advanced.level.as_deref(),
+            )?;
+        }
+        if let Some(ref map) = advanced.channel_map {
+            let source_channels = input_info
+                .and_then(|info| info.streams.iter().find(|s| s.stream_type == StreamType::Audio))
+                .and_then(|s| s.channels)
+                .ok_or_else(|| {
+                    ConvertError::ConversionFailed("Channel mapping needs an input with a readable audio stream".to_string())
+                })?;
+            validate_channel_map(map, source_channels)?;
+            let last_audio_codec = args.iter().rposition(|a| a == "-c:a").and_then(|p| args.get(p + 1));
+            if last_audio_codec.is_some_and(|c| c == "copy") {
+                return Err(ConvertError::ConversionFailed(
+                    "Channel mapping needs re-encoding; it cannot be combined with audio stream copy".to_string(),
+                ));
+            }
+            append_filter(&mut args, "-af", &format!("pan={}", map));
+        }
+        if let Some(ref layout) = advanced.audio_channel_layout {
+            if advanced.channel_map.is_some() {
+                return Err(ConvertError::ConversionFailed(
+                    "Use either a channel map or an output channel layout, not both".to_string(),
+                ));
+            }
+            let last_audio_codec = args.iter().rposition(|a| a == "-c:a").and_then(|p| args.get(p + 1));
+            if last_audio_codec.is_some_and(|c| c == "copy") {
+                return Err(ConvertError::ConversionFailed(
+                    "Changing the channel layout needs re-encoding; it cannot be combined with audio stream copy".to_string(),
+                ));
+            }
+            let source_layout = input_info
+                .and_then(|info| info.streams.iter().find(|s| s.stream_type == StreamType::Audio))
+                .and_then(|s| s.channel_layout.as_deref());
+            append_filter(&mut args, "-af", &channel_layout_filter(source_layout, layout)?);
+        }
+        if let Some(target) = advanced.downmix {
+            if advanced.channel_map.is_some() || advanced.audio_channel_layout.is_some() {
+                return Err(ConvertError::ConversionFailed(
+                    "Use either a downmix or a channel map/output channel layout, not both".to_string(),
+                ));
+            }
+            let source = input_info.and_then(|info| info.streams.iter().find(|s| s.stream_type == StreamType::Audio));
+            if source.and_then(|s| s.channels).is_none_or(|c| c > target.channels()) {
+                if last_value(&args, "-c:a") == Some("copy") {
+                    return Err(ConvertError::ConversionFailed(
+                        "Downmixing needs re-encoding; it cannot be combined with audio stream copy".to_string(),
+                    ));
+                }
+                let source_layout = source.and_then(|s| s.channel_layout.as_deref());
+                append_filter(&mut args, "-af", &channel_layout_filter(source_layout, target.name())?);
+            }
+        }
+        if advanced.normalize_audio {
+            if last_value(&args, "-c:a") == Some("copy") {
+                return Err(ConvertError::ConversionFailed(
+                    "Loudness normalization needs re-encoding; it cannot be combined with audio stream copy".to_string(),
+                ));
+            }
+            let target = advanced.loudness_target.unwrap_or(DEFAULT_LOUDNESS_TARGET);
+            if !(-70.0..=-5.0).contains(&target) {
+                return Err(ConvertError::ConversionFailed(format!(
+                    "Loudness target must be between -70 and -5 LUFS, not {}",
+                    target
+                )));
+            }
+            append_filter(&mut args, "-af", &loudnorm_filter(target));
+            // loudnorm works (and outputs) at 192 kHz; keep the source rate instead
+            if !args.iter().any(|a| a == "-ar") {
+                let rate = input_info
+                    .and_then(|info| info.streams.iter().find(|s| s.stream_type == StreamType::Audio))
+                    .and_then(|s| s.sample_rate.clone())
+                    .unwrap_or_else(|| "48000".to_string());
+                args.extend(["-ar".to_string(), rate]);
+            }
+        }
+        if let Some(ref volume) = advanced.volume {
+            if last_value(&args, "-c:a") == Some("copy") {
+                return Err(ConvertError::ConversionFailed(
+                    "Changing the volume needs re-encoding; it cannot be combined with audio stream copy".to_string(),
+                ));
+            }
+            append_filter(&mut args, "-af", &volume_filter(volume)?);
+        }
+        if let Some(ref placement) = advanced.force_key_frames {
+            if output_video_codec(&args) == Some("copy") {
+                return Err(ConvertError::ConversionFailed(
+                    "Forced keyframes need re-encoding; they cannot be combined with video stream copy".to_string(),
+                ));
+            }
+            replace_option(&mut args, "-force_key_frames", &force_key_frames_value(placement, input_info, trim)?);
+        }
+        if let Some(ref timebase) = advanced.timebase {
+            let (num, den) = parse_timebase(timebase)?;
+            if output_video_codec(&args) != Some("copy") {
+                replace_option(&mut args, "-enc_time_base:v", &format!("{}/{}", num, den));
+            }
+            if matches!(target_container(&args, &options.output_path).as_str(), "mp4" | "mov") {
+                if num != 1 {
+                    return Err(ConvertError::ConversionFailed(format!(
+                        "MP4/MOV timescales need a timebase of the form 1/N, not '{}'",
+                        timebase
+                    )));
+                }
+                replace_option(&mut args, "-video_track_timescale", &den.to_string());
+            }
+        }
+        let rotate_filter = advanced.rotate.filter(|_| !advanced.rotate_metadata_only);
+        if let Some(mode) = advanced.rotate.filter(|_| advanced.rotate_metadata_only) {
+            apply_rotate_metadata(&mut args, mode, &options.output_path)?;
+        }
+        if advanced.crop.is_some() || advanced.scale.is_some() || rotate_filter.is_some() {
+            if output_video_codec(&args) == Some("copy") {
+                return Err(ConvertError::ConversionFailed(
+                    "Cropping, rotating or scaling needs re-encoding; it cannot be combined with video stream copy"
+                        .to_string(),
+                ));
+            }
+            let even = requires_even_dimensions(&args, &options.output_path);
+            let filter = geometry_filter(advanced.crop, rotate_filter, advanced.scale, input_info, even)?;
+            prepend_filter(&mut args, "-vf", &filter);
+        }
+        // First in the chain: later filters (scaling, overlays) then work on SDR frames
+        if advanced.tonemap_to_sdr && input_info.is_some_and(has_hdr_video) {
+            if output_video_codec(&args) == Some("copy") {
+                return Err(ConvertError::ConversionFailed(
+                    "Tone mapping needs re-encoding; it cannot be combined with video stream copy".to_string(),
+                ));
+            }
+            if advanced.preserve_dynamic_hdr {
+                return Err(ConvertError::ConversionFailed(
+                    "Tone mapping to SDR drops HDR metadata; it cannot be combined with preserving dynamic HDR"
+                        .to_string(),
+                ));
+            }
+            prepend_filter(&mut args, "-vf", TONEMAP_FILTER);
+            replace_option(&mut args, "-color_primaries", "bt709");
+            replace_option(&mut args, "-color_trc", "bt709");
+            replace_option(&mut args, "-colorspace", "bt709");
+        }
+        if let Some(ref burn) = advanced.burn_subtitles {
+            if output_video_codec(&args) == Some("copy") {
+                return Err(ConvertError::ConversionFailed(
+                    "Burning in subtitles needs re-encoding; it cannot be combined with video stream copy".to_string(),
+                ));
+            }
+            let (file, track) = match burn {
+                BurnSubtitleOptions::Embedded { stream_index } => {
+                    (options.input_path.as_str(), Some(subtitle_track(input_info, *stream_index)?))
+                }
+                BurnSubtitleOptions::External { path } => {
+                    if !std::path::Path::new(path).is_file() {
+                        return Err(ConvertError::ConversionFailed(format!("Subtitle file not found: {}", path)));
+                    }
+                    (path.as_str(), None)
+                }
+            };
+            let offset = trim.map_or(0.0, |(start, _)| start);
+            append_filter(&mut args, "-vf", &burn_subtitles_filter(file, track, offset));
+        }
+        if let Some(ref overlay) = advanced.review_overlay {
+            if output_video_codec(&args) == Some("copy") {
+                return Err(ConvertError::ConversionFailed(
+                    "A burned-in overlay needs re-encoding; it cannot be combined with video stream copy".to_string(),
+                ));
+            }
+            append_filter(&mut args, "-vf", &review_overlay_filter(overlay));
+        }
+        if let Some(fps) = advanced.fps {
+            apply_frame_rate(&mut args, fps)?;
+        }
+        if advanced.preserve_dynamic_hdr {
+            match output_video_codec(&args) {
+                // Dolby Vision codec tags in MP4 are not in the official spec
+                Some("copy") => {
+                    args.push("-strict".to_string());
+                    args.push("unofficial".to_string());
+                }
+                Some("libx265") => apply_hdr10_plus_x265(&mut args),
+                _ => {}
+            }
+        }
+    }
+    
+    if let Some(speed) = options.advanced.as_ref().and_then(|a| a.speed) {
+        if options.advanced.as_ref().is_some_and(|a| a.target_duration_secs.is_some()) {
+            return Err(ConvertError::ConversionFailed(
+                "Speed and target duration both set the output length; use one of them".to_string(),
+            ));
+        }
+        if !(speed.is_finite() && (1.0 / MAX_SPEED..=MAX_SPEED).contains(&speed)) {
+            return Err(ConvertError::ConversionFailed(format!(
+                "Invalid speed {} (expected {} to {})",
+                speed,
+                1.0 / MAX_SPEED,
+                MAX_SPEED
+            )));
+        }
+        // Without a probe, retime whatever the output keeps
+        let selection = options.stream_selection.clone().unwrap_or_default();
+        let video = input_info.is_none_or(|info| info.has_video) && selection.include_video && !args.iter().any(|a| a == "-vn");
+        let audio = input_info.is_none_or(|info| info.has_audio) && selection.include_audio && !args.iter().any(|a| a == "-an");
+        apply_speed(&mut args, speed, video, audio)?;
+    }
+    
+    if options.advanced.as_ref().is_some_and(|a| a.max_compatibility) {
+        apply_max_compatibility(&mut args, &options.output_path, input_info)?;
+    }
+    drop_stale_hevc_tag(&mut args);
+    
+    // The MOV muxer drops tags it has no atom for, ReplayGain included, unless told to write
+    // metadata keys. MP4/M4A get theirs as iTunes tags after encoding instead (the flag would
+    // hide title, artist and album from iTunes), see `apply_measurements`.
+    if input_info.is_some_and(|info| info.replay_gain.is_some())
+        && target_container(&args, &options.output_path) == "mov"
+    {
+        push_movflags(&mut args, "+use_metadata_tags");
+    }
+    
+    if let Some(advanced) = options.advanced.as_ref() {
+        apply_metadata(&mut args, advanced)?;
+    }
+    
+    // Odd-sized sources make H.264/H.265 encoders fail outright; round down to even instead
+    if options.settings.fix_odd_dimensions
+        && input_info.and_then(odd_video_dimensions).is_some()
+        && requires_even_dimensions(&args, &options.output_path)
+        && !args.iter().any(|a| a.contains(EVEN_DIMENSIONS_FILTER))
+    {
+        append_filter(&mut args, "-vf", EVEN_DIMENSIONS_FILTER);
+    }
+    
+    // Attachments are only carried over on request, and only Matroska can store them
+    let preserve_attachments = options.advanced.as_ref().is_some_and(|a| a.preserve_attachments)
+        && target_container(&args, &options.output_path) == "matroska";
+    // Per-stream codecs address streams by their position among all input streams of a type
+    let per_stream_codecs = options.advanced.as_ref().is_some_and(|a| !a.stream_codecs.is_empty());
+    // Layered video: keep the base layer, not whichever layer ffmpeg finds largest
+    let base_layer = input_info
+        .filter(|info| info.has_dependent_video())
+        .and_then(|info| info.base_video_stream());
+    if preserve_attachments || per_stream_codecs || base_layer.is_some() || mapped_streams.is_some() {
+        // Explicit maps replace ffmpeg's default stream selection, so map every AV/subtitle
+        // stream (or exactly the ones picked in the stream map)
+        let input_end = args.iter().position(|a| a == "-i").map_or(0, |p| p + 2);
+        let mut maps: Vec<String> = match mapped_streams {
+            Some(ref mapped) => mapped.iter().flat_map(|index| ["-map".to_string(), format!("0:{}", index)]).collect(),
+            None => {
+                let video = base_layer.map_or_else(|| "0:v?".to_string(), |s| format!("0:{}", s.index));
+                vec!["-map".to_string(), video, "-map".to_string(), "0:a?".to_string(), "-map".to_string(), "0:s?".to_string()]
+            }
+        };
+        if preserve_attachments {
+            maps.extend(["-map", "0:t?"].map(String::from));
+        }
+        for (offset, arg) in maps.into_iter().enumerate() {
+            args.insert(input_end + offset, arg);
+        }
+    }
+    if preserve_attachments {
+        args.push("-c:t".to_string());
+        args.push("copy".to_string());
+    }
+    
+    // Audio presets drop video with -vn, which takes the cover art with it
+    if options.advanced.as_ref().is_none_or(|a| a.embed_cover) {
+        if let Some(info) = input_info {
+            apply_cover_art(&mut args, info, &options.output_path);
+        }
+    }
+    let image_sequence = options.advanced.as_ref().is_some_and(|a| a.image_sequence);
+    if image_sequence {
+        apply_image_sequence(&mut args)?;
+    }
+    
+    // Threading: explicit option wins, then a -threads in extra_args, then the per-codec default
+    let threads = options.advanced.as_ref().and_then(|a| a.threads);
+    if threads.is_some() || !args.iter().any(|a| a == "-threads") {
+        let threads = threads.or_else(|| {
+            output_video_codec(&args)
+                .filter(|c| *c != "copy")
+                .map(recommend_threads)
+        });
+        if let Some(n) = threads {
+            args.push("-threads".to_string());
+            args.push(n.to_string());
+        }
+    }
+    
+    if is_stream_url(&options.output_path) {
+        // Read the input at its native rate, and use the container the protocol expects
+        let input_pos = args.iter().position(|a| a == "-i").unwrap_or(0);
+        args.insert(input_pos, "-re".to_string());
+        let container = if options.output_path.starts_with("srt://") { "mpegts" } else { "flv" };
+        replace_option(&mut args, "-f", container);
+        args.push(options.output_path.clone());
+        return Ok(args);
+    }
+    
+    // Overwrite output without asking
+    args.push("-y".to_string());
+    
+    // Output file
+    if image_sequence {
+        args.push(sequence_output_pattern(options));
+    } else {
+        args.push(options.output_path.clone());
+    }
+    
+    Ok(args)
+}
+
+/// Codecs, as ffprobe names them, that each container takes by stream copy. VP9 in MP4
+/// is left out on purpose: ffmpeg writes it, but few players open it.
+const COPY_COMPATIBLE: &[(&[&str], &[&str])] = &[
+    (
+        &["mp4", "mov"],
+        &["h264", "hevc", "av1", "mpeg4", "mjpeg", "aac", "mp3", "ac3", "eac3", "alac", "opus", "flac", "mov_text"],
+    ),
+    (
+        &["matroska", "mkv"],
+        &[
+            "h264", "hevc", "av1", "vp8", "vp9", "mpeg4", "mpeg2video", "prores", "ffv1", "mjpeg",
+            "aac", "mp3", "ac3", "eac3", "dts", "truehd", "flac", "alac", "opus", "vorbis",
+            "pcm_s16le", "pcm_s24le", "pcm_s32le", "pcm_f32le",
+            "ass", "ssa", "subrip", "webvtt", "hdmv_pgs_subtitle", "dvd_subtitle",
+        ],
+    ),
+    (&["webm"], &["vp8", "vp9", "av1", "opus", "vorbis", "webvtt"]),
+    (&["mpegts", "ts"], &["h264", "hevc", "mpeg2video", "aac", "mp3", "ac3", "eac3", "opus", "dvb_subtitle"]),
+    (&["mp3"], &["mp3"]),
+    (&["flac"], &["flac"]),
+    (&["ogg"], &["vorbis", "opus", "flac"]),
+    (&["wav"], &["pcm_u8", "pcm_s16le", "pcm_s24le", "pcm_s32le", "pcm_f32le"]),
+];
+
+/// Output format forced by the advanced options or the preset, if any
+fn copy_format(options: &ConvertOptions) -> Option<String> {
+    options
+        .advanced
+        .as_ref()
+        .and_then(|a| a.format.clone())
+        .or_else(|| options.preset_id.as_deref().and_then(find_preset).and_then(|p| p.format))
+}
+
+/// Why `copy_streams` can't copy this input, or `None` when it can
+pub fn stream_copy_blocker(options: &ConvertOptions, info: &MediaInfo) -> Option<String> {
+    let advanced = options.advanced.as_ref()?;
+    let extra = advanced.extra_args.as_deref().map(parse_extra_args).unwrap_or_default();
+    let needs_encoding = [
+        (
+            advanced.crop.is_some()
+                || advanced.scale.is_some()
+                || (advanced.rotate.is_some() && !advanced.rotate_metadata_only),
+            "cropping, rotating or scaling",
+        ),
+        (advanced.fps.is_some(), "a frame rate change"),
+        (advanced.image_sequence, "image sequence output"),
+        (advanced.tonemap_to_sdr && has_hdr_video(info), "tone mapping"),
+        (advanced.burn_subtitles.is_some() || advanced.review_overlay.is_some(), "burned-in text"),
+        (
+            advanced.normalize_audio
+                || advanced.channel_map.is_some()
+                || advanced.audio_channel_layout.is_some()
+                || advanced.downmix.is_some()
+                || advanced.volume.is_some(),
+            "audio processing",
+        ),
+        (advanced.target_bitrate.is_some() || advanced.two_pass, "a target bitrate"),
+        (advanced.force_key_frames.is_some(), "forced keyframes"),
+        (advanced.closed_gop || advanced.no_scenecut, "GOP settings"),
+        (
+            advanced.tune.is_some() || advanced.profile.is_some() || advanced.level.is_some(),
+            "an encoder tune, profile or level",
+        ),
+        (advanced.pcm_format.is_some(), "a PCM format"),
+        (advanced.image_quality.is_some(), "an image quality"),
+        (!advanced.stream_codecs.is_empty(), "per-stream codecs"),
+        (advanced.gif.is_some(), "GIF palette settings"),
+        (advanced.target_duration_secs.is_some() || advanced.speed.is_some(), "retiming"),
+        (advanced.max_compatibility, "maximum compatibility"),
+        (
+            extra.iter().any(|a| a == "-vf" || a == "-af" || a == "-filter_complex"),
+            "filters in the extra arguments",
+        ),
+    ];
+    if let Some((_, what)) = needs_encoding.iter().find(|(needed, _)| *needed) {
+        return Some(format!("{} needs re-encoding", what));
+    }
+    
+    let format_args: Vec<String> = copy_format(options).map(|f| vec!["-f".to_string(), f]).unwrap_or_default();
+    let container = target_container(&format_args, &options.output_path);
+    let Some((_, codecs)) = COPY_COMPATIBLE.iter().find(|(formats, _)| formats.contains(&container.as_str())) else {
+        return Some(format!("no stream-copy support known for '{}'", container));
+    };
+    let selection = options.stream_selection.clone().unwrap_or_default();
+    for stream in &info.streams {
+        let kind = match stream.stream_type {
+            StreamType::Video if !stream.has_disposition("attached_pic") => "video",
+            StreamType::Audio => "audio",
+            StreamType::Subtitle => "subtitle",
+            _ => continue,
+        };
+        let included = selection.includes(stream);
+        let codec = stream.codec_name.as_deref().unwrap_or("unknown");
+        if included && !codecs.contains(&codec) {
+            return Some(format!("{} codec {} cannot be copied into {}", kind, codec, container));
+        }
+    }
+    None
+}
+
+/// PCM encoders accepted by `pcm_format` (bit depth, int/float, endianness)
+pub const PCM_FORMATS: &[&str] = &[
+    "pcm_u8", "pcm_s16le", "pcm_s16be", "pcm_s24le", "pcm_s24be", "pcm_s32le", "pcm_s32be",
+    "pcm_f32le", "pcm_f32be", "pcm_f64le", "pcm_f64be",
+];
+
+/// PCM encoder holding `stream`'s decoded samples (`sample_fmt`, planar or packed) without
+/// loss. 24-bit sources decode to 32-bit samples and are written back as 24-bit.
+pub fn source_pcm_format(stream: &StreamInfo) -> &'static str {
+    match stream.sample_fmt.as_deref().map(|f| f.trim_end_matches('p')) {
+        Some("u8") => "pcm_u8",
+        Some("s32") if stream.bits_per_raw_sample == Some(24) => "pcm_s24le",
+        Some("s32" | "s64") => "pcm_s32le",
+        Some("flt") => "pcm_f32le",
+        Some("dbl") => "pcm_f64le",
+        _ => "pcm_s16le",
+    }
+}
+
+/// Map a hardware encoder to the software encoder producing the same format
+fn software_equivalent(codec: &str) -> Option<&'static str> {
+    let base = codec
+        .strip_suffix("_nvenc")
+        .or_else(|| codec.strip_suffix("_qsv"))
+        .or_else(|| codec.strip_suffix("_videotoolbox"))
+        .or_else(|| codec.strip_suffix("_vaapi"))
+        .or_else(|| codec.strip_suffix("_amf"))
+        .or_else(|| codec.strip_suffix("_mf"))?;
+    match base {
+        "h264" => Some("libx264"),
+        "hevc" => Some("libx265"),
+        "av1" => Some("libsvtav1"),
+        "vp9" => Some("libvpx-vp9"),
+        "mpeg2" => Some("mpeg2video"),
+        "prores" => Some("prores_ks"),
+        "mjpeg" => Some("mjpeg"),
+        _ => None,
+    }
+}
+
+/// Input stream indices to `-map` when the selection has per-stream entries, in input
+/// order; `None` leaves stream selection to the type flags. Every entry must name a stream
+/// of the input.
+fn explicit_stream_maps(
+    selection: &StreamSelection,
+    input_info: Option<&MediaInfo>,
+) -> Result<Option<Vec<u32>>, ConvertError> {
+    if selection.stream_map.is_empty() {
+        return Ok(None);
+    }
+    let info = input_info.ok_or_else(|| {
+        ConvertError::ConversionFailed("Per-stream mapping needs a readable input".to_string())
+    })?;
+    for entry in &selection.stream_map {
+        if !info.streams.iter().any(|s| s.index == entry.stream_index) {
+            return Err(ConvertError::ConversionFailed(format!(
+                "Input has no stream #{} (it has {})",
+                entry.stream_index,
+                info.streams.len()
+            )));
+        }
+    }
+    let mapped: Vec<u32> = info.streams.iter().filter(|s| selection.includes(s)).map(|s| s.index).collect();
+    if mapped.is_empty() {
+        return Err(ConvertError::ConversionFailed("The stream map leaves no streams to convert".to_string()));
+    }
+    Ok(Some(mapped))
+}
+
+/// `-c:<type>:<n> <codec>` flags for per-stream codec overrides, validated against the probe.
+/// `n` counts the earlier input streams of the same type, which matches the output stream
+/// numbering once every stream is mapped.
+fn stream_codec_args(
+    specs: &[StreamCodec],
+    info: &MediaInfo,
+    selection: &StreamSelection,
+) -> Result<Vec<String>, ConvertError> {
+    let mut args = Vec::new();
+    for spec in specs {
+        let stream = info
+            .streams
+            .iter()
+            .find(|s| s.index == spec.stream_index)
+            .ok_or_else(|| {
+                ConvertError::ConversionFailed(format!("Input has no stream #{}", spec.stream_index))
+            })?;
+        let (specifier, included) = match stream.stream_type {
+            StreamType::Video => ("v", selection.includes(stream)),
+            StreamType::Audio => ("a", selection.includes(stream)),
+            StreamType::Subtitle => ("s", selection.includes(stream)),
+            _ => {
+                return Err(ConvertError::ConversionFailed(format!(
+                    "Stream #{} is not a video, audio or subtitle stream",
+                    spec.stream_index
+                )))
+            }
+        };
+        if !included {
+            return Err(ConvertError::ConversionFailed(format!(
+                "Stream #{} is excluded by the stream selection",
+                spec.stream_index
+            )));
+        }
+        let type_index = info
+            .streams
+            .iter()
+            .filter(|s| s.stream_type == stream.stream_type && s.index < spec.stream_index)
+            .filter(|s| selection.stream_map.is_empty() || selection.includes(s))
+            .count();
+        args.push(format!("-c:{}:{}", specifier, type_index));
+        args.push(spec.codec.clone());
+    }
+    Ok(args)
+}
+
+/// Strip hardware decode flags and swap hardware encoders for their software counterparts
+fn force_software_codecs(args: &mut Vec<String>) {
+    // Hardware decoding (-hwaccel, -hwaccel_device, -hwaccel_output_format take a value)
+    while let Some(pos) = args.iter().position(|a| a.starts_with("-hwaccel")) {
+        args.remove(pos);
+        if pos < args.len() {
+            args.remove(pos);
+        }
+    }
+    
+    // Hardware encoding
+    let mut i = 0;
+    while i + 1 < args.len() {
+        if args[i] == "-c:v" || args[i].starts_with("-c:v:") || args[i] == "-vcodec" || args[i] == "-codec:v" {
+            if let Some(sw) = software_equivalent(&args[i + 1]) {
+                args[i + 1] = sw.to_string();
+            }
+        }
+        i += 1;
+    }
+}
+
+/// Audio bitrate used when targeting a file size
+const SHARING_AUDIO_KBPS: u64 = 128;
+/// Height cap for `convert_for_sharing`
+const SHARING_MAX_HEIGHT: u32 = 720;
+
+/// Video bitrate (kbps) that makes `duration_secs` of video plus audio fit in `max_bytes`.
+/// Keeps 5% headroom for container overhead and rate-control overshoot.
+pub fn target_video_bitrate_kbps(max_bytes: u64, duration_secs: f64, audio_kbps: u64) -> Option<u64> {
+    if duration_secs <= 0.0 {
+        return None;
+    }
+    let total_kbps = (max_bytes as f64 * 8.0 * 0.95 / 1000.0 / duration_secs) as u64;
+    total_kbps.checked_sub(audio_kbps).filter(|&v| v > 0)
+}
+
+/// Build options for a compact, widely compatible MP4 (H.264/AAC, at most 720p)
+/// whose bitrate is derived from the duration so the file stays under `max_mb`
+pub fn sharing_options(
+    input_path: &str,
+    output_path: &str,
+    max_mb: f64,
+    ffprobe_path: Option<&std::path::Path>,
+) -> Result<ConvertOptions, ConvertError> {
+    if max_mb <= 0.0 {
+        return Err(ConvertError::ConversionFailed("Size limit must be positive".to_string()));
+    }
+    let info = crate::probe::probe_file(input_path, ffprobe_path)
+        .map_err(|e| ConvertError::ConversionFailed(e.to_string()))?;
+    let duration = info.format.duration.ok_or_else(|| {
+        ConvertError::ConversionFailed("Cannot target a size: input duration is unknown".to_string())
+    })?;
+    
+    let max_bytes = (max_mb * 1024.0 * 1024.0) as u64;
+    let video_kbps = target_video_bitrate_kbps(max_bytes, duration, SHARING_AUDIO_KBPS)
+        .filter(|&v| v >= 100)
+        .ok_or_else(|| {
+            ConvertError::ConversionFailed(format!(
+                "{:.0}s of video cannot fit in {} MB at a watchable quality",
+                duration, max_mb
+            ))
+        })?;
+    
+    let mut extra = format!(
+        "-preset medium -b:v {v}k -maxrate {v}k -bufsize {b}k -pix_fmt yuv420p -b:a {a}k -movflags +faststart",
+        v = video_kbps,
+        b = video_kbps * 2,
+        a = SHARING_AUDIO_KBPS
+    );
+    let height = info
+        .streams
+        .iter()
+        .filter(|s| s.stream_type == crate::probe::StreamType::Video)
+        .find_map(|s| s.height);
+    if height.is_some_and(|h| h > SHARING_MAX_HEIGHT) {
+        extra.push_str(&format!(" -vf scale=-2:{}", SHARING_MAX_HEIGHT));
+    }
+    
+    Ok(ConvertOptions {
+        input_path: input_path.to_string(),
+        output_path: output_path.to_string(),
+        preset_id: None,
+        advanced: Some(AdvancedOptions {
+            format: Some("mp4".to_string()),
+            video_codec: Some("libx264".to_string()),
+            audio_codec: Some("aac".to_string()),
+            extra_args: Some(extra),
+            ..Default::default()
+        }),
+        stream_selection: None,
+        settings: AppSettings::default(),
+        job_id: 0,
+    })
+}
+
+/// A chapter title usable as a file name: characters Windows or Unix reject become `_`
+fn chapter_file_stem(title: &str) -> String {
+    let stem: String = title
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    // Windows also drops trailing dots and spaces
+    stem.trim().trim_end_matches('.').to_string()
+}
+
+/// One conversion per chapter of `info`, cut with the chapter's start and end times and
+/// named `<NN> - <chapter title>` in `output_dir`
+pub fn chapter_split_options(info: &MediaInfo, output_dir: &str, preset_id: &str) -> Result<Vec<ConvertOptions>, ConvertError> {
+    if info.chapters.is_empty() {
+        return Err(ConvertError::ConversionFailed(format!(
+            "{} has no chapters to split by",
+            info.filename
+        )));
+    }
+    let preset = find_preset(preset_id).ok_or_else(|| ConvertError::PresetNotFound(preset_id.to_string()))?;
+    let width = info.chapters.len().to_string().len().max(2);
+    
+    Ok(info
+        .chapters
+        .iter()
+        .enumerate()
+        .map(|(i, chapter)| {
+            let title = chapter
+                .title
+                .as_deref()
+                .map(chapter_file_stem)
+                .filter(|t| !t.is_empty())
+                .unwrap_or_else(|| format!("Chapter {}", i + 1));
+            let file_name = format!("{:0width$} - {}.{}", i + 1, title, preset.extension, width = width);
+            ConvertOptions {
+                input_path: info.path.clone(),
+                output_path: std::path::Path::new(output_dir).join(file_name).to_string_lossy().to_string(),
+                preset_id: Some(preset_id.to_string()),
+                advanced: Some(AdvancedOptions {
+                    trim_start: Some(format!("{:.3}", chapter.start_time)),
+                    trim_end: Some(format!("{:.3}", chapter.end_time)),
+                    ..Default::default()
+                }),
+                stream_selection: None,
+                settings: AppSettings::default(),
+                job_id: 0,
+            }
+        })
+        .collect())
+}
+
+/// Prefix marking a `comment` tag written by `embed_settings`
+const PROVENANCE_PREFIX: &str = "Convertify: ";
+
+/// Compact description of a conversion: the preset plus the ffmpeg arguments
+/// without the input/output paths (which are machine specific)
+fn provenance_tag(preset_id: Option<&str>, args: &[String]) -> String {
+    let mut settings: Vec<&str> = Vec::new();
+    let mut iter = args.iter().take(args.len().saturating_sub(1));
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-i" => {
+                iter.next();
+            }
+            "-y" => {}
+            _ => settings.push(arg),
+        }
+    }
+    format!(
+        "{}preset={} args={}",
+        PROVENANCE_PREFIX,
+        preset_id.unwrap_or("none"),
+        settings.join(" ")
+    )
+}
+
+/// Find the settings embedded by `embed_settings` among a file's format tags
+pub fn provenance_from_tags(tags: &std::collections::HashMap<String, String>) -> Option<String> {
+    tags.iter()
+        .filter(|(key, _)| key.eq_ignore_ascii_case("comment"))
+        .find_map(|(_, value)| value.strip_prefix(PROVENANCE_PREFIX))
+        .map(String::from)
+}
+
+/// Value of the last `flag` in the arguments
+pub fn last_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    args.iter()
+        .rposition(|a| a == flag)
+        .and_then(|pos| args.get(pos + 1))
+        .map(String::as_str)
+}
+
+/// Output container: the last `-f` in the arguments, otherwise guessed from the output extension
+pub fn target_container(args: &[String], output_path: &str) -> String {
+    if let Some(format) = args
+        .iter()
+        .rposition(|a| a == "-f")
+        .and_then(|pos| args.get(pos + 1))
+    {
+        return match format.as_str() {
+            "mkv" => "matroska".to_string(),
+            other => other.to_string(),
+        };
+    }
+    let ext = std::path::Path::new(output_path)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match ext.as_str() {
+        "mkv" | "mka" => "matroska".to_string(),
+        "m4a" | "m4v" => "mp4".to_string(),
+        "ts" => "mpegts".to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// Append a `key=value` to the x265 parameter list, merging with an existing -x265-params
+fn push_x265_param(args: &mut Vec<String>, param: &str) {
+    match args.iter().position(|a| a == "-x265-params") {
+        Some(pos) if pos + 1 < args.len() => {
+            args[pos + 1] = format!("{}:{}", args[pos + 1], param);
+        }
+        _ => {
+            args.push("-x265-params".to_string());
+            args.push(param.to_string());
+        }
+    }
+}
+
+/// x265 settings for carrying HDR10+ through a re-encode: 10-bit Main10 with BT.2020/PQ
+/// signalling, HDR10 SEI (`hdr10`) so the mastering display and light level side data are
+/// written, headers on every keyframe, and `dhdr10-opt` so the per-frame dynamic metadata
+/// ffmpeg hands to x265 is only repeated when it changes
+fn apply_hdr10_plus_x265(args: &mut Vec<String>) {
+    let ten_bit = args
+        .iter()
+        .rposition(|a| a == "-pix_fmt")
+        .and_then(|pos| args.get(pos + 1))
+        .is_some_and(|fmt| fmt.contains("10") || fmt.contains("12"));
+    if !ten_bit {
+        replace_option(args, "-pix_fmt", "yuv420p10le");
+    }
+    replace_option(args, "-profile:v", "main10");
+    replace_option(args, "-color_primaries", "bt2020");
+    replace_option(args, "-color_trc", "smpte2084");
+    replace_option(args, "-colorspace", "bt2020nc");
+    for param in [
+        "hdr10=1",
+        "repeat-headers=1",
+        "colorprim=bt2020",
+        "transfer=smpte2084",
+        "colormatrix=bt2020nc",
+        "dhdr10-opt=1",
+    ] {
+        push_x265_param(args, param);
+    }
+}
+
+/// Add MP4/MOV muxer flags, merging with the last -movflags (the one ffmpeg uses)
+fn push_movflags(args: &mut Vec<String>, flags: &str) {
+    match args.iter().rposition(|a| a == "-movflags") {
+        Some(pos) if pos + 1 < args.len() => {
+            args[pos + 1].push_str(flags);
+        }
+        _ => {
+            args.push("-movflags".to_string());
+            args.push(flags.to_string());
+        }
+    }
+}
+
+/// Filter rounding width and height down to even numbers, which 4:2:0 encoders require
+pub const EVEN_DIMENSIONS_FILTER: &str = "scale=trunc(iw/2)*2:trunc(ih/2)*2";
+
+/// HDR to SDR: linearize, map highlights into SDR range with the Hable curve, then
+/// convert to BT.709 primaries, transfer and matrix in limited range
+const TONEMAP_FILTER: &str = "zscale=t=linear:npl=100,format=gbrpf32le,zscale=p=bt709,\
+tonemap=tonemap=hable:desat=0,zscale=t=bt709:m=bt709:r=tv,format=yuv420p";
+
+/// Whether the input's main video stream is HDR
+pub fn has_hdr_video(info: &MediaInfo) -> bool {
+    info.streams
+        .iter()
+        .find(|s| s.stream_type == StreamType::Video && !s.has_disposition("attached_pic"))
+        .is_some_and(|s| s.is_hdr)
+}
+
+/// Width and height of the input's main video stream if either is odd
+pub fn odd_video_dimensions(info: &MediaInfo) -> Option<(u32, u32)> {
+    let video = info
+        .streams
+        .iter()
+        .find(|s| s.stream_type == StreamType::Video && !s.has_disposition("attached_pic"))?;
+    let (width, height) = (video.width?, video.height?);
+    (width % 2 == 1 || height % 2 == 1).then_some((width, height))
+}
+
+/// `crop`, `rotate` and `scale` as one filter chain, checked against the input's size when
+/// known. With `even`, -1 becomes -2 so the computed side is rounded to an even number too.
+fn geometry_filter(
+    crop: Option<CropRect>,
+    rotate: Option<RotateMode>,
+    scale: Option<(i32, i32)>,
+    input_info: Option<&MediaInfo>,
+    even: bool,
+) -> Result<String, ConvertError> {
+    let mut filters = Vec::new();
+    if let Some(crop) = crop {
+        if crop.w == 0 || crop.h == 0 {
+            return Err(ConvertError::ConversionFailed("Crop width and height must be positive".to_string()));
+        }
+        let (Some(right), Some(bottom)) = (crop.x.checked_add(crop.w), crop.y.checked_add(crop.h)) else {
+            return Err(ConvertError::ConversionFailed(format!(
+                "Crop {}x{} at {},{} is out of range",
+                crop.w, crop.h, crop.x, crop.y
+            )));
+        };
+        let video = input_info.and_then(|info| {
+            info.streams
+                .iter()
+                .find(|s| s.stream_type == StreamType::Video && !s.has_disposition("attached_pic"))
+        });
+        if let Some((width, height)) = video.and_then(|v| Some((v.width?, v.height?))) {
+            if right > width || bottom > height {
+                return Err(ConvertError::ConversionFailed(format!(
+                    "Crop {}x{} at {},{} does not fit in the {}x{} input",
+                    crop.w, crop.h, crop.x, crop.y, width, height
+                )));
+            }
+        }
+        if even && scale.is_none() && (crop.w % 2 == 1 || crop.h % 2 == 1) {
+            return Err(ConvertError::ConversionFailed(format!(
+                "Crop size {}x{} must be even for this encoder",
+                crop.w, crop.h
+            )));
+        }
+        filters.push(format!("crop={}:{}:{}:{}", crop.w, crop.h, crop.x, crop.y));
+    }
+    if let Some(rotate) = rotate {
+        filters.push(rotate.filter().to_string());
+    }
+    if let Some((width, height)) = scale {
+        let valid = |side: i32| side > 0 || side == -1;
+        if !valid(width) || !valid(height) || (width == -1 && height == -1) {
+            return Err(ConvertError::ConversionFailed(format!(
+                "Invalid scale {}x{}: sizes must be positive, and at most one of them -1",
+                width, height
+            )));
+        }
+        if even && (width % 2 == 1 || height % 2 == 1) {
+            return Err(ConvertError::ConversionFailed(format!(
+                "Scale size {}x{} must be even for this encoder",
+                width, height
+            )));
+        }
+        let side = |value: i32| if value == -1 && even { -2 } else { value };
+        filters.push(format!("scale={}:{}", side(width), side(height)));
+    }
+    Ok(filters.join(","))
+}
+
+/// Containers whose muxer writes the display matrix players honor
+const ROTATE_METADATA_CONTAINERS: &[&str] = &["mp4", "mov"];
+
+/// Copy the video and set the rotation in its display matrix instead of turning the pixels.
+/// FFmpeg 6+ ignores the old `rotate` tag; `-display_rotation` is an input option, in degrees
+/// counter-clockwise.
+fn apply_rotate_metadata(args: &mut Vec<String>, mode: RotateMode, output_path: &str) -> Result<(), ConvertError> {
+    let degrees = mode.metadata_degrees().ok_or_else(|| {
+        ConvertError::ConversionFailed("Mirroring cannot be stored as metadata; it needs re-encoding".to_string())
+    })?;
+    let container = target_container(args, output_path);
+    if !ROTATE_METADATA_CONTAINERS.contains(&container.as_str()) {
+        return Err(ConvertError::ConversionFailed(format!(
+            "'{}' cannot store rotation metadata (supported: {}); rotate with re-encoding instead",
+            container,
+            ROTATE_METADATA_CONTAINERS.join(", ")
+        )));
+    }
+    if args.iter().any(|a| a == "-vf") {
+        return Err(ConvertError::ConversionFailed(
+            "Metadata-only rotation copies the video, so it cannot be combined with video filters".to_string(),
+        ));
+    }
+    replace_option(args, "-c:v", "copy");
+    let counter_clockwise = if degrees > 180 { 360 - degrees as i32 } else { -(degrees as i32) };
+    let input_pos = args.iter().position(|a| a == "-i").unwrap_or(0);
+    args.insert(input_pos, "-display_rotation:v:0".to_string());
+    args.insert(input_pos + 1, counter_clockwise.to_string());
+    Ok(())
+}
+
+/// Append user-supplied arguments; a -vf/-af among them joins the chain already in `args`
+/// (from the preset) since ffmpeg rejects repeated filter options
+fn push_extra_args(args: &mut Vec<String>, extra: Vec<String>) {
+    let mut extra = extra.into_iter();
+    while let Some(arg) = extra.next() {
+        if arg == "-vf" || arg == "-af" {
+            if let Some(filter) = extra.next() {
+                append_filter(args, &arg, &filter);
+                continue;
+            }
+        }
+        args.push(arg);
+    }
+}
+
+/// Whether the output video encoder (explicit, or the container's default H.264) needs
+/// even dimensions. Stream copy never does.
+fn requires_even_dimensions(args: &[String], output_path: &str) -> bool {
+    match output_video_codec(args) {
+        Some(codec) => {
+            codec == "libx264"
+                || codec == "libx265"
+                || codec.starts_with("h264_")
+                || codec.starts_with("hevc_")
+        }
+        None => matches!(target_container(args, output_path).as_str(), "mp4" | "mov" | "matroska"),
+    }
+}
+
+/// GIF frame delays are whole hundredths of a second, and most viewers clamp anything
+/// faster than 50 fps to 10 fps
+const MAX_GIF_FPS: u32 = 50;
+
+/// Use the palette filtergraph for `gif`, in place of one already there (the GIF preset's)
+fn apply_gif_options(args: &mut Vec<String>, gif: &GifOptions, output_path: &str) -> Result<(), ConvertError> {
+    let container = target_container(args, output_path);
+    if container != "gif" {
+        return Err(ConvertError::ConversionFailed(format!(
+            "GIF options need a GIF output, not '{}'",
+            container
+        )));
+    }
+    if gif.fps == 0 || gif.fps > MAX_GIF_FPS {
+        return Err(ConvertError::ConversionFailed(format!(
+            "GIF frame rate must be between 1 and {}, not {}",
+            MAX_GIF_FPS, gif.fps
+        )));
+    }
+    if gif.width == 0 || gif.width < -1 {
+        return Err(ConvertError::ConversionFailed(format!(
+            "GIF width must be positive or -1 (source width), not {}",
+            gif.width
+        )));
+    }
+    let filter = gif_palette_filter(gif.fps, gif.width, gif.dither);
+    match args.iter().position(|a| a.contains("palettegen")) {
+        Some(pos) => args[pos] = filter,
+        None => append_filter(args, "-vf", &filter),
+    }
+    Ok(())
+}
+
+/// Highest output frame rate accepted; anything above is a typo
+const MAX_FRAME_RATE: f64 = 1000.0;
+
+/// Set the output frame rate. With a filter chain it becomes an `fps` filter in it (taking
+/// over one already there, like the GIF preset's) so the filters see the final rate;
+/// otherwise `-r`.
+fn apply_frame_rate(args: &mut Vec<String>, fps: f64) -> Result<(), ConvertError> {
+    if !(fps.is_finite() && fps > 0.0 && fps <= MAX_FRAME_RATE) {
+        return Err(ConvertError::ConversionFailed(format!(
+            "Invalid frame rate {} (expected more than 0 and at most {})",
+            fps, MAX_FRAME_RATE
+        )));
+    }
+    if output_video_codec(args) == Some("copy") {
+        return Err(ConvertError::ConversionFailed(
+            "Changing the frame rate needs re-encoding; it cannot be combined with video stream copy".to_string(),
+        ));
+    }
+    let fps_filter = format!("fps={}", fps);
+    match args.iter().rposition(|a| a == "-vf").and_then(|pos| args.get_mut(pos + 1)) {
+        Some(chain) => {
+            let mut replaced = false;
+            let filters: Vec<String> = chain
+                .split(',')
+                .map(|filter| {
+                    if filter.starts_with("fps=") {
+                        replaced = true;
+                        fps_filter.clone()
+                    } else {
+                        filter.to_string()
+                    }
+                })
+                .collect();
+            *chain = if replaced { filters.join(",") } else { format!("{},{}", fps_filter, chain) };
+            remove_option(args, "-r");
+        }
+        None => replace_option(args, "-r", &fps.to_string()),
+    }
+    Ok(())
+}
+
+/// Frame rates every player handles (at the 1080p Main@4.0 limit of 30 fps)
+const STANDARD_FRAME_RATES: &[f64] = &[24000.0 / 1001.0, 24.0, 25.0, 30000.0 / 1001.0, 30.0];
+
+/// Override codecs and formats with the most widely playable settings (see `max_compatibility`)
+fn apply_max_compatibility(
+    args: &mut Vec<String>,
+    output_path: &str,
+    info: Option<&MediaInfo>,
+) -> Result<(), ConvertError> {
+    let container = target_container(args, output_path);
+    if container != "mp4" && container != "mov" {
+        return Err(ConvertError::ConversionFailed(format!(
+            "Maximum compatibility output must be MP4 or MOV, not '{}'",
+            container
+        )));
+    }
+    
+    replace_option(args, "-c:v", "libx264");
+    replace_option(args, "-profile:v", "main");
+    replace_option(args, "-level", "4.0");
+    replace_option(args, "-pix_fmt", "yuv420p");
+    append_filter(args, "-vf", EVEN_DIMENSIONS_FILTER);
+    
+    // Snap unusual or high frame rates to the nearest standard rate at or below 30 fps
+    let fps = info
+        .and_then(|i| i.streams.iter().find(|s| s.stream_type == StreamType::Video))
+        .and_then(|s| s.frame_rate_fps);
+    if let Some(fps) = fps {
+        let is_standard = STANDARD_FRAME_RATES.iter().any(|r| (r - fps).abs() < 0.01);
+        if !is_standard {
+            let target = match fps {
+                f if (f - 50.0).abs() < 0.5 => "25",
+                f if (f - 60000.0 / 1001.0).abs() < 0.01 => "30000/1001",
+                _ => "30",
+            };
+            replace_option(args, "-r", target);
+        }
+    }
+    
+    replace_option(args, "-c:a", "aac");
+    replace_option(args, "-ac", "2");
+    push_movflags(args, "+faststart");
+    Ok(())
+}
+
+/// Remove an HEVC sample entry tag (`-tag:v hvc1`) once the encoder is no longer HEVC, e.g.
+/// an H.265 preset switched to libx264; the MP4 muxer rejects the mismatched tag
+fn drop_stale_hevc_tag(args: &mut Vec<String>) {
+    let is_hevc = output_video_codec(args)
+        .is_none_or(|codec| codec == "copy" || codec.contains("hevc") || codec.contains("265"));
+    if is_hevc {
+        return;
+    }
+    while let Some(pos) = args
+        .windows(2)
+        .position(|w| w[0] == "-tag:v" && (w[1] == "hvc1" || w[1] == "hev1"))
+    {
+        args.drain(pos..pos + 2);
+    }
+}
+
+/// Emit closed-GOP / no-scene-cut flags for the selected video encoder
+fn apply_gop_options(args: &mut Vec<String>, closed_gop: bool, no_scenecut: bool) -> Result<(), ConvertError> {
+    let codec = output_video_codec(args).unwrap_or("").to_string();
+    match codec.as_str() {
+        "libx264" | "mpeg2video" | "mpeg4" => {
+            if closed_gop {
+                args.push("-flags".to_string());
+                args.push("+cgop".to_string());
+            }
+            if no_scenecut {
+                args.push("-sc_threshold".to_string());
+                args.push("0".to_string());
+            }
+        }
+        "libx265" => {
+            if closed_gop {
+                push_x265_param(args, "open-gop=0");
+            }
+            if no_scenecut {
+                push_x265_param(args, "scenecut=0");
+            }
+        }
+        _ => {
+            return Err(ConvertError::ConversionFailed(format!(
+                "Closed GOP / scene-cut options are not supported by video encoder '{}' \
+                 (supported: libx264, libx265, mpeg2video, mpeg4)",
+                if codec.is_empty() { "none" } else { &codec }
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Legal `-tune` / `-profile:v` / `-level` values per video encoder
+struct EncoderTuning {
+    codec: &'static str,
+    tunes: &'static [&'static str],
+    profiles: &'static [&'static str],
+    levels: &'static [&'static str],
+}
+
+const H264_LEVELS: &[&str] = &[
+    "1", "1b", "1.1", "1.2", "1.3", "2", "2.1", "2.2", "3", "3.1", "3.2", "4", "4.1", "4.2", "5", "5.1",
+    "5.2", "6", "6.1", "6.2",
+];
+const HEVC_LEVELS: &[&str] = &["1", "2", "2.1", "3", "3.1", "4", "4.1", "5", "5.1", "5.2", "6", "6.1", "6.2"];
+
+const ENCODER_TUNING: &[EncoderTuning] = &[
+    EncoderTuning {
+        codec: "libx264",
+        tunes: &["film", "animation", "grain", "stillimage", "fastdecode", "zerolatency", "psnr", "ssim"],
+        profiles: &["baseline", "main", "high", "high10", "high422", "high444"],
+        levels: H264_LEVELS,
+    },
+    EncoderTuning {
+        codec: "libx265",
+        tunes: &["grain", "animation", "fastdecode", "zerolatency", "psnr", "ssim"],
+        profiles: &[
+            "main", "main10", "main12", "mainstillpicture", "main422-10", "main422-12", "main444-8",
+            "main444-10", "main444-12",
+        ],
+        levels: HEVC_LEVELS,
+    },
+    EncoderTuning {
+        codec: "h264_nvenc",
+        tunes: &["hq", "ll", "ull", "lossless"],
+        profiles: &["baseline", "main", "high", "high444p"],
+        levels: H264_LEVELS,
+    },
+    EncoderTuning {
+        codec: "hevc_nvenc",
+        tunes: &["hq", "ll", "ull", "lossless"],
+        profiles: &["main", "main10", "rext"],
+        levels: HEVC_LEVELS,
+    },
+];
+
+/// Remove every `flag <value>` pair
+fn remove_option(args: &mut Vec<String>, flag: &str) {
+    while let Some(pos) = args.iter().position(|a| a == flag) {
+        args.remove(pos);
+        if pos < args.len() {
+            args.remove(pos);
+        }
+    }
+}
+
+/// Remove every `flag <value>` pair, then append `flag value`
+fn replace_option(args: &mut Vec<String>, flag: &str, value: &str) {
+    remove_option(args, flag);
+    args.push(flag.to_string());
+    args.push(value.to_string());
+}
+
+/// Quality-based rate control options that would override a target bitrate
+const QUALITY_OPTIONS: &[&str] = &["-crf", "-cq", "-qp", "-q:v", "-qscale:v", "-global_quality"];
+
+/// Switch the video encoder to a target average bitrate like "2500k" or "4M"
+fn apply_target_bitrate(args: &mut Vec<String>, bitrate: &str) -> Result<(), ConvertError> {
+    let digits = bitrate.trim_end_matches(['k', 'K', 'm', 'M']);
+    if digits.is_empty() || digits.parse::<f64>().map_or(true, |v| v <= 0.0) || digits.len() + 1 < bitrate.len() {
+        return Err(ConvertError::ConversionFailed(format!(
+            "Invalid target bitrate '{}' (e.g. 2500k or 4M)",
+            bitrate
+        )));
+    }
+    for flag in QUALITY_OPTIONS {
+        remove_option(args, flag);
+    }
+    replace_option(args, "-b:v", bitrate);
+    Ok(())
+}
+
+/// Set an image encoder's quality from a 1-100 scale (100 best): JPEG's qscale (2-31, lower
+/// is better), WebP's quality, or AV1's CRF (0-63, lower is better)
+fn apply_image_quality(args: &mut Vec<String>, quality: u32) -> Result<(), ConvertError> {
+    if !(1..=100).contains(&quality) {
+        return Err(ConvertError::ConversionFailed(format!(
+            "Image quality must be between 1 and 100, not {}",
+            quality
+        )));
+    }
+    let worse = f64::from(100 - quality) / 99.0;
+    let (flag, value) = match output_video_codec(args) {
+        Some("mjpeg") => ("-q:v", 2 + (worse * 29.0).round() as u32),
+        Some("libwebp" | "libwebp_anim") => ("-quality", quality),
+        Some("libaom-av1" | "libsvtav1") => ("-crf", (worse * 63.0).round() as u32),
+        codec => {
+            return Err(ConvertError::ConversionFailed(format!(
+                "Image quality applies to JPEG, WebP and AVIF output, not {}",
+                codec.unwrap_or("this format")
+            )))
+        }
+    };
+    for flag in QUALITY_OPTIONS.iter().chain(&["-quality"]) {
+        remove_option(args, flag);
+    }
+    replace_option(args, flag, &value.to_string());
+    Ok(())
+}
+
+/// Two-pass encoding needs a software video encoder working towards a bitrate
+fn check_two_pass(args: &[String], output_path: &str) -> Result<(), ConvertError> {
+    let fail = |msg: &str| Err(ConvertError::ConversionFailed(format!("Two-pass encoding: {}", msg)));
+    if is_stream_url(output_path) {
+        return fail("not possible for live stream outputs");
+    }
+    match output_video_codec(args) {
+        None | Some("copy") => return fail("needs a video encoder (not stream copy)"),
+        Some(codec) if crate::capabilities::is_hardware_encoder(codec) => {
+            return fail("hardware encoders don't support it; use a software encoder")
+        }
+        _ => {}
+    }
+    if !args.iter().any(|a| a == "-b:v") {
+        return fail("needs a target video bitrate");
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+const NULL_OUTPUT: &str = "NUL";
+#[cfg(not(windows))]
+const NULL_OUTPUT: &str = "/dev/null";
+
+/// Muxer options that the null muxer of the first pass would reject
+const MUXER_OPTIONS: &[&str] = &["-movflags", "-video_track_timescale", "-f"];
+
+/// Arguments for one pass of a two-pass encode. Pass 1 only analyzes the video (no
+/// audio, no output file); pass 2 writes the real output. Statistics go to files named
+/// after `log_prefix`.
+fn two_pass_args(args: &[String], pass: u32, log_prefix: &str) -> Vec<String> {
+    let mut head = args.to_vec();
+    let output = head.split_off(output_options_end(&head));
+    if output_video_codec(&head) == Some("libx265") {
+        push_x265_param(&mut head, &format!("pass={}:stats={}.log", pass, log_prefix));
+    } else {
+        head.extend(["-pass".to_string(), pass.to_string(), "-passlogfile".to_string(), log_prefix.to_string()]);
+    }
+    if pass == 1 {
+        for flag in MUXER_OPTIONS {
+            remove_option(&mut head, flag);
+        }
+        head.extend(["-an", "-f", "null", "-y", NULL_OUTPUT].map(String::from));
+    } else {
+        head.extend(output);
+    }
+    head
+}
+
+/// Emit tune/profile/level for the selected video encoder after checking the values are legal for it
+fn apply_encoder_tuning(
+    args: &mut Vec<String>,
+    tune: Option<&str>,
+    profile: Option<&str>,
+    level: Option<&str>,
+) -> Result<(), ConvertError> {
+    let codec = output_video_codec(args).unwrap_or("").to_string();
+    let tuning = ENCODER_TUNING.iter().find(|t| t.codec == codec).ok_or_else(|| {
+        ConvertError::ConversionFailed(format!(
+            "Tune/profile/level are not supported for video encoder '{}' (supported: {})",
+            if codec.is_empty() { "none" } else { &codec },
+            ENCODER_TUNING.iter().map(|t| t.codec).collect::<Vec<_>>().join(", ")
+        ))
+    })?;
+    let check = |kind: &str, value: Option<&str>, legal: &[&str]| match value {
+        Some(v) if !legal.contains(&v) => Err(ConvertError::ConversionFailed(format!(
+            "Invalid {} '{}' for {} (expected one of: {})",
+            kind,
+            v,
+            codec,
+            legal.join(", ")
+        ))),
+        _ => Ok(()),
+    };
+    check("tune", tune, tuning.tunes)?;
+    check("profile", profile, tuning.profiles)?;
+    check("level", level, tuning.levels)?;
+    
+    if let Some(tune) = tune {
+        replace_option(args, "-tune", tune);
+    }
+    if let Some(profile) = profile {
+        replace_option(args, "-profile:v", profile);
+    }
+    if let Some(level) = level {
+        // libx265 ignores -level; the level goes through its own parameter list
+        if codec == "libx265" {
+            push_x265_param(args, &format!("level-idc={}", level));
+        } else {
+            replace_option(args, "-level", level);
+        }
+    }
+    Ok(())
+}
+
+/// The video encoder selected by the last `-c:v` in the arguments
+fn output_video_codec(args: &[String]) -> Option<&str> {
+    args.iter()
+        .rposition(|a| a == "-c:v")
+        .and_then(|pos| args.get(pos + 1))
+        .map(String::as_str)
+}
+
+/// Thread count that scales well for an encoder on this machine.
+/// Encoders stop gaining beyond a point (x265's frame parallelism, libvpx/libaom
+/// tiles), so those are capped below the core count.
+pub fn recommend_threads(codec: &str) -> u32 {
+    let cores = std::thread::available_parallelism()
+        .map(|n| n.get() as u32)
+        .unwrap_or(1);
+    let cap = match codec {
+        "libx264" => 16,
+        "libx265" => 8,
+        "libvpx" | "libvpx-vp9" | "libaom-av1" => 8,
+        _ => 32,
+    };
+    cores.min(cap)
+}
+
+/// Split an image-sequence file name like `frame_%04d.png` into its parts around the number
+fn sequence_pattern(file_name: &str) -> Option<(&str, &str)> {
+    let start = file_name.find('%')?;
+    let rest = &file_name[start + 1..];
+    let digits = rest.chars().take_while(|c| c.is_ascii_digit()).count();
+    if !rest[digits..].starts_with('d') {
+        return None;
+    }
+    Some((&file_name[..start], &rest[digits + 1..]))
+}
+
+/// Whether `path` is an image-sequence pattern (`img%03d.jpg`) rather than a single file
+pub fn is_sequence_path(path: &str) -> bool {
+    std::path::Path::new(path)
+        .file_name()
+        .is_some_and(|name| sequence_pattern(&name.to_string_lossy()).is_some())
+}
+
+/// File name pattern of image-sequence output: `frame_00001.png`, `frame_00002.png`, ...
+const SEQUENCE_FILE_STEM: &str = "frame_%05d";
+
+/// Encoders image-sequence output can use
+const SEQUENCE_ENCODERS: &[&str] = &["png", "mjpeg", "libwebp", "bmp", "tiff"];
+
+/// Pattern path image-sequence output is written to. `output_path` may already be a pattern
+/// (kept as-is), a directory, or a file path like `clip.png` whose stem becomes the directory
+/// (`clip/frame_%05d.png`). The extension comes from that file path, else the preset, else PNG.
+pub fn sequence_output_pattern(options: &ConvertOptions) -> String {
+    if is_sequence_path(&options.output_path) {
+        return options.output_path.clone();
+    }
+    let path = std::path::Path::new(&options.output_path);
+    let file_extension = path
+        .extension()
+        .filter(|_| !path.is_dir())
+        .map(|e| e.to_string_lossy().to_lowercase());
+    let (dir, extension) = match file_extension {
+        Some(ext) => (path.with_extension(""), ext),
+        None => (
+            path.to_path_buf(),
+            options
+                .preset_id
+                .as_deref()
+                .and_then(find_preset)
+                .map_or_else(|| "png".to_string(), |p| p.extension),
+        ),
+    };
+    dir.join(format!("{}.{}", SEQUENCE_FILE_STEM, extension))
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Create the directory of an image-sequence pattern. Refuses one that already holds
+/// matching frames, which would be overwritten and mixed in with the new ones.
+fn prepare_sequence_dir(pattern_path: &str) -> Result<(), ConvertError> {
+    if let Some(dir) = std::path::Path::new(pattern_path).parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir).map_err(|e| {
+            ConvertError::InvalidOutputPath(format!("Could not create {}: {}", dir.display(), e))
+        })?;
+    }
+    if count_sequence_files(pattern_path).is_some_and(|n| n > 0) {
+        return Err(ConvertError::InvalidOutputPath(format!(
+            "{} already has frames in it; choose an empty directory",
+            std::path::Path::new(pattern_path).parent().unwrap_or(std::path::Path::new(".")).display()
+        )));
+    }
+    Ok(())
+}
+
+/// Switch a still-image job to one image per frame: drop the single-frame limit and use the
+/// image2 muxer, which numbers the files
+fn apply_image_sequence(args: &mut Vec<String>) -> Result<(), ConvertError> {
+    if let Some(codec) = output_video_codec(args).filter(|c| !SEQUENCE_ENCODERS.contains(c)) {
+        return Err(ConvertError::ConversionFailed(format!(
+            "Image sequences can be written as PNG, JPEG, WebP, BMP or TIFF, not with '{}'",
+            codec
+        )));
+    }
+    remove_option(args, "-frames:v");
+    // Only the output's -f; one before -i would be the input's
+    let input_end = args.iter().position(|a| a == "-i").map_or(0, |p| p + 2);
+    match args.iter().rposition(|a| a == "-f").filter(|&p| p >= input_end && p + 1 < args.len()) {
+        Some(pos) => args[pos + 1] = "image2".to_string(),
+        None => {
+            args.push("-f".to_string());
+            args.push("image2".to_string());
+        }
+    }
+    if !args.iter().any(|a| a == "-an") {
+        args.push("-an".to_string());
+    }
+    Ok(())
+}
+
+/// Frame numbers of the existing files matching an image-sequence pattern
+fn sequence_numbers(pattern_path: &str) -> Option<Vec<u64>> {
+    let path = std::path::Path::new(pattern_path);
+    let name = path.file_name()?.to_string_lossy();
+    let (prefix, suffix) = sequence_pattern(&name)?;
+    let dir = match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => std::path::Path::new("."),
+    };
+    let numbers = std::fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let digits = name.strip_prefix(prefix)?.strip_suffix(suffix)?;
+            if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+                return None;
+            }
+            digits.parse().ok()
+        })
+        .collect();
+    Some(numbers)
+}
+
+/// Number of existing files matching an image-sequence pattern
+pub fn count_sequence_files(pattern_path: &str) -> Option<u64> {
+    sequence_numbers(pattern_path).map(|numbers| numbers.len() as u64)
+}
+
+/// Rate image-sequence input is read at when `input_frame_rate` isn't set (ffmpeg's default)
+const DEFAULT_SEQUENCE_FRAME_RATE: f64 = 25.0;
+
+fn sequence_frame_rate(options: &ConvertOptions) -> f64 {
+    options
+        .advanced
+        .as_ref()
+        .and_then(|a| a.input_frame_rate)
+        .unwrap_or(DEFAULT_SEQUENCE_FRAME_RATE)
+}
+
+/// Input options for an image-sequence input: its frame rate, and the first frame number
+/// (ffmpeg only looks for a start among 0-4 on its own)
+fn sequence_input_args(options: &ConvertOptions) -> Result<Vec<String>, ConvertError> {
+    let fps = sequence_frame_rate(options);
+    if !(fps.is_finite() && fps > 0.0 && fps <= MAX_FRAME_RATE) {
+        return Err(ConvertError::ConversionFailed(format!(
+            "Invalid input frame rate {} (expected more than 0 and at most {})",
+            fps, MAX_FRAME_RATE
+        )));
+    }
+    let mut args = vec!["-framerate".to_string(), fps.to_string()];
+    if let Some(first) = sequence_numbers(&options.input_path).and_then(|n| n.into_iter().min()) {
+        args.push("-start_number".to_string());
+        args.push(first.to_string());
+    }
+    Ok(args)
+}
+
+/// Length of an image-sequence input: its frame count at the chosen frame rate. ffprobe
+/// can't know the rate the frames are meant to play at.
+pub fn sequence_input_duration(options: &ConvertOptions) -> Option<f64> {
+    let frames = count_sequence_files(&options.input_path).filter(|n| *n > 0)?;
+    Some(frames as f64 / sequence_frame_rate(options))
+}
+
+/// Parse an ffprobe/ffmpeg rate like "30000/1001" or "25"
+pub fn parse_frame_rate(rate: &str) -> Option<f64> {
+    let fps = match rate.split_once('/') {
+        Some((num, den)) => num.parse::<f64>().ok()? / den.parse::<f64>().ok()?,
+        None => rate.parse().ok()?,
+    };
+    (fps.is_finite() && fps > 0.0).then_some(fps)
+}
+
+/// Progress estimated from the output's growth against the input's size, for jobs with
+/// neither a duration nor a frame count. `None` once the output is larger than the input,
+/// where the estimate stops meaning anything and progress falls back to indeterminate.
+fn size_growth_percent(size_kb: u64, input_bytes: u64) -> Option<f64> {
+    if input_bytes == 0 {
+        return None;
+    }
+    let percent = (size_kb * 1024) as f64 / input_bytes as f64 * 100.0;
+    (percent < 100.0).then_some(percent)
+}
+
+/// Frames a file-producing job will handle: the input file count for image-sequence input,
+/// else `-frames:v`, else for image-sequence output the source's frame count when every
+/// frame is kept, or duration x output frame rate. Ordinary conversions report progress by
+/// time, unless the duration is unknown and every source frame is kept, where the source's
+/// frame count is used.
+fn expected_frames(options: &ConvertOptions, args: &[String], info: Option<&MediaInfo>, duration: Option<f64>) -> Option<u64> {
+    if is_sequence_path(&options.input_path) {
+        return count_sequence_files(&options.input_path).filter(|n| *n > 0);
+    }
+    let last_value = |flag: &str| args.iter().rposition(|a| a == flag).and_then(|p| args.get(p + 1));
+    let retimed = args.iter().any(|a| a == "-r")
+        || options.advanced.as_ref().is_some_and(|a| {
+            a.fps.is_some()
+                || a.target_duration_secs.is_some()
+                || a.speed.is_some()
+                || a.trim_start.is_some()
+                || a.trim_end.is_some()
+                || a.trim_duration.is_some()
+        });
+    let source_frames = || {
+        info?.streams
+            .iter()
+            .find(|s| s.stream_type == StreamType::Video && !s.has_disposition("attached_pic"))
+            .and_then(|s| s.nb_frames)
+    };
+    if !is_sequence_path(&options.output_path) {
+        let keeps_frames = duration.is_none() && !retimed && !args.iter().any(|a| a == "-vn" || a == "-frames:v");
+        return if keeps_frames { source_frames() } else { None };
+    }
+    if let Some(frames) = last_value("-frames:v").and_then(|n| n.parse().ok()) {
+        return Some(frames);
+    }
+    if let Some(frames) = source_frames().filter(|_| !retimed) {
+        return Some(frames);
+    }
+    let fps = options
+        .advanced
+        .as_ref()
+        .and_then(|a| a.fps)
+        .or_else(|| last_value("-r").and_then(|r| parse_frame_rate(r)))
+        .or_else(|| {
+            info?.streams
+                .iter()
+                .find(|s| s.stream_type == StreamType::Video)
+                .and_then(|s| s.frame_rate_fps)
+        })?;
+    Some((duration? * fps).ceil() as u64)
+}
+
+/// Parse a user-entered time: "HH:MM:SS(.ms)", "MM:SS(.ms)" or plain seconds
+pub fn parse_timestamp(time: &str) -> Option<f64> {
+    let mut secs = 0.0;
+    let parts: Vec<&str> = time.trim().split(':').collect();
+    if parts.len() > 3 {
+        return None;
+    }
+    for (i, part) in parts.iter().enumerate() {
+        let value: f64 = part.trim().parse().ok()?;
+        // Only the first field may exceed 59 (e.g. "90" or "90:00")
+        if !value.is_finite() || value < 0.0 || (i > 0 && value >= 60.0) {
+            return None;
+        }
+        secs = secs * 60.0 + value;
+    }
+    Some(secs)
+}
+
+/// Trim start and length (`None` = to the end) in seconds, from the trim options
+fn trim_range(advanced: &AdvancedOptions, input_duration: Option<f64>) -> Result<Option<(f64, Option<f64>)>, ConvertError> {
+    let parse = |name: &str, value: &Option<String>| -> Result<Option<f64>, ConvertError> {
+        value
+            .as_deref()
+            .filter(|v| !v.trim().is_empty())
+            .map(|v| {
+                parse_timestamp(v).ok_or_else(|| {
+                    ConvertError::ConversionFailed(format!(
+                        "Invalid {} '{}': use HH:MM:SS, MM:SS or seconds",
+                        name, v
+                    ))
+                })
+            })
+            .transpose()
+    };
+    let start = parse("trim start", &advanced.trim_start)?;
+    let end = parse("trim end", &advanced.trim_end)?;
+    let length = parse("trim duration", &advanced.trim_duration)?;
+    if start.is_none() && end.is_none() && length.is_none() {
+        return Ok(None);
+    }
+    let start = start.unwrap_or(0.0);
+    
+    let length = match (end, length) {
+        (Some(_), Some(_)) => {
+            return Err(ConvertError::ConversionFailed(
+                "Set either a trim end or a trim duration, not both".to_string(),
+            ))
+        }
+        (Some(end), None) if end <= start => {
+            return Err(ConvertError::ConversionFailed(format!(
+                "Trim start ({:.3}s) must be before trim end ({:.3}s)",
+                start, end
+            )))
+        }
+        (Some(end), None) => Some(end - start),
+        (None, Some(length)) if length <= 0.0 => {
+            return Err(ConvertError::ConversionFailed("Trim duration must be greater than 0".to_string()))
+        }
+        (None, length) => length,
+    };
+    if let Some(total) = input_duration {
+        if start >= total {
+            return Err(ConvertError::ConversionFailed(format!(
+                "Trim start ({:.3}s) is past the end of the input ({:.3}s)",
+                start, total
+            )));
+        }
+    }
+    Ok(Some((start, length)))
+}
+
+/// Length of the converted part of the input: the trim range clipped to the input duration
+pub fn trimmed_duration(advanced: Option<&AdvancedOptions>, input_duration: Option<f64>) -> Option<f64> {
+    let Some((start, length)) = advanced.and_then(|a| trim_range(a, input_duration).ok().flatten()) else {
+        return input_duration;
+    };
+    match (input_duration, length) {
+        (Some(total), Some(length)) => Some(length.min(total - start)),
+        (Some(total), None) => Some(total - start),
+        (None, length) => length,
+    }
+}
+
+/// Parse ffmpeg's progress time ("HH:MM:SS.ms", occasionally "MM:SS"). `None` for the
+/// "N/A" and negative times ffmpeg reports before the first frame is out.
+fn parse_time_str(time: &str) -> Option<f64> {
+    let time = time.trim();
+    if time.starts_with('-') {
+        return None;
+    }
+    parse_timestamp(time)
+}
+
+/// Output options accepted in user-supplied or imported extra arguments (name without the
+/// stream specifier, and whether a value follows). Everything else is refused: extra inputs,
+/// options that read or write other files (`-passlogfile`, `-stats_enc_pre`, `-segment_list`,
+/// `-hls_segment_filename`, ...) and overwrite switches.
+const ALLOWED_EXTRA_OPTIONS: &[(&str, bool)] = &[
+    // Codecs and rate control
+    ("-c", true), ("-codec", true), ("-vcodec", true), ("-acodec", true), ("-scodec", true),
+    ("-b", true), ("-maxrate", true), ("-minrate", true), ("-bufsize", true), ("-crf", true),
+    ("-cq", true), ("-qp", true), ("-q", true), ("-qscale", true), ("-global_quality", true),
+    ("-qmin", true), ("-qmax", true), ("-qcomp", true), ("-rc", true), ("-rc-lookahead", true),
+    ("-lossless", true), ("-compression_level", true), ("-quality", true), ("-speed", true),
+    ("-usage", true), ("-deadline", true), ("-cpu-used", true), ("-row-mt", true),
+    ("-tile-columns", true), ("-tile-rows", true), ("-lag-in-frames", true),
+    ("-spatial_aq", true), ("-temporal_aq", true), ("-b_ref_mode", true), ("-aq-mode", true),
+    ("-aq-strength", true), ("-application", true), ("-vbr", true), ("-cutoff", true),
+    // Encoder tuning
+    ("-preset", true), ("-tune", true), ("-profile", true), ("-level", true), ("-pix_fmt", true),
+    ("-g", true), ("-keyint_min", true), ("-bf", true), ("-refs", true), ("-sc_threshold", true),
+    ("-force_key_frames", true), ("-flags", true), ("-threads", true), ("-strict", true),
+    ("-x264-params", true), ("-x264opts", true), ("-x265-params", true), ("-still-picture", true),
+    ("-svtav1-params", true), ("-aom-params", true), ("-trellis", true), ("-subq", true),
+    // Frames, sizes and audio layout
+    ("-r", true), ("-s", true), ("-aspect", true), ("-fps_mode", true), ("-vsync", true),
+    ("-frames", true), ("-vframes", true), ("-aframes", true), ("-ar", true), ("-ac", true),
+    ("-channel_layout", true), ("-ch_layout", true), ("-sample_fmt", true),
+    ("-color_primaries", true), ("-color_trc", true), ("-colorspace", true),
+    ("-color_range", true), ("-enc_time_base", true), ("-sws_flags", true),
+    // Filters (their graphs are checked against `ALLOWED_FILTERS`)
+    ("-vf", true), ("-af", true), ("-filter", true), ("-filter_complex", true), ("-lavfi", true),
+    // Timing, mapping and metadata
+    ("-ss", true), ("-t", true), ("-to", true), ("-map", true), ("-map_metadata", true),
+    ("-map_chapters", true), ("-metadata", true), ("-disposition", true), ("-tag", true),
+    ("-timecode", true), ("-an", false), ("-vn", false), ("-sn", false), ("-dn", false),
+    ("-shortest", false),
+    // Muxing
+    ("-f", true), ("-movflags", true), ("-brand", true), ("-fflags", true),
+    ("-avoid_negative_ts", true), ("-max_muxing_queue_size", true), ("-frag_duration", true),
+    ("-id3v2_version", true), ("-write_id3v1", true), ("-loop", true), ("-plays", true),
+    ("-hls_time", true), ("-hls_list_size", true),
+];
+
+/// Options whose value is a filter graph
+const FILTER_GRAPH_OPTIONS: &[&str] = &["-vf", "-af", "-filter", "-filter_complex", "-lavfi"];
+
+/// Muxers refused in extra arguments: `tee` writes to any paths named in its value
+const BLOCKED_EXTRA_FORMATS: &[&str] = &["tee"];
+
+/// Filters accepted in extra arguments' graphs. Left out on purpose: filters that read files
+/// (`movie`, `subtitles`, `ass`, `lut3d`, ...), load shared libraries (`frei0r`, `ladspa`,
+/// `lv2`) or take outside commands (`sendcmd`, `zmq`).
+const ALLOWED_FILTERS: &[&str] = &[
+    // Video
+    "scale", "crop", "pad", "fps", "format", "setsar", "setdar", "setpts", "settb", "transpose",
+    "hflip", "vflip", "rotate", "yadif", "bwdif", "hqdn3d", "nlmeans", "unsharp", "eq", "hue",
+    "colorspace", "colorchannelmixer", "zscale", "tonemap", "drawtext", "drawbox", "fade",
+    "trim", "select", "thumbnail", "tile", "split", "palettegen", "paletteuse", "minterpolate",
+    "framerate", "deblock", "gblur", "boxblur", "vignette", "setparams", "tpad", "reverse",
+    "overlay", "hstack", "vstack", "blend", "null", "copy", "showinfo",
+    // Audio
+    "volume", "loudnorm", "dynaudnorm", "aresample", "atempo", "pan", "aformat", "highpass",
+    "lowpass", "acompressor", "alimiter", "afade", "atrim", "asetpts", "aselect", "asplit",
+    "amix", "amerge", "channelsplit", "silenceremove", "apad", "areverse", "equalizer",
+    "anull", "ashowinfo",
+];
+
+/// Options of allowed filters that read a file
+const BLOCKED_FILTER_OPTIONS: &[&str] = &["textfile"];
+
+/// Check a filter graph from extra arguments against `ALLOWED_FILTERS`
+fn check_extra_filter_graph(graph: &str) -> Result<(), String> {
+    for spec in split_unquoted(graph, &[',', ';']) {
+        // Drop the `[in]` link labels in front of the filter
+        let mut rest = spec.trim();
+        while let Some(after) = rest.strip_prefix('[') {
+            rest = after.split_once(']').map_or("", |(_, r)| r).trim_start();
+        }
+        let (name, options) = rest.split_once('=').unwrap_or((rest, ""));
+        // `name@instance` names one instance of a filter; `[out]` labels may follow the name
+        let name = name.split(['@', '[']).next().unwrap_or("").trim();
+        if !ALLOWED_FILTERS.contains(&name) {
+            return Err(format!("Filter '{}' is not allowed in extra arguments", name));
+        }
+        for option in split_unquoted(options, &[':']) {
+            let key = option.split('=').next().unwrap_or("").trim();
+            if BLOCKED_FILTER_OPTIONS.contains(&key) {
+                return Err(format!("Filter option '{}' is not allowed in extra arguments", key));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Safety check for extra arguments, shared by manual entry and preset import: every token
+/// must be an allowed option or that option's value. A stray token would otherwise be taken
+/// by ffmpeg as one more output file.
+pub fn check_extra_args(args: &[String]) -> Result<(), String> {
+    let mut tokens = args.iter();
+    while let Some(arg) = tokens.next() {
+        if !arg.starts_with('-') {
+            return Err(format!("Extra argument '{}' is not an option", arg));
+        }
+        // `-c:v`, `-b:a`, `-metadata:s:a:0` are `-c`, `-b`, `-metadata` for some streams
+        let name = arg.split(':').next().unwrap_or(arg);
+        let Some(&(_, takes_value)) = ALLOWED_EXTRA_OPTIONS.iter().find(|(option, _)| *option == name) else {
+            return Err(format!("Extra argument '{}' is not allowed", arg));
+        };
+        if !takes_value {
+            continue;
+        }
+        let value = tokens
+            .next()
+            .ok_or_else(|| format!("Extra argument '{}' needs a value", arg))?;
+        if FILTER_GRAPH_OPTIONS.contains(&name) {
+            check_extra_filter_graph(value)?;
+        }
+        if name == "-f" && BLOCKED_EXTRA_FORMATS.contains(&value.as_str()) {
+            return Err(format!("Output format '{}' is not allowed in extra arguments", value));
+        }
+    }
+    Ok(())
+}
+
+/// Split an extra arguments string into arguments, shell style: whitespace separates,
+/// single or double quotes group (the other quote type is literal inside), `\` escapes a
+/// quote, backslash or space, and `""` is an empty argument. In a filter graph value a single
+/// quote opened mid-argument (`text='...'`) is ffmpeg's own quoting and is passed through,
+/// with `\'` inside it rewritten to `'\\\''` so the filter parser still sees an apostrophe
+fn parse_extra_args(extra: &str) -> Vec<String> {
+    let mut args: Vec<String> = Vec::new();
+    let mut current = String::new();
+    // Set once a token has begun, so a quoted empty string ("") still counts as one
+    let mut in_token = false;
+    let mut quote: Option<char> = None;
+    // Whether the open quote belongs to ffmpeg's filter syntax rather than to the shell
+    let mut filter_quote = false;
+    let mut chars = extra.chars().peekable();
+    
+    while let Some(c) = chars.next() {
+        match (c, quote) {
+            // Backslash escapes the closing quote and itself; other backslashes stay as they
+            // are so ffmpeg's own escaping (`\:` in filter options, Windows paths) survives
+            ('\\', Some(q)) => match chars.peek() {
+                // ffmpeg can't escape inside its quotes: close, escape the quote for both
+                // the graph and option parsers, and reopen
+                Some('\'') if filter_quote => {
+                    current.push_str(r"'\\\''");
+                    chars.next();
+                }
+                Some(&next) if !filter_quote && (next == q || next == '\\') => {
+                    current.push(next);
+                    chars.next();
+                }
+                _ => current.push(c),
+            },
+            ('\\', None) => match chars.peek() {
+                Some(&next) if next.is_whitespace() || next == '"' || next == '\'' || next == '\\' => {
+                    current.push(next);
+                    chars.next();
+                    in_token = true;
+                }
+                // A trailing backslash is kept literally
+                _ => {
+                    current.push(c);
+                    in_token = true;
+                }
+            },
+            (c, Some(q)) if c == q => {
+                if filter_quote {
+                    current.push(c);
+                    filter_quote = false;
+                }
+                quote = None;
+            }
+            (_, Some(_)) => current.push(c),
+            ('"' | '\'', None) => {
+                filter_quote = c == '\''
+                    && !current.is_empty()
+                    && args.last().is_some_and(|a| is_filter_option(a));
+                if filter_quote {
+                    current.push(c);
+                }
+                quote = Some(c);
+                in_token = true;
+            }
+            (c, None) if c.is_whitespace() => {
+                if in_token {
+                    args.push(std::mem::take(&mut current));
+                    in_token = false;
+                }
+            }
+            (_, None) => {
+                current.push(c);
+                in_token = true;
+            }
+        }
+    }
+    
+    if in_token {
+        args.push(current);
+    }
+    
+    args
+}
+
+/// Options whose value is a filter graph
+fn is_filter_option(arg: &str) -> bool {
+    matches!(arg, "-vf" | "-af" | "-filter_complex" | "-lavfi") || arg.starts_with("-filter:")
+}
+
+/// Warnings for dynamic HDR metadata (Dolby Vision, HDR10+) the output will not keep
+pub fn dynamic_hdr_warnings(info: &crate::probe::MediaInfo, args: &[String]) -> Vec<String> {
+    let codec = output_video_codec(args).unwrap_or("");
+    let mut warnings = Vec::new();
+    for stream in &info.streams {
+        if stream.dolby_vision_profile.is_some() && codec != "copy" {
+            warnings.push(
+                "This conversion will lose Dolby Vision metadata (only a video stream copy keeps it)".to_string(),
+            );
+        }
+        if stream.has_hdr10_plus && codec != "copy" && codec != "libx265" {
+            warnings.push(format!(
+                "This conversion will lose HDR10+ metadata ({} cannot carry it)",
+                if codec.is_empty() { "the default encoder" } else { codec }
+            ));
+        }
+    }
+    warnings
+}
+
+/// Speed factors outside this range noticeably hurt quality (dropped/duplicated frames, audio artifacts)
+const MAX_COMFORTABLE_SPEED: f64 = 2.0;
+
+/// Largest speed-up (and, inverted, slow-down) `speed` accepts
+const MAX_SPEED: f64 = 100.0;
+
+/// `atempo` filters for a speed `factor`, chained so each stays within the 0.5-2.0
+/// range older ffmpeg builds accept
+fn atempo_chain(factor: f64) -> String {
+    let mut remaining = factor;
+    let mut filters = Vec::new();
+    while remaining > 2.0 {
+        filters.push("atempo=2.0".to_string());
+        remaining /= 2.0;
+    }
+    while remaining < 0.5 {
+        filters.push("atempo=0.5".to_string());
+        remaining /= 0.5;
+    }
+    filters.push(format!("atempo={:.6}", remaining));
+    filters.join(",")
+}
+
+/// Live streaming targets (pushed to a server instead of written to a file)
+const STREAM_PROTOCOLS: &[&str] = &["rtmp://", "rtmps://", "srt://"];
+
+/// Whether the output is a live streaming URL rather than a file path
+pub fn is_stream_url(output_path: &str) -> bool {
+    STREAM_PROTOCOLS.iter().any(|p| output_path.starts_with(p))
+}
+
+/// ffmpeg errors that may go away on their own (a file briefly locked by an antivirus
+/// scanner or a sync client, a dropped connection), worth retrying the conversion for.
+/// "Permission denied" from ffmpeg is not: it nearly always means a read-only destination
+/// (a sidecar that can't be spawned yet is retried separately).
+fn is_transient_error(message: &str) -> bool {
+    let message = message.to_lowercase();
+    [
+        "resource temporarily unavailable",
+        "device or resource busy",
+        "text file busy",
+        "being used by another process",
+        "connection reset",
+        "connection timed out",
+    ]
+    .iter()
+    .any(|pattern| message.contains(pattern))
+}
+
+/// Resolves once a cancel or stop is requested (checked every 100 ms)
+async fn interrupt_requested(cancel: &AtomicBool, stop: &AtomicBool) {
+    while !cancel.load(Ordering::Relaxed) && !stop.load(Ordering::Relaxed) {
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
 }
 
-#[derive(Debug, Clone, Serialize)]
-pub struct ConvertProgress {
-    pub percent: f64,
-    pub time_secs: f64,
-    pub speed: Option<String>,
-    pub bitrate: Option<String>,
-    pub size_kb: Option<u64>,
+/// First retry after 1s, doubling up to 30s
+fn retry_delay(attempt: u32) -> std::time::Duration {
+    let secs = 1u64 << attempt.saturating_sub(1).min(5);
+    std::time::Duration::from_secs(secs.min(30))
 }
 
-#[derive(Debug, Clone, Serialize)]
-pub struct ConvertResult {
-    pub success: bool,
-    pub output_path: String,
-    pub duration_secs: f64,
-    pub message: Option<String>,
+/// ffmpeg errors that mean the streaming server could not be reached or refused the stream
+fn is_connection_error(message: &str) -> bool {
+    let message = message.to_lowercase();
+    [
+        "connection refused",
+        "connection timed out",
+        "connection reset",
+        "network is unreachable",
+        "failed to resolve",
+        "no route to host",
+        "broken pipe",
+        "server error",
+        "handshake",
+    ]
+    .iter()
+    .any(|pattern| message.contains(pattern))
 }
 
-/// Check if ffmpeg is available
-pub fn check_ffmpeg(sidecar_path: Option<&std::path::Path>) -> Result<String, ConvertError> {
-    use std::process::Command;
+/// Write measured ReplayGain track tags, replacing input tags of the same name in any
+/// letter case so players don't see two conflicting values. MP4/M4A players read them from
+/// iTunes freeform tags (`----:com.apple.iTunes:replaygain_track_gain`), which ffmpeg can't
+/// write: for those outputs the tags are returned instead, to be added to the finished file.
+fn apply_replay_gain_tags(
+    args: &mut Vec<String>,
+    output_path: &str,
+    gain: &ReplayGain,
+    input_info: Option<&MediaInfo>,
+) -> Vec<(String, String)> {
+    use crate::probe::{REPLAYGAIN_ALBUM_GAIN, REPLAYGAIN_ALBUM_PEAK, REPLAYGAIN_TRACK_GAIN, REPLAYGAIN_TRACK_PEAK};
     
-    let ffmpeg_cmd = if let Some(path) = sidecar_path {
-        path.to_string_lossy().to_string()
-    } else {
-        "ffmpeg".to_string()
-    };
+    let mut values = Vec::new();
+    if let Some(db) = gain.track_gain_db {
+        values.push((REPLAYGAIN_TRACK_GAIN, format!("{:+.2} dB", db)));
+    }
+    if let Some(peak) = gain.track_peak {
+        values.push((REPLAYGAIN_TRACK_PEAK, format!("{:.6}", peak)));
+    }
+    if let Some(db) = gain.album_gain_db {
+        values.push((REPLAYGAIN_ALBUM_GAIN, format!("{:+.2} dB", db)));
+    }
+    if let Some(peak) = gain.album_peak {
+        values.push((REPLAYGAIN_ALBUM_PEAK, format!("{:.6}", peak)));
+    }
+    let container = target_container(args, output_path);
+    if matches!(container.as_str(), "mp4" | "ipod") {
+        return values
+            .into_iter()
+            .map(|(name, value)| (name.to_lowercase(), value))
+            .collect();
+    }
     
-    let output = Command::new(&ffmpeg_cmd)
-        .arg("-version")
-        .output()
-        .map_err(|_| ConvertError::FfmpegNotFound)?;
+    let mut tags: Vec<String> = values.iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+    let stale = input_info
+        .into_iter()
+        .flat_map(|info| info.format.tags.keys())
+        .filter(|key| {
+            [REPLAYGAIN_TRACK_GAIN, REPLAYGAIN_TRACK_PEAK]
+                .iter()
+                .any(|name| key.eq_ignore_ascii_case(name) && key.as_str() != *name)
+        });
+    tags.extend(stale.map(|key| format!("{}=", key)));
     
-    if output.status.success() {
-        let version = String::from_utf8_lossy(&output.stdout);
-        let first_line = version.lines().next().unwrap_or("ffmpeg version unknown");
-        Ok(first_line.to_string())
-    } else {
-        Err(ConvertError::FfmpegNotFound)
+    let mut pos = output_options_end(args);
+    for tag in tags {
+        args.insert(pos, "-metadata".to_string());
+        args.insert(pos + 1, tag);
+        pos += 2;
     }
+    // QuickTime reads them as metadata keys, which the muxer only writes when asked to
+    if container == "mov" && !args.iter().any(|a| a.contains("use_metadata_tags")) {
+        let output = args.split_off(output_options_end(args));
+        push_movflags(args, "+use_metadata_tags");
+        args.extend(output);
+    }
+    Vec::new()
 }
 
-/// Build ffmpeg arguments from options
-fn build_ffmpeg_args(options: &ConvertOptions) -> Result<Vec<String>, ConvertError> {
-    let mut args: Vec<String> = Vec::new();
-    
-    // Input file
-    args.push("-i".to_string());
-    args.push(options.input_path.clone());
-    
-    // Stream selection flags
-    let stream_sel = options.stream_selection.clone().unwrap_or_default();
+/// The attached-picture (cover art) stream, if the input has one
+fn cover_art_stream(info: &MediaInfo) -> Option<&StreamInfo> {
+    info.streams
+        .iter()
+        .find(|s| s.stream_type == StreamType::Video && s.has_disposition("attached_pic"))
+}
+
+/// Map the input's cover art into an audio-only output (one with -vn) whose container can
+/// store it, keeping the audio stream ffmpeg would pick by default (the one with the most
+/// channels). Covers that aren't JPEG or PNG are re-encoded to JPEG. Left alone when streams
+/// are mapped explicitly or video filters are set.
+fn apply_cover_art(args: &mut Vec<String>, info: &MediaInfo, output_path: &str) {
+    let Some(cover) = cover_art_stream(info) else {
+        return;
+    };
+    if !args.iter().any(|a| a == "-vn")
+        || args.iter().any(|a| matches!(a.as_str(), "-an" | "-map" | "-vf" | "-filter_complex"))
+        || !matches!(target_container(args, output_path).as_str(), "mp3" | "mp4" | "ipod" | "mov" | "flac")
+    {
+        return;
+    }
+    // max_by_key keeps the last of equal keys; reversing makes the first stream win ties
+    let Some(audio) = info
+        .streams
+        .iter()
+        .filter(|s| s.stream_type == StreamType::Audio)
+        .rev()
+        .max_by_key(|s| s.channels.unwrap_or(0))
+    else {
+        return;
+    };
     
-    if !stream_sel.include_video {
-        args.push("-vn".to_string());
+    args.retain(|a| a != "-vn");
+    let input_end = args.iter().position(|a| a == "-i").map_or(0, |p| p + 2);
+    let maps = ["-map".to_string(), format!("0:{}", audio.index), "-map".to_string(), format!("0:{}", cover.index)];
+    args.splice(input_end..input_end, maps);
+    let codec = if matches!(cover.codec_name.as_deref(), Some("mjpeg" | "png")) { "copy" } else { "mjpeg" };
+    replace_option(args, "-c:v", codec);
+    args.push("-disposition:v:0".to_string());
+    args.push("attached_pic".to_string());
+}
+
+/// `-map_metadata 0` for `copy_metadata`, then `-metadata key=value` for each tag (sorted by
+/// key). Each pair is a single ffmpeg argument, so values with spaces need no quoting.
+fn apply_metadata(args: &mut Vec<String>, advanced: &AdvancedOptions) -> Result<(), ConvertError> {
+    let mut extra = Vec::new();
+    if advanced.copy_metadata {
+        extra.push("-map_metadata".to_string());
+        extra.push("0".to_string());
     }
-    if !stream_sel.include_audio {
-        args.push("-an".to_string());
+    if let Some(metadata) = &advanced.metadata {
+        let mut tags: Vec<_> = metadata.iter().collect();
+        tags.sort();
+        for (key, value) in tags {
+            let key = key.trim();
+            if key.is_empty() || key.contains('=') {
+                return Err(ConvertError::ConversionFailed(format!(
+                    "Invalid metadata key '{}': keys must be non-empty and cannot contain '='",
+                    key
+                )));
+            }
+            extra.push("-metadata".to_string());
+            extra.push(format!("{}={}", key, value));
+        }
     }
-    if !stream_sel.include_subtitles {
-        args.push("-sn".to_string());
+    let pos = output_options_end(args);
+    args.splice(pos..pos, extra);
+    Ok(())
+}
+
+/// Index where options that belong to the output go: before the trailing "-y <output>",
+/// or before the URL for streaming outputs (which have no -y)
+fn output_options_end(args: &[String]) -> usize {
+    if args.len() >= 2 && args[args.len() - 2] == "-y" {
+        args.len() - 2
+    } else {
+        args.len().saturating_sub(1)
     }
-    if !stream_sel.include_data {
-        args.push("-dn".to_string());
+}
+
+/// Bitmap subtitle formats, which the `subtitles` filter (libass) can't render
+const IMAGE_SUBTITLE_CODECS: &[&str] = &["hdmv_pgs_subtitle", "dvd_subtitle", "dvb_subtitle", "xsub"];
+
+/// Position of input stream `stream_index` among the input's subtitle streams
+/// (what the `subtitles` filter's `si` option counts), checking it is a text subtitle
+fn subtitle_track(input_info: Option<&MediaInfo>, stream_index: u32) -> Result<u32, ConvertError> {
+    let info = input_info.ok_or_else(|| {
+        ConvertError::ConversionFailed("Cannot burn in an embedded subtitle track: the input could not be probed".to_string())
+    })?;
+    let mut subtitles = info.streams.iter().filter(|s| s.stream_type == StreamType::Subtitle);
+    let track = subtitles.position(|s| s.index == stream_index).ok_or_else(|| {
+        ConvertError::ConversionFailed(format!("Stream #{} is not a subtitle stream", stream_index))
+    })?;
+    let codec = info
+        .streams
+        .iter()
+        .find(|s| s.index == stream_index)
+        .and_then(|s| s.codec_name.as_deref())
+        .unwrap_or("");
+    if IMAGE_SUBTITLE_CODECS.contains(&codec) {
+        return Err(ConvertError::ConversionFailed(format!(
+            "Subtitle stream #{} is image-based ({}); only text subtitles can be burned in",
+            stream_index, codec
+        )));
     }
-    
-    // Preset or advanced options
-    if let Some(ref preset_id) = options.preset_id {
-        let preset = find_preset(preset_id)
-            .ok_or_else(|| ConvertError::PresetNotFound(preset_id.clone()))?;
-        
-        let preset_args = preset.build_args();
-        args.extend(preset_args);
+    Ok(track as u32)
+}
+
+/// Loudness target when normalizing without an explicit one
+const DEFAULT_LOUDNESS_TARGET: f64 = -16.0;
+/// True peak ceiling (dBTP) and loudness range (LU) used with every target
+const LOUDNORM_TRUE_PEAK: f64 = -1.5;
+const LOUDNORM_RANGE: f64 = 11.0;
+
+/// Largest gain (or cut) `volume` accepts, in dB; 40 dB is a 100x multiplier
+const MAX_VOLUME_DB: f64 = 40.0;
+
+/// `volume` filter for a gain like "1.5" (multiplier) or "+6dB"
+fn volume_filter(volume: &str) -> Result<String, ConvertError> {
+    let invalid = |reason: &str| ConvertError::ConversionFailed(format!("Invalid volume '{}': {}", volume, reason));
+    let value = volume.trim();
+    let db_value = value
+        .strip_suffix("dB")
+        .or_else(|| value.strip_suffix("db"))
+        .or_else(|| value.strip_suffix("DB"));
+    if let Some(db) = db_value {
+        let db: f64 = db.trim().parse().map_err(|_| invalid("expected a number of dB like +6dB"))?;
+        if !db.is_finite() || db.abs() > MAX_VOLUME_DB {
+            return Err(invalid(&format!("must be within ±{} dB", MAX_VOLUME_DB)));
+        }
+        return Ok(format!("volume={}dB", db));
     }
-    
-    // Advanced options override preset
-    if let Some(ref advanced) = options.advanced {
-        if let Some(ref format) = advanced.format {
-            args.push("-f".to_string());
-            args.push(format.clone());
+    let factor: f64 = value.parse().map_err(|_| invalid("expected a multiplier like 1.5 or a gain like +6dB"))?;
+    let max_factor = 10f64.powf(MAX_VOLUME_DB / 20.0);
+    if !factor.is_finite() || factor <= 0.0 || factor > max_factor {
+        return Err(invalid(&format!("multiplier must be above 0 and at most {}", max_factor)));
+    }
+    Ok(format!("volume={}", factor))
+}
+
+/// Single-pass `loudnorm` filter for an integrated loudness target
+fn loudnorm_filter(target: f64) -> String {
+    format!("loudnorm=I={}:TP={}:LRA={}", target, LOUDNORM_TRUE_PEAK, LOUDNORM_RANGE)
+}
+
+/// Arguments that run the job's audio chain through loudnorm in measurement mode (JSON
+/// summary on stderr) without writing anything: same input, trim and filters, no video
+fn loudnorm_analysis_args(args: &[String]) -> Vec<String> {
+    let mut head = args[..output_options_end(args)].to_vec();
+    extend_loudnorm_filter(&mut head, ":print_format=json");
+    for flag in MUXER_OPTIONS {
+        remove_option(&mut head, flag);
+    }
+    replace_option(&mut head, "-c:a", "pcm_s16le");
+    head.extend(["-vn", "-sn", "-dn", "-f", "null", "-y", NULL_OUTPUT].map(String::from));
+    head
+}
+
+/// Swap the single-pass loudnorm filter for one fed with measured values
+fn apply_loudnorm_measurement(args: &mut [String], measured: &crate::analysis::LoudnormMeasurement) {
+    extend_loudnorm_filter(
+        args,
+        &format!(
+            ":measured_I={}:measured_TP={}:measured_LRA={}:measured_thresh={}:offset={}:linear=true",
+            measured.input_i, measured.input_tp, measured.input_lra, measured.input_thresh, measured.target_offset
+        ),
+    );
+}
+
+/// Append `options` to the loudnorm filter in the `-af` chain
+fn extend_loudnorm_filter(args: &mut [String], options: &str) {
+    let Some(pos) = args.iter().rposition(|a| a == "-af") else {
+        return;
+    };
+    if let Some(chain) = args.get_mut(pos + 1) {
+        *chain = split_filter_chain(chain)
+            .into_iter()
+            .map(|f| if f.starts_with("loudnorm=") { format!("{}{}", f, options) } else { f.to_string() })
+            .collect::<Vec<_>>()
+            .join(",");
+    }
+}
+
+/// Put `filter` in front of the existing `-vf`/`-af` chain (given as `flag`), or add a new one
+fn prepend_filter(args: &mut Vec<String>, flag: &str, filter: &str) {
+    if let Some(pos) = args.iter().rposition(|a| a == flag) {
+        if let Some(existing) = args.get_mut(pos + 1) {
+            *existing = format!("{},{}", filter, existing);
+            return;
         }
-        if let Some(ref vcodec) = advanced.video_codec {
-            // Remove any existing -c:v if present
-            if let Some(pos) = args.iter().position(|a| a == "-c:v") {
-                args.remove(pos);
-                if pos < args.len() {
-                    args.remove(pos);
+    }
+    let pos = output_options_end(args);
+    args.insert(pos, flag.to_string());
+    args.insert(pos + 1, filter.to_string());
+}
+
+/// Parse a timebase like "1/24000" into (numerator, denominator)
+fn parse_timebase(timebase: &str) -> Result<(u32, u32), ConvertError> {
+    timebase
+        .split_once('/')
+        .and_then(|(num, den)| Some((num.trim().parse::<u32>().ok()?, den.trim().parse::<u32>().ok()?)))
+        .filter(|(num, den)| *num > 0 && *den > 0)
+        .ok_or_else(|| {
+            ConvertError::ConversionFailed(format!(
+                "Invalid timebase '{}' (expected a fraction like 1/24000)",
+                timebase
+            ))
+        })
+}
+
+/// Output times of the input's chapter starts, given the trim (start, length): chapters
+/// starting outside the converted part are left out
+fn chapter_keyframe_times(input_info: Option<&MediaInfo>, trim: Option<(f64, Option<f64>)>) -> Result<Vec<f64>, ConvertError> {
+    let chapters = input_info.map(|info| info.chapters.as_slice()).unwrap_or_default();
+    if chapters.is_empty() {
+        return Err(ConvertError::ConversionFailed(
+            "Keyframes at chapters need an input with chapters".to_string(),
+        ));
+    }
+    let (start, length) = trim.unwrap_or((0.0, None));
+    let times: Vec<f64> = chapters
+        .iter()
+        .map(|chapter| chapter.start_time - start)
+        .filter(|t| *t >= 0.0 && length.is_none_or(|length| *t < length))
+        .collect();
+    if times.is_empty() {
+        return Err(ConvertError::ConversionFailed(
+            "No chapter starts within the trimmed part".to_string(),
+        ));
+    }
+    Ok(times)
+}
+
+/// `-force_key_frames` value for a placement, checking timestamps against the input duration
+fn force_key_frames_value(
+    placement: &KeyframePlacement,
+    input_info: Option<&MediaInfo>,
+    trim: Option<(f64, Option<f64>)>,
+) -> Result<String, ConvertError> {
+    let invalid = |reason: String| ConvertError::ConversionFailed(format!("Invalid keyframe placement: {}", reason));
+    let duration = input_info.and_then(|info| info.format.duration);
+    match placement {
+        KeyframePlacement::Timestamps { times } => {
+            if times.is_empty() {
+                return Err(invalid("no timestamps given".to_string()));
+            }
+            if let Some(t) = times.iter().find(|t| !t.is_finite() || **t < 0.0) {
+                return Err(invalid(format!("{} is not a valid time", t)));
+            }
+            if let Some(dur) = duration {
+                if let Some(t) = times.iter().find(|t| **t > dur) {
+                    return Err(invalid(format!("{:.3}s is past the end of the input ({:.3}s)", t, dur)));
                 }
             }
-            args.push("-c:v".to_string());
-            args.push(vcodec.clone());
+            let mut sorted = times.clone();
+            sorted.sort_by(f64::total_cmp);
+            Ok(sorted.iter().map(|t| format!("{:.3}", t)).collect::<Vec<_>>().join(","))
         }
-        if let Some(ref acodec) = advanced.audio_codec {
-            // Remove any existing -c:a if present
-            if let Some(pos) = args.iter().position(|a| a == "-c:a") {
-                args.remove(pos);
-                if pos < args.len() {
-                    args.remove(pos);
-                }
+        KeyframePlacement::Interval { seconds } => {
+            if !seconds.is_finite() || *seconds <= 0.0 {
+                return Err(invalid(format!("interval must be positive, got {}", seconds)));
             }
-            args.push("-c:a".to_string());
-            args.push(acodec.clone());
+            Ok(format!("expr:gte(t,n_forced*{})", seconds))
         }
-        if let Some(ref extra) = advanced.extra_args {
-            // Parse extra args (split by whitespace, respecting quotes)
-            let parsed = parse_extra_args(extra);
-            args.extend(parsed);
+        KeyframePlacement::Expression { expr } => {
+            if expr == "source" || (expr.starts_with("expr:") && expr.len() > 5) {
+                Ok(expr.clone())
+            } else {
+                Err(invalid(format!("'{}' must be 'source' or start with 'expr:'", expr)))
+            }
+        }
+        KeyframePlacement::Chapters => {
+            let times = chapter_keyframe_times(input_info, trim)?;
+            force_key_frames_value(&KeyframePlacement::Timestamps { times }, input_info, trim)
         }
     }
-    
-    // Overwrite output without asking
-    args.push("-y".to_string());
-    
-    // Output file
-    args.push(options.output_path.clone());
-    
-    Ok(args)
 }
 
-/// Parse time string "HH:MM:SS.ms" to seconds
-fn parse_time_str(time: &str) -> f64 {
-    let parts: Vec<&str> = time.split(':').collect();
-    if parts.len() == 3 {
-        let hours: f64 = parts[0].parse().unwrap_or(0.0);
-        let minutes: f64 = parts[1].parse().unwrap_or(0.0);
-        let seconds: f64 = parts[2].parse().unwrap_or(0.0);
-        hours * 3600.0 + minutes * 60.0 + seconds
-    } else {
-        0.0
+/// Output channel layouts accepted by `audio_channel_layout` (ffmpeg layout names)
+const CHANNEL_LAYOUTS: &[&str] = &["mono", "stereo", "2.1", "3.0", "quad", "4.0", "5.0", "5.1", "6.1", "7.1"];
+
+/// Filter converting `source` (from probe, if known) to the `target` layout. Surround to
+/// stereo gets an explicit ITU-style downmix; the `<` renormalizes gains so it never clips.
+fn channel_layout_filter(source: Option<&str>, target: &str) -> Result<String, ConvertError> {
+    if !CHANNEL_LAYOUTS.contains(&target) {
+        return Err(ConvertError::ConversionFailed(format!(
+            "Unknown channel layout '{}' (expected one of: {})",
+            target,
+            CHANNEL_LAYOUTS.join(", ")
+        )));
     }
+    let downmix = match (source, target) {
+        (Some("5.1"), "stereo") => Some("pan=stereo|FL<FL+0.707*FC+0.707*BL|FR<FR+0.707*FC+0.707*BR"),
+        (Some("5.1(side)"), "stereo") => Some("pan=stereo|FL<FL+0.707*FC+0.707*SL|FR<FR+0.707*FC+0.707*SR"),
+        (Some("7.1"), "stereo") => {
+            Some("pan=stereo|FL<FL+0.707*FC+0.707*BL+0.707*SL|FR<FR+0.707*FC+0.707*BR+0.707*SR")
+        }
+        _ => None,
+    };
+    Ok(match downmix {
+        Some(pan) => pan.to_string(),
+        // Everything else (including upmixes) goes through libswresample's standard matrix
+        None => format!("aformat=channel_layouts={}", target),
+    })
 }
 
-/// Parse extra arguments string into a vector
-fn parse_extra_args(extra: &str) -> Vec<String> {
-    let mut args = Vec::new();
-    let mut current = String::new();
-    let mut in_quotes = false;
-    let mut quote_char = ' ';
-    
-    for c in extra.chars() {
-        match c {
-            '"' | '\'' if !in_quotes => {
-                in_quotes = true;
-                quote_char = c;
-            }
-            c if c == quote_char && in_quotes => {
-                in_quotes = false;
+/// Check a `pan` channel map: a layout followed by `out=in` terms that only reference
+/// input channels (`c<N>`) the source actually has
+fn validate_channel_map(map: &str, source_channels: u32) -> Result<(), ConvertError> {
+    let invalid = |reason: String| ConvertError::ConversionFailed(format!("Invalid channel map '{}': {}", map, reason));
+    if map.contains([',', ';', '[', ']']) {
+        return Err(invalid("only a single pan expression is allowed".to_string()));
+    }
+    let mut parts = map.split('|');
+    if parts.next().is_none_or(|layout| layout.trim().is_empty()) {
+        return Err(invalid("missing output layout (e.g. \"stereo\")".to_string()));
+    }
+    let mut terms = 0;
+    for term in parts {
+        terms += 1;
+        let Some((_, inputs)) = term.split_once(['=', '<']) else {
+            return Err(invalid(format!("'{}' is not an out=in term", term)));
+        };
+        let chars: Vec<char> = inputs.chars().collect();
+        for (i, c) in chars.iter().enumerate() {
+            let starts_ref = *c == 'c' && (i == 0 || !chars[i - 1].is_ascii_alphanumeric());
+            if !starts_ref {
+                continue;
             }
-            ' ' if !in_quotes => {
-                if !current.is_empty() {
-                    args.push(current.clone());
-                    current.clear();
+            let digits: String = chars[i + 1..].iter().take_while(|d| d.is_ascii_digit()).collect();
+            if let Ok(channel) = digits.parse::<u32>() {
+                if channel >= source_channels {
+                    return Err(invalid(format!(
+                        "input channel c{} does not exist (source has {} channels)",
+                        channel, source_channels
+                    )));
                 }
             }
-            _ => {
-                current.push(c);
+        }
+    }
+    if terms == 0 {
+        return Err(invalid("no channel routes given".to_string()));
+    }
+    Ok(())
+}
+
+/// The filters of a `-vf`/`-af` chain. Commas inside quotes or escaped with a backslash
+/// belong to a filter's options (`aeval='val(0)*0.5,val(1)'`) and don't split it.
+fn split_filter_chain(chain: &str) -> Vec<&str> {
+    split_unquoted(chain, &[','])
+}
+
+/// Split filter syntax on `separators` found outside single quotes and not escaped
+fn split_unquoted<'a>(text: &'a str, separators: &[char]) -> Vec<&'a str> {
+    let mut parts = Vec::new();
+    let (mut start, mut quoted, mut escaped) = (0, false, false);
+    for (i, c) in text.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '\'' => quoted = !quoted,
+            c if !quoted && separators.contains(&c) => {
+                parts.push(&text[start..i]);
+                start = i + c.len_utf8();
             }
+            _ => {}
+        }
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+/// Add `filter` at the end of the existing `-vf`/`-af` chain (given as `flag`), or start a new one
+fn append_filter(args: &mut Vec<String>, flag: &str, filter: &str) {
+    if let Some(pos) = args.iter().rposition(|a| a == flag) {
+        if let Some(existing) = args.get_mut(pos + 1) {
+            *existing = format!("{},{}", existing, filter);
+            return;
+        }
+    }
+    args.push(flag.to_string());
+    args.push(filter.to_string());
+}
+
+/// Speed the output up (factor > 1) or down so `source_secs` of media lasts `target_secs`.
+/// Returns the applied factor. Filtering is impossible on stream-copied tracks.
+fn apply_target_duration(
+    args: &mut Vec<String>,
+    source_secs: f64,
+    target_secs: f64,
+    video: bool,
+    audio: bool,
+) -> Result<f64, ConvertError> {
+    if target_secs <= 0.0 || source_secs <= 0.0 {
+        return Err(ConvertError::ConversionFailed(
+            "Target and source durations must be positive".to_string(),
+        ));
+    }
+    let factor = source_secs / target_secs;
+    apply_speed(args, factor, video, audio)?;
+    Ok(factor)
+}
+
+/// Play video (`setpts`) and/or audio (`atempo`) `factor` times faster. Filtering is
+/// impossible on stream-copied tracks.
+fn apply_speed(args: &mut Vec<String>, factor: f64, video: bool, audio: bool) -> Result<(), ConvertError> {
+    let last_codec = |flag: &str| args.iter().rposition(|a| a == flag).and_then(|p| args.get(p + 1));
+    if (video && last_codec("-c:v").is_some_and(|c| c == "copy"))
+        || (audio && last_codec("-c:a").is_some_and(|c| c == "copy"))
+    {
+        return Err(ConvertError::ConversionFailed(
+            "Changing the duration needs re-encoding; it cannot be combined with stream copy".to_string(),
+        ));
+    }
+    if video {
+        prepend_filter(args, "-vf", &format!("setpts={:.6}*PTS", 1.0 / factor));
+    }
+    if audio {
+        prepend_filter(args, "-af", &atempo_chain(factor));
+    }
+    Ok(())
+}
+
+/// Ways to end a running conversion early
+pub struct Interrupts {
+    /// Kill ffmpeg and discard the output
+    pub cancel: Arc<AtomicBool>,
+    /// Let ffmpeg finalize what it has encoded and keep it
+    pub stop: Arc<AtomicBool>,
+}
+
+/// Where a conversion writes: stream URLs as given, image sequences as a frame pattern
+/// (see `sequence_output_pattern`), files under a free name (`_01`, `_02`, ... if taken)
+pub fn resolve_output_path(options: &ConvertOptions) -> String {
+    if is_stream_url(&options.output_path) {
+        options.output_path.clone()
+    } else if options.advanced.as_ref().is_some_and(|a| a.image_sequence) {
+        sequence_output_pattern(options)
+    } else {
+        ensure_unique_output_path(&options.output_path)
+    }
+}
+
+/// `build_ffmpeg_args` plus the edits that need its whole result: retiming to
+/// `target_duration_secs` (of `duration`, the trimmed input length), then the provenance
+/// comment, so the tag describes the retimed command. Returns the retime's speed factor.
+fn job_args(
+    options: &ConvertOptions,
+    input_info: Option<&MediaInfo>,
+    duration: Option<f64>,
+) -> Result<(Vec<String>, Option<f64>), ConvertError> {
+    let mut args = build_ffmpeg_args(options, input_info)?;
+    let retime_factor = match options.advanced.as_ref().and_then(|a| a.target_duration_secs) {
+        Some(target) => {
+            let (Some(info), Some(source)) = (input_info, duration) else {
+                return Err(ConvertError::ConversionFailed(
+                    "Cannot target a duration: input duration is unknown".to_string(),
+                ));
+            };
+            let selection = options.stream_selection.clone().unwrap_or_default();
+            Some(apply_target_duration(
+                &mut args,
+                source,
+                target,
+                info.has_video && selection.include_video,
+                info.has_audio && selection.include_audio,
+            )?)
         }
+        None => None,
+    };
+    if options.advanced.as_ref().is_some_and(|a| a.embed_settings) {
+        let tag = provenance_tag(options.preset_id.as_deref(), &args);
+        let pos = output_options_end(&args);
+        args.insert(pos, "-metadata".to_string());
+        args.insert(pos + 1, format!("comment={}", tag));
     }
+    Ok((args, retime_factor))
+}
+
+/// Measurements made with separate ffmpeg runs before encoding, applied to `args`:
+/// ReplayGain tags (`write_replaygain`) and the first pass of two-pass loudness
+/// normalization. Failures are logged as warnings: the tags are left out, normalization
+/// falls back to one (dynamic) pass. Returns the iTunes tags to add to the finished file
+/// (see `apply_replay_gain_tags`); fails only when `cancel_flag` stops a measurement.
+async fn apply_measurements(
+    args: &mut Vec<String>,
+    options: &ConvertOptions,
+    input_info: Option<&MediaInfo>,
+    ffmpeg_path: Option<&std::path::Path>,
+    cancel_flag: &Arc<AtomicBool>,
+    conv_log: &mut crate::logger::ConversionLog,
+) -> Result<Vec<(String, String)>, ConvertError> {
+    use crate::logger::LogLevel as AppLogLevel;
+    let ffmpeg_path = ffmpeg_path.map(|p| p.to_path_buf());
     
-    if !current.is_empty() {
-        args.push(current);
+    // ReplayGain tags: a failed measurement costs the tags, not the conversion
+    let mut file_tags = Vec::new();
+    let selection = options.stream_selection.clone().unwrap_or_default();
+    let writes_audio = input_info.is_some_and(|info| info.has_audio)
+        && selection.include_audio
+        && !args.iter().any(|a| a == "-an");
+    if writes_audio && options.advanced.as_ref().is_some_and(|a| a.write_replaygain) {
+        let input_path = options.input_path.clone();
+        let sidecar = ffmpeg_path.clone();
+        let cancel = cancel_flag.clone();
+        let measured = tokio::task::spawn_blocking(move || {
+            crate::analysis::measure_replay_gain(&input_path, sidecar.as_deref(), &cancel)
+        })
+        .await
+        .map_err(|e| ConvertError::ConversionFailed(e.to_string()))
+        .and_then(|r| r);
+        match measured {
+            Ok(gain) => {
+                file_tags = apply_replay_gain_tags(args, &options.output_path, &gain, input_info);
+                conv_log.add_entry(
+                    AppLogLevel::Info,
+                    &format!(
+                        "ReplayGain: track gain {:+.2} dB, peak {:.6}",
+                        gain.track_gain_db.unwrap_or(0.0),
+                        gain.track_peak.unwrap_or(0.0)
+                    ),
+                    Some(&format!("ffmpeg {}", args.join(" "))),
+                );
+            }
+            Err(ConvertError::Cancelled) => return Err(ConvertError::Cancelled),
+            Err(e) => conv_log.add_entry(
+                AppLogLevel::Warning,
+                &format!("ReplayGain tags not written: {}", e),
+                None,
+            ),
+        }
+    } else if let Some(gain) = input_info.and_then(|info| info.replay_gain.as_ref()).filter(|_| writes_audio) {
+        // MP4/M4A keep the source's ReplayGain as iTunes tags too; other containers copy it
+        if matches!(target_container(args, &options.output_path).as_str(), "mp4" | "ipod") {
+            file_tags = apply_replay_gain_tags(args, &options.output_path, gain, input_info);
+        }
     }
     
-    args
+    // Two-pass loudness normalization: measure first; a failed measurement falls back to
+    // single-pass (dynamic) normalization
+    if writes_audio
+        && options
+            .advanced
+            .as_ref()
+            .is_some_and(|a| a.normalize_audio && a.loudness_two_pass)
+    {
+        let analysis_args = loudnorm_analysis_args(args);
+        let sidecar = ffmpeg_path.clone();
+        let cancel = cancel_flag.clone();
+        let measured = tokio::task::spawn_blocking(move || {
+            crate::analysis::measure_loudnorm(&analysis_args, sidecar.as_deref(), &cancel)
+        })
+        .await
+        .map_err(|e| ConvertError::ConversionFailed(e.to_string()))
+        .and_then(|r| r);
+        match measured {
+            Ok(measured) => {
+                apply_loudnorm_measurement(args, &measured);
+                conv_log.add_entry(
+                    AppLogLevel::Info,
+                    &format!(
+                        "Measured loudness {} LUFS, true peak {} dBTP, range {} LU",
+                        measured.input_i, measured.input_tp, measured.input_lra
+                    ),
+                    Some(&format!("ffmpeg {}", args.join(" "))),
+                );
+            }
+            Err(ConvertError::Cancelled) => return Err(ConvertError::Cancelled),
+            Err(e) => conv_log.add_entry(
+                AppLogLevel::Warning,
+                &format!("Loudness measurement failed, normalizing in one pass: {}", e),
+                None,
+            ),
+        }
+    }
+    Ok(file_tags)
+}
+
+/// The ffmpeg runs of a job: `args` itself, or both passes of a two-pass job sharing the
+/// statistics files at `log_prefix`
+fn job_passes(args: &[String], log_prefix: Option<&str>) -> Vec<Vec<String>> {
+    match log_prefix {
+        Some(prefix) => vec![two_pass_args(args, 1, prefix), two_pass_args(args, 2, prefix)],
+        None => vec![args.to_vec()],
+    }
 }
 
-/// Get the duration of the input file in seconds
-fn get_duration(input_path: &str, ffprobe_path: Option<&std::path::Path>) -> Option<f64> {
-    crate::probe::probe_file(input_path, ffprobe_path)
-        .ok()
-        .and_then(|info| info.format.duration)
+/// Statistics file prefix shown in previews of two-pass jobs (ffmpeg's own default)
+const PREVIEW_PASSLOG_PREFIX: &str = "ffmpeg2pass";
+
+/// The ffmpeg runs `start_conversion` would make for `options` (both passes of a two-pass
+/// job), built the same way: output naming, retiming, provenance and measurements. Runs
+/// the probe and any measurement passes, but writes no output.
+pub async fn preview_passes(
+    options: ConvertOptions,
+    mut reserved: HashSet<String>,
+    ffmpeg_path: Option<std::path::PathBuf>,
+    ffprobe_path: Option<std::path::PathBuf>,
+) -> Result<Vec<Vec<String>>, ConvertError> {
+    let mut options = options;
+    options.output_path = reserve_output_path(&options, &mut reserved);
+    options.output_path = resolve_output_path(&options);
+    
+    // Unprobeable inputs (streams, sequences) still get a command, as they would convert
+    let (input_path, probe_path) = (options.input_path.clone(), ffprobe_path.clone());
+    let dynamic_hdr = options.advanced.as_ref().is_some_and(|a| a.preserve_dynamic_hdr);
+    let probe_retry = options.settings.probe_retry.clone();
+    let mut input_info = tokio::task::spawn_blocking(move || {
+        let mut info = crate::probe::probe_file_with_retry(&input_path, probe_path.as_deref(), &probe_retry).ok();
+        if let Some(info) = info.as_mut().filter(|_| dynamic_hdr) {
+            crate::probe::detect_hdr10_plus(info, &input_path, probe_path.as_deref());
+        }
+        info
+    })
+    .await
+    .map_err(|e| ConvertError::ConversionFailed(e.to_string()))?;
+    if let Some(info) = input_info.as_mut().filter(|_| is_sequence_path(&options.input_path)) {
+        info.format.duration = sequence_input_duration(&options);
+    }
+    
+    let input_duration = input_info.as_ref().and_then(|info| info.format.duration);
+    let trimmed = trimmed_duration(options.advanced.as_ref(), input_duration);
+    let (mut args, _) = job_args(&options, input_info.as_ref(), trimmed)?;
+    // Measurements log into a scratch log, which is dropped
+    let mut scratch_log = crate::logger::ConversionLog::new(&options.input_path, &options.output_path, None, None, "");
+    let never_cancelled = Arc::new(AtomicBool::new(false));
+    apply_measurements(&mut args, &options, input_info.as_ref(), ffmpeg_path.as_deref(), &never_cancelled, &mut scratch_log).await?;
+    let two_pass = options.advanced.as_ref().is_some_and(|a| a.two_pass);
+    Ok(job_passes(&args, two_pass.then_some(PREVIEW_PASSLOG_PREFIX)))
 }
 
 /// Start a conversion with progress reporting and logging
 pub async fn start_conversion(
     app_handle: AppHandle,
     options: ConvertOptions,
-    cancel_flag: Arc<AtomicBool>,
+    interrupts: Interrupts,
+    pause: Arc<PauseControl>,
     ffmpeg_path: Option<std::path::PathBuf>,
     ffprobe_path: Option<std::path::PathBuf>,
     log_store: Arc<crate::logger::LogStore>,
 ) -> Result<ConvertResult, ConvertError> {
     use crate::logger::{ConversionLog, LogLevel as AppLogLevel};
+    let Interrupts { cancel: cancel_flag, stop: stop_flag } = interrupts;
     
-    // If output file already exists, use a unique path (_01, _02, ...)
-    let output_path = ensure_unique_output_path(&options.output_path);
+    let live = is_stream_url(&options.output_path);
     let options = ConvertOptions {
-        output_path,
-        input_path: options.input_path,
-        preset_id: options.preset_id,
-        advanced: options.advanced,
-        stream_selection: options.stream_selection,
+        output_path: resolve_output_path(&options),
+        ..options
     };
+    // Image sequences go to a directory, which must not hold frames yet
+    if !live && options.advanced.as_ref().is_some_and(|a| a.image_sequence) {
+        prepare_sequence_dir(&options.output_path)?;
+    }
+    
+    // Probe first: per-stream options are validated against the input's streams,
+    // and the duration drives progress
+    let mut input_info =
+        crate::probe::probe_file_with_retry(&options.input_path, ffprobe_path.as_deref(), &options.settings.probe_retry).ok();
+    // HDR10+ detection decodes a frame, so it only runs when the metadata is to be kept
+    if let Some(info) = input_info.as_mut().filter(|_| options.advanced.as_ref().is_some_and(|a| a.preserve_dynamic_hdr)) {
+        crate::probe::detect_hdr10_plus(info, &options.input_path, ffprobe_path.as_deref());
+    }
+    if let Some(info) = input_info.as_mut().filter(|_| is_sequence_path(&options.input_path)) {
+        info.format.duration = sequence_input_duration(&options);
+    }
+    
+    // Trimmed conversions only cover part of the input
+    let input_duration = input_info.as_ref().and_then(|info| info.format.duration);
+    let trimmed = trimmed_duration(options.advanced.as_ref(), input_duration);
     
     // Build ffmpeg arguments first to include in log
-    let args = build_ffmpeg_args(&options)?;
+    let (mut args, retime_factor) = job_args(&options, input_info.as_ref(), trimmed)?;
     let ffmpeg_command = format!("ffmpeg {}", args.join(" "));
     
     // Create advanced options string for logging
@@ -277,21 +4100,31 @@ pub async fn start_conversion(
     conv_log.add_entry(AppLogLevel::Info, "Starting conversion", None);
     
     // Validate input file exists
-    if !std::path::Path::new(&options.input_path).exists() {
+    if check_input_exists(&options.input_path).status == CheckStatus::Error {
         conv_log.add_entry(AppLogLevel::Error, "Input file not found", Some(&options.input_path));
         conv_log.finish(false, Some("Input file not found".to_string()));
         log_store.add_log(conv_log);
         return Err(ConvertError::InputNotFound(options.input_path.clone()));
     }
     
-    // Validate output directory exists
-    if let Some(parent) = std::path::Path::new(&options.output_path).parent() {
-        if !parent.exists() {
-            let err_msg = format!("Output directory does not exist: {}", parent.display());
-            conv_log.add_entry(AppLogLevel::Error, &err_msg, None);
-            conv_log.finish(false, Some(err_msg.clone()));
+    // Validate output directory exists and is writable
+    let output_check = check_output_writable(&options.output_path);
+    if !live && output_check.status == CheckStatus::Error {
+        conv_log.add_entry(AppLogLevel::Error, &output_check.message, None);
+        conv_log.finish(false, Some(output_check.message.clone()));
+        log_store.add_log(conv_log);
+        return Err(ConvertError::InvalidOutputPath(output_check.message));
+    }
+    
+    // Fail now rather than leave a truncated file when the volume clearly can't hold it
+    let space = input_info.as_ref().filter(|_| !live).and_then(|info| output_space(&options, info));
+    if let Some((required, available)) = space {
+        if required > available {
+            let e = ConvertError::InsufficientDiskSpace { required, available };
+            conv_log.add_entry(AppLogLevel::Error, &e.to_string(), None);
+            conv_log.finish(false, Some(e.to_string()));
             log_store.add_log(conv_log);
-            return Err(ConvertError::InvalidOutputPath(err_msg));
+            return Err(e);
         }
     }
     
@@ -302,140 +4135,399 @@ pub async fn start_conversion(
         conv_log.add_entry(AppLogLevel::Debug, "Using system FFmpeg", None);
     }
     
-    // Get input duration for progress calculation
-    let duration = get_duration(&options.input_path, ffprobe_path.as_deref());
-    if let Some(dur) = duration {
+    // Input duration for progress calculation
+    if let Some(dur) = input_duration {
         conv_log.add_entry(AppLogLevel::Info, &format!("Input duration: {:.2}s", dur), None);
     }
+    if trimmed != input_duration {
+        if let Some(dur) = trimmed {
+            conv_log.add_entry(AppLogLevel::Info, &format!("Trimmed duration: {:.2}s", dur), None);
+        }
+    }
+    let mut duration = trimmed;
     
-    let start_time = std::time::Instant::now();
+    let mut warnings = input_info
+        .as_ref()
+        .map(|info| dynamic_hdr_warnings(info, &args))
+        .unwrap_or_default();
+    if options.advanced.as_ref().is_some_and(|a| a.copy_streams) {
+        let blocker = match input_info.as_ref() {
+            Some(info) => stream_copy_blocker(&options, info),
+            None => Some("the input could not be probed".to_string()),
+        };
+        match blocker {
+            Some(reason) => warnings.push(format!("Streams not copied: {}; re-encoding instead", reason)),
+            None => conv_log.add_entry(AppLogLevel::Info, "Copying streams without re-encoding", None),
+        }
+    }
+    if options.advanced.as_ref().is_some_and(|a| a.rotate.is_some() && a.rotate_metadata_only) {
+        warnings.push(format!(
+            "Rotation was stored as metadata without re-encoding ({} only); players that ignore \
+             the display matrix will still show the original orientation",
+            ROTATE_METADATA_CONTAINERS.join("/")
+        ));
+    }
     
-    // If we have a sidecar path, add its directory to PATH so ffmpeg-sidecar can find it
-    if let Some(ref path) = ffmpeg_path {
-        if let Some(parent) = path.parent() {
-            let current_path = std::env::var("PATH").unwrap_or_default();
-            let new_path = format!("{}:{}", parent.display(), current_path);
-            std::env::set_var("PATH", new_path);
+    // Retimed to a fixed output length; progress is then measured against the target
+    let target_duration = options.advanced.as_ref().and_then(|a| a.target_duration_secs);
+    if let (Some(target), Some(factor)) = (target_duration, retime_factor) {
+        conv_log.add_entry(
+            AppLogLevel::Info,
+            &format!("Retiming to {:.3}s (speed {:.3}x)", target, factor),
+            None,
+        );
+        if !(1.0 / MAX_COMFORTABLE_SPEED..=MAX_COMFORTABLE_SPEED).contains(&factor) {
+            warnings.push(format!(
+                "Reaching {:.3}s needs a {:.2}x speed change; quality will suffer noticeably",
+                target, factor
+            ));
         }
+        duration = Some(target);
+    }
+    // A speed change scales the output length, which progress is measured against
+    let speed = options.advanced.as_ref().and_then(|a| a.speed);
+    if let Some(speed) = speed {
+        duration = duration.map(|d| d / speed);
+        let expected = duration.map_or_else(|| "unknown".to_string(), |d| format!("{:.3}s", d));
+        conv_log.add_entry(AppLogLevel::Info, &format!("Speed {:.3}x, output duration {}", speed, expected), None);
+    }
+    
+    for warning in &warnings {
+        conv_log.add_entry(AppLogLevel::Warning, warning, None);
     }
     
-    let mut cmd = FfmpegCommand::new();
+    let file_tags =
+        match apply_measurements(&mut args, &options, input_info.as_ref(), ffmpeg_path.as_deref(), &cancel_flag, &mut conv_log).await {
+            Ok(tags) => tags,
+            Err(e) => {
+                conv_log.add_entry(AppLogLevel::Warning, "Conversion cancelled by user", None);
+                conv_log.finish(false, Some("Cancelled".to_string()));
+                log_store.add_log(conv_log);
+                return Err(e);
+            }
+        };
     
-    for arg in &args {
-        cmd.arg(arg);
+    if let Some(fps) = options.advanced.as_ref().and_then(|a| a.fps) {
+        let source_fps = input_info
+            .as_ref()
+            .and_then(|info| info.streams.iter().find(|s| s.stream_type == StreamType::Video))
+            .and_then(|s| s.frame_rate_fps);
+        let source = source_fps.map_or_else(|| "unknown".to_string(), |f| format!("{:.3}", f));
+        conv_log.add_entry(
+            AppLogLevel::Info,
+            &format!("Frame rate: {} fps -> {:.3} fps", source, fps),
+            None,
+        );
+    }
+    if let Some(target) = options.advanced.as_ref().and_then(|a| a.downmix) {
+        let source = input_info
+            .as_ref()
+            .and_then(|info| info.streams.iter().find(|s| s.stream_type == StreamType::Audio));
+        let layout = source
+            .and_then(|s| s.channel_layout.clone())
+            .unwrap_or_else(|| "unknown layout".to_string());
+        let message = match source.and_then(|s| s.channels) {
+            Some(channels) if channels <= target.channels() => {
+                format!("Audio is already {} ({} channels); no downmix needed", layout, channels)
+            }
+            Some(channels) => format!("Downmixing audio: {} ({} channels) -> {}", layout, channels, target.name()),
+            None => format!("Downmixing audio: {} -> {}", layout, target.name()),
+        };
+        conv_log.add_entry(AppLogLevel::Info, &message, None);
+    }
+    if options.advanced.as_ref().is_some_and(|a| a.tonemap_to_sdr) {
+        let message = if input_info.as_ref().is_some_and(has_hdr_video) {
+            "Tone mapping HDR video to SDR (BT.709)"
+        } else {
+            "Source is not HDR; tone mapping skipped"
+        };
+        conv_log.add_entry(AppLogLevel::Info, message, None);
+    }
+    if let Some((width, height)) = input_info.as_ref().and_then(odd_video_dimensions) {
+        if args.iter().any(|a| a.contains(EVEN_DIMENSIONS_FILTER)) {
+            conv_log.add_entry(
+                AppLogLevel::Info,
+                &format!("Input is {}x{}: rounding down to even dimensions for the encoder", width, height),
+                None,
+            );
+        }
     }
     
-    conv_log.add_entry(AppLogLevel::Info, "Spawning FFmpeg process", None);
+    // Image-sequence jobs count frames; time-based progress is meaningless for them
+    let total_frames = expected_frames(&options, &args, input_info.as_ref(), duration);
+    if let Some(total) = total_frames {
+        conv_log.add_entry(AppLogLevel::Info, &format!("Expecting {} frames", total), None);
+    }
     
-    // Spawn the process
-    let mut child = cmd.spawn().map_err(|e| {
-        let err_msg = format!("Failed to spawn ffmpeg: {}", e);
-        conv_log.add_entry(AppLogLevel::Error, &err_msg, None);
-        conv_log.finish(false, Some(err_msg.clone()));
-        log_store.add_log(conv_log.clone());
-        ConvertError::ConversionFailed(err_msg)
-    })?;
+    let start_time = std::time::Instant::now();
+    let snapshot_interval = options.settings.progress_log_interval_secs;
+    let mut last_snapshot = start_time;
+    let mut last_snapshot_step = 0;
+    let mut eta = EtaEstimator::default();
+    let length_known = !live && (total_frames.is_some() || duration.is_some_and(|d| d > 0.0));
+    // Without a length, the output's growth against the input's size stands in for progress
+    let input_bytes = if live || length_known || is_sequence_path(&options.input_path) {
+        None
+    } else {
+        std::fs::metadata(&options.input_path).ok().map(|m| m.len())
+    };
     
-    // Iterate over events
-    let iter = child.iter().map_err(|e| {
-        let err_msg = format!("Failed to get iterator: {}", e);
-        conv_log.add_entry(AppLogLevel::Error, &err_msg, None);
-        conv_log.finish(false, Some(err_msg.clone()));
-        log_store.add_log(conv_log.clone());
-        ConvertError::ConversionFailed(err_msg)
-    })?;
+    // Two-pass: the same job twice with pass options, sharing one statistics file set
+    let two_pass_logs = options
+        .advanced
+        .as_ref()
+        .filter(|a| a.two_pass)
+        .map(|_| crate::temp::TempFileSet::new(&options.settings, "2pass"));
+    let log_prefix = two_pass_logs.as_ref().map(|logs| logs.prefix_path().to_string_lossy().to_string());
+    let passes = job_passes(&args, log_prefix.as_deref());
+    let pass_count = passes.len();
+    
+    // Wall-clock time spent converting, not counting pauses
+    let paused_before = pause.paused_time();
+    let active_secs = || {
+        let paused = pause.paused_time().saturating_sub(paused_before);
+        start_time.elapsed().saturating_sub(paused).as_secs_f64()
+    };
     
-    let mut last_error: Option<String> = None;
     let mut warning_count = 0;
     let mut error_count = 0;
+    let mut stopping = false;
+    let max_retries = options.settings.max_retries;
+    let mut attempt = 0;
+    // When the current attempt began, in active seconds; the ETA only looks at this attempt
+    let mut attempt_started = 0.0;
     
-    for event in iter {
-        // Check cancellation
-        if cancel_flag.load(Ordering::Relaxed) {
-            child.kill().ok();
-            conv_log.add_entry(AppLogLevel::Warning, "Conversion cancelled by user", None);
-            conv_log.finish(false, Some("Cancelled".to_string()));
-            log_store.add_log(conv_log);
-            return Err(ConvertError::Cancelled);
-        }
+    // Spawn failures and transient ffmpeg errors start the job over, with growing delays
+    let (status, last_error) = loop {
+        let mut last_error: Option<String> = None;
+        let mut status = None;
+        let mut spawn_error = None;
         
-        match event {
-            FfmpegEvent::Progress(progress) => {
-                // Parse time from string format "HH:MM:SS.ms"
-                let time_secs = parse_time_str(&progress.time);
-                let percent = if let Some(dur) = duration {
-                    if dur > 0.0 {
-                        (time_secs / dur * 100.0).min(100.0)
-                    } else {
-                        0.0
+        for (pass, pass_args) in passes.iter().enumerate() {
+            let mut cmd = sidecar_command(ffmpeg_path.as_deref());
+            
+            for arg in pass_args {
+                cmd.arg(arg);
+            }
+            
+            if pass_count > 1 {
+                conv_log.add_entry(
+                    AppLogLevel::Info,
+                    &format!("Pass {} of {}", pass + 1, pass_count),
+                    Some(&format!("ffmpeg {}", pass_args.join(" "))),
+                );
+            }
+            conv_log.add_entry(AppLogLevel::Info, "Spawning FFmpeg process", None);
+            
+            // Spawn the process
+            let mut child = match cmd.spawn() {
+                Ok(child) => child,
+                Err(e) => {
+                    spawn_error = Some(e);
+                    break;
+                }
+            };
+            
+            // pause_convert/resume_convert act on this process until the pass ends
+            let _attached = pause.attach(child.as_inner().id());
+            
+            // Iterate over events
+            let iter = child.iter().map_err(|e| {
+                let err_msg = format!("Failed to get iterator: {}", e);
+                conv_log.add_entry(AppLogLevel::Error, &err_msg, None);
+                conv_log.finish(false, Some(err_msg.clone()));
+                log_store.add_log(conv_log.clone());
+                ConvertError::ConversionFailed(err_msg)
+            })?;
+            
+            for event in iter {
+                // A stop asks ffmpeg to quit ('q' on stdin) so it finishes the file properly.
+                // Only the last pass writes the output; stopping an earlier one is a cancel.
+                if !stopping && stop_flag.load(Ordering::Relaxed) && pass + 1 == pass_count {
+                    stopping = true;
+                    match child.quit() {
+                        Ok(()) => conv_log.add_entry(AppLogLevel::Warning, "Stopped by user; finalizing the output", None),
+                        Err(e) => {
+                            conv_log.add_entry(AppLogLevel::Warning, &format!("Could not stop ffmpeg cleanly: {}", e), None);
+                            cancel_flag.store(true, Ordering::Relaxed);
+                        }
+                    }
+                }
+                // Check cancellation
+                if cancel_flag.load(Ordering::Relaxed) || (stop_flag.load(Ordering::Relaxed) && !stopping) {
+                    child.kill().ok();
+                    conv_log.add_entry(AppLogLevel::Warning, "Conversion cancelled by user", None);
+                    conv_log.finish(false, Some("Cancelled".to_string()));
+                    log_store.add_log(conv_log);
+                    return Err(ConvertError::Cancelled);
+                }
+            
+                match event {
+                    FfmpegEvent::Progress(progress) => {
+                        // Lines ffmpeg wrote just before being suspended
+                        if pause.is_paused() {
+                            continue;
+                        }
+                        // No usable time yet: skip rather than flash the bar back to 0%
+                        let Some(time_secs) = parse_time_str(&progress.time) else {
+                            continue;
+                        };
+                        let pass_percent = if !length_known {
+                            input_bytes.and_then(|bytes| size_growth_percent(progress.size_kb as u64, bytes))
+                        } else if let Some(total) = total_frames {
+                            Some((progress.frame as f64 / total as f64 * 100.0).min(100.0))
+                        } else {
+                            duration.map(|dur| (time_secs / dur * 100.0).min(100.0))
+                        };
+                        // Passes share the bar: 0-50% for the first of two, 50-100% for the second
+                        let percent = pass_percent.map(|p| (pass as f64 * 100.0 + p) / pass_count as f64);
+                    
+                        let progress_event = ConvertProgress {
+                            job_id: options.job_id,
+                            percent,
+                            time_secs,
+                            speed: if progress.speed > 0.0 { Some(format!("{:.2}x", progress.speed)) } else { None },
+                            bitrate: if progress.bitrate_kbps > 0.0 { Some(format!("{:.0} kbps", progress.bitrate_kbps)) } else { None },
+                            size_kb: Some(progress.size_kb as u64),
+                            frame: Some(progress.frame as u64),
+                            total_frames,
+                            live,
+                            eta_secs: percent.and_then(|p| eta.update(active_secs() - attempt_started, p)),
+                        };
+                    
+                        // Periodic snapshot in the log (every 10% and every `snapshot_interval` seconds)
+                        // so slowdowns can be diagnosed after the fact
+                        let step = percent.map_or(0, |p| (p / 10.0) as u32);
+                        let interval_elapsed = snapshot_interval > 0
+                            && last_snapshot.elapsed().as_secs() >= snapshot_interval;
+                        if step > last_snapshot_step || interval_elapsed {
+                            conv_log.add_entry(
+                                AppLogLevel::Info,
+                                &format!(
+                                    "Progress {}: time={} speed={} bitrate={}",
+                                    percent.map_or("N/A".to_string(), |p| format!("{:.0}%", p)),
+                                    progress.time,
+                                    progress_event.speed.as_deref().unwrap_or("N/A"),
+                                    progress_event.bitrate.as_deref().unwrap_or("N/A"),
+                                ),
+                                Some("Progress"),
+                            );
+                            last_snapshot_step = step;
+                            last_snapshot = std::time::Instant::now();
+                        }
+                    
+                        let _ = app_handle.emit("convert-progress", &progress_event);
                     }
-                } else {
-                    0.0
-                };
-                
-                let progress_event = ConvertProgress {
-                    percent,
-                    time_secs,
-                    speed: if progress.speed > 0.0 { Some(format!("{:.2}x", progress.speed)) } else { None },
-                    bitrate: if progress.bitrate_kbps > 0.0 { Some(format!("{:.0} kbps", progress.bitrate_kbps)) } else { None },
-                    size_kb: Some(progress.size_kb as u64),
-                };
-                
-                let _ = app_handle.emit("convert-progress", &progress_event);
-            }
-            FfmpegEvent::Log(level, msg) => {
-                match level {
-                    LogLevel::Error | LogLevel::Fatal => {
-                        error_count += 1;
-                        conv_log.add_entry(AppLogLevel::Error, &msg, Some("FFmpeg"));
-                        last_error = Some(msg);
+                    FfmpegEvent::Log(level, msg) => {
+                        match level {
+                            LogLevel::Error | LogLevel::Fatal => {
+                                error_count += 1;
+                                conv_log.add_entry(AppLogLevel::Error, &msg, Some("FFmpeg"));
+                                last_error = Some(msg);
+                            }
+                            LogLevel::Warning => {
+                                warning_count += 1;
+                                conv_log.add_entry(AppLogLevel::Warning, &msg, Some("FFmpeg"));
+                            }
+                            LogLevel::Info => {
+                                conv_log.add_entry(AppLogLevel::Info, &msg, Some("FFmpeg"));
+                            }
+                            _ => {
+                                // Log debug/verbose messages as debug
+                                conv_log.add_entry(AppLogLevel::Debug, &msg, Some("FFmpeg"));
+                            }
+                        }
                     }
-                    LogLevel::Warning => {
-                        warning_count += 1;
-                        conv_log.add_entry(AppLogLevel::Warning, &msg, Some("FFmpeg"));
+                    FfmpegEvent::ParsedVersion(v) => {
+                        conv_log.add_entry(AppLogLevel::Info, &format!("FFmpeg version: {}", v.version), None);
                     }
-                    LogLevel::Info => {
-                        conv_log.add_entry(AppLogLevel::Info, &msg, Some("FFmpeg"));
+                    FfmpegEvent::ParsedConfiguration(config) => {
+                        conv_log.add_entry(AppLogLevel::Debug, &format!("FFmpeg config: {:?}", config), None);
                     }
-                    _ => {
-                        // Log debug/verbose messages as debug
-                        conv_log.add_entry(AppLogLevel::Debug, &msg, Some("FFmpeg"));
+                    FfmpegEvent::ParsedInput(input) => {
+                        conv_log.add_entry(AppLogLevel::Info, &format!("Input #{}: duration={:?}s", input.index, input.duration), None);
                     }
+                    FfmpegEvent::ParsedOutput(output) => {
+                        conv_log.add_entry(AppLogLevel::Info, &format!("Output #{}: {}", output.index, output.to), None);
+                    }
+                    FfmpegEvent::ParsedStreamMapping(mapping) => {
+                        conv_log.add_entry(AppLogLevel::Debug, &format!("Stream mapping: {}", mapping), None);
+                    }
+                    FfmpegEvent::Done => {
+                        conv_log.add_entry(AppLogLevel::Info, "FFmpeg process completed", None);
+                        break;
+                    }
+                    _ => {}
                 }
             }
-            FfmpegEvent::ParsedVersion(v) => {
-                conv_log.add_entry(AppLogLevel::Info, &format!("FFmpeg version: {}", v.version), None);
-            }
-            FfmpegEvent::ParsedConfiguration(config) => {
-                conv_log.add_entry(AppLogLevel::Debug, &format!("FFmpeg config: {:?}", config), None);
-            }
-            FfmpegEvent::ParsedInput(input) => {
-                conv_log.add_entry(AppLogLevel::Info, &format!("Input #{}: duration={:?}s", input.index, input.duration), None);
-            }
-            FfmpegEvent::ParsedOutput(output) => {
-                conv_log.add_entry(AppLogLevel::Info, &format!("Output #{}: {}", output.index, output.to), None);
+            
+            // Wait for process to finish
+            let pass_status = child.wait().map_err(|e| {
+                let err_msg = format!("Failed to wait for ffmpeg: {}", e);
+                conv_log.add_entry(AppLogLevel::Error, &err_msg, None);
+                conv_log.finish(false, Some(err_msg.clone()));
+                log_store.add_log(conv_log.clone());
+                ConvertError::ConversionFailed(err_msg)
+            })?;
+            status = Some(pass_status);
+            if !pass_status.success() {
+                break;
             }
-            FfmpegEvent::ParsedStreamMapping(mapping) => {
-                conv_log.add_entry(AppLogLevel::Debug, &format!("Stream mapping: {}", mapping), None);
+        }
+        
+        let failure = if let Some(e) = spawn_error {
+            let err_msg = format!("Failed to spawn ffmpeg: {}", e);
+            conv_log.add_entry(AppLogLevel::Error, &err_msg, None);
+            // A missing binary won't show up by waiting
+            if e.kind() == std::io::ErrorKind::NotFound || attempt >= max_retries {
+                conv_log.finish(false, Some(err_msg.clone()));
+                log_store.add_log(conv_log);
+                return Err(ConvertError::ConversionFailed(err_msg));
             }
-            FfmpegEvent::Done => {
-                conv_log.add_entry(AppLogLevel::Info, "FFmpeg process completed", None);
-                break;
+            err_msg
+        } else {
+            let status = status.expect("at least one pass runs");
+            let transient = last_error.as_deref().is_some_and(is_transient_error);
+            if status.success() || stopping || !transient || attempt >= max_retries {
+                break (status, last_error);
             }
-            _ => {}
+            last_error.unwrap_or_default()
+        };
+        
+        attempt += 1;
+        let delay = retry_delay(attempt);
+        conv_log.add_entry(
+            AppLogLevel::Warning,
+            &format!("Retrying {}/{} in {}s", attempt, max_retries, delay.as_secs()),
+            Some(&failure),
+        );
+        let _ = app_handle.emit("convert-retry", &ConvertRetry {
+            job_id: options.job_id,
+            attempt,
+            max_retries,
+            delay_secs: delay.as_secs(),
+            reason: failure,
+        });
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = interrupt_requested(&cancel_flag, &stop_flag) => {}
         }
-    }
-    
-    // Wait for process to finish
-    let status = child.wait().map_err(|e| {
-        let err_msg = format!("Failed to wait for ffmpeg: {}", e);
-        conv_log.add_entry(AppLogLevel::Error, &err_msg, None);
-        conv_log.finish(false, Some(err_msg.clone()));
-        log_store.add_log(conv_log.clone());
-        ConvertError::ConversionFailed(err_msg)
-    })?;
+        if cancel_flag.load(Ordering::Relaxed) || stop_flag.load(Ordering::Relaxed) {
+            conv_log.add_entry(AppLogLevel::Warning, "Conversion cancelled by user", None);
+            conv_log.finish(false, Some("Cancelled".to_string()));
+            log_store.add_log(conv_log);
+            return Err(ConvertError::Cancelled);
+        }
+        
+        // The summary and the ETA describe the attempt that counts, not the failed ones
+        warning_count = 0;
+        error_count = 0;
+        eta = EtaEstimator::default();
+        last_snapshot_step = 0;
+        attempt_started = active_secs();
+    };
     
-    let elapsed = start_time.elapsed().as_secs_f64();
+    let elapsed = active_secs();
     
     // Log summary
     conv_log.add_entry(AppLogLevel::Info, &format!("Conversion took {:.2}s", elapsed), None);
@@ -446,16 +4538,100 @@ pub async fn start_conversion(
         conv_log.add_entry(AppLogLevel::Info, &format!("Total errors: {}", error_count), None);
     }
     
+    // Image-sequence output is measured in files written rather than by probing
+    let frames_written = if is_sequence_path(&options.output_path) {
+        count_sequence_files(&options.output_path)
+    } else {
+        None
+    };
+    
+    if stopping {
+        let output_exists = match frames_written {
+            Some(frames) => frames > 0,
+            None => std::fs::metadata(&options.output_path).is_ok_and(|m| m.len() > 0),
+        };
+        if !live && !output_exists {
+            conv_log.add_entry(AppLogLevel::Warning, "Stopped before any output was written", None);
+            conv_log.finish(false, Some("Cancelled".to_string()));
+            log_store.add_log(conv_log);
+            return Err(ConvertError::Cancelled);
+        }
+        let output_duration = if live || frames_written.is_some() {
+            None
+        } else {
+            crate::probe::probe_file(&options.output_path, ffprobe_path.as_deref())
+                .ok()
+                .and_then(|info| info.format.duration)
+        };
+        let message = match output_duration {
+            Some(secs) => format!("Stopped early; the output covers the first {:.1}s", secs),
+            None => "Stopped early; the output is incomplete".to_string(),
+        };
+        conv_log.add_entry(AppLogLevel::Info, &message, Some(&options.output_path));
+        conv_log.finish(true, None);
+        log_store.add_log(conv_log);
+        
+        let result = ConvertResult {
+            success: true,
+            output_path: options.output_path,
+            duration_secs: elapsed,
+            message: Some(message),
+            output_duration_secs: output_duration,
+            stopped: true,
+            frames_written,
+        };
+        let _ = app_handle.emit("convert-done", &result);
+        return Ok(result);
+    }
+    
     if status.success() {
         conv_log.add_entry(AppLogLevel::Info, "Conversion successful", None);
+        if !file_tags.is_empty() {
+            match crate::mp4tags::write_freeform_tags(std::path::Path::new(&options.output_path), &file_tags) {
+                Ok(()) => conv_log.add_entry(AppLogLevel::Info, "ReplayGain tags written", None),
+                Err(e) => conv_log.add_entry(AppLogLevel::Warning, &format!("ReplayGain tags not written: {}", e), None),
+            }
+        }
+        
+        let mut output_path = options.output_path;
+        if !live && frames_written.is_none() && options.advanced.as_ref().is_some_and(|a| a.replace_original) {
+            match replace_original(&options.input_path, &output_path) {
+                Ok((backup_path, final_path)) => {
+                    conv_log.add_entry(AppLogLevel::Info, "Original moved to backup", Some(&backup_path));
+                    conv_log.backup_path = Some(backup_path);
+                    conv_log.output_path = final_path.clone();
+                    output_path = final_path;
+                }
+                Err(e) => {
+                    conv_log.add_entry(AppLogLevel::Warning, &format!("Could not replace original: {}", e), None);
+                }
+            }
+        }
+        // Report how long the retimed output actually is (container rounding can shift it slightly)
+        let output_duration = if (target_duration.is_some() || speed.is_some()) && frames_written.is_none() {
+            crate::probe::probe_file(&output_path, ffprobe_path.as_deref())
+                .ok()
+                .and_then(|info| info.format.duration)
+        } else {
+            None
+        };
+        if let Some(actual) = output_duration {
+            conv_log.add_entry(AppLogLevel::Info, &format!("Output duration: {:.3}s", actual), None);
+        }
+        if let Some(frames) = frames_written {
+            conv_log.add_entry(AppLogLevel::Info, &format!("Wrote {} frames", frames), None);
+        }
         conv_log.finish(true, None);
         log_store.add_log(conv_log);
         
         let result = ConvertResult {
             success: true,
-            output_path: options.output_path,
+            output_path,
             duration_secs: elapsed,
-            message: None,
+            message: if warnings.is_empty() { None } else { Some(warnings.join("\n")) },
+            output_duration_secs: output_duration,
+            stopped: false,
+            frames_written,
         };
         let _ = app_handle.emit("convert-done", &result);
         Ok(result)
@@ -466,15 +4642,80 @@ pub async fn start_conversion(
         log_store.add_log(conv_log);
         
         let _ = app_handle.emit("convert-error", &error_msg);
+        if live && is_connection_error(&error_msg) {
+            return Err(ConvertError::StreamConnectionFailed(error_msg));
+        }
         Err(ConvertError::ConversionFailed(error_msg))
     }
 }
 
+/// Move the original to a `.bak` backup and put the output where the original was
+/// (keeping the output's extension). Returns (backup path, final output path).
+fn replace_original(input_path: &str, output_path: &str) -> std::io::Result<(String, String)> {
+    let backup_path = ensure_unique_output_path(&format!("{}.bak", input_path));
+    std::fs::rename(input_path, &backup_path)?;
+    
+    let input = std::path::Path::new(input_path);
+    let extension = std::path::Path::new(output_path)
+        .extension()
+        .map(|e| e.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let final_path = input.with_extension(extension);
+    if final_path.exists() {
+        // Another file already has that name: leave the output where it was written
+        return Ok((backup_path, output_path.to_string()));
+    }
+    if let Err(e) = std::fs::rename(output_path, &final_path) {
+        // Put the original back rather than leave only a backup behind
+        std::fs::rename(&backup_path, input_path)?;
+        return Err(e);
+    }
+    Ok((backup_path, final_path.to_string_lossy().to_string()))
+}
+
+/// Undo `replace_original`: delete the converted file and move the backup back
+pub fn restore_original(input_path: &str, output_path: &str, backup_path: &str) -> Result<(), ConvertError> {
+    if !std::path::Path::new(backup_path).exists() {
+        return Err(ConvertError::InputNotFound(backup_path.to_string()));
+    }
+    if std::path::Path::new(input_path).exists() && input_path != output_path {
+        return Err(ConvertError::InvalidOutputPath(format!(
+            "Cannot restore, a file already exists at {}",
+            input_path
+        )));
+    }
+    if std::path::Path::new(output_path).exists() {
+        std::fs::remove_file(output_path)
+            .map_err(|e| ConvertError::ConversionFailed(format!("Failed to remove converted file: {}", e)))?;
+    }
+    std::fs::rename(backup_path, input_path)
+        .map_err(|e| ConvertError::ConversionFailed(format!("Failed to restore original: {}", e)))
+}
+
 /// If the given output path already exists, return a unique path with _01, _02, ... suffix.
 /// Otherwise return the path unchanged.
 pub fn ensure_unique_output_path(path: &str) -> String {
+    unique_output_path(path, &HashSet::new())
+}
+
+/// Pick the output path a conversion will write and reserve it, so a conversion running
+/// alongside with the same requested output gets the next free name instead. Stream URLs
+/// and image sequences (which pick their own directory) keep the requested path.
+pub fn reserve_output_path(options: &ConvertOptions, reserved: &mut HashSet<String>) -> String {
+    let path = if is_stream_url(&options.output_path) || options.advanced.as_ref().is_some_and(|a| a.image_sequence) {
+        options.output_path.clone()
+    } else {
+        unique_output_path(&options.output_path, reserved)
+    };
+    reserved.insert(path.clone());
+    path
+}
+
+/// Like `ensure_unique_output_path`, also counting paths in `reserved` as taken
+fn unique_output_path(path: &str, reserved: &HashSet<String>) -> String {
+    let is_taken = |p: &std::path::Path| p.exists() || reserved.contains(p.to_string_lossy().as_ref());
     let path_obj = std::path::Path::new(path);
-    if !path_obj.exists() {
+    if !is_taken(path_obj) {
         return path.to_string();
     }
     let parent = path_obj.parent().unwrap_or(std::path::Path::new("."));
@@ -485,19 +4726,52 @@ pub fn ensure_unique_output_path(path: &str) -> String {
         .unwrap_or_else(|| "mp4".to_string());
     for n in 1..=9999 {
         let candidate = parent.join(format!("{}_{:02}.{}", stem, n, extension));
-        if !candidate.exists() {
+        if !is_taken(&candidate) {
             return candidate.to_string_lossy().to_string();
         }
     }
     path.to_string()
 }
 
+/// Naming template used when none is given
+const DEFAULT_NAME_TEMPLATE: &str = "{name}_Convertified";
+
+/// Output file name (without extension) from a naming template, where `{name}` is the
+/// input's file name and `{preset}` the preset id. Path separators are replaced so the
+/// file stays in the output directory; a blank result falls back to the default template.
+pub fn output_file_stem(name_template: Option<&str>, input_stem: &str, preset_id: Option<&str>) -> String {
+    let render = |template: &str| {
+        template
+            .replace("{preset}", preset_id.unwrap_or(""))
+            .replace("{name}", input_stem)
+            .replace(['/', '\\'], "_")
+            .trim()
+            .to_string()
+    };
+    match name_template.map(render) {
+        Some(stem) if !stem.is_empty() && stem != "." && stem != ".." => stem,
+        _ => render(DEFAULT_NAME_TEMPLATE),
+    }
+}
+
 /// Generate output path from input path and preset/format
-/// Uses "_Convertified" postfix and adds _01, _02 if file exists
-pub fn generate_output_path(input_path: &str, preset_id: Option<&str>, format: Option<&str>) -> String {
+/// Named by `name_template` (default "{name}_Convertified") and adds _01, _02 if file exists.
+/// The file goes next to the input unless `output_dir` is given; paths in
+/// `reserved` (outputs of in-flight or planned conversions) count as taken.
+pub fn generate_output_path(
+    input_path: &str,
+    preset_id: Option<&str>,
+    format: Option<&str>,
+    output_dir: Option<&str>,
+    name_template: Option<&str>,
+    reserved: &HashSet<String>,
+) -> String {
     let path = std::path::Path::new(input_path);
     let stem = path.file_stem().unwrap_or_default().to_string_lossy();
-    let parent = path.parent().unwrap_or(std::path::Path::new("."));
+    let parent = match output_dir {
+        Some(dir) => std::path::Path::new(dir),
+        None => path.parent().unwrap_or(std::path::Path::new(".")),
+    };
     
     let extension = if let Some(preset_id) = preset_id {
         find_preset(preset_id)
@@ -509,26 +4783,210 @@ pub fn generate_output_path(input_path: &str, preset_id: Option<&str>, format: O
         "mp4".to_string()
     };
     
+    let is_taken = |p: &std::path::Path| p.exists() || reserved.contains(p.to_string_lossy().as_ref());
+    
     // Try base name first
-    let base_output = parent.join(format!("{}_Convertified.{}", stem, extension));
-    if !base_output.exists() {
+    let stem = output_file_stem(name_template, &stem, preset_id);
+    let base_output = parent.join(format!("{}.{}", stem, extension));
+    if !is_taken(&base_output) {
         return base_output.to_string_lossy().to_string();
     }
     
     // If exists, add _01, _02, ...
     for n in 1..=9999 {
         let suffix = format!("_{:02}", n);
-        let output_path = parent.join(format!("{}_Convertified{}.{}", stem, suffix, extension));
-        if !output_path.exists() {
+        let output_path = parent.join(format!("{}{}.{}", stem, suffix, extension));
+        if !is_taken(&output_path) {
             return output_path.to_string_lossy().to_string();
         }
     }
     parent
-        .join(format!("{}_Convertified_99.{}", stem, extension))
+        .join(format!("{}_99.{}", stem, extension))
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Output pattern for image-sequence output: `frame_%05d.<ext>` in a new
+/// `<name>_Convertified_frames` directory (`_01`, `_02`, ... if taken) next to the input,
+/// or in `output_dir`. The extension follows the preset, PNG by default.
+pub fn generate_sequence_output_path(
+    input_path: &str,
+    preset_id: Option<&str>,
+    output_dir: Option<&str>,
+    reserved: &HashSet<String>,
+) -> String {
+    let path = std::path::Path::new(input_path);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let parent = match output_dir {
+        Some(dir) => std::path::Path::new(dir),
+        None => path.parent().unwrap_or(std::path::Path::new(".")),
+    };
+    let extension = preset_id
+        .and_then(find_preset)
+        .map_or_else(|| "png".to_string(), |p| p.extension);
+    let file_name = format!("{}.{}", SEQUENCE_FILE_STEM, extension);
+    
+    let candidates = std::iter::once(String::new()).chain((1..=9999).map(|n| format!("_{:02}", n)));
+    for suffix in candidates {
+        let dir = parent.join(format!("{}_Convertified_frames{}", stem, suffix));
+        let pattern = dir.join(&file_name).to_string_lossy().to_string();
+        if !dir.exists() && !reserved.contains(&pattern) {
+            return pattern;
+        }
+    }
+    parent
+        .join(format!("{}_Convertified_frames_99", stem))
+        .join(file_name)
         .to_string_lossy()
         .to_string()
 }
 
+/// An input file paired with the output path a batch run will write
+#[derive(Debug, Clone, Serialize)]
+pub struct PlannedOutput {
+    pub input_path: String,
+    pub output_path: String,
+}
+
+/// Compute the output path of every input in a batch, in order.
+/// Each planned path is reserved so later inputs with the same name get _01, _02, ...
+pub fn plan_batch_outputs(
+    inputs: &[String],
+    preset_id: Option<&str>,
+    output_dir: Option<&str>,
+    name_template: Option<&str>,
+    in_flight: &HashSet<String>,
+) -> Vec<PlannedOutput> {
+    let mut reserved = in_flight.clone();
+    inputs
+        .iter()
+        .map(|input| {
+            let output_path = generate_output_path(input, preset_id, None, output_dir, name_template, &reserved);
+            reserved.insert(output_path.clone());
+            PlannedOutput {
+                input_path: input.clone(),
+                output_path,
+            }
+        })
+        .collect()
+}
+
+/// Encoders each output container can hold. Kept as data so it is easy to extend;
+/// `codecs_for_container` intersects it with what the ffmpeg build actually has.
+struct ContainerCodecs {
+    formats: &'static [&'static str],
+    video: &'static [&'static str],
+    audio: &'static [&'static str],
+    subtitle: &'static [&'static str],
+}
+
+const CONTAINER_CODECS: &[ContainerCodecs] = &[
+    ContainerCodecs {
+        formats: &["mp4", "mov", "m4v"],
+        video: &[
+            "libx264", "libx265", "h264_nvenc", "hevc_nvenc", "h264_qsv", "hevc_qsv",
+            "h264_videotoolbox", "hevc_videotoolbox", "libaom-av1", "libsvtav1", "av1_nvenc",
+            "libvpx-vp9", "mpeg4", "prores_ks", "mjpeg",
+        ],
+        audio: &["aac", "libfdk_aac", "libmp3lame", "ac3", "eac3", "alac", "libopus", "pcm_s16le", "pcm_s24le"],
+        subtitle: &["mov_text"],
+    },
+    ContainerCodecs {
+        formats: &["matroska", "mkv"],
+        video: &[
+            "libx264", "libx265", "h264_nvenc", "hevc_nvenc", "h264_qsv", "hevc_qsv",
+            "h264_videotoolbox", "hevc_videotoolbox", "libaom-av1", "libsvtav1", "av1_nvenc",
+            "libvpx", "libvpx-vp9", "mpeg4", "mpeg2video", "prores_ks", "ffv1", "mjpeg",
+        ],
+        audio: &[
+            "aac", "libfdk_aac", "libmp3lame", "ac3", "eac3", "flac", "alac", "libopus", "libvorbis",
+            "pcm_s16le", "pcm_s24le", "pcm_f32le",
+        ],
+        subtitle: &["ass", "srt", "subrip", "webvtt"],
+    },
+    ContainerCodecs {
+        formats: &["webm"],
+        video: &["libvpx", "libvpx-vp9", "libaom-av1", "libsvtav1"],
+        audio: &["libopus", "libvorbis"],
+        subtitle: &["webvtt"],
+    },
+    ContainerCodecs {
+        formats: &["avi"],
+        video: &["mpeg4", "libx264", "mjpeg", "ffv1"],
+        audio: &["libmp3lame", "ac3", "pcm_s16le"],
+        subtitle: &[],
+    },
+    ContainerCodecs {
+        formats: &["mpegts", "ts"],
+        video: &["libx264", "libx265", "h264_nvenc", "hevc_nvenc", "mpeg2video"],
+        audio: &["aac", "libmp3lame", "ac3", "eac3", "libopus"],
+        subtitle: &["dvb_subtitle"],
+    },
+    ContainerCodecs {
+        formats: &["mp3"],
+        video: &[],
+        audio: &["libmp3lame"],
+        subtitle: &[],
+    },
+    ContainerCodecs {
+        formats: &["ipod", "m4a"],
+        video: &[],
+        audio: &["aac", "libfdk_aac", "alac"],
+        subtitle: &[],
+    },
+    ContainerCodecs {
+        formats: &["flac"],
+        video: &[],
+        audio: &["flac"],
+        subtitle: &[],
+    },
+    ContainerCodecs {
+        formats: &["ogg", "opus"],
+        video: &[],
+        audio: &["libopus", "libvorbis", "flac"],
+        subtitle: &[],
+    },
+    ContainerCodecs {
+        formats: &["wav"],
+        video: &[],
+        audio: &["pcm_u8", "pcm_s16le", "pcm_s24le", "pcm_s32le", "pcm_f32le", "pcm_f64le"],
+        subtitle: &[],
+    },
+];
+
+/// Encoders usable for each stream type in a given container
+#[derive(Debug, Clone, Serialize)]
+pub struct ContainerCodecSupport {
+    pub format: String,
+    pub video: Vec<String>,
+    pub audio: Vec<String>,
+    pub subtitle: Vec<String>,
+}
+
+/// Encoders that are both valid in `format` and present in `available` (from `list_encoders`)
+pub fn codecs_for_container(format: &str, available: &[EncoderInfo]) -> Option<ContainerCodecSupport> {
+    let entry = CONTAINER_CODECS.iter().find(|c| c.formats.contains(&format))?;
+    let filter = |names: &[&str]| -> Vec<String> {
+        names
+            .iter()
+            .filter(|n| available.iter().any(|e| e.name == **n))
+            .map(|n| n.to_string())
+            .collect()
+    };
+    Some(ContainerCodecSupport {
+        format: format.to_string(),
+        video: filter(entry.video),
+        audio: filter(entry.audio),
+        subtitle: filter(entry.subtitle),
+    })
+}
+
+/// Whether `container` is known to accept `codec`; `None` when the container is not in the table
+pub fn container_accepts(container: &str, codec: &str) -> Option<bool> {
+    let entry = CONTAINER_CODECS.iter().find(|c| c.formats.contains(&container))?;
+    Some([entry.video, entry.audio, entry.subtitle].iter().any(|names| names.contains(&codec)))
+}
+
 /// Map format to common extension
 fn format_to_extension(format: &str) -> String {
     match format {
@@ -553,8 +5011,711 @@ fn format_to_extension(format: &str) -> String {
         "image2" | "png" => "png".to_string(),
         "mjpeg" | "jpeg" | "jpg" => "jpg".to_string(),
         "webp" => "webp".to_string(),
+        "apng" => "apng".to_string(),
+        "avif" => "avif".to_string(),
         "rawvideo" => "raw".to_string(),
         "null" => "null".to_string(),
         _ => format.to_string(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(input_path: &str, output_path: &str) -> ConvertOptions {
+        ConvertOptions {
+            input_path: input_path.to_string(),
+            output_path: output_path.to_string(),
+            preset_id: None,
+            advanced: None,
+            stream_selection: None,
+            settings: AppSettings::default(),
+            job_id: 0,
+        }
+    }
+
+    fn has_pair(args: &[String], flag: &str, value: &str) -> bool {
+        args.windows(2).any(|w| w[0] == flag && w[1] == value)
+    }
+
+    fn video_info(width: u32, height: u32) -> MediaInfo {
+        let json = format!(
+            r#"{{"streams": [{{"index": 0, "codec_type": "video", "codec_name": "h264", "width": {}, "height": {}}}],
+                "format": {{"duration": "10.0"}}}}"#,
+            width, height,
+        );
+        crate::probe::parse_probe_output(&json, "in.mov").unwrap()
+    }
+
+    fn even_filter_count(args: &[String]) -> usize {
+        args.iter().filter(|a| a.contains(EVEN_DIMENSIONS_FILTER)).count()
+    }
+
+    #[test]
+    fn prepend_to_path_uses_platform_separator() {
+        let sep = if cfg!(windows) { ";" } else { ":" };
+        let current = std::ffi::OsString::from(format!("/usr/bin{sep}/bin"));
+        let path = prepend_to_path(std::path::Path::new("/opt/sidecar"), Some(current)).unwrap();
+        assert_eq!(path, std::ffi::OsString::from(format!("/opt/sidecar{sep}/usr/bin{sep}/bin")));
+    }
+
+    #[test]
+    fn prepend_to_path_drops_duplicates_and_handles_empty_path() {
+        let sep = if cfg!(windows) { ";" } else { ":" };
+        let current = std::ffi::OsString::from(format!("/usr/bin{sep}/opt/sidecar"));
+        let path = prepend_to_path(std::path::Path::new("/opt/sidecar"), Some(current)).unwrap();
+        assert_eq!(path, std::ffi::OsString::from(format!("/opt/sidecar{sep}/usr/bin")));
+        assert_eq!(prepend_to_path(std::path::Path::new("/opt/sidecar"), None).unwrap(), "/opt/sidecar");
+        // Windows quotes such an entry instead of refusing it
+        if cfg!(unix) {
+            assert_eq!(prepend_to_path(std::path::Path::new("/opt:sidecar"), None), None);
+        }
+    }
+
+    #[test]
+    fn sidecar_command_leaves_global_path_alone() {
+        let before = std::env::var_os("PATH");
+        let sidecar = std::path::Path::new("/opt/convertify/bin/ffmpeg");
+        let cmd = sidecar_command(Some(sidecar));
+        let inner = cmd.as_inner();
+
+        assert_eq!(std::env::var_os("PATH"), before);
+        assert_eq!(inner.get_program(), sidecar.as_os_str());
+        let child_path = inner
+            .get_envs()
+            .find(|(key, _)| *key == "PATH")
+            .and_then(|(_, value)| value)
+            .expect("child PATH is set");
+        assert_eq!(std::env::split_paths(child_path).next().unwrap(), sidecar.parent().unwrap());
+    }
+
+    #[test]
+    fn sidecar_command_without_sidecar_uses_inherited_path() {
+        let cmd = sidecar_command(None);
+        assert_eq!(cmd.as_inner().get_program(), "ffmpeg");
+        assert_eq!(cmd.as_inner().get_envs().count(), 0);
+    }
+
+    #[test]
+    fn time_str_rejects_unavailable_times() {
+        assert_eq!(parse_time_str("N/A"), None);
+        assert_eq!(parse_time_str(""), None);
+        assert_eq!(parse_time_str("   "), None);
+    }
+
+    #[test]
+    fn time_str_rejects_negative_times() {
+        assert_eq!(parse_time_str("-00:00:00.000"), None);
+        assert_eq!(parse_time_str("-1.5"), None);
+    }
+
+    #[test]
+    fn time_str_accepts_minutes_and_seconds() {
+        assert_eq!(parse_time_str("01:30"), Some(90.0));
+        assert_eq!(parse_time_str("00:01:30.50"), Some(90.5));
+    }
+
+    #[test]
+    fn time_str_keeps_extra_decimals() {
+        let secs = parse_time_str("00:00:02.123456").unwrap();
+        assert!((secs - 2.123456).abs() < 1e-9);
+    }
+
+    #[test]
+    fn extra_args_unescape_quotes() {
+        assert_eq!(parse_extra_args(r#"-metadata title="Say \"hi\"""#), ["-metadata", r#"title=Say "hi""#]);
+        assert_eq!(parse_extra_args(r"-metadata 'title=It\'s'"), ["-metadata", "title=It's"]);
+        assert_eq!(parse_extra_args(r"a\ b c\\d"), [r"a b", r"c\d"]);
+    }
+
+    #[test]
+    fn extra_args_mix_quote_types() {
+        assert_eq!(parse_extra_args(r#""it's" 'a "b"'"#), ["it's", r#"a "b""#]);
+        assert_eq!(parse_extra_args(r#"-metadata title='My Song' -metadata comment="""#), [
+            "-metadata",
+            "title=My Song",
+            "-metadata",
+            "comment=",
+        ]);
+        assert_eq!(parse_extra_args(r#""" ''"#), ["", ""]);
+    }
+
+    #[test]
+    fn extra_args_keep_trailing_backslash() {
+        assert_eq!(parse_extra_args(r"-i dir\"), ["-i", r"dir\"]);
+        assert_eq!(parse_extra_args(r#""open\"#), [r"open\"]);
+    }
+
+    #[test]
+    fn extra_args_keep_ffmpeg_escapes() {
+        assert_eq!(parse_extra_args(r"-vf scale=w=1280\:h=720"), ["-vf", r"scale=w=1280\:h=720"]);
+    }
+
+    #[test]
+    fn extra_args_keep_filter_quoting() {
+        // `It\'s` becomes `It\'s` after the graph parser and `It's` after the option parser
+        assert_eq!(
+            parse_extra_args(r"-vf drawtext=text='It\'s fine':fontsize=24"),
+            ["-vf", r"drawtext=text='It'\\\''s fine':fontsize=24"],
+        );
+        assert_eq!(parse_extra_args("-af volume='0.5'"), ["-af", "volume='0.5'"]);
+    }
+
+    #[test]
+    fn odd_dimensions_get_even_filter() {
+        let args = build_ffmpeg_args(&options("in.mov", "out.mp4"), Some(&video_info(1279, 720))).unwrap();
+        assert!(has_pair(&args, "-vf", EVEN_DIMENSIONS_FILTER));
+        let args = build_ffmpeg_args(&options("in.mov", "out.mp4"), Some(&video_info(1280, 719))).unwrap();
+        assert_eq!(even_filter_count(&args), 1);
+    }
+
+    #[test]
+    fn even_filter_only_when_needed() {
+        let args = build_ffmpeg_args(&options("in.mov", "out.mp4"), Some(&video_info(1280, 720))).unwrap();
+        assert_eq!(even_filter_count(&args), 0);
+        // VP9 in WebM copes with odd sizes
+        let args = build_ffmpeg_args(&options("in.mov", "out.webm"), Some(&video_info(1279, 720))).unwrap();
+        assert_eq!(even_filter_count(&args), 0);
+        let mut opts = options("in.mov", "out.mp4");
+        opts.settings.fix_odd_dimensions = false;
+        let args = build_ffmpeg_args(&opts, Some(&video_info(1279, 720))).unwrap();
+        assert_eq!(even_filter_count(&args), 0);
+    }
+
+    fn audio_options(volume: &str, normalize_audio: bool) -> ConvertOptions {
+        let mut opts = options("in.wav", "out.m4a");
+        opts.advanced = Some(AdvancedOptions {
+            volume: Some(volume.to_string()),
+            normalize_audio,
+            ..Default::default()
+        });
+        opts
+    }
+
+    #[test]
+    fn volume_accepts_multipliers_and_decibels() {
+        assert_eq!(volume_filter("1.5").unwrap(), "volume=1.5");
+        assert_eq!(volume_filter("+6dB").unwrap(), "volume=6dB");
+        assert_eq!(volume_filter(" -3dB ").unwrap(), "volume=-3dB");
+        assert_eq!(volume_filter("-3db").unwrap(), "volume=-3dB");
+    }
+
+    #[test]
+    fn volume_rejects_bad_gains() {
+        for volume in ["loud", "0", "-1.5", "+50dB", "200", "NaNdB"] {
+            assert!(volume_filter(volume).is_err(), "{} should be rejected", volume);
+        }
+    }
+
+    #[test]
+    fn volume_goes_into_the_audio_chain() {
+        for (volume, filter) in [("1.5", "volume=1.5"), ("+6dB", "volume=6dB"), ("-3dB", "volume=-3dB")] {
+            let args = build_ffmpeg_args(&audio_options(volume, false), None).unwrap();
+            assert!(has_pair(&args, "-af", filter), "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn volume_applies_after_normalization() {
+        let args = build_ffmpeg_args(&audio_options("-3dB", true), None).unwrap();
+        let chain = last_value(&args, "-af").unwrap();
+        assert_eq!(chain, format!("{},volume=-3dB", loudnorm_filter(DEFAULT_LOUDNESS_TARGET)));
+    }
+
+    #[test]
+    fn replace_original_moves_output_into_place() {
+        let dir = crate::test_support::temp_dir("replace-original");
+        let input = dir.join("clip.mov");
+        let output = dir.join("clip_converted.mp4");
+        std::fs::write(&input, "original").unwrap();
+        std::fs::write(&output, "converted").unwrap();
+
+        let (backup, final_path) = replace_original(input.to_str().unwrap(), output.to_str().unwrap()).unwrap();
+        assert_eq!(std::fs::read_to_string(&backup).unwrap(), "original");
+        assert_eq!(final_path, dir.join("clip.mp4").to_str().unwrap());
+        assert_eq!(std::fs::read_to_string(&final_path).unwrap(), "converted");
+    }
+
+    #[test]
+    fn replace_original_rolls_back_when_output_cannot_move() {
+        let dir = crate::test_support::temp_dir("replace-original-rollback");
+        let input = dir.join("clip.mov");
+        std::fs::write(&input, "original").unwrap();
+
+        let missing = dir.join("never_written.mp4");
+        assert!(replace_original(input.to_str().unwrap(), missing.to_str().unwrap()).is_err());
+        assert_eq!(std::fs::read_to_string(&input).unwrap(), "original");
+        assert!(!dir.join("clip.mov.bak").exists());
+    }
+
+    #[test]
+    fn parallel_items_with_the_same_output_get_distinct_paths() {
+        let dir = crate::test_support::temp_dir("reserve-output");
+        let requested = dir.join("clip.mp4").to_string_lossy().to_string();
+        let opts = options("a.mov", &requested);
+        let mut reserved = HashSet::new();
+
+        let first = reserve_output_path(&opts, &mut reserved);
+        let second = reserve_output_path(&opts, &mut reserved);
+        assert_eq!(first, requested);
+        assert_eq!(second, dir.join("clip_01.mp4").to_string_lossy());
+        assert!(reserved.contains(&first) && reserved.contains(&second));
+
+        // Existing files are skipped too
+        std::fs::write(dir.join("clip_02.mp4"), "").unwrap();
+        assert_eq!(reserve_output_path(&opts, &mut reserved), dir.join("clip_03.mp4").to_string_lossy());
+    }
+
+    #[test]
+    fn stream_copy_keeps_metadata_edits() {
+        let mut opts = options("in.mov", "out.mp4");
+        opts.advanced = Some(AdvancedOptions {
+            copy_streams: true,
+            copy_metadata: true,
+            metadata: Some(HashMap::from([("title".to_string(), "Summer trip".to_string())])),
+            ..Default::default()
+        });
+        let args = build_ffmpeg_args(&opts, Some(&video_info(1280, 720))).unwrap();
+        assert!(has_pair(&args, "-c", "copy"));
+        assert!(has_pair(&args, "-map_metadata", "0"));
+        assert!(has_pair(&args, "-metadata", "title=Summer trip"));
+        assert_eq!(&args[args.len() - 2..], ["-y", "out.mp4"]);
+    }
+
+    #[test]
+    fn encoder_settings_block_stream_copy() {
+        let copy = AdvancedOptions {
+            copy_streams: true,
+            ..Default::default()
+        };
+        let blockers = [
+            AdvancedOptions {
+                force_key_frames: Some(KeyframePlacement::Interval { seconds: 2.0 }),
+                ..copy.clone()
+            },
+            AdvancedOptions { closed_gop: true, ..copy.clone() },
+            AdvancedOptions { no_scenecut: true, ..copy.clone() },
+            AdvancedOptions { tune: Some("film".to_string()), ..copy.clone() },
+            AdvancedOptions { profile: Some("high".to_string()), ..copy.clone() },
+            AdvancedOptions { level: Some("4.1".to_string()), ..copy.clone() },
+            AdvancedOptions { pcm_format: Some("pcm_s24le".to_string()), ..copy.clone() },
+            AdvancedOptions {
+                stream_codecs: vec![StreamCodec { stream_index: 0, codec: "libx265".to_string() }],
+                ..copy.clone()
+            },
+            AdvancedOptions {
+                gif: Some(GifOptions { fps: 10, width: 320, dither: Default::default() }),
+                ..copy.clone()
+            },
+        ];
+        let info = video_info(1280, 720);
+        let mut opts = options("in.mov", "out.mp4");
+        opts.advanced = Some(copy.clone());
+        assert_eq!(stream_copy_blocker(&opts, &info), None);
+        for advanced in blockers {
+            opts.advanced = Some(advanced);
+            assert!(stream_copy_blocker(&opts, &info).is_some(), "{:?}", opts.advanced);
+        }
+    }
+
+    fn image_args(preset_id: &str, quality: u32) -> Result<Vec<String>, ConvertError> {
+        let mut opts = options("photo.png", "photo.out");
+        opts.preset_id = Some(preset_id.to_string());
+        opts.advanced = Some(AdvancedOptions {
+            image_quality: Some(quality),
+            ..Default::default()
+        });
+        build_ffmpeg_args(&opts, None)
+    }
+
+    #[test]
+    fn image_quality_maps_to_each_encoder() {
+        let args = image_args("avif", 100).unwrap();
+        assert!(has_pair(&args, "-crf", "0") && has_pair(&args, "-b:v", "0"));
+        assert_eq!(args.iter().filter(|a| *a == "-crf").count(), 1);
+        assert!(has_pair(&image_args("avif", 1).unwrap(), "-crf", "63"));
+        assert!(has_pair(&image_args("jpg", 100).unwrap(), "-q:v", "2"));
+        assert!(has_pair(&image_args("jpg", 1).unwrap(), "-q:v", "31"));
+        assert!(has_pair(&image_args("webp", 60).unwrap(), "-quality", "60"));
+    }
+
+    #[test]
+    fn image_quality_is_checked() {
+        assert!(image_args("avif", 0).is_err());
+        assert!(image_args("avif", 101).is_err());
+        assert!(image_args("png", 80).is_err());
+    }
+
+    #[test]
+    fn retiming_comes_before_provenance() {
+        let mut opts = options("in.mov", "out.mp4");
+        opts.advanced = Some(AdvancedOptions {
+            target_duration_secs: Some(5.0),
+            embed_settings: true,
+            ..Default::default()
+        });
+        let (args, factor) = job_args(&opts, Some(&video_info(1280, 720)), Some(10.0)).unwrap();
+        assert_eq!(factor, Some(2.0));
+        let comment = args.iter().find(|a| a.starts_with("comment=")).unwrap();
+        assert!(comment.contains("setpts="), "{}", comment);
+        assert_eq!(&args[args.len() - 2..], ["-y", "out.mp4"]);
+    }
+
+    #[test]
+    fn retiming_needs_a_known_duration() {
+        let mut opts = options("in.mov", "out.mp4");
+        opts.advanced = Some(AdvancedOptions {
+            target_duration_secs: Some(5.0),
+            ..Default::default()
+        });
+        assert!(job_args(&opts, Some(&video_info(1280, 720)), None).is_err());
+    }
+
+    #[test]
+    fn preview_shows_both_passes_at_the_output_a_run_would_use() {
+        let dir = crate::test_support::temp_dir("preview");
+        let requested = dir.join("clip.mp4").to_string_lossy().to_string();
+        std::fs::write(&requested, "").unwrap();
+        let reserved = HashSet::from([dir.join("clip_01.mp4").to_string_lossy().to_string()]);
+        let mut opts = options(&dir.join("missing.mov").to_string_lossy(), &requested);
+        opts.preset_id = Some("mp4_h264".to_string());
+        opts.advanced = Some(AdvancedOptions {
+            target_bitrate: Some("2M".to_string()),
+            two_pass: true,
+            ..Default::default()
+        });
+
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let passes = runtime.block_on(preview_passes(opts, reserved, None, None)).unwrap();
+        assert_eq!(passes.len(), 2);
+        assert!(has_pair(&passes[0], "-pass", "1") && passes[0].last().unwrap() == NULL_OUTPUT);
+        assert!(has_pair(&passes[1], "-pass", "2"));
+        assert!(has_pair(&passes[1], "-passlogfile", PREVIEW_PASSLOG_PREFIX));
+        assert_eq!(passes[1].last().unwrap(), &dir.join("clip_02.mp4").to_string_lossy());
+    }
+
+    #[test]
+    fn extra_args_accept_only_allowed_options_and_filters() {
+        let check = |extra: &str| check_extra_args(&parse_extra_args(extra));
+        assert!(check("-crf 18 -preset slow -b:a 192k -metadata:s:a:0 language=eng -an").is_ok());
+        assert!(check("-vf scale=1280:-2,fps=30 -af loudnorm=I=-16").is_ok());
+        assert!(check("-filter_complex [0:v]split[a][b];[a][b]hstack[out] -map [out]").is_ok());
+        assert!(check(r"-vf drawtext=text='It\'s fine':fontsize=24").is_ok());
+
+        // A bare token would be another output file
+        assert!(check("-crf 18 /home/user/.bashrc").is_err());
+        assert!(check("-an -vf").is_err());
+        for option in ["-i x", "-y", "-stats_enc_pre f", "-segment_list f", "-hls_segment_filename f", "-/vf f"] {
+            assert!(check(option).is_err(), "{} accepted", option);
+        }
+        assert!(check("-f tee [f=mp4]/tmp/a.mp4").is_err());
+        for graph in ["frei0r=x", "ladspa=f=lib.so", "lv2=p=x", "subtitles=/etc/passwd", "ass=a.ass", "movie=a.mp4"] {
+            assert!(check(&format!("-vf {}", graph)).is_err(), "{} accepted", graph);
+        }
+        assert!(check("-vf scale=640:-2,drawtext=textfile=/etc/passwd").is_err());
+    }
+
+    #[test]
+    fn hdr10_plus_re_encode_sets_up_x265() {
+        let mut opts = options("in.mkv", "out.mkv");
+        opts.advanced = Some(AdvancedOptions {
+            video_codec: Some("libx265".to_string()),
+            preserve_dynamic_hdr: true,
+            ..Default::default()
+        });
+        let args = build_ffmpeg_args(&opts, None).unwrap();
+        assert!(has_pair(&args, "-pix_fmt", "yuv420p10le"));
+        assert!(has_pair(&args, "-profile:v", "main10"));
+        assert!(has_pair(&args, "-color_trc", "smpte2084"));
+        let params = last_value(&args, "-x265-params").unwrap();
+        for param in ["hdr10=1", "repeat-headers=1", "transfer=smpte2084", "dhdr10-opt=1"] {
+            assert!(params.split(':').any(|p| p == param), "{} missing from {}", param, params);
+        }
+    }
+
+    #[test]
+    fn hevc_tag_is_dropped_when_the_encoder_is_not_hevc() {
+        let mut opts = options("in.mov", "out.mp4");
+        opts.preset_id = Some("mp4_h265_nvenc".to_string());
+        let args = build_ffmpeg_args(&opts, Some(&video_info(1920, 1080))).unwrap();
+        assert!(has_pair(&args, "-tag:v", "hvc1"));
+
+        opts.advanced = Some(AdvancedOptions { max_compatibility: true, ..Default::default() });
+        let args = build_ffmpeg_args(&opts, Some(&video_info(1920, 1080))).unwrap();
+        assert!(has_pair(&args, "-c:v", "libx264"));
+        assert!(!args.iter().any(|a| a == "-tag:v"));
+    }
+
+    #[test]
+    fn movflags_merge_into_the_last_occurrence() {
+        let mut args: Vec<String> = ["-movflags", "+faststart", "-c:v", "libx264", "-movflags", "+frag_keyframe"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        push_movflags(&mut args, "+use_metadata_tags");
+        assert_eq!(args[1], "+faststart");
+        assert_eq!(args[5], "+frag_keyframe+use_metadata_tags");
+        assert_eq!(args.len(), 6);
+    }
+
+    #[test]
+    fn size_growth_progress_stops_once_the_output_outgrows_the_input() {
+        assert_eq!(size_growth_percent(512, 2 * 1024 * 1024), Some(25.0));
+        assert_eq!(size_growth_percent(2048, 2 * 1024 * 1024), None);
+        assert_eq!(size_growth_percent(4096, 2 * 1024 * 1024), None);
+        assert_eq!(size_growth_percent(10, 0), None);
+    }
+
+    #[test]
+    fn wav_preset_matches_the_source_sample_format() {
+        let json = r#"{"streams": [{"index": 0, "codec_type": "audio", "codec_name": "flac",
+                "sample_fmt": "s32", "bits_per_raw_sample": "24", "channels": 2}],
+            "format": {"duration": "10.0"}}"#;
+        let info = crate::probe::parse_probe_output(json, "in.flac").unwrap();
+        let mut opts = options("in.flac", "out.wav");
+        opts.preset_id = Some("wav".to_string());
+        let args = build_ffmpeg_args(&opts, Some(&info)).unwrap();
+        assert_eq!(last_value(&args, "-c:a"), Some("pcm_s24le"));
+
+        opts.advanced = Some(AdvancedOptions {
+            pcm_format: Some("pcm_f32le".to_string()),
+            ..Default::default()
+        });
+        let args = build_ffmpeg_args(&opts, Some(&info)).unwrap();
+        assert_eq!(last_value(&args, "-c:a"), Some("pcm_f32le"));
+        assert_eq!(args.iter().filter(|a| *a == "-c:a").count(), 1);
+    }
+
+    #[test]
+    fn chapter_keyframes_follow_the_trim() {
+        let json = r#"{"streams": [{"index": 0, "codec_type": "video", "codec_name": "h264", "width": 1920, "height": 1080}],
+            "format": {"duration": "300.0"},
+            "chapters": [
+                {"id": 0, "start_time": "0.000000", "end_time": "60.000000"},
+                {"id": 1, "start_time": "60.000000", "end_time": "150.500000"},
+                {"id": 2, "start_time": "150.500000", "end_time": "300.000000"}
+            ]}"#;
+        let info = crate::probe::parse_probe_output(json, "in.mkv").unwrap();
+        assert_eq!(
+            force_key_frames_value(&KeyframePlacement::Chapters, Some(&info), None).unwrap(),
+            "0.000,60.000,150.500"
+        );
+        assert_eq!(
+            force_key_frames_value(&KeyframePlacement::Chapters, Some(&info), Some((30.0, Some(100.0)))).unwrap(),
+            "30.000"
+        );
+        assert!(force_key_frames_value(&KeyframePlacement::Chapters, Some(&video_info(640, 480)), None).is_err());
+    }
+
+    #[test]
+    fn loudnorm_analysis_keeps_commas_inside_filter_options() {
+        let chain = format!("aeval='val(0)*0.5,val(1)',{},volume=2", loudnorm_filter(-16.0));
+        let args: Vec<String> = ["-i", "in.wav", "-af", &chain, "-y", "out.m4a"].map(String::from).to_vec();
+        let analysis = loudnorm_analysis_args(&args);
+        let expected = format!("aeval='val(0)*0.5,val(1)',{}:print_format=json,volume=2", loudnorm_filter(-16.0));
+        assert!(has_pair(&analysis, "-af", &expected));
+        assert_eq!(split_filter_chain(r"drawtext=text=a\,b,null"), vec![r"drawtext=text=a\,b", "null"]);
+    }
+
+    #[test]
+    fn replay_gain_goes_to_itunes_tags_in_m4a_and_metadata_keys_in_mov() {
+        let gain = ReplayGain {
+            track_gain_db: Some(-6.2),
+            track_peak: Some(0.98),
+            album_gain_db: None,
+            album_peak: None,
+        };
+        let mut args: Vec<String> = ["-i", "in.flac", "-f", "ipod", "-y", "out.m4a"].map(String::from).to_vec();
+        let file_tags = apply_replay_gain_tags(&mut args, "out.m4a", &gain, None);
+        assert_eq!(file_tags[0], ("replaygain_track_gain".to_string(), "-6.20 dB".to_string()));
+        assert!(!args.iter().any(|a| a == "-metadata"));
+
+        let mut args: Vec<String> = ["-i", "in.flac", "-movflags", "+faststart", "-y", "out.mov"].map(String::from).to_vec();
+        assert!(apply_replay_gain_tags(&mut args, "out.mov", &gain, None).is_empty());
+        assert!(has_pair(&args, "-movflags", "+faststart+use_metadata_tags"));
+        assert!(has_pair(&args, "-metadata", "REPLAYGAIN_TRACK_GAIN=-6.20 dB"));
+        assert_eq!(args.last().map(String::as_str), Some("out.mov"));
+    }
+
+    #[test]
+    fn raised_probe_limits_reach_ffmpeg_as_input_options() {
+        let mut info = video_info(1920, 1080);
+        let args = build_ffmpeg_args(&options("in.ts", "out.mp4"), Some(&info)).unwrap();
+        assert!(!args.iter().any(|a| a == "-analyzeduration" || a == "-probesize"));
+
+        info.probe_limits = crate::probe::ProbeLimits {
+            analyze_duration_us: Some(100_000_000),
+            probe_size: Some(50_000_000),
+        };
+        let args = build_ffmpeg_args(&options("in.ts", "out.mp4"), Some(&info)).unwrap();
+        let input = args.iter().position(|a| a == "-i").unwrap();
+        assert!(has_pair(&args[..input], "-analyzeduration", "100000000"));
+        assert!(has_pair(&args[..input], "-probesize", "50000000"));
+    }
+
+    #[test]
+    fn metadata_rotation_sets_the_display_matrix_before_the_input() {
+        let mut opts = options("in.mov", "out.mp4");
+        opts.advanced = Some(AdvancedOptions {
+            rotate: Some(RotateMode::Rotate90CW),
+            rotate_metadata_only: true,
+            ..Default::default()
+        });
+        let args = build_ffmpeg_args(&opts, None).unwrap();
+        let rotation = args.iter().position(|a| a == "-display_rotation:v:0").unwrap();
+        assert_eq!(args[rotation + 1], "-90");
+        assert!(rotation < args.iter().position(|a| a == "-i").unwrap());
+        assert!(has_pair(&args, "-c:v", "copy"));
+        assert!(!args.iter().any(|a| a.starts_with("rotate=")));
+
+        opts.advanced.as_mut().unwrap().rotate = Some(RotateMode::Rotate90CCW);
+        let args = build_ffmpeg_args(&opts, None).unwrap();
+        assert!(has_pair(&args, "-display_rotation:v:0", "90"));
+    }
+
+    #[test]
+    fn remux_converts_or_drops_subtitles_the_container_cannot_copy() {
+        let json = r#"{"streams": [
+                {"index": 0, "codec_type": "video", "codec_name": "h264"},
+                {"index": 1, "codec_type": "subtitle", "codec_name": "hdmv_pgs_subtitle"},
+                {"index": 2, "codec_type": "subtitle", "codec_name": "subrip"},
+                {"index": 3, "codec_type": "subtitle", "codec_name": "mov_text"}],
+            "format": {"duration": "10.0"}}"#;
+        let info = crate::probe::parse_probe_output(json, "in.mkv").unwrap();
+        let subtitles: Vec<&StreamInfo> = info.streams.iter().filter(|s| s.stream_type == StreamType::Subtitle).collect();
+
+        let (args, warning) = remux_args("in.mkv", "out.mp4", &subtitles);
+        assert!(!has_pair(&args, "-map", "0:1"));
+        assert!(has_pair(&args, "-map", "0:2") && has_pair(&args, "-map", "0:3"));
+        assert!(has_pair(&args, "-c:s:0", "mov_text"));
+        assert!(!args.iter().any(|a| a == "-c:s:1"));
+        assert!(warning.unwrap().contains("#1 (hdmv_pgs_subtitle)"));
+
+        let (args, warning) = remux_args("in.mp4", "out.mkv", &subtitles);
+        assert!(has_pair(&args, "-map", "0:1"));
+        assert!(!args.iter().any(|a| a.starts_with("-c:s")));
+        assert!(warning.is_none());
+    }
+
+    #[test]
+    fn source_replay_gain_keeps_itunes_tags_in_m4a() {
+        let json = r#"{"streams": [{"index": 0, "codec_type": "audio", "codec_name": "flac", "channels": 2}],
+            "format": {"duration": "10.0", "tags": {"TITLE": "Song", "REPLAYGAIN_TRACK_GAIN": "-6.20 dB"}}}"#;
+        let info = crate::probe::parse_probe_output(json, "in.flac").unwrap();
+        assert!(info.replay_gain.is_some());
+        let mut opts = options("in.flac", "out.m4a");
+        opts.preset_id = Some("aac".to_string());
+        let args = build_ffmpeg_args(&opts, Some(&info)).unwrap();
+        assert!(!args.iter().any(|a| a.contains("use_metadata_tags")));
+
+        let args = build_ffmpeg_args(&options("in.flac", "out.mov"), Some(&info)).unwrap();
+        assert!(args.iter().any(|a| a.contains("use_metadata_tags")));
+    }
+
+    #[test]
+    fn default_advanced_options_match_an_empty_request() {
+        let parsed: AdvancedOptions = serde_json::from_str("{}").unwrap();
+        assert!(parsed.embed_cover);
+        assert!(AdvancedOptions::default().embed_cover);
+    }
+
+    #[test]
+    fn crop_offsets_that_overflow_are_rejected() {
+        let crop = CropRect {
+            w: 100,
+            h: 100,
+            x: u32::MAX - 10,
+            y: 0,
+        };
+        assert!(geometry_filter(Some(crop), None, None, Some(&video_info(1920, 1080)), false).is_err());
+        assert!(geometry_filter(Some(crop), None, None, None, false).is_err());
+    }
+
+    #[test]
+    fn name_templates_fill_in_the_input_and_preset() {
+        assert_eq!(output_file_stem(None, "clip", Some("mp4_h264")), "clip_Convertified");
+        assert_eq!(output_file_stem(Some("{name}-{preset}"), "clip", Some("webm_vp9")), "clip-webm_vp9");
+        assert_eq!(output_file_stem(Some("../{name}"), "clip", None), ".._clip");
+        assert_eq!(output_file_stem(Some("  "), "clip", None), "clip_Convertified");
+        let path = generate_output_path("/media/clip.mov", Some("mp4_h264"), None, None, Some("web_{name}"), &HashSet::new());
+        assert_eq!(path, "/media/web_clip.mp4");
+    }
+
+    #[test]
+    fn container_table_lists_what_each_container_holds() {
+        assert_eq!(container_accepts("mp4", "libx264"), Some(true));
+        assert_eq!(container_accepts("m4v", "mov_text"), Some(true));
+        assert_eq!(container_accepts("webm", "libx264"), Some(false));
+        assert_eq!(container_accepts("mkv", "subrip"), Some(true));
+        assert_eq!(container_accepts("mp3", "aac"), Some(false));
+        assert_eq!(container_accepts("nut", "libx264"), None);
+    }
+
+    #[test]
+    fn container_codecs_are_limited_to_this_build() {
+        use crate::capabilities::CodecKind;
+        let available: Vec<EncoderInfo> = [
+            ("libx264", CodecKind::Video),
+            ("libvpx-vp9", CodecKind::Video),
+            ("libopus", CodecKind::Audio),
+            ("webvtt", CodecKind::Subtitle),
+        ]
+        .iter()
+        .map(|(name, kind)| EncoderInfo {
+            name: name.to_string(),
+            kind: kind.clone(),
+            description: String::new(),
+        })
+        .collect();
+        let webm = codecs_for_container("webm", &available).unwrap();
+        assert_eq!(webm.video, ["libvpx-vp9"]);
+        assert_eq!(webm.audio, ["libopus"]);
+        assert_eq!(webm.subtitle, ["webvtt"]);
+        let mp3 = codecs_for_container("mp3", &available).unwrap();
+        assert!(mp3.video.is_empty() && mp3.audio.is_empty());
+        assert!(codecs_for_container("nut", &available).is_none());
+    }
+
+    #[test]
+    fn segment_pattern_escapes_percent_signs() {
+        let pattern = segment_pattern(std::path::Path::new("out"), "100% live", "mp4");
+        assert!(pattern.ends_with("100%% live_part%03d.mp4"));
+    }
+
+    #[test]
+    fn permission_errors_from_ffmpeg_are_not_retried() {
+        assert!(!is_transient_error("out.mp4: Permission denied"));
+        assert!(is_transient_error("in.mov: Resource temporarily unavailable"));
+        assert!(is_transient_error("The process cannot access the file because it is being used by another process"));
+    }
+
+    #[test]
+    fn retry_wait_ends_on_cancel() {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let stop = AtomicBool::new(false);
+        let setter = cancel.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            setter.store(true, Ordering::Relaxed);
+        });
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_time().build().unwrap();
+        let started = std::time::Instant::now();
+        runtime.block_on(async {
+            tokio::select! {
+                _ = tokio::time::sleep(std::time::Duration::from_secs(30)) => {}
+                _ = interrupt_requested(&cancel, &stop) => {}
+            }
+        });
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+    }
+
+    #[test]
+    fn layered_input_maps_the_base_layer() {
+        let fixture = include_str!("../tests/fixtures/ffprobe_layered.json");
+        let info = crate::probe::parse_probe_output(fixture, "spatial_capture.mov").unwrap();
+        let args = build_ffmpeg_args(&options("spatial_capture.mov", "out.mp4"), Some(&info)).unwrap();
+        assert!(has_pair(&args, "-map", "0:0"));
+        assert!(!args.iter().any(|a| a == "0:v?" || a == "0:1"));
+    }
+}
@@ -0,0 +1,188 @@
+use crate::convert::{build_ffmpeg_args, last_value, target_container, ConvertError, ConvertOptions};
+use crate::preflight::CheckStatus;
+use crate::probe::{MediaInfo, StreamType};
+use serde::Serialize;
+
+/// What a class of playback device handles reliably. Deliberately conservative: a
+/// warning means "may not play", not "cannot play".
+struct DeviceProfile {
+    id: &'static str,
+    name: &'static str,
+    /// Codec families (h264, hevc, vp9, av1, aac, opus, ...)
+    video_codecs: &'static [&'static str],
+    audio_codecs: &'static [&'static str],
+    containers: &'static [&'static str],
+    /// Highest H.264 level decoded in hardware
+    max_h264_level: f32,
+    /// 10-bit decoding for codecs other than H.264 (10-bit H.264 is never hardware decoded)
+    ten_bit: bool,
+}
+
+const DEVICES: &[DeviceProfile] = &[
+    DeviceProfile {
+        id: "old_iphone",
+        name: "Older iPhone/iPad (before iPhone 7)",
+        video_codecs: &["h264"],
+        audio_codecs: &["aac", "alac", "mp3"],
+        containers: &["mp4", "mov"],
+        max_h264_level: 4.2,
+        ten_bit: false,
+    },
+    DeviceProfile {
+        id: "iphone",
+        name: "Recent iPhone/iPad",
+        video_codecs: &["h264", "hevc"],
+        audio_codecs: &["aac", "alac", "mp3", "ac3", "eac3", "flac"],
+        containers: &["mp4", "mov"],
+        max_h264_level: 5.2,
+        ten_bit: true,
+    },
+    DeviceProfile {
+        id: "android",
+        name: "Android phone",
+        video_codecs: &["h264", "hevc", "vp9"],
+        audio_codecs: &["aac", "mp3", "opus", "vorbis", "flac"],
+        containers: &["mp4", "matroska", "webm"],
+        max_h264_level: 5.1,
+        ten_bit: false,
+    },
+    DeviceProfile {
+        id: "smart_tv",
+        name: "Smart TV",
+        video_codecs: &["h264", "hevc", "vp9"],
+        audio_codecs: &["aac", "mp3", "ac3", "eac3"],
+        containers: &["mp4", "matroska", "mpegts"],
+        max_h264_level: 4.1,
+        ten_bit: true,
+    },
+    DeviceProfile {
+        id: "web_browser",
+        name: "Web browser",
+        // HEVC only plays in Safari and some Chromium builds
+        video_codecs: &["h264", "vp9", "av1"],
+        audio_codecs: &["aac", "mp3", "opus", "vorbis"],
+        containers: &["mp4", "webm"],
+        max_h264_level: 5.2,
+        ten_bit: false,
+    },
+];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceCompatibility {
+    pub device: &'static str,
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub issues: Vec<String>,
+}
+
+/// Codec family of an encoder or decoder name (`libx264` and `h264_nvenc` are both "h264")
+fn codec_family(name: &str) -> &str {
+    match name {
+        n if n == "libx264" || n.starts_with("h264") => "h264",
+        n if n == "libx265" || n.starts_with("hevc") => "hevc",
+        "libvpx-vp9" | "vp9" => "vp9",
+        "libvpx" | "vp8" => "vp8",
+        n if n == "libaom-av1" || n == "libsvtav1" || n.starts_with("av1") => "av1",
+        "libfdk_aac" => "aac",
+        "libmp3lame" => "mp3",
+        "libopus" => "opus",
+        "libvorbis" => "vorbis",
+        n if n.starts_with("pcm_") => "pcm",
+        other => other,
+    }
+}
+
+/// What the output will contain, as far as the arguments and the input tell
+struct OutputTraits {
+    container: String,
+    video: Option<String>,
+    audio: Option<String>,
+    pix_fmt: Option<String>,
+    profile: Option<String>,
+    level: Option<f32>,
+}
+
+fn output_traits(options: &ConvertOptions, args: &[String], info: Option<&MediaInfo>) -> OutputTraits {
+    let input_stream = |kind: StreamType| info?.streams.iter().find(|s| s.stream_type == kind);
+    let input_video = input_stream(StreamType::Video);
+    // Stream copy keeps the input's codec; no explicit encoder means ffmpeg's container default (unknown here)
+    let family = |flag: &str, kind: StreamType| match last_value(args, flag) {
+        Some("copy") => input_stream(kind).and_then(|s| s.codec_name.clone()),
+        Some(encoder) => Some(codec_family(encoder).to_string()),
+        None => None,
+    };
+    let level = last_value(args, "-level")
+        .map(str::to_string)
+        .or_else(|| {
+            last_value(args, "-x265-params")?
+                .split(':')
+                .find_map(|p| p.strip_prefix("level-idc=").map(str::to_string))
+        })
+        .and_then(|l| l.parse().ok());
+    OutputTraits {
+        container: target_container(args, &options.output_path),
+        video: if args.iter().any(|a| a == "-vn") { None } else { family("-c:v", StreamType::Video) },
+        audio: if args.iter().any(|a| a == "-an") { None } else { family("-c:a", StreamType::Audio) },
+        // Encoders keep the input pixel format when they support it
+        pix_fmt: last_value(args, "-pix_fmt")
+            .map(str::to_string)
+            .or_else(|| input_video.and_then(|s| s.pix_fmt.clone())),
+        profile: last_value(args, "-profile:v").map(str::to_string),
+        level,
+    }
+}
+
+fn device_issues(device: &DeviceProfile, out: &OutputTraits) -> Vec<String> {
+    let mut issues = Vec::new();
+    if !device.containers.contains(&out.container.as_str()) {
+        issues.push(format!("'{}' container is not widely supported", out.container));
+    }
+    if let Some(video) = out.video.as_deref().filter(|v| !device.video_codecs.contains(v)) {
+        issues.push(format!("{} video may not play", video));
+    }
+    if let Some(audio) = out.audio.as_deref().filter(|a| !device.audio_codecs.contains(a)) {
+        issues.push(format!("{} audio may not play", audio));
+    }
+
+    let video = out.video.as_deref().unwrap_or("");
+    if let Some(pix_fmt) = out.pix_fmt.as_deref().filter(|_| !video.is_empty()) {
+        let high_depth = pix_fmt.contains("10") || pix_fmt.contains("12");
+        if high_depth && (video == "h264" || !device.ten_bit) {
+            issues.push(format!("{}-bit {} is not hardware decoded", if pix_fmt.contains("12") { 12 } else { 10 }, video));
+        }
+        if pix_fmt.contains("422") || pix_fmt.contains("444") {
+            issues.push(format!("Pixel format {} is not 4:2:0; most players need yuv420p", pix_fmt));
+        }
+    }
+    if video == "h264" {
+        if let Some(profile) = out.profile.as_deref().filter(|p| p.starts_with("high") && *p != "high") {
+            issues.push(format!("H.264 profile '{}' is rarely hardware decoded", profile));
+        }
+        if let Some(level) = out.level.filter(|l| *l > device.max_h264_level) {
+            issues.push(format!("H.264 level {} exceeds {}", level, device.max_h264_level));
+        }
+    }
+    issues
+}
+
+/// Flag output settings likely to fail on common playback devices. Unlike the container/codec
+/// checks in `preflight`, this is about players, not about what ffmpeg can write.
+pub fn check_device_compatibility(
+    options: &ConvertOptions,
+    info: Option<&MediaInfo>,
+) -> Result<Vec<DeviceCompatibility>, ConvertError> {
+    let args = build_ffmpeg_args(options, info)?;
+    let traits = output_traits(options, &args, info);
+    Ok(DEVICES
+        .iter()
+        .map(|device| {
+            let issues = device_issues(device, &traits);
+            DeviceCompatibility {
+                device: device.id,
+                name: device.name,
+                status: if issues.is_empty() { CheckStatus::Pass } else { CheckStatus::Warning },
+                issues,
+            }
+        })
+        .collect())
+}
@@ -0,0 +1,213 @@
+use crate::convert::{build_ffmpeg_args, last_value, parse_frame_rate, trimmed_duration, ConvertError, ConvertOptions};
+use crate::probe::{MediaInfo, StreamInfo, StreamType};
+use serde::Serialize;
+
+/// How far an estimate can be trusted
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Confidence {
+    /// Every stream has a known bitrate (explicit `-b:v`/`-b:a`, stream copy, PCM): within ~10%
+    High,
+    /// Some stream is quality-based (CRF and the like), where size depends on the content
+    Low,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SizeEstimate {
+    /// Most likely size
+    pub bytes: u64,
+    pub min_bytes: u64,
+    pub max_bytes: u64,
+    pub confidence: Confidence,
+}
+
+/// Bits per pixel at each encoder's default CRF for typical content, and that CRF.
+/// Every 6 CRF steps roughly halves (or doubles) the bitrate.
+const CRF_BITS_PER_PIXEL: &[(&str, f64, f64)] = &[
+    ("libx264", 23.0, 0.06),
+    ("libx265", 28.0, 0.03),
+    ("libvpx-vp9", 31.0, 0.035),
+    ("libsvtav1", 35.0, 0.025),
+    ("libaom-av1", 35.0, 0.025),
+];
+
+/// Bits per pixel assumed for encoders and quality settings without a better model
+const FALLBACK_BITS_PER_PIXEL: f64 = 0.1;
+
+/// ffmpeg's default bitrate for lossy audio encoders without `-b:a`
+fn default_audio_bitrate(codec: &str) -> f64 {
+    match codec {
+        "libopus" => 96_000.0,
+        "libvorbis" => 112_000.0,
+        "ac3" | "eac3" => 192_000.0,
+        _ => 128_000.0,
+    }
+}
+
+/// "2500k", "4M" or "128000" in bits/s
+fn parse_bitrate(value: &str) -> Option<f64> {
+    let (digits, scale) = match value.chars().last()? {
+        'k' | 'K' => (&value[..value.len() - 1], 1_000.0),
+        'm' | 'M' => (&value[..value.len() - 1], 1_000_000.0),
+        _ => (value, 1.0),
+    };
+    digits.parse::<f64>().ok().map(|v| v * scale)
+}
+
+fn first_stream(info: &MediaInfo, kind: StreamType) -> Option<&StreamInfo> {
+    info.streams
+        .iter()
+        .find(|s| s.stream_type == kind && !s.has_disposition("attached_pic"))
+}
+
+/// Frame rate of the video output: `-r`, else the source's, else 30
+fn output_fps(args: &[String], stream: &StreamInfo) -> f64 {
+    last_value(args, "-r")
+        .and_then(parse_frame_rate)
+        .or(stream.frame_rate_fps)
+        .filter(|fps| *fps > 0.0 && *fps < 1000.0)
+        .unwrap_or(30.0)
+}
+
+/// Seconds of video the output holds: all of `duration`, unless `-frames:v` (stills,
+/// thumbnails, frame grabs) stops it after that many frames
+fn video_seconds(args: &[String], info: &MediaInfo, duration: f64) -> f64 {
+    let frames = last_value(args, "-frames:v")
+        .or_else(|| last_value(args, "-vframes"))
+        .and_then(|n| n.parse::<f64>().ok());
+    match (frames, first_stream(info, StreamType::Video)) {
+        (Some(frames), Some(stream)) => (frames / output_fps(args, stream)).min(duration),
+        _ => duration,
+    }
+}
+
+/// Video bits/s and whether it is exact
+fn video_bitrate(args: &[String], info: &MediaInfo) -> Option<(f64, bool)> {
+    if args.iter().any(|a| a == "-vn") {
+        return None;
+    }
+    let stream = first_stream(info, StreamType::Video)?;
+    if let Some(bitrate) = last_value(args, "-b:v").and_then(parse_bitrate) {
+        return Some((bitrate, true));
+    }
+    let codec = last_value(args, "-c:v");
+    if codec == Some("copy") {
+        if let Some(bitrate) = stream.bit_rate {
+            return Some((bitrate as f64, true));
+        }
+    }
+
+    // Quality-based: pixels per second times a bits-per-pixel model
+    let (width, height) = (stream.width? as f64, stream.height? as f64);
+    let fps = output_fps(args, stream);
+    let bits_per_pixel = codec
+        .and_then(|codec| CRF_BITS_PER_PIXEL.iter().find(|(name, _, _)| *name == codec))
+        .map(|(_, default_crf, bpp)| {
+            let crf = last_value(args, "-crf").and_then(|c| c.parse::<f64>().ok()).unwrap_or(*default_crf);
+            bpp * 2f64.powf((default_crf - crf) / 6.0)
+        })
+        .unwrap_or(FALLBACK_BITS_PER_PIXEL);
+    Some((width * height * fps * bits_per_pixel, false))
+}
+
+/// Audio bits/s and whether it is exact
+fn audio_bitrate(args: &[String], info: &MediaInfo) -> Option<(f64, bool)> {
+    if args.iter().any(|a| a == "-an") {
+        return None;
+    }
+    let stream = first_stream(info, StreamType::Audio)?;
+    if let Some(bitrate) = last_value(args, "-b:a").and_then(parse_bitrate) {
+        return Some((bitrate, true));
+    }
+    let channels = last_value(args, "-ac")
+        .and_then(|c| c.parse::<f64>().ok())
+        .or(stream.channels.map(f64::from))
+        .unwrap_or(2.0);
+    let sample_rate = last_value(args, "-ar")
+        .or(stream.sample_rate.as_deref())
+        .and_then(|r| r.parse::<f64>().ok())
+        .unwrap_or(48_000.0);
+    match last_value(args, "-c:a") {
+        Some("copy") => stream.bit_rate.map(|b| (b as f64, true)),
+        // Uncompressed: pcm_s24le is 24 bits per sample, and so on
+        Some(pcm) if pcm.starts_with("pcm_") => {
+            let bits: f64 = pcm
+                .trim_start_matches("pcm_")
+                .trim_start_matches(|c: char| c.is_ascii_alphabetic())
+                .trim_end_matches(|c: char| c.is_ascii_alphabetic())
+                .parse()
+                .unwrap_or(16.0);
+            Some((sample_rate * channels * bits, true))
+        }
+        // Lossless compression lands around 60% of 16-bit PCM for music
+        Some("flac") | Some("alac") => Some((sample_rate * channels * 16.0 * 0.6, false)),
+        Some(codec) => Some((default_audio_bitrate(codec), true)),
+        None => Some((default_audio_bitrate(""), false)),
+    }
+}
+
+/// Rough output size of a conversion, from the probed input and the bitrates the arguments
+/// ask for. Quality-based video (CRF) gives a wide range and low confidence.
+pub fn estimate_output_size(options: &ConvertOptions, info: &MediaInfo) -> Result<SizeEstimate, ConvertError> {
+    let args = build_ffmpeg_args(options, Some(info))?;
+    let advanced = options.advanced.as_ref();
+    let duration = advanced
+        .and_then(|a| a.target_duration_secs)
+        .or_else(|| trimmed_duration(advanced, info.format.duration))
+        .filter(|d| *d > 0.0)
+        .ok_or_else(|| ConvertError::ConversionFailed("Input duration is unknown".to_string()))?;
+
+    let video = video_bitrate(&args, info);
+    let audio = audio_bitrate(&args, info);
+    let bits = video.map_or(0.0, |(rate, _)| rate * video_seconds(&args, info, duration))
+        + audio.map_or(0.0, |(rate, _)| rate * duration);
+    let exact = [video, audio].iter().flatten().all(|(_, exact)| *exact);
+    // ~2% muxing overhead
+    let bytes = bits / 8.0 * 1.02;
+    let (low, high, confidence) = if exact {
+        (0.9, 1.1, Confidence::High)
+    } else {
+        (0.5, 2.0, Confidence::Low)
+    };
+    Ok(SizeEstimate {
+        bytes: bytes as u64,
+        min_bytes: (bytes * low) as u64,
+        max_bytes: (bytes * high) as u64,
+        confidence,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::AppSettings;
+
+    fn long_video() -> MediaInfo {
+        let json = r#"{"streams": [{"index": 0, "codec_type": "video", "codec_name": "h264",
+                "width": 1920, "height": 1080, "r_frame_rate": "25/1", "avg_frame_rate": "25/1"}],
+            "format": {"duration": "7200.0"}}"#;
+        crate::probe::parse_probe_output(json, "in.mkv").unwrap()
+    }
+
+    fn preset_options(preset_id: &str, output_path: &str) -> ConvertOptions {
+        ConvertOptions {
+            input_path: "in.mkv".to_string(),
+            output_path: output_path.to_string(),
+            preset_id: Some(preset_id.to_string()),
+            advanced: None,
+            stream_selection: None,
+            settings: AppSettings::default(),
+            job_id: 0,
+        }
+    }
+
+    #[test]
+    fn single_frame_outputs_are_estimated_as_one_frame() {
+        let info = long_video();
+        let still = estimate_output_size(&preset_options("png", "out.png"), &info).unwrap();
+        assert!(still.max_bytes < 10_000_000, "{} bytes for one frame", still.max_bytes);
+
+        let video = estimate_output_size(&preset_options("mp4_h264", "out.mp4"), &info).unwrap();
+        assert!(video.min_bytes > 1_000_000_000);
+    }
+}
@@ -0,0 +1,330 @@
+use crate::convert::{ffmpeg_program, run_ffmpeg, ConvertError};
+use crate::probe::{probe_file, StreamType};
+use serde::{Deserialize, Serialize};
+use std::process::Command;
+
+/// Check a filtergraph by running it on one frame of a synthetic input into the null muxer.
+/// Simple graphs go through `-vf` (or `-af` when `audio`); graphs with labels or several
+/// chains go through `-filter_complex` with both a video and an audio test input.
+/// On failure returns ffmpeg's error message.
+pub fn validate_filtergraph(
+    filter: &str,
+    audio: bool,
+    sidecar_path: Option<&std::path::Path>,
+) -> Result<(), String> {
+    if filter.trim().is_empty() {
+        return Err("Filter is empty".to_string());
+    }
+    
+    let video_src = "color=c=black:s=320x240:r=25:d=1";
+    let audio_src = "anullsrc=r=48000:cl=stereo";
+    let is_complex = filter.contains(';') || filter.contains('[');
+    
+    let mut args: Vec<&str> = vec!["-hide_banner", "-v", "error"];
+    if is_complex {
+        args.extend(["-f", "lavfi", "-i", video_src, "-f", "lavfi", "-i", audio_src]);
+        args.extend(["-filter_complex", filter]);
+    } else if audio {
+        args.extend(["-f", "lavfi", "-i", audio_src, "-af", filter]);
+    } else {
+        args.extend(["-f", "lavfi", "-i", video_src, "-vf", filter]);
+    }
+    args.extend(["-frames:v", "1", "-t", "0.1", "-f", "null", "-"]);
+    
+    let output = Command::new(ffmpeg_program(sidecar_path))
+        .args(&args)
+        .output()
+        .map_err(|e| format!("Failed to run ffmpeg: {}", e))?;
+    
+    if output.status.success() {
+        Ok(())
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let message = stderr.trim();
+        Err(if message.is_empty() {
+            "Invalid filtergraph".to_string()
+        } else {
+            message.to_string()
+        })
+    }
+}
+
+/// Render a difference video of two versions of a clip (e.g. original vs converted).
+/// B is scaled to A's resolution, both start at t=0 and the output stops at the shorter
+/// input. Luma differences are amplified 4x and chroma is neutralised so artifacts show
+/// up as bright areas on black.
+pub fn diff_video(
+    path_a: &str,
+    path_b: &str,
+    output_path: &str,
+    ffmpeg_path: Option<&std::path::Path>,
+    ffprobe_path: Option<&std::path::Path>,
+) -> Result<String, ConvertError> {
+    let video_size = |path: &str| -> Result<(u32, u32), ConvertError> {
+        let info = probe_file(path, ffprobe_path).map_err(|_| ConvertError::InputNotFound(path.to_string()))?;
+        info.streams
+            .iter()
+            .filter(|s| s.stream_type == StreamType::Video)
+            .find_map(|s| Some((s.width?, s.height?)))
+            .ok_or_else(|| ConvertError::ConversionFailed(format!("No video stream in {}", path)))
+    };
+    let (width, height) = video_size(path_a)?;
+    video_size(path_b)?;
+    
+    let graph = format!(
+        "[0:v]format=yuv420p,setpts=PTS-STARTPTS[a];\
+         [1:v]scale={}:{},format=yuv420p,setpts=PTS-STARTPTS[b];\
+         [a][b]blend=all_mode=difference:shortest=1,lutyuv=y=val*4:u=128:v=128[out]",
+        width, height
+    );
+    let args: Vec<String> = [
+        "-i", path_a, "-i", path_b,
+        "-filter_complex", &graph,
+        "-map", "[out]",
+        "-c:v", "libx264", "-crf", "18", "-pix_fmt", "yuv420p",
+        "-an", "-y", output_path,
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect();
+    
+    run_ffmpeg(ffmpeg_path, &args)?;
+    Ok(output_path.to_string())
+}
+
+/// How `paletteuse` spreads the error of mapping to 256 colors
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DitherMode {
+    /// Ordered pattern: visible crosshatch, but compresses best and doesn't shimmer
+    Bayer,
+    /// Error diffusion (ffmpeg's default): smoothest gradients, larger files
+    #[default]
+    Sierra,
+    /// Flat color bands, smallest files
+    None,
+}
+
+impl DitherMode {
+    fn paletteuse_options(self) -> &'static str {
+        match self {
+            DitherMode::Bayer => "=dither=bayer:bayer_scale=3",
+            DitherMode::Sierra => "",
+            DitherMode::None => "=dither=none",
+        }
+    }
+}
+
+/// Frame rate, width and dithering of an animated GIF (the GIF preset's values by default)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GifOptions {
+    #[serde(default = "default_gif_fps")]
+    pub fps: u32,
+    /// Output width; -1 keeps the source size
+    #[serde(default = "default_gif_width")]
+    pub width: i32,
+    #[serde(default)]
+    pub dither: DitherMode,
+}
+
+/// Frame rate and width of the GIF preset
+pub const GIF_DEFAULT_FPS: u32 = 15;
+pub const GIF_DEFAULT_WIDTH: i32 = 480;
+
+fn default_gif_fps() -> u32 {
+    GIF_DEFAULT_FPS
+}
+
+fn default_gif_width() -> i32 {
+    GIF_DEFAULT_WIDTH
+}
+
+/// Two-pass-in-one-graph GIF filter: a palette generated from the clip itself keeps
+/// colors far better than ffmpeg's default 256-color quantization
+pub fn gif_palette_filter(fps: u32, width: i32, dither: DitherMode) -> String {
+    format!(
+        "fps={},scale={}:-1:flags=lanczos,split[s0][s1];[s0]palettegen[p];[s1][p]paletteuse{}",
+        fps,
+        width,
+        dither.paletteuse_options()
+    )
+}
+
+/// Running counter burned into review copies
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum OverlayCounter {
+    #[default]
+    Timecode,
+    Frame,
+    None,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverlayPosition {
+    TopLeft,
+    TopCenter,
+    TopRight,
+    BottomLeft,
+    #[default]
+    BottomCenter,
+    BottomRight,
+}
+
+/// Burned-in reference text for review copies: a custom label (e.g. the scene name)
+/// followed by the timecode or frame number
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReviewOverlay {
+    pub label: Option<String>,
+    #[serde(default)]
+    pub counter: OverlayCounter,
+    #[serde(default)]
+    pub position: OverlayPosition,
+    /// Text height in pixels (default 24)
+    pub font_size: Option<u32>,
+}
+
+/// Turn or mirror the picture
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum RotateMode {
+    #[serde(rename = "rotate_90_cw")]
+    Rotate90CW,
+    #[serde(rename = "rotate_90_ccw")]
+    Rotate90CCW,
+    #[serde(rename = "rotate_180")]
+    Rotate180,
+    #[serde(rename = "flip_h")]
+    FlipH,
+    #[serde(rename = "flip_v")]
+    FlipV,
+}
+
+impl RotateMode {
+    pub fn filter(self) -> &'static str {
+        match self {
+            RotateMode::Rotate90CW => "transpose=clock",
+            RotateMode::Rotate90CCW => "transpose=cclock",
+            RotateMode::Rotate180 => "hflip,vflip",
+            RotateMode::FlipH => "hflip",
+            RotateMode::FlipV => "vflip",
+        }
+    }
+
+    /// Clockwise angle for metadata-only rotation (the display matrix); mirroring has no
+    /// metadata equivalent
+    pub fn metadata_degrees(self) -> Option<u32> {
+        match self {
+            RotateMode::Rotate90CW => Some(90),
+            RotateMode::Rotate90CCW => Some(270),
+            RotateMode::Rotate180 => Some(180),
+            RotateMode::FlipH | RotateMode::FlipV => None,
+        }
+    }
+}
+
+/// Region of the picture to keep, in input pixels from the top-left corner
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct CropRect {
+    pub w: u32,
+    pub h: u32,
+    #[serde(default)]
+    pub x: u32,
+    #[serde(default)]
+    pub y: u32,
+}
+
+/// Subtitles to render into the picture (hard subs) for players without subtitle support
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "source", rename_all = "snake_case")]
+pub enum BurnSubtitleOptions {
+    /// A text subtitle track of the input, by stream index as reported by `probe_media_file`
+    Embedded { stream_index: u32 },
+    /// A separate .srt/.ass/.vtt file
+    External { path: String },
+}
+
+/// `subtitles` filter rendering `file` (its `track`-th subtitle stream, counting subtitle
+/// streams only). `offset_secs` is where the video starts in the file, for trimmed inputs
+/// whose timestamps restart at 0 while the subtitle file's don't.
+pub fn burn_subtitles_filter(file: &str, track: Option<u32>, offset_secs: f64) -> String {
+    let mut filter = format!("subtitles=filename={}", escape_filter_option(file));
+    if let Some(track) = track {
+        filter.push_str(&format!(":si={}", track));
+    }
+    if offset_secs > 0.0 {
+        filter = format!("setpts=PTS+{:.3}/TB,{},setpts=PTS-STARTPTS", offset_secs, filter);
+    }
+    filter
+}
+
+/// Fonts tried in order for drawtext; without one ffmpeg falls back to fontconfig,
+/// which Windows and macOS builds often lack
+const FONT_CANDIDATES: &[&str] = &[
+    "C:/Windows/Fonts/arial.ttf",
+    "/System/Library/Fonts/Supplemental/Arial.ttf",
+    "/Library/Fonts/Arial.ttf",
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/TTF/DejaVuSans.ttf",
+];
+
+/// Backslash-escape `specials` (and backslashes) for one level of ffmpeg's parsing
+fn escape_level(value: &str, specials: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if c == '\\' || specials.contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Escape a drawtext/filter option value so it survives both the option parser
+/// (`:` separates options) and the filtergraph parser (`,` `;` `[` `]` separate filters)
+fn escape_filter_option(value: &str) -> String {
+    escape_level(&escape_level(value, "':"), "'[],;")
+}
+
+/// `drawtext` filter for a review overlay. The label is taken literally: drawtext's own
+/// `%{...}` expansion is only used for the counter.
+pub fn review_overlay_filter(overlay: &ReviewOverlay) -> String {
+    // Escape for drawtext's text expansion first, so '%' and '\' in labels stay literal
+    let mut text = overlay
+        .label
+        .as_deref()
+        .map(|label| escape_level(label, "%"))
+        .unwrap_or_default();
+    let counter = match overlay.counter {
+        OverlayCounter::Timecode => "%{pts:hms}",
+        OverlayCounter::Frame => "%{frame_num}",
+        OverlayCounter::None => "",
+    };
+    if !counter.is_empty() {
+        if !text.is_empty() {
+            text.push_str("  ");
+        }
+        text.push_str(counter);
+    }
+    
+    let (x, y) = match overlay.position {
+        OverlayPosition::TopLeft => ("10", "10"),
+        OverlayPosition::TopCenter => ("(w-tw)/2", "10"),
+        OverlayPosition::TopRight => ("w-tw-10", "10"),
+        OverlayPosition::BottomLeft => ("10", "h-th-10"),
+        OverlayPosition::BottomCenter => ("(w-tw)/2", "h-th-10"),
+        OverlayPosition::BottomRight => ("w-tw-10", "h-th-10"),
+    };
+    let mut filter = format!(
+        "drawtext=text={}:x={}:y={}:fontsize={}:fontcolor=white:box=1:boxcolor=black@0.6:boxborderw=6",
+        escape_filter_option(&text),
+        x,
+        y,
+        overlay.font_size.unwrap_or(24)
+    );
+    if let Some(font) = FONT_CANDIDATES.iter().find(|f| std::path::Path::new(f).exists()) {
+        filter.push_str(&format!(":fontfile={}", escape_filter_option(font)));
+    }
+    filter
+}
@@ -0,0 +1,96 @@
+use std::collections::BTreeSet;
+use std::sync::Mutex;
+
+/// Running conversions. One conversion operation (a single file, a batch) runs at a time;
+/// a batch may run several jobs, each its own ffmpeg process, at once.
+#[derive(Default)]
+pub struct JobRegistry {
+    state: Mutex<JobState>,
+}
+
+#[derive(Default)]
+struct JobState {
+    /// An operation holds the registry (see `begin`)
+    busy: bool,
+    active: BTreeSet<u64>,
+    next_id: u64,
+}
+
+/// Held by the running conversion operation; frees the registry when dropped
+pub struct OperationGuard<'a> {
+    registry: &'a JobRegistry,
+}
+
+impl Drop for OperationGuard<'_> {
+    fn drop(&mut self) {
+        self.registry.state.lock().unwrap().busy = false;
+    }
+}
+
+/// Finishes its job when dropped, so the job is cleared even if it panics
+pub struct JobGuard<'a> {
+    registry: &'a JobRegistry,
+    id: u64,
+}
+
+impl Drop for JobGuard<'_> {
+    fn drop(&mut self) {
+        self.registry.finish_job(self.id);
+    }
+}
+
+impl JobRegistry {
+    /// Claim the registry for a conversion operation. Fails if one is already running.
+    pub fn begin(&self) -> Result<OperationGuard<'_>, String> {
+        let mut state = self.state.lock().unwrap();
+        if state.busy {
+            return Err("A conversion is already in progress".to_string());
+        }
+        state.busy = true;
+        Ok(OperationGuard { registry: self })
+    }
+
+    /// Id for a new job (unique for the app's lifetime), active until `finish_job`
+    pub fn start_job(&self) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        state.next_id += 1;
+        let id = state.next_id;
+        state.active.insert(id);
+        id
+    }
+
+    pub fn finish_job(&self, id: u64) {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).active.remove(&id);
+    }
+
+    /// Finish job `id` once the returned guard is dropped
+    pub fn finish_on_drop(&self, id: u64) -> JobGuard<'_> {
+        JobGuard { registry: self, id }
+    }
+
+    pub fn is_busy(&self) -> bool {
+        self.state.lock().unwrap().busy
+    }
+
+    /// Ids of the jobs running right now, oldest first
+    pub fn active_jobs(&self) -> Vec<u64> {
+        self.state.lock().unwrap().active.iter().copied().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn job_is_finished_when_it_panics() {
+        let jobs = JobRegistry::default();
+        let id = jobs.start_job();
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _job = jobs.finish_on_drop(id);
+            panic!("encoder crashed");
+        }));
+        assert!(panicked.is_err());
+        assert!(jobs.active_jobs().is_empty());
+    }
+}
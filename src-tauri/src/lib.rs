@@ -1,20 +1,53 @@
+mod analysis;
+mod cancel;
+mod capabilities;
+mod concat;
 mod convert;
+mod devices;
+mod estimate;
+mod filters;
+mod jobs;
 mod logger;
+mod mp4tags;
+mod pause;
+mod preflight;
 mod presets;
+mod previews;
 mod probe;
+mod profiles;
+mod settings;
+mod subtitles;
+mod temp;
+#[cfg(test)]
+mod test_support;
 
+use analysis::BitrateAnalysis;
+use cancel::CancelRegistry;
+use capabilities::EncoderInfo;
+use concat::{ConcatFpsReport, ConcatJob, ConcatResult};
 use convert::{
-    check_ffmpeg, generate_output_path, start_conversion, AdvancedOptions, ConvertOptions,
-    ConvertResult, StreamSelection,
+    chapter_split_options, check_ffmpeg, generate_output_path, plan_batch_outputs, provenance_from_tags,
+    sharing_options, start_conversion, AdvancedOptions, BatchItemResult, BatchJobStarted, BatchProgress, BatchResult, ContainerCodecSupport,
+    ConvertError, ConvertOptions, ConvertResult, PlannedOutput, QuickRemuxResult, SegmentResult, SegmentSplit, SharingResult,
+    StreamSelection,
 };
-use logger::{ConversionLog, LogStore};
-use presets::{get_all_presets, Preset};
-use probe::{check_ffprobe, probe_file, MediaInfo};
+use devices::DeviceCompatibility;
+use estimate::SizeEstimate;
+use jobs::JobRegistry;
+use logger::{ConversionLog, LogLevel, LogStore};
+use pause::PauseControl;
+use preflight::{PreflightChecks, PreflightReport};
+use previews::SpriteSheet;
+use presets::{get_all_presets, Preset, PresetSuggestion};
+use probe::{check_ffprobe, probe_file, probe_file_with_retry, MediaInfo, ProbeCache};
+use profiles::{AppliedProfile, JobProfile, ProfileStore};
+use settings::{AppSettings, SettingsStore};
+use temp::CleanupResult;
 
+use std::collections::{HashMap, HashSet};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use tauri::{Manager, State};
-use tokio::sync::Mutex;
+use tauri::{Emitter, Manager, State};
 
 /// Get the path to a sidecar binary (bundled FFmpeg/FFprobe)
 pub fn get_sidecar_path(app: &tauri::AppHandle, name: &str) -> Option<std::path::PathBuf> {
@@ -63,19 +96,30 @@ pub fn get_sidecar_path(app: &tauri::AppHandle, name: &str) -> Option<std::path:
     None
 }
 
-/// Shared state for cancellation and logging
+/// Shared state for cancellation, logging and settings
 pub struct AppState {
-    cancel_flag: Arc<AtomicBool>,
-    converting: Arc<Mutex<bool>>,
+    cancellation: Arc<CancelRegistry>,
+    jobs: Arc<JobRegistry>,
+    pause: Arc<PauseControl>,
+    /// Output paths of conversions that are running but may not exist on disk yet
+    reserved_outputs: Arc<std::sync::Mutex<HashSet<String>>>,
     log_store: Arc<LogStore>,
+    settings: Arc<SettingsStore>,
+    profiles: Arc<ProfileStore>,
+    probe_cache: Arc<ProbeCache>,
 }
 
 impl Default for AppState {
     fn default() -> Self {
         Self {
-            cancel_flag: Arc::new(AtomicBool::new(false)),
-            converting: Arc::new(Mutex::new(false)),
+            cancellation: Arc::new(CancelRegistry::default()),
+            jobs: Arc::new(JobRegistry::default()),
+            pause: Arc::new(PauseControl::default()),
+            reserved_outputs: Arc::new(std::sync::Mutex::new(HashSet::new())),
             log_store: Arc::new(LogStore::default()),
+            settings: Arc::new(SettingsStore::default()),
+            profiles: Arc::new(ProfileStore::default()),
+            probe_cache: Arc::new(ProbeCache::default()),
         }
     }
 }
@@ -88,6 +132,65 @@ fn get_presets() -> Vec<Preset> {
     get_all_presets()
 }
 
+/// Presets this machine can run: hardware-encoder presets only when their encoder works here
+#[tauri::command]
+async fn get_available_presets(app: tauri::AppHandle) -> Result<Vec<Preset>, String> {
+    let sidecar_path = get_sidecar_path(&app, "ffmpeg");
+    let (working, encoders, muxers) = tokio::task::spawn_blocking(move || {
+        let sidecar_path = sidecar_path.as_deref();
+        capabilities::detect_hw_encoders(sidecar_path).map(|working| {
+            (
+                working,
+                capabilities::list_encoders(sidecar_path).ok(),
+                capabilities::list_muxers(sidecar_path).ok(),
+            )
+        })
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+    Ok(presets::available_presets(&working, encoders.as_deref(), muxers.as_deref()))
+}
+
+/// Export a preset as versioned JSON for sharing
+#[tauri::command]
+fn export_preset(id: String) -> Result<String, String> {
+    presets::export_preset(&id)
+}
+
+/// Import a shared preset (from `export_preset`) as a custom preset
+#[tauri::command]
+fn import_preset(app: tauri::AppHandle, json: String) -> Result<Preset, String> {
+    let sidecar_path = get_sidecar_path(&app, "ffmpeg");
+    let encoders = capabilities::list_encoders(sidecar_path.as_deref()).ok();
+    presets::import_preset(&json, encoders.as_deref())
+}
+
+/// Save a user-built preset (new, or replacing their preset with the same id)
+#[tauri::command]
+fn save_custom_preset(app: tauri::AppHandle, preset: Preset) -> Result<Preset, String> {
+    let sidecar_path = get_sidecar_path(&app, "ffmpeg");
+    let encoders = capabilities::list_encoders(sidecar_path.as_deref()).ok();
+    presets::save_custom_preset(preset, encoders.as_deref())
+}
+
+/// Try a preset's codecs, format and arguments on this ffmpeg build before using it
+#[tauri::command]
+async fn validate_preset(app: tauri::AppHandle, state: State<'_, AppState>, preset: Preset) -> Result<(), String> {
+    let sidecar_path = get_sidecar_path(&app, "ffmpeg");
+    let settings = state.settings.get();
+    tokio::task::spawn_blocking(move || {
+        presets::validate_preset_with_ffmpeg(&preset, &settings, sidecar_path.as_deref())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Delete a user preset; built-in presets can't be deleted
+#[tauri::command]
+fn delete_custom_preset(id: String) -> Result<(), String> {
+    presets::delete_custom_preset(&id)
+}
+
 /// Check if ffmpeg is installed and return version
 #[tauri::command]
 fn check_ffmpeg_installed(app: tauri::AppHandle) -> Result<String, String> {
@@ -95,6 +198,36 @@ fn check_ffmpeg_installed(app: tauri::AppHandle) -> Result<String, String> {
     check_ffmpeg(sidecar_path.as_deref()).map_err(|e| e.to_string())
 }
 
+/// Video/audio/subtitle encoders valid for a container and available in this ffmpeg build
+#[tauri::command]
+async fn codecs_for_container(app: tauri::AppHandle, format: String) -> Result<ContainerCodecSupport, String> {
+    let sidecar_path = get_sidecar_path(&app, "ffmpeg");
+    let available = capabilities::list_encoders(sidecar_path.as_deref())?;
+    convert::codecs_for_container(&format, &available)
+        .ok_or_else(|| format!("Unknown container format: {}", format))
+}
+
+/// Encoders compiled into the bundled ffmpeg
+#[tauri::command]
+async fn list_encoders(app: tauri::AppHandle) -> Result<Vec<EncoderInfo>, String> {
+    let sidecar_path = get_sidecar_path(&app, "ffmpeg");
+    capabilities::list_encoders(sidecar_path.as_deref())
+}
+
+/// Decoders compiled into the bundled ffmpeg
+#[tauri::command]
+async fn list_decoders(app: tauri::AppHandle) -> Result<Vec<EncoderInfo>, String> {
+    let sidecar_path = get_sidecar_path(&app, "ffmpeg");
+    capabilities::list_decoders(sidecar_path.as_deref())
+}
+
+/// Whether the bundled ffmpeg can encode with `codec` (an encoder name like "libx265")
+#[tauri::command]
+async fn check_codec_support(app: tauri::AppHandle, codec: String) -> Result<bool, String> {
+    let sidecar_path = get_sidecar_path(&app, "ffmpeg");
+    capabilities::has_encoder(sidecar_path.as_deref(), &codec)
+}
+
 /// Check if ffprobe is installed and return version
 #[tauri::command]
 fn check_ffprobe_installed(app: tauri::AppHandle) -> Result<String, String> {
@@ -104,15 +237,525 @@ fn check_ffprobe_installed(app: tauri::AppHandle) -> Result<String, String> {
 
 /// Probe a media file for info
 #[tauri::command]
-fn probe_media_file(app: tauri::AppHandle, path: String) -> Result<MediaInfo, String> {
+fn probe_media_file(app: tauri::AppHandle, state: State<'_, AppState>, path: String) -> Result<MediaInfo, String> {
     let sidecar_path = get_sidecar_path(&app, "ffprobe");
-    probe_file(&path, sidecar_path.as_deref()).map_err(|e| e.to_string())
+    state
+        .probe_cache
+        .probe(&path, sidecar_path.as_deref(), &state.settings.get().probe_retry)
+        .map_err(|e| e.to_string())
+}
+
+/// Bitrate over time (one point per `interval_secs`, default 1 s) for graphing.
+/// Cancel with `cancel_operation(operation_id)`, an id the caller picks for this analysis.
+#[tauri::command]
+async fn analyze_bitrate(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    input_path: String,
+    interval_secs: Option<f64>,
+    operation_id: Option<String>,
+) -> Result<BitrateAnalysis, String> {
+    let sidecar_path = get_sidecar_path(&app, "ffprobe");
+    let operation_id = cancel::operation_id(cancel::BITRATE_ANALYSIS, operation_id);
+    let cancel_flag = state.cancellation.register(&operation_id);
+    let result = tokio::task::spawn_blocking(move || {
+        analysis::analyze_bitrate(&input_path, interval_secs.unwrap_or(1.0), sidecar_path.as_deref(), &cancel_flag)
+    })
+    .await;
+    state.cancellation.remove(&operation_id);
+    result.map_err(|e| e.to_string())?.map_err(|e| e.to_string())
+}
+
+/// Recommend a preset for a file based on its type, resolution and codec
+#[tauri::command]
+fn suggest_preset(app: tauri::AppHandle, state: State<'_, AppState>, input_path: String) -> Result<PresetSuggestion, String> {
+    let sidecar_path = get_sidecar_path(&app, "ffprobe");
+    let info = state
+        .probe_cache
+        .probe(&input_path, sidecar_path.as_deref(), &state.settings.get().probe_retry)
+        .map_err(|e| e.to_string())?;
+    Ok(presets::suggest_preset(&info))
+}
+
+/// Probe all media files in a folder, emitting `probe-folder-progress` as files complete.
+/// Cancel with `cancel_operation(operation_id)`: the caller's id, or the generated one the
+/// progress events carry.
+#[tauri::command]
+async fn probe_folder(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    dir: String,
+    recursive: bool,
+    concurrency: Option<usize>,
+    operation_id: Option<String>,
+) -> Result<Vec<MediaInfo>, String> {
+    let sidecar_path = get_sidecar_path(&app, "ffprobe");
+    let concurrency = concurrency.unwrap_or_else(|| {
+        std::thread::available_parallelism()
+            .map(|n| n.get().min(4))
+            .unwrap_or(2)
+    });
+    let operation_id = cancel::operation_id(cancel::PROBE_FOLDER, operation_id);
+    let cancel_flag = state.cancellation.register(&operation_id);
+    let result = probe::probe_folder(
+        app,
+        std::path::PathBuf::from(dir),
+        probe::FolderScan {
+            recursive,
+            concurrency,
+            retry: state.settings.get().probe_retry,
+            operation_id: operation_id.clone(),
+        },
+        sidecar_path,
+        state.probe_cache.clone(),
+        cancel_flag,
+    )
+    .await;
+    state.cancellation.remove(&operation_id);
+    result.map_err(|e| e.to_string())
+}
+
+/// Read the conversion settings embedded in a file by a previous conversion, if any
+#[tauri::command]
+fn read_conversion_provenance(app: tauri::AppHandle, path: String) -> Result<Option<String>, String> {
+    let sidecar_path = get_sidecar_path(&app, "ffprobe");
+    let info = probe_file(&path, sidecar_path.as_deref()).map_err(|e| e.to_string())?;
+    Ok(provenance_from_tags(&info.format.tags))
+}
+
+/// Convert a standalone subtitle file to another subtitle format
+#[tauri::command]
+async fn convert_subtitle(
+    app: tauri::AppHandle,
+    input_path: String,
+    output_path: String,
+    target_format: String,
+    charset: Option<String>,
+) -> Result<String, String> {
+    let sidecar_path = get_sidecar_path(&app, "ffmpeg");
+    subtitles::convert_subtitle_file(
+        &input_path,
+        &output_path,
+        &target_format,
+        charset.as_deref(),
+        sidecar_path.as_deref(),
+    )
+    .map_err(|e| e.to_string())
+}
+
+/// Extract a file's attachments (fonts, images) into a directory
+#[tauri::command]
+async fn extract_attachments(app: tauri::AppHandle, input_path: String, output_dir: String) -> Result<Vec<String>, String> {
+    let ffmpeg_path = get_sidecar_path(&app, "ffmpeg");
+    let ffprobe_path = get_sidecar_path(&app, "ffprobe");
+    convert::extract_attachments(&input_path, &output_dir, ffmpeg_path.as_deref(), ffprobe_path.as_deref())
+        .map_err(|e| e.to_string())
+}
+
+/// Save one embedded subtitle track to its own file (.srt/.ass/.vtt/.sup by format);
+/// returns the path written. Logged in the conversion history like a conversion.
+#[tauri::command]
+async fn extract_subtitles(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    input_path: String,
+    stream_index: u32,
+    output_path: String,
+) -> Result<String, String> {
+    let ffmpeg_path = get_sidecar_path(&app, "ffmpeg");
+    let ffprobe_path = get_sidecar_path(&app, "ffprobe");
+    let info = state
+        .probe_cache
+        .probe(&input_path, ffprobe_path.as_deref(), &state.settings.get().probe_retry)
+        .map_err(|e| e.to_string())?;
+    let (args, output_path) =
+        subtitles::subtitle_extraction_args(&info, stream_index, &output_path).map_err(|e| e.to_string())?;
+    
+    let mut log = ConversionLog::new(&input_path, &output_path, None, None, &format!("ffmpeg {}", args.join(" ")));
+    log.add_entry(LogLevel::Info, &format!("Extracting subtitle stream #{}", stream_index), None);
+    let result = tokio::task::spawn_blocking(move || convert::run_ffmpeg(ffmpeg_path.as_deref(), &args))
+        .await
+        .map_err(|e| e.to_string())?;
+    match result {
+        Ok(_) => {
+            log.add_entry(LogLevel::Info, "Subtitle extracted", None);
+            log.finish(true, None);
+            state.log_store.add_log(log);
+            Ok(output_path)
+        }
+        Err(e) => {
+            log.add_entry(LogLevel::Error, &e.to_string(), Some("FFmpeg"));
+            log.finish(false, Some(e.to_string()));
+            state.log_store.add_log(log);
+            Err(e.to_string())
+        }
+    }
+}
+
+/// Save a file's embedded cover art (album art) as a .jpg or .png
+#[tauri::command]
+async fn extract_cover_art(app: tauri::AppHandle, input_path: String, output_path: String) -> Result<String, String> {
+    let ffmpeg_path = get_sidecar_path(&app, "ffmpeg");
+    let ffprobe_path = get_sidecar_path(&app, "ffprobe");
+    convert::extract_cover_art(&input_path, &output_path, ffmpeg_path.as_deref(), ffprobe_path.as_deref())
+        .map_err(|e| e.to_string())
+}
+
+/// Save the audio track on its own, copied in its original codec unless `reencode`
+#[tauri::command]
+async fn extract_audio(
+    app: tauri::AppHandle,
+    input_path: String,
+    output_path: String,
+    reencode: bool,
+) -> Result<ConvertResult, String> {
+    let ffmpeg_path = get_sidecar_path(&app, "ffmpeg");
+    let ffprobe_path = get_sidecar_path(&app, "ffprobe");
+    tokio::task::spawn_blocking(move || {
+        convert::extract_audio(&input_path, &output_path, reencode, ffmpeg_path.as_deref(), ffprobe_path.as_deref())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| e.to_string())
+}
+
+/// Cut a file into consecutive parts of `segment_secs` each (stream copy unless `reencode`),
+/// emitting `split-progress`; cancelled with `cancel_operation("split")`
+#[tauri::command]
+async fn split_by_duration(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    input_path: String,
+    output_dir: String,
+    segment_secs: f64,
+    reencode: bool,
+) -> Result<SegmentResult, String> {
+    let ffmpeg_path = get_sidecar_path(&app, "ffmpeg");
+    let ffprobe_path = get_sidecar_path(&app, "ffprobe");
+    let cancel_flag = state.cancellation.register(cancel::SPLIT);
+    let result = tokio::task::spawn_blocking(move || {
+        convert::split_by_duration(
+            &input_path,
+            &output_dir,
+            SegmentSplit { segment_secs, reencode },
+            ffmpeg_path.as_deref(),
+            ffprobe_path.as_deref(),
+            &cancel_flag,
+            |progress| {
+                let _ = app.emit("split-progress", &progress);
+            },
+        )
+    })
+    .await;
+    state.cancellation.remove(cancel::SPLIT);
+    result.map_err(|e| e.to_string())?.map_err(|e| e.to_string())
+}
+
+/// Split a stereo file into left and right mono WAV files; returns their paths
+#[tauri::command]
+async fn split_stereo(app: tauri::AppHandle, input_path: String, output_dir: String) -> Result<Vec<String>, String> {
+    let ffmpeg_path = get_sidecar_path(&app, "ffmpeg");
+    let ffprobe_path = get_sidecar_path(&app, "ffprobe");
+    convert::split_stereo(&input_path, &output_dir, ffmpeg_path.as_deref(), ffprobe_path.as_deref())
+        .map_err(|e| e.to_string())
+}
+
+/// Check a -vf/-af/-filter_complex string before running a long encode; returns ffmpeg's error if invalid
+#[tauri::command]
+async fn validate_filtergraph(app: tauri::AppHandle, filter: String, audio: Option<bool>) -> Result<(), String> {
+    let sidecar_path = get_sidecar_path(&app, "ffmpeg");
+    filters::validate_filtergraph(&filter, audio.unwrap_or(false), sidecar_path.as_deref())
+}
+
+/// Check the frame rates of clips to be joined and recommend a common rate
+#[tauri::command]
+async fn check_concat_fps(app: tauri::AppHandle, inputs: Vec<String>) -> Result<ConcatFpsReport, String> {
+    let sidecar_path = get_sidecar_path(&app, "ffprobe");
+    concat::analyze_concat_fps(&inputs, sidecar_path.as_deref()).map_err(|e| e.to_string())
+}
+
+/// Join files end to end, stream-copying when they match and re-encoding otherwise.
+/// Runs as a conversion: one at a time, stopped by `cancel_convert`, logged in the history.
+#[tauri::command]
+async fn concat_files(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    inputs: Vec<String>,
+    output_path: String,
+    reencode: bool,
+    normalize_fps: Option<f64>,
+) -> Result<ConcatResult, String> {
+    // Fails if already converting
+    let _operation = state.jobs.begin()?;
+    let cancel_flag = state.cancellation.register(cancel::CONVERSION);
+    let job_id = state.jobs.start_job();
+    let job = state.jobs.finish_on_drop(job_id);
+    
+    let ffmpeg_path = get_sidecar_path(&app, "ffmpeg");
+    let ffprobe_path = get_sidecar_path(&app, "ffprobe");
+    let settings = state.settings.get();
+    let mut log = ConversionLog::new(&inputs.join(", "), &output_path, None, None, "");
+    log.add_entry(LogLevel::Info, &format!("Joining {} files", inputs.len()), None);
+    let concat_job = ConcatJob {
+        inputs,
+        output_path,
+        reencode,
+        normalize_fps,
+    };
+    let joined = tokio::task::spawn_blocking(move || {
+        let result = concat::concat_files(
+            &concat_job,
+            &settings,
+            ffmpeg_path.as_deref(),
+            ffprobe_path.as_deref(),
+            &cancel_flag,
+            &mut log,
+        );
+        (result, log)
+    })
+    .await;
+    drop(job);
+    state.cancellation.remove(cancel::CONVERSION);
+    
+    let (result, mut log) = joined.map_err(|e| e.to_string())?;
+    match result {
+        Ok(result) => {
+            log.add_entry(LogLevel::Info, "Files joined", None);
+            log.finish(true, None);
+            state.log_store.add_log(log);
+            Ok(result)
+        }
+        Err(ConvertError::Cancelled) => {
+            log.add_entry(LogLevel::Warning, "Join cancelled by user", None);
+            log.finish(false, Some("Cancelled".to_string()));
+            state.log_store.add_log(log);
+            Err(ConvertError::Cancelled.to_string())
+        }
+        Err(e) => {
+            log.add_entry(LogLevel::Error, &e.to_string(), Some("FFmpeg"));
+            log.finish(false, Some(e.to_string()));
+            state.log_store.add_log(log);
+            Err(e.to_string())
+        }
+    }
+}
+
+/// Render a video highlighting the differences between two versions of a clip
+#[tauri::command]
+async fn diff_video(app: tauri::AppHandle, path_a: String, path_b: String, output_path: String) -> Result<String, String> {
+    let ffmpeg_path = get_sidecar_path(&app, "ffmpeg");
+    let ffprobe_path = get_sidecar_path(&app, "ffprobe");
+    filters::diff_video(&path_a, &path_b, &output_path, ffmpeg_path.as_deref(), ffprobe_path.as_deref())
+        .map_err(|e| e.to_string())
+}
+
+/// Render a small animated GIF/WebP preview of a clip around a timestamp
+#[tauri::command]
+async fn generate_preview_clip(
+    app: tauri::AppHandle,
+    input_path: String,
+    at_secs: f64,
+    duration_secs: f64,
+    output_path: String,
+) -> Result<String, String> {
+    let ffmpeg_path = get_sidecar_path(&app, "ffmpeg");
+    let ffprobe_path = get_sidecar_path(&app, "ffprobe");
+    previews::generate_preview_clip(
+        &input_path,
+        at_secs,
+        duration_secs,
+        &output_path,
+        ffmpeg_path.as_deref(),
+        ffprobe_path.as_deref(),
+    )
+    .map_err(|e| e.to_string())
+}
+
+/// Save a poster frame, emitting `thumbnail-done` with the path. Independent of the
+/// conversion lock, so it works while a conversion runs.
+#[tauri::command]
+async fn generate_thumbnail(
+    app: tauri::AppHandle,
+    input_path: String,
+    timestamp: Option<String>,
+    output_path: String,
+    width: Option<u32>,
+) -> Result<String, String> {
+    let ffmpeg_path = get_sidecar_path(&app, "ffmpeg");
+    let ffprobe_path = get_sidecar_path(&app, "ffprobe");
+    let path = tokio::task::spawn_blocking(move || {
+        previews::generate_thumbnail(
+            &input_path,
+            timestamp.as_deref(),
+            &output_path,
+            width,
+            ffmpeg_path.as_deref(),
+            ffprobe_path.as_deref(),
+        )
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| e.to_string())?;
+    let _ = app.emit("thumbnail-done", &path);
+    Ok(path)
+}
+
+/// Tile evenly spaced frames into one image for scrubbing previews, plus a JSON index
+/// of which time each cell shows
+#[tauri::command]
+async fn generate_sprite_sheet(
+    app: tauri::AppHandle,
+    input_path: String,
+    rows: u32,
+    cols: u32,
+    thumb_width: u32,
+    output_path: String,
+) -> Result<SpriteSheet, String> {
+    let ffmpeg_path = get_sidecar_path(&app, "ffmpeg");
+    let ffprobe_path = get_sidecar_path(&app, "ffprobe");
+    tokio::task::spawn_blocking(move || {
+        previews::generate_sprite_sheet(
+            &input_path,
+            rows,
+            cols,
+            thumb_width,
+            &output_path,
+            ffmpeg_path.as_deref(),
+            ffprobe_path.as_deref(),
+        )
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| e.to_string())
+}
+
+/// Run the pre-conversion checks (input, output, disk space, codecs, container, streams)
+/// so the UI can show readiness before the user starts. `checks` turns individual checks off.
+#[tauri::command]
+async fn preflight(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    mut options: ConvertOptions,
+    checks: Option<PreflightChecks>,
+) -> Result<PreflightReport, String> {
+    let ffmpeg_path = get_sidecar_path(&app, "ffmpeg");
+    let ffprobe_path = get_sidecar_path(&app, "ffprobe");
+    options.settings = state.settings.get();
+    Ok(preflight::run_preflight(
+        &options,
+        &checks.unwrap_or_default(),
+        ffmpeg_path.as_deref(),
+        ffprobe_path.as_deref(),
+    ))
+}
+
+/// Per-device (phones, TVs, browsers) playback warnings for the output these options produce
+#[tauri::command]
+async fn check_device_compatibility(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    mut options: ConvertOptions,
+) -> Result<Vec<DeviceCompatibility>, String> {
+    let ffprobe_path = get_sidecar_path(&app, "ffprobe");
+    options.settings = state.settings.get();
+    let info = probe_file_with_retry(&options.input_path, ffprobe_path.as_deref(), &options.settings.probe_retry).ok();
+    devices::check_device_compatibility(&options, info.as_ref()).map_err(|e| e.to_string())
+}
+
+/// Approximate output size of a conversion, as a range with a confidence level
+#[tauri::command]
+async fn estimate_output_size(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    mut options: ConvertOptions,
+) -> Result<SizeEstimate, String> {
+    let ffprobe_path = get_sidecar_path(&app, "ffprobe");
+    options.settings = state.settings.get();
+    let info = probe_file_with_retry(&options.input_path, ffprobe_path.as_deref(), &options.settings.probe_retry)
+        .map_err(|e| e.to_string())?;
+    estimate::estimate_output_size(&options, &info).map_err(|e| e.to_string())
+}
+
+/// The ffmpeg command lines a conversion would run (two for a two-pass job), quoted for
+/// the platform's shell. Measurement passes (ReplayGain, two-pass loudness) are run to
+/// fill in their values; no output is written.
+#[tauri::command]
+async fn build_command_preview(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    mut options: ConvertOptions,
+) -> Result<Vec<String>, String> {
+    let ffmpeg_path = get_sidecar_path(&app, "ffmpeg");
+    let ffprobe_path = get_sidecar_path(&app, "ffprobe");
+    options.settings = state.settings.get();
+    let reserved = state.reserved_outputs.lock().unwrap().clone();
+    let program = convert::ffmpeg_program(ffmpeg_path.as_deref());
+    let passes = convert::preview_passes(options, reserved, ffmpeg_path, ffprobe_path)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(passes.iter().map(|args| convert::shell_command_line(&program, args)).collect())
+}
+
+/// Whether a freshly dropped file looks fully written (heuristic, see `probe::is_file_stable`)
+#[tauri::command]
+async fn is_file_stable(path: String) -> Result<bool, String> {
+    probe::is_file_stable(&path).await.map_err(|e| e.to_string())
+}
+
+/// Whether a file looks like something the app can open (by extension)
+#[tauri::command]
+fn is_supported_file(path: String) -> bool {
+    probe::is_media_file(&path)
+}
+
+/// Generate output path from input and preset, named by `name_template`; with
+/// `image_sequence`, a frame pattern in a new directory
+#[tauri::command]
+fn get_output_path(
+    state: State<'_, AppState>,
+    input_path: String,
+    preset_id: Option<String>,
+    format: Option<String>,
+    output_dir: Option<String>,
+    image_sequence: Option<bool>,
+    name_template: Option<String>,
+) -> String {
+    let reserved = state.reserved_outputs.lock().unwrap();
+    if image_sequence.unwrap_or(false) {
+        return convert::generate_sequence_output_path(
+            &input_path,
+            preset_id.as_deref(),
+            output_dir.as_deref(),
+            &reserved,
+        );
+    }
+    generate_output_path(
+        &input_path,
+        preset_id.as_deref(),
+        format.as_deref(),
+        output_dir.as_deref(),
+        name_template.as_deref(),
+        &reserved,
+    )
 }
 
-/// Generate output path from input and preset
+/// Preview the output path every input of a batch will be written to
 #[tauri::command]
-fn get_output_path(input_path: String, preset_id: Option<String>, format: Option<String>) -> String {
-    generate_output_path(&input_path, preset_id.as_deref(), format.as_deref())
+fn preview_batch_outputs(
+    state: State<'_, AppState>,
+    inputs: Vec<String>,
+    preset_id: Option<String>,
+    output_dir: Option<String>,
+    name_template: Option<String>,
+) -> Vec<PlannedOutput> {
+    let reserved = state.reserved_outputs.lock().unwrap();
+    plan_batch_outputs(
+        &inputs,
+        preset_id.as_deref(),
+        output_dir.as_deref(),
+        name_template.as_deref(),
+        &reserved,
+    )
 }
 
 /// Start conversion
@@ -126,51 +769,374 @@ async fn start_convert(
     advanced: Option<AdvancedOptions>,
     stream_selection: Option<StreamSelection>,
 ) -> Result<ConvertResult, String> {
-    // Check if already converting
-    let mut converting = state.converting.lock().await;
-    if *converting {
-        return Err("A conversion is already in progress".to_string());
-    }
-    *converting = true;
-    
-    // Reset cancel flag
-    state.cancel_flag.store(false, Ordering::Relaxed);
-    
     let options = ConvertOptions {
         input_path,
         output_path,
         preset_id,
         advanced,
         stream_selection,
+        settings: AppSettings::default(),
+        job_id: 0,
     };
     
-    let cancel_flag = state.cancel_flag.clone();
+    run_conversion(app_handle, &state, options).await
+}
+
+/// Run one conversion with logging, refusing to start while another is in progress
+async fn run_conversion(
+    app_handle: tauri::AppHandle,
+    state: &AppState,
+    options: ConvertOptions,
+) -> Result<ConvertResult, String> {
+    // Fails if already converting
+    let _operation = state.jobs.begin()?;
+    
+    // Fresh cancellation and stop tokens for this conversion
+    let cancel_flag = state.cancellation.register(cancel::CONVERSION);
+    let stop_flag = state.cancellation.register(cancel::STOP_CONVERSION);
+    
+    let job_id = state.jobs.start_job();
+    let job = state.jobs.finish_on_drop(job_id);
+    let result = convert_one(app_handle, state, job_id, options, cancel_flag, stop_flag).await;
+    drop(job);
+    
+    state.cancellation.remove(cancel::CONVERSION);
+    state.cancellation.remove(cancel::STOP_CONVERSION);
+    
+    result.map_err(|e| e.to_string())
+}
+
+/// Run one conversion as job `job_id` with the current settings; the caller holds the
+/// operation (`JobRegistry::begin`)
+async fn convert_one(
+    app_handle: tauri::AppHandle,
+    state: &AppState,
+    job_id: u64,
+    mut options: ConvertOptions,
+    cancel_flag: Arc<AtomicBool>,
+    stop_flag: Arc<AtomicBool>,
+) -> Result<ConvertResult, ConvertError> {
+    options.settings = state.settings.get();
+    options.job_id = job_id;
+    
     let log_store = state.log_store.clone();
+    let reserved_output = convert::reserve_output_path(&options, &mut state.reserved_outputs.lock().unwrap());
+    options.output_path = reserved_output.clone();
+    let _reservation = OutputReservation {
+        reserved: &state.reserved_outputs,
+        path: reserved_output,
+    };
     
     // Get sidecar paths
     let ffmpeg_path = get_sidecar_path(&app_handle, "ffmpeg");
     let ffprobe_path = get_sidecar_path(&app_handle, "ffprobe");
     
     // Run conversion with logging
-    let result = start_conversion(app_handle, options, cancel_flag, ffmpeg_path, ffprobe_path, log_store).await;
+    start_conversion(
+        app_handle,
+        options,
+        convert::Interrupts { cancel: cancel_flag, stop: stop_flag },
+        state.pause.clone(),
+        ffmpeg_path,
+        ffprobe_path,
+        log_store,
+    )
+    .await
+}
+
+/// An output path reserved by a running conversion, released when dropped (also if the
+/// conversion panics)
+struct OutputReservation<'a> {
+    reserved: &'a std::sync::Mutex<HashSet<String>>,
+    path: String,
+}
+
+impl Drop for OutputReservation<'_> {
+    fn drop(&mut self) {
+        self.reserved.lock().unwrap_or_else(|e| e.into_inner()).remove(&self.path);
+    }
+}
+
+/// Convert several files as a single operation, up to `max_parallel` (default 1, at most
+/// the number of CPU cores) at a time: `cancel_convert` stops the whole batch, and no
+/// other conversion can start until it is done. Emits `batch-job-started` when a file
+/// starts (mapping its job id, which progress events carry, to its index) and
+/// `batch-progress` after each file. A failed file doesn't stop the rest unless
+/// `stop_on_error` is set.
+#[tauri::command]
+async fn start_batch_convert(
+    app_handle: tauri::AppHandle,
+    state: State<'_, AppState>,
+    items: Vec<ConvertOptions>,
+    stop_on_error: Option<bool>,
+    max_parallel: Option<usize>,
+) -> Result<BatchResult, String> {
+    run_batch(app_handle, &state, items, stop_on_error.unwrap_or(false), max_parallel.unwrap_or(1)).await
+}
+
+/// Convert each chapter of a file into its own file in `output_dir`, named after the
+/// chapter. Runs as a batch: `batch-progress` after each chapter, `cancel_convert` stops it.
+#[tauri::command]
+async fn split_by_chapters(
+    app_handle: tauri::AppHandle,
+    state: State<'_, AppState>,
+    input_path: String,
+    output_dir: String,
+    preset_id: String,
+) -> Result<BatchResult, String> {
+    let ffprobe_path = get_sidecar_path(&app_handle, "ffprobe");
+    let info = state
+        .probe_cache
+        .probe(&input_path, ffprobe_path.as_deref(), &state.settings.get().probe_retry)
+        .map_err(|e| e.to_string())?;
+    let items = chapter_split_options(&info, &output_dir, &preset_id).map_err(|e| e.to_string())?;
+    std::fs::create_dir_all(&output_dir).map_err(|e| format!("Failed to create {}: {}", output_dir, e))?;
+    run_batch(app_handle, &state, items, false, 1).await
+}
+
+async fn run_batch(
+    app_handle: tauri::AppHandle,
+    state: &AppState,
+    items: Vec<ConvertOptions>,
+    stop_on_error: bool,
+    max_parallel: usize,
+) -> Result<BatchResult, String> {
+    let _operation = state.jobs.begin()?;
     
-    // Mark as not converting
-    *converting = false;
+    // Every job shares the tokens, so cancelling stops all of them
+    let cancel_flag = state.cancellation.register(cancel::CONVERSION);
+    let stop_flag = state.cancellation.register(cancel::STOP_CONVERSION);
+    let total = items.len();
+    let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let max_parallel = max_parallel.clamp(1, cores);
+    let mut batch = BatchResult::default();
+    let mut pending = items.into_iter().enumerate();
+    let mut running = tokio::task::JoinSet::new();
+    // What each running task converts, to report it even if the task panics
+    let mut started = HashMap::new();
+    let mut halted = false;
     
-    result.map_err(|e| e.to_string())
+    loop {
+        while !halted && running.len() < max_parallel {
+            let Some((index, options)) = pending.next() else {
+                break;
+            };
+            let job_id = state.jobs.start_job();
+            let _ = app_handle.emit("batch-job-started", BatchJobStarted { index, job_id });
+            let app = app_handle.clone();
+            let (cancel_flag, stop_flag) = (cancel_flag.clone(), stop_flag.clone());
+            let paths = (options.input_path.clone(), options.output_path.clone());
+            let task = running.spawn(async move {
+                let state = app.state::<AppState>();
+                let _job = state.jobs.finish_on_drop(job_id);
+                convert_one(app.clone(), &state, job_id, options, cancel_flag, stop_flag).await
+            });
+            started.insert(task.id(), (index, job_id, paths));
+        }
+        let Some(joined) = running.join_next_with_id().await else {
+            break;
+        };
+        let (task, outcome) = match joined {
+            Ok(finished) => finished,
+            // A panicked job counts as failed; the others carry on
+            Err(e) => {
+                let message = format!("Conversion stopped unexpectedly: {}", e);
+                let (_, _, (input_path, output_path)) = &started[&e.id()];
+                let mut log = ConversionLog::new(input_path, output_path, None, None, "");
+                log.add_entry(LogLevel::Error, &message, None);
+                log.finish(false, Some(message.clone()));
+                state.log_store.add_log(log);
+                (e.id(), Err(ConvertError::ConversionFailed(message)))
+            }
+        };
+        let (index, job_id, (input_path, _)) = started.remove(&task).expect("every running job is tracked");
+        let cancelled = matches!(outcome, Err(ConvertError::Cancelled));
+        let item = BatchItemResult {
+            index,
+            job_id,
+            input_path,
+            result: outcome.as_ref().ok().cloned(),
+            error: outcome.as_ref().err().map(|e| e.to_string()),
+        };
+        let _ = app_handle.emit(
+            "batch-progress",
+            BatchProgress {
+                index,
+                total,
+                item: item.clone(),
+            },
+        );
+        let failed = item.error.is_some();
+        batch.items.push(item);
+        
+        // A stop keeps the running items' partial outputs and skips the rest; jobs already
+        // running finish (or stop) on their own
+        if cancelled || cancel_flag.load(Ordering::Relaxed) || stop_flag.load(Ordering::Relaxed) {
+            batch.cancelled = true;
+            halted = true;
+        }
+        if failed && stop_on_error {
+            halted = true;
+        }
+    }
+    
+    state.cancellation.remove(cancel::CONVERSION);
+    state.cancellation.remove(cancel::STOP_CONVERSION);
+    
+    batch.items.sort_by_key(|item| item.index);
+    batch.succeeded = batch.items.iter().filter(|i| i.error.is_none()).count();
+    batch.failed = batch.items.len() - batch.succeeded;
+    Ok(batch)
+}
+
+/// One-click conversion to an H.264/AAC MP4 (max 720p) sized to fit under `max_mb`
+#[tauri::command]
+async fn convert_for_sharing(
+    app_handle: tauri::AppHandle,
+    state: State<'_, AppState>,
+    input_path: String,
+    max_mb: f64,
+) -> Result<SharingResult, String> {
+    let ffprobe_path = get_sidecar_path(&app_handle, "ffprobe");
+    let output_path = {
+        let reserved = state.reserved_outputs.lock().unwrap();
+        generate_output_path(&input_path, Some("mp4_h264"), None, None, None, &reserved)
+    };
+    let options = sharing_options(&input_path, &output_path, max_mb, ffprobe_path.as_deref())
+        .map_err(|e| e.to_string())?;
+    
+    let result = run_conversion(app_handle, &state, options).await?;
+    Ok(SharingResult::new(result, max_mb))
+}
+
+/// Fast container change: try a stream copy first (probing only the stream list), and only
+/// fall back to a regular (re-encoding) conversion if the copy fails
+#[tauri::command]
+async fn quick_remux(
+    app_handle: tauri::AppHandle,
+    state: State<'_, AppState>,
+    input_path: String,
+    target_ext: String,
+) -> Result<QuickRemuxResult, String> {
+    let target_ext = target_ext.trim_start_matches('.').to_lowercase();
+    
+    let (output_path, remux_error) = {
+        let operation = state.jobs.begin()?;
+        // Reserve the name so a parallel conversion can't pick the same one
+        let reservation = {
+            let mut reserved = state.reserved_outputs.lock().unwrap();
+            let path = generate_output_path(&input_path, None, Some(&target_ext), None, None, &reserved);
+            reserved.insert(path.clone());
+            OutputReservation {
+                reserved: &state.reserved_outputs,
+                path,
+            }
+        };
+        let cancel_flag = state.cancellation.register(cancel::CONVERSION);
+        let ffmpeg_path = get_sidecar_path(&app_handle, "ffmpeg");
+        let ffprobe_path = get_sidecar_path(&app_handle, "ffprobe");
+        let (input, output) = (input_path.clone(), reservation.path.clone());
+        let remuxed = tokio::task::spawn_blocking(move || {
+            convert::remux_streams(&input, &output, ffmpeg_path.as_deref(), ffprobe_path.as_deref(), &cancel_flag)
+        })
+        .await
+        .map_err(|e| e.to_string());
+        state.cancellation.remove(cancel::CONVERSION);
+        // The fallback conversion takes the operation and reserves the path itself
+        drop(operation);
+        
+        match remuxed? {
+            Ok(result) => {
+                return Ok(QuickRemuxResult {
+                    result,
+                    remuxed: true,
+                    remux_error: None,
+                })
+            }
+            Err(ConvertError::Cancelled) => return Err(ConvertError::Cancelled.to_string()),
+            Err(e) => (reservation.path.clone(), e.to_string()),
+        }
+    };
+    
+    let options = ConvertOptions {
+        input_path,
+        output_path,
+        preset_id: None,
+        advanced: None,
+        stream_selection: None,
+        settings: AppSettings::default(),
+        job_id: 0,
+    };
+    let result = run_conversion(app_handle, &state, options).await?;
+    Ok(QuickRemuxResult {
+        result,
+        remuxed: false,
+        remux_error: Some(remux_error),
+    })
 }
 
 /// Cancel the current conversion
 #[tauri::command]
 async fn cancel_convert(state: State<'_, AppState>) -> Result<(), String> {
-    state.cancel_flag.store(true, Ordering::Relaxed);
+    state.cancellation.cancel(cancel::CONVERSION);
+    // A suspended ffmpeg produces no events, so the conversion would never see the cancel
+    state.pause.resume().ok();
+    Ok(())
+}
+
+/// Stop the current conversion early but keep a playable file of what was encoded so far
+#[tauri::command]
+async fn stop_convert(state: State<'_, AppState>) -> Result<(), String> {
+    state.cancellation.cancel(cancel::STOP_CONVERSION);
+    // ffmpeg has to be running to read the quit command
+    state.pause.resume().ok();
     Ok(())
 }
 
+/// Suspend the running conversion's ffmpeg process
+#[tauri::command]
+fn pause_convert(state: State<'_, AppState>) -> Result<(), String> {
+    state.pause.pause()
+}
+
+/// Continue a paused conversion
+#[tauri::command]
+fn resume_convert(state: State<'_, AppState>) -> Result<(), String> {
+    state.pause.resume()
+}
+
+/// Cancel a specific long-running operation by id (e.g. "split", "probe-folder-3")
+#[tauri::command]
+fn cancel_operation(state: State<'_, AppState>, id: String) -> bool {
+    state.cancellation.cancel(&id)
+}
+
+/// Ids of the cancellable operations currently running
+#[tauri::command]
+fn list_operations(state: State<'_, AppState>) -> Vec<String> {
+    state.cancellation.active()
+}
+
 /// Check if a conversion is in progress
 #[tauri::command]
 async fn is_converting(state: State<'_, AppState>) -> Result<bool, String> {
-    Ok(*state.converting.lock().await)
+    Ok(state.jobs.is_busy())
+}
+
+/// Ids of the conversion jobs running right now (several during a parallel batch)
+#[tauri::command]
+fn list_conversion_jobs(state: State<'_, AppState>) -> Vec<u64> {
+    state.jobs.active_jobs()
+}
+
+/// Undo the most recent conversion if it replaced its original: restore the backup and delete the output
+#[tauri::command]
+fn undo_last_conversion(state: State<'_, AppState>) -> Result<String, String> {
+    state
+        .log_store
+        .restore_last_backup(convert::restore_original)
+        .ok_or_else(|| "The last conversion did not replace an original file".to_string())?
+        .map_err(|e| e.to_string())
 }
 
 /// Get all conversion logs
@@ -179,6 +1145,23 @@ fn get_conversion_logs(state: State<'_, AppState>) -> Vec<ConversionLog> {
     state.log_store.get_logs()
 }
 
+/// Get the conversion logs matching a minimum entry level, failure and a search text
+#[tauri::command]
+fn get_conversion_logs_filtered(
+    state: State<'_, AppState>,
+    level: Option<LogLevel>,
+    only_failed: bool,
+    search: Option<String>,
+) -> Vec<ConversionLog> {
+    state.log_store.get_logs_filtered(level, only_failed, search)
+}
+
+/// Change how many conversion logs are kept in memory (0 keeps none)
+#[tauri::command]
+fn set_max_logs(state: State<'_, AppState>, max_logs: usize) {
+    state.log_store.set_max_logs(max_logs);
+}
+
 /// Get the last conversion log
 #[tauri::command]
 fn get_last_conversion_log(state: State<'_, AppState>) -> Option<ConversionLog> {
@@ -197,6 +1180,19 @@ fn export_conversion_logs(state: State<'_, AppState>) -> String {
     state.log_store.export_logs()
 }
 
+/// Export logs as JSON
+#[tauri::command]
+fn export_conversion_logs_json(state: State<'_, AppState>) -> String {
+    state.log_store.export_logs_json()
+}
+
+/// Write the logs as JSON to a path picked by the user
+#[tauri::command]
+fn save_conversion_logs_json(state: State<'_, AppState>, path: String) -> Result<(), String> {
+    std::fs::write(&path, state.log_store.export_logs_json())
+        .map_err(|e| format!("Failed to write {}: {}", path, e))
+}
+
 /// Path to the log file in the system folder (if file logging is enabled)
 #[tauri::command]
 fn get_log_file_path(state: State<'_, AppState>) -> Option<String> {
@@ -206,6 +1202,117 @@ fn get_log_file_path(state: State<'_, AppState>) -> Option<String> {
         .and_then(|p| p.to_str().map(String::from))
 }
 
+/// Start writing conversion logs to `dir` (the system log folder when omitted) and
+/// remember the choice
+#[tauri::command]
+fn enable_file_logging(app: tauri::AppHandle, state: State<'_, AppState>, dir: Option<String>) -> Result<(), String> {
+    state.log_store.enable_file_logging(log_dir_path(&app, dir.as_deref())?)?;
+    let mut settings = state.settings.get();
+    settings.file_logging = true;
+    settings.log_dir = dir;
+    state.settings.update(settings)
+}
+
+/// Folder for log files: `dir`, or the system log folder
+fn log_dir_path(app: &tauri::AppHandle, dir: Option<&str>) -> Result<std::path::PathBuf, String> {
+    match dir {
+        Some(dir) => Ok(std::path::PathBuf::from(dir)),
+        None => app.path().app_log_dir().map_err(|e| e.to_string()),
+    }
+}
+
+/// Stop writing conversion logs to a file and remember the choice
+#[tauri::command]
+fn disable_file_logging(state: State<'_, AppState>) -> Result<(), String> {
+    state.log_store.disable_file_logging();
+    let mut settings = state.settings.get();
+    settings.file_logging = false;
+    state.settings.update(settings)
+}
+
+/// Whether conversion logs are currently written to a file
+#[tauri::command]
+fn is_file_logging_enabled(state: State<'_, AppState>) -> bool {
+    state.log_store.is_file_logging_enabled()
+}
+
+/// Get the current application settings
+#[tauri::command]
+fn get_settings(state: State<'_, AppState>) -> AppSettings {
+    state.settings.get()
+}
+
+/// Replace the application settings and persist them. A changed `file_logging` or
+/// `log_dir` starts, moves or stops file logging right away.
+#[tauri::command]
+fn update_settings(app: tauri::AppHandle, state: State<'_, AppState>, settings: AppSettings) -> Result<(), String> {
+    let current = state.settings.get();
+    if settings.file_logging != current.file_logging || settings.log_dir != current.log_dir {
+        if settings.file_logging {
+            state.log_store.enable_file_logging(log_dir_path(&app, settings.log_dir.as_deref())?)?;
+        } else {
+            state.log_store.disable_file_logging();
+        }
+    }
+    state.settings.update(settings)
+}
+
+/// Remove intermediate files left behind by crashed or force-quit jobs, from the system
+/// temp dir and the configured temp dir. Refused while any job is running.
+#[tauri::command]
+async fn cleanup_temp_files(state: State<'_, AppState>) -> Result<CleanupResult, String> {
+    if state.jobs.is_busy() || !state.cancellation.active().is_empty() {
+        return Err("Cannot clean up temp files while a job is running".to_string());
+    }
+    let mut dirs = vec![std::env::temp_dir()];
+    let configured = temp::temp_dir(&state.settings.get());
+    if !dirs.contains(&configured) {
+        dirs.push(configured);
+    }
+    Ok(temp::cleanup_temp_files(&dirs))
+}
+
+/// Save a named job profile (replaces an existing profile with the same name)
+#[tauri::command]
+fn save_profile(state: State<'_, AppState>, name: String, config: JobProfile) -> Result<(), String> {
+    state.profiles.save(JobProfile { name, ..config })
+}
+
+/// List saved job profiles
+#[tauri::command]
+fn list_profiles(state: State<'_, AppState>) -> Vec<JobProfile> {
+    state.profiles.list()
+}
+
+/// Get a saved profile so the UI can load its settings, along with the output paths its
+/// preset, output folder and naming template give `input_paths`
+#[tauri::command]
+fn apply_profile(
+    state: State<'_, AppState>,
+    name: String,
+    input_paths: Option<Vec<String>>,
+) -> Result<AppliedProfile, String> {
+    let profile = state
+        .profiles
+        .get(&name)
+        .ok_or_else(|| format!("Profile not found: {}", name))?;
+    let reserved = state.reserved_outputs.lock().unwrap();
+    let outputs = plan_batch_outputs(
+        &input_paths.unwrap_or_default(),
+        profile.preset_id.as_deref(),
+        profile.output_dir.as_deref(),
+        profile.name_template.as_deref(),
+        &reserved,
+    );
+    Ok(AppliedProfile { profile, outputs })
+}
+
+/// Delete a saved job profile
+#[tauri::command]
+fn delete_profile(state: State<'_, AppState>, name: String) -> Result<(), String> {
+    state.profiles.delete(&name)
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
@@ -214,29 +1321,102 @@ pub fn run() {
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_clipboard_manager::init())
         .setup(|app| {
-            let log_dir = app.path().app_log_dir().ok();
+            let config_dir = app.path().app_config_dir().ok();
+            presets::init_custom_presets(config_dir.clone());
+            let settings = SettingsStore::new(config_dir.clone());
+            let log_settings = settings.get();
+            let log_dir = if log_settings.file_logging {
+                log_settings.log_dir.map(std::path::PathBuf::from).or_else(|| app.path().app_log_dir().ok())
+            } else {
+                None
+            };
             let state = AppState {
-                cancel_flag: Arc::new(AtomicBool::new(false)),
-                converting: Arc::new(Mutex::new(false)),
-                log_store: Arc::new(LogStore::new(50, log_dir)),
+                cancellation: Arc::new(CancelRegistry::default()),
+                jobs: Arc::new(JobRegistry::default()),
+                pause: Arc::new(PauseControl::default()),
+                reserved_outputs: Arc::new(std::sync::Mutex::new(HashSet::new())),
+                log_store: Arc::new(LogStore::new(50, log_dir, logger::DEFAULT_MAX_LOG_FILE_BYTES, logger::DEFAULT_LOG_ARCHIVES)),
+                settings: Arc::new(settings),
+                profiles: Arc::new(ProfileStore::new(config_dir)),
+                probe_cache: Arc::new(ProbeCache::default()),
             };
             app.manage(state);
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
             get_presets,
+            get_available_presets,
+            export_preset,
+            import_preset,
+            save_custom_preset,
+            delete_custom_preset,
+            validate_preset,
             check_ffmpeg_installed,
             check_ffprobe_installed,
+            codecs_for_container,
+            list_encoders,
+            list_decoders,
+            check_codec_support,
             probe_media_file,
+            probe_folder,
+            analyze_bitrate,
+            suggest_preset,
+            read_conversion_provenance,
             get_output_path,
+            preview_batch_outputs,
+            convert_subtitle,
+            extract_attachments,
+            extract_subtitles,
+            extract_cover_art,
+            extract_audio,
+            split_stereo,
+            split_by_duration,
+            validate_filtergraph,
+            check_concat_fps,
+            concat_files,
+            diff_video,
+            generate_preview_clip,
+            generate_thumbnail,
+            generate_sprite_sheet,
+            is_supported_file,
+            is_file_stable,
+            preflight,
+            check_device_compatibility,
+            estimate_output_size,
+            build_command_preview,
             start_convert,
+            start_batch_convert,
+            split_by_chapters,
+            convert_for_sharing,
+            quick_remux,
             cancel_convert,
+            stop_convert,
+            pause_convert,
+            resume_convert,
+            cancel_operation,
+            list_operations,
             is_converting,
+            list_conversion_jobs,
+            undo_last_conversion,
             get_conversion_logs,
+            get_conversion_logs_filtered,
+            set_max_logs,
             get_last_conversion_log,
             clear_conversion_logs,
             export_conversion_logs,
+            export_conversion_logs_json,
+            save_conversion_logs_json,
             get_log_file_path,
+            enable_file_logging,
+            disable_file_logging,
+            is_file_logging_enabled,
+            get_settings,
+            update_settings,
+            cleanup_temp_files,
+            save_profile,
+            list_profiles,
+            apply_profile,
+            delete_profile,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -1,9 +1,15 @@
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Mutex;
 use chrono::{DateTime, Local};
 
+/// Entries kept per conversion; beyond this the oldest entries are dropped
+const MAX_ENTRIES_PER_LOG: usize = 2000;
+
+/// Serialized as "info", "warning", "error", "debug"
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum LogLevel {
     Info,
     Warning,
@@ -11,6 +17,18 @@ pub enum LogLevel {
     Debug,
 }
 
+impl LogLevel {
+    /// Debug < Info < Warning < Error
+    fn severity(&self) -> u8 {
+        match self {
+            LogLevel::Debug => 0,
+            LogLevel::Info => 1,
+            LogLevel::Warning => 2,
+            LogLevel::Error => 3,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LogEntry {
     pub timestamp: String,
@@ -31,6 +49,8 @@ pub struct ConversionLog {
     pub ffmpeg_command: String,
     pub success: bool,
     pub error_message: Option<String>,
+    /// Where the original was moved when the conversion replaced it (cleared once undone)
+    pub backup_path: Option<String>,
     pub entries: Vec<LogEntry>,
 }
 
@@ -48,11 +68,15 @@ impl ConversionLog {
             ffmpeg_command: ffmpeg_command.to_string(),
             success: false,
             error_message: None,
+            backup_path: None,
             entries: Vec::new(),
         }
     }
 
     pub fn add_entry(&mut self, level: LogLevel, message: &str, context: Option<&str>) {
+        // Make room for the new entry, dropping the oldest
+        let excess = (self.entries.len() + 1).saturating_sub(MAX_ENTRIES_PER_LOG);
+        self.entries.drain(..excess);
         let now: DateTime<Local> = Local::now();
         self.entries.push(LogEntry {
             timestamp: now.format("%H:%M:%S%.3f").to_string(),
@@ -109,53 +133,143 @@ fn format_log_for_file(log: &ConversionLog) -> String {
     output
 }
 
+/// Log file in the system log dir; archives are `conversion_log.1.txt` (newest) and up
+const LOG_FILE_NAME: &str = "conversion_log.txt";
+
+/// Size at which the log file is rotated, by default
+pub const DEFAULT_MAX_LOG_FILE_BYTES: u64 = 5 * 1024 * 1024;
+/// Rotated log files kept, by default
+pub const DEFAULT_LOG_ARCHIVES: usize = 3;
+
+fn archive_path(dir: &std::path::Path, index: usize) -> PathBuf {
+    dir.join(format!("conversion_log.{}.txt", index))
+}
+
+/// Shift `conversion_log.txt` to `.1`, `.1` to `.2` and so on, dropping the oldest
+/// beyond `max_archives`
+fn rotate_log_file(dir: &std::path::Path, max_archives: usize) {
+    let path = dir.join(LOG_FILE_NAME);
+    if max_archives == 0 {
+        let _ = std::fs::remove_file(&path);
+        return;
+    }
+    let _ = std::fs::remove_file(archive_path(dir, max_archives));
+    for index in (1..max_archives).rev() {
+        let _ = std::fs::rename(archive_path(dir, index), archive_path(dir, index + 1));
+    }
+    let _ = std::fs::rename(&path, archive_path(dir, 1));
+}
+
+/// Keep only the last `max_logs` entries
+fn trim_oldest(logs: &mut Vec<ConversionLog>, max_logs: usize) {
+    let excess = logs.len().saturating_sub(max_logs);
+    logs.drain(..excess);
+}
+
 /// Global log storage (in-memory and optional file in system log dir)
 pub struct LogStore {
     logs: Mutex<Vec<ConversionLog>>,
-    max_logs: usize,
+    max_logs: AtomicUsize,
     log_dir: Mutex<Option<PathBuf>>,
+    /// The log file is rotated before it would grow past this (0 = never)
+    max_file_bytes: u64,
+    max_archives: usize,
 }
 
 impl LogStore {
-    pub fn new(max_logs: usize, log_dir: Option<PathBuf>) -> Self {
+    pub fn new(max_logs: usize, log_dir: Option<PathBuf>, max_file_bytes: u64, max_archives: usize) -> Self {
         Self {
             logs: Mutex::new(Vec::new()),
-            max_logs,
+            max_logs: AtomicUsize::new(max_logs),
             log_dir: Mutex::new(log_dir),
+            max_file_bytes,
+            max_archives,
         }
     }
 
     pub fn add_log(&self, log: ConversionLog) {
         let mut logs = self.logs.lock().unwrap();
         logs.push(log.clone());
-        // Keep only the last max_logs entries
-        while logs.len() > self.max_logs {
-            logs.remove(0);
-        }
+        trim_oldest(&mut logs, self.max_logs.load(Ordering::Relaxed));
         drop(logs);
 
         // Append to log file in system folder if configured
         if let Ok(guard) = self.log_dir.lock() {
             if let Some(ref dir) = *guard {
-                let path = dir.join("conversion_log.txt");
-                if let Some(parent) = path.parent() {
-                    let _ = std::fs::create_dir_all(parent);
+                let path = dir.join(LOG_FILE_NAME);
+                let _ = std::fs::create_dir_all(dir);
+                let text = format_log_for_file(&log);
+                let current = std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+                if self.max_file_bytes > 0 && current > 0 && current + text.len() as u64 > self.max_file_bytes {
+                    rotate_log_file(dir, self.max_archives);
                 }
                 if let Ok(mut f) = std::fs::OpenOptions::new().create(true).append(true).open(&path) {
-                    let _ = std::io::Write::write_all(&mut f, format_log_for_file(&log).as_bytes());
+                    let _ = std::io::Write::write_all(&mut f, text.as_bytes());
                 }
             }
         }
     }
 
+    /// Change how many conversion logs are kept in memory, dropping the oldest ones
+    /// beyond the new cap right away (0 keeps none)
+    pub fn set_max_logs(&self, max_logs: usize) {
+        let mut logs = self.logs.lock().unwrap();
+        self.max_logs.store(max_logs, Ordering::Relaxed);
+        trim_oldest(&mut logs, max_logs);
+    }
+
     pub fn get_logs(&self) -> Vec<ConversionLog> {
         self.logs.lock().unwrap().clone()
     }
 
+    /// Conversions with at least one entry at `level` or above, only failed ones if
+    /// `only_failed`, and whose input/output path or ffmpeg command contains `search`
+    /// (case-insensitive)
+    pub fn get_logs_filtered(&self, level: Option<LogLevel>, only_failed: bool, search: Option<String>) -> Vec<ConversionLog> {
+        let search = search.map(|s| s.trim().to_lowercase()).filter(|s| !s.is_empty());
+        self.logs
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|log| !only_failed || !log.success)
+            .filter(|log| {
+                level.as_ref().is_none_or(|level| {
+                    log.entries.iter().any(|e| e.level.severity() >= level.severity())
+                })
+            })
+            .filter(|log| {
+                search.as_deref().is_none_or(|search| {
+                    [&log.input_path, &log.output_path, &log.ffmpeg_command]
+                        .iter()
+                        .any(|field| field.to_lowercase().contains(search))
+                })
+            })
+            .cloned()
+            .collect()
+    }
+
     pub fn get_last_log(&self) -> Option<ConversionLog> {
         self.logs.lock().unwrap().last().cloned()
     }
 
+    /// Restore the backup of the most recent conversion, if it replaced its original, with
+    /// `restore(input path, output path, backup path)`. The backup is only forgotten once
+    /// `restore` succeeds; the logs stay locked meanwhile so it can't be restored twice.
+    /// Returns the input path on success, `None` if there is no backup.
+    pub fn restore_last_backup<E>(
+        &self,
+        restore: impl FnOnce(&str, &str, &str) -> Result<(), E>,
+    ) -> Option<Result<String, E>> {
+        let mut logs = self.logs.lock().unwrap();
+        let last = logs.last_mut()?;
+        let backup = last.backup_path.as_deref()?;
+        if let Err(e) = restore(&last.input_path, &last.output_path, backup) {
+            return Some(Err(e));
+        }
+        last.backup_path = None;
+        Some(Ok(last.input_path.clone()))
+    }
+
     pub fn clear_logs(&self) {
         self.logs.lock().unwrap().clear();
     }
@@ -203,14 +317,164 @@ impl LogStore {
         output
     }
 
+    /// All logs as a JSON array of `ConversionLog`, for tools that parse them
+    pub fn export_logs_json(&self) -> String {
+        let logs = self.logs.lock().unwrap();
+        serde_json::to_string_pretty(&*logs).expect("conversion logs serialize to JSON")
+    }
+
+    /// Start writing conversion logs to `dir`, creating it if needed. Fails if the log
+    /// file can't be opened there.
+    pub fn enable_file_logging(&self, dir: PathBuf) -> Result<(), String> {
+        std::fs::create_dir_all(&dir)
+            .map_err(|e| format!("Cannot create log folder {}: {}", dir.display(), e))?;
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join(LOG_FILE_NAME))
+            .map_err(|e| format!("Log folder is not writable: {}: {}", dir.display(), e))?;
+        *self.log_dir.lock().unwrap() = Some(dir);
+        Ok(())
+    }
+
+    /// Keep logs in memory only from now on
+    pub fn disable_file_logging(&self) {
+        *self.log_dir.lock().unwrap() = None;
+    }
+
+    pub fn is_file_logging_enabled(&self) -> bool {
+        self.log_dir.lock().unwrap().is_some()
+    }
+
     /// Path to the log file in the system log folder, if file logging is enabled
     pub fn get_log_file_path(&self) -> Option<PathBuf> {
-        self.log_dir.lock().ok().and_then(|g| g.as_ref().cloned()).map(|d| d.join("conversion_log.txt"))
+        self.log_dir.lock().ok().and_then(|g| g.as_ref().cloned()).map(|d| d.join(LOG_FILE_NAME))
     }
 }
 
 impl Default for LogStore {
     fn default() -> Self {
-        Self::new(50, None) // Keep last 50 conversion logs, no file logging by default
+        // Keep last 50 conversion logs, no file logging by default
+        Self::new(50, None, DEFAULT_MAX_LOG_FILE_BYTES, DEFAULT_LOG_ARCHIVES)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log(input: &str, success: bool, levels: &[LogLevel]) -> ConversionLog {
+        let mut log = ConversionLog::new(input, "/out/result.mp4", None, None, "ffmpeg -i in -c:v libx264 out");
+        for level in levels {
+            log.add_entry(level.clone(), "entry", None);
+        }
+        log.finish(success, (!success).then(|| "failed".to_string()));
+        log
+    }
+
+    fn seeded() -> LogStore {
+        let store = LogStore::default();
+        store.add_log(log("/media/Holiday.mov", true, &[LogLevel::Info]));
+        store.add_log(log("/media/broken.avi", false, &[LogLevel::Info, LogLevel::Error]));
+        store.add_log(log("/media/noisy.mkv", true, &[LogLevel::Debug, LogLevel::Warning]));
+        store.add_log(log("/media/quiet.mov", false, &[LogLevel::Debug]));
+        store
+    }
+
+    fn inputs(logs: &[ConversionLog]) -> Vec<&str> {
+        logs.iter().map(|l| l.input_path.as_str()).collect()
+    }
+
+    #[test]
+    fn no_filters_return_everything() {
+        assert_eq!(seeded().get_logs_filtered(None, false, None).len(), 4);
+        assert_eq!(seeded().get_logs_filtered(None, false, Some("  ".to_string())).len(), 4);
+    }
+
+    #[test]
+    fn level_is_a_threshold() {
+        let store = seeded();
+        assert_eq!(
+            inputs(&store.get_logs_filtered(Some(LogLevel::Warning), false, None)),
+            ["/media/broken.avi", "/media/noisy.mkv"],
+        );
+        assert_eq!(inputs(&store.get_logs_filtered(Some(LogLevel::Error), false, None)), ["/media/broken.avi"]);
+        assert_eq!(store.get_logs_filtered(Some(LogLevel::Debug), false, None).len(), 4);
+    }
+
+    #[test]
+    fn only_failed_drops_successes() {
+        assert_eq!(
+            inputs(&seeded().get_logs_filtered(None, true, None)),
+            ["/media/broken.avi", "/media/quiet.mov"],
+        );
+    }
+
+    #[test]
+    fn search_ignores_case() {
+        let store = seeded();
+        assert_eq!(inputs(&store.get_logs_filtered(None, false, Some("HOLIDAY".to_string()))), ["/media/Holiday.mov"]);
+        assert_eq!(store.get_logs_filtered(None, false, Some("LIBX264".to_string())).len(), 4);
+        assert!(store.get_logs_filtered(None, false, Some("webm".to_string())).is_empty());
+    }
+
+    #[test]
+    fn filters_combine() {
+        let store = seeded();
+        assert_eq!(
+            inputs(&store.get_logs_filtered(Some(LogLevel::Warning), true, Some(".AVI".to_string()))),
+            ["/media/broken.avi"],
+        );
+        // Each filter alone matches noisy.mkv or quiet.mov, but not all three together
+        assert!(store.get_logs_filtered(Some(LogLevel::Warning), true, Some(".mkv".to_string())).is_empty());
+        assert!(store.get_logs_filtered(Some(LogLevel::Warning), true, Some("quiet".to_string())).is_empty());
+    }
+
+    #[test]
+    fn backup_is_kept_until_restore_succeeds() {
+        let store = LogStore::default();
+        let mut replaced = log("/media/clip.mov", true, &[]);
+        replaced.backup_path = Some("/media/clip.mov.bak".to_string());
+        store.add_log(replaced);
+
+        let failed = store.restore_last_backup(|_, _, _| Err("disk full"));
+        assert_eq!(failed, Some(Err("disk full")));
+        assert_eq!(store.get_last_log().unwrap().backup_path.as_deref(), Some("/media/clip.mov.bak"));
+
+        let restored = store.restore_last_backup(|input, _, backup| {
+            assert_eq!((input, backup), ("/media/clip.mov", "/media/clip.mov.bak"));
+            Ok::<_, &str>(())
+        });
+        assert_eq!(restored, Some(Ok("/media/clip.mov".to_string())));
+        assert_eq!(store.restore_last_backup(|_, _, _| Ok::<_, &str>(())), None);
+    }
+
+    #[test]
+    fn log_file_rotates_and_drops_oldest_archive() {
+        let dir = crate::test_support::temp_dir("log-rotation");
+        // Smaller than one formatted log, so every write after the first rotates
+        let store = LogStore::new(50, Some(dir.clone()), 64, 2);
+        for name in ["first", "second", "third", "fourth"] {
+            store.add_log(log(&format!("/media/{}.mov", name), true, &[LogLevel::Info]));
+        }
+        let read = |path: PathBuf| std::fs::read_to_string(path).unwrap();
+
+        assert!(read(dir.join(LOG_FILE_NAME)).contains("/media/fourth.mov"));
+        assert!(read(archive_path(&dir, 1)).contains("/media/third.mov"));
+        assert!(read(archive_path(&dir, 2)).contains("/media/second.mov"));
+        assert!(!archive_path(&dir, 3).exists());
+        let all: String = std::fs::read_dir(&dir).unwrap().map(|e| read(e.unwrap().path())).collect();
+        assert!(!all.contains("/media/first.mov"));
+    }
+
+    #[test]
+    fn entries_keep_the_newest_at_the_cap() {
+        let mut log = ConversionLog::new("/media/long.mkv", "/out/result.mp4", None, None, "ffmpeg");
+        for i in 0..MAX_ENTRIES_PER_LOG + 5 {
+            log.add_entry(LogLevel::Info, &i.to_string(), None);
+        }
+        assert_eq!(log.entries.len(), MAX_ENTRIES_PER_LOG);
+        assert_eq!(log.entries[0].message, "5");
+        assert_eq!(log.entries.last().unwrap().message, (MAX_ENTRIES_PER_LOG + 4).to_string());
     }
 }
@@ -0,0 +1,396 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Namespace of the iTunes freeform tags that players and taggers read ReplayGain from
+/// in MP4/M4A (`----:com.apple.iTunes:replaygain_track_gain`)
+pub const ITUNES_MEAN: &str = "com.apple.iTunes";
+
+/// Boxes on the way from `moov` to the chunk offset tables
+const SAMPLE_TABLE_PATH: &[&[u8; 4]] = &[b"trak", b"mdia", b"minf", b"stbl"];
+
+/// A box inside a byte slice: where it starts, where its payload starts, where it ends
+#[derive(Debug, Clone, Copy)]
+struct BoxRange {
+    kind: [u8; 4],
+    start: usize,
+    payload: usize,
+    end: usize,
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// The boxes laid out one after another in `data`
+fn children(data: &[u8]) -> io::Result<Vec<BoxRange>> {
+    let mut boxes = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let header = data.get(pos..pos + 8).ok_or_else(|| invalid("Truncated box header"))?;
+        let kind = [header[4], header[5], header[6], header[7]];
+        let (size, header_len) = match u32::from_be_bytes([header[0], header[1], header[2], header[3]]) {
+            0 => ((data.len() - pos) as u64, 8),
+            1 => {
+                let large = data.get(pos + 8..pos + 16).ok_or_else(|| invalid("Truncated box header"))?;
+                (u64::from_be_bytes(large.try_into().unwrap()), 16)
+            }
+            size => (size as u64, 8),
+        };
+        let end = pos
+            .checked_add(usize::try_from(size).map_err(|_| invalid("Box too large"))?)
+            .filter(|end| size >= header_len && *end <= data.len())
+            .ok_or_else(|| invalid("Box runs past its parent"))?;
+        boxes.push(BoxRange {
+            kind,
+            start: pos,
+            payload: pos + header_len as usize,
+            end,
+        });
+        pos = end;
+    }
+    Ok(boxes)
+}
+
+fn make_box(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(payload.len() + 8);
+    bytes.extend_from_slice(&(payload.len() as u32 + 8).to_be_bytes());
+    bytes.extend_from_slice(kind);
+    bytes.extend_from_slice(payload);
+    bytes
+}
+
+/// A full box's payload: version 0 and no flags, then `body`
+fn full_payload(body: &[u8]) -> Vec<u8> {
+    let mut payload = vec![0; 4];
+    payload.extend_from_slice(body);
+    payload
+}
+
+/// One `----` item: mean, name and a UTF-8 data box
+fn freeform_item(name: &str, value: &str) -> Vec<u8> {
+    let mut data = 1u32.to_be_bytes().to_vec(); // type: UTF-8 text
+    data.extend_from_slice(&[0; 4]); // locale
+    data.extend_from_slice(value.as_bytes());
+    let mut item = make_box(b"mean", &full_payload(ITUNES_MEAN.as_bytes()));
+    item.extend(make_box(b"name", &full_payload(name.as_bytes())));
+    item.extend(make_box(b"data", &data));
+    make_box(b"----", &item)
+}
+
+/// The mean and name of a `----` item
+fn freeform_key(item: &[u8]) -> Option<(String, String)> {
+    let boxes = children(item).ok()?;
+    let text = |kind: &[u8; 4]| {
+        let b = boxes.iter().find(|b| &b.kind == kind)?;
+        item.get(b.payload + 4..b.end).map(|t| String::from_utf8_lossy(t).to_string())
+    };
+    Some((text(b"mean")?, text(b"name")?))
+}
+
+/// `ilst` payload with `tags` added, dropping existing iTunes freeform items of the same names
+fn rebuild_ilst(payload: &[u8], tags: &[(String, String)]) -> io::Result<Vec<u8>> {
+    let mut rebuilt = Vec::with_capacity(payload.len());
+    for item in children(payload)? {
+        let bytes = &payload[item.start..item.end];
+        let replaced = &item.kind == b"----"
+            && freeform_key(&bytes[item.payload - item.start..]).is_some_and(|(mean, name)| {
+                mean == ITUNES_MEAN && tags.iter().any(|(tag, _)| tag.eq_ignore_ascii_case(&name))
+            });
+        if !replaced {
+            rebuilt.extend_from_slice(bytes);
+        }
+    }
+    for (name, value) in tags {
+        rebuilt.extend(freeform_item(name, value));
+    }
+    Ok(rebuilt)
+}
+
+/// Whether a `meta` payload is iTunes-style metadata (`hdlr` of type `mdir`)
+fn is_itunes_meta(payload: &[u8]) -> bool {
+    let Some(Ok(boxes)) = payload.get(4..).map(children) else {
+        return false;
+    };
+    boxes
+        .iter()
+        .find(|b| &b.kind == b"hdlr")
+        .and_then(|b| payload.get(4 + b.payload + 8..4 + b.payload + 12))
+        .is_some_and(|handler| handler == b"mdir")
+}
+
+/// `meta` payload holding `tags`: the existing one's with its `ilst` rebuilt, or a new one
+fn rebuild_meta(payload: Option<&[u8]>, tags: &[(String, String)]) -> io::Result<Vec<u8>> {
+    let Some(payload) = payload else {
+        let mut handler = vec![0; 4]; // pre_defined
+        handler.extend_from_slice(b"mdirappl");
+        handler.extend_from_slice(&[0; 9]); // reserved, empty name
+        let mut meta = full_payload(&make_box(b"hdlr", &full_payload(&handler)));
+        meta.extend(make_box(b"ilst", &rebuild_ilst(&[], tags)?));
+        return Ok(meta);
+    };
+    let body = &payload[4..];
+    let mut rebuilt = payload[..4].to_vec();
+    let mut has_ilst = false;
+    for child in children(body)? {
+        if &child.kind == b"ilst" {
+            has_ilst = true;
+            rebuilt.extend(make_box(b"ilst", &rebuild_ilst(&body[child.payload..child.end], tags)?));
+        } else {
+            rebuilt.extend_from_slice(&body[child.start..child.end]);
+        }
+    }
+    if !has_ilst {
+        rebuilt.extend(make_box(b"ilst", &rebuild_ilst(&[], tags)?));
+    }
+    Ok(rebuilt)
+}
+
+/// `udta` payload with `tags` in its iTunes `meta`, which is added if missing
+fn rebuild_udta(payload: &[u8], tags: &[(String, String)]) -> io::Result<Vec<u8>> {
+    let mut rebuilt = Vec::with_capacity(payload.len());
+    let mut tagged = false;
+    for child in children(payload)? {
+        let child_payload = &payload[child.payload..child.end];
+        if !tagged && &child.kind == b"meta" && is_itunes_meta(child_payload) {
+            tagged = true;
+            rebuilt.extend(make_box(b"meta", &rebuild_meta(Some(child_payload), tags)?));
+        } else {
+            rebuilt.extend_from_slice(&payload[child.start..child.end]);
+        }
+    }
+    if !tagged {
+        rebuilt.extend(make_box(b"meta", &rebuild_meta(None, tags)?));
+    }
+    Ok(rebuilt)
+}
+
+/// A new `moov` box with `tags` under `udta/meta/ilst`
+fn moov_with_tags(moov: &[u8], tags: &[(String, String)]) -> io::Result<Vec<u8>> {
+    let header = children(moov)?.first().copied().ok_or_else(|| invalid("Empty moov box"))?;
+    let payload = &moov[header.payload..header.end];
+    let mut rebuilt = Vec::with_capacity(moov.len());
+    let mut has_udta = false;
+    for child in children(payload)? {
+        if &child.kind == b"udta" {
+            has_udta = true;
+            rebuilt.extend(make_box(b"udta", &rebuild_udta(&payload[child.payload..child.end], tags)?));
+        } else {
+            rebuilt.extend_from_slice(&payload[child.start..child.end]);
+        }
+    }
+    if !has_udta {
+        rebuilt.extend(make_box(b"udta", &rebuild_udta(&[], tags)?));
+    }
+    if rebuilt.len() + 8 > u32::MAX as usize {
+        return Err(invalid("moov box too large"));
+    }
+    Ok(make_box(b"moov", &rebuilt))
+}
+
+/// Move every chunk offset at or past `from` by `delta` bytes (`stco`/`co64` of each track)
+fn shift_chunk_offsets(data: &mut [u8], depth: usize, from: u64, delta: i64) -> io::Result<()> {
+    for child in children(data)? {
+        let payload = &mut data[child.payload..child.end];
+        match &child.kind {
+            kind if depth < SAMPLE_TABLE_PATH.len() && kind == SAMPLE_TABLE_PATH[depth] => {
+                shift_chunk_offsets(payload, depth + 1, from, delta)?;
+            }
+            b"stco" | b"co64" if depth == SAMPLE_TABLE_PATH.len() => {
+                let width = if &child.kind == b"stco" { 4 } else { 8 };
+                let entries = payload.get_mut(8..).ok_or_else(|| invalid("Truncated chunk offset table"))?;
+                for entry in entries.chunks_exact_mut(width) {
+                    let offset = if width == 4 {
+                        u32::from_be_bytes(entry.try_into().unwrap()) as u64
+                    } else {
+                        u64::from_be_bytes(entry.try_into().unwrap())
+                    };
+                    if offset < from {
+                        continue;
+                    }
+                    let shifted = offset.checked_add_signed(delta).ok_or_else(|| invalid("Chunk offset out of range"))?;
+                    if width == 4 {
+                        let shifted = u32::try_from(shifted).map_err(|_| invalid("Chunk offset out of range"))?;
+                        entry.copy_from_slice(&shifted.to_be_bytes());
+                    } else {
+                        entry.copy_from_slice(&shifted.to_be_bytes());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Position, size and type of the file's top-level boxes, read from their headers only
+fn top_level_boxes(file: &mut File, file_len: u64) -> io::Result<Vec<(u64, u64, [u8; 4])>> {
+    let mut boxes = Vec::new();
+    let mut pos = 0;
+    while pos < file_len {
+        let mut header = [0; 16];
+        file.seek(SeekFrom::Start(pos))?;
+        file.read_exact(&mut header[..8])?;
+        let kind = [header[4], header[5], header[6], header[7]];
+        let size = match u32::from_be_bytes([header[0], header[1], header[2], header[3]]) {
+            0 => file_len - pos,
+            1 => {
+                file.read_exact(&mut header[8..])?;
+                u64::from_be_bytes(header[8..].try_into().unwrap())
+            }
+            size => size as u64,
+        };
+        if size < 8 || pos + size > file_len {
+            return Err(invalid("Not an MP4 file"));
+        }
+        boxes.push((pos, size, kind));
+        pos += size;
+    }
+    Ok(boxes)
+}
+
+/// Add iTunes freeform tags (`----:com.apple.iTunes:<name>`), given as (name, value), to
+/// the MP4/M4A file at `path`, replacing existing ones of the same names. ffmpeg's MP4
+/// muxer can't write these, so they go into the finished file: a `moov` at the end is
+/// rewritten in place; one in front of the media data (faststart) means rewriting the
+/// file with the chunk offsets moved. Fragmented files are not supported.
+pub fn write_freeform_tags(path: &Path, tags: &[(String, String)]) -> io::Result<()> {
+    let mut file = File::options().read(true).write(true).open(path)?;
+    let file_len = file.metadata()?.len();
+    let boxes = top_level_boxes(&mut file, file_len)?;
+    if boxes.iter().any(|(_, _, kind)| kind == b"moof") {
+        return Err(invalid("Fragmented MP4 files can't take iTunes tags"));
+    }
+    let (moov_start, moov_size, _) = *boxes
+        .iter()
+        .find(|(_, _, kind)| kind == b"moov")
+        .ok_or_else(|| invalid("No moov box"))?;
+    let moov_end = moov_start + moov_size;
+    let mut moov = vec![0; usize::try_from(moov_size).map_err(|_| invalid("moov box too large"))?];
+    file.seek(SeekFrom::Start(moov_start))?;
+    file.read_exact(&mut moov)?;
+
+    let mut new_moov = moov_with_tags(&moov, tags)?;
+    if moov_end == file_len {
+        file.seek(SeekFrom::Start(moov_start))?;
+        file.write_all(&new_moov)?;
+        file.set_len(moov_start + new_moov.len() as u64)?;
+        return Ok(());
+    }
+
+    // Whatever follows the moov moves by the size difference
+    let delta = new_moov.len() as i64 - moov_size as i64;
+    let payload_start = children(&new_moov)?[0].payload;
+    shift_chunk_offsets(&mut new_moov[payload_start..], 0, moov_end, delta)?;
+    let temp_path = path.with_file_name(format!(
+        "{}.tags.tmp",
+        path.file_name().unwrap_or_default().to_string_lossy()
+    ));
+    let mut rewrite = || -> io::Result<()> {
+        let mut out = File::create(&temp_path)?;
+        file.seek(SeekFrom::Start(0))?;
+        io::copy(&mut (&mut file).take(moov_start), &mut out)?;
+        out.write_all(&new_moov)?;
+        file.seek(SeekFrom::Start(moov_end))?;
+        io::copy(&mut file, &mut out)?;
+        out.sync_all()
+    };
+    if let Err(e) = rewrite() {
+        std::fs::remove_file(&temp_path).ok();
+        return Err(e);
+    }
+    std::fs::rename(&temp_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A moov holding one track whose single chunk is at `chunk`
+    fn sample_moov(chunk: u32) -> Vec<u8> {
+        let mut stco = full_payload(&1u32.to_be_bytes());
+        stco.extend_from_slice(&chunk.to_be_bytes());
+        let mut moov = make_box(b"stco", &stco);
+        for kind in SAMPLE_TABLE_PATH.iter().rev() {
+            moov = make_box(kind, &moov);
+        }
+        make_box(b"moov", &moov)
+    }
+
+    /// ftyp, then an mdat holding the track's chunk, with the moov before or after it
+    fn sample_file(moov_first: bool) -> Vec<u8> {
+        let mut file = make_box(b"ftyp", b"M4A \0\0\0\0");
+        let mdat = make_box(b"mdat", b"audio!!!");
+        if moov_first {
+            let chunk = file.len() + sample_moov(0).len() + 8;
+            file.extend(sample_moov(chunk as u32));
+            file.extend(mdat);
+        } else {
+            let chunk = file.len() + 8;
+            file.extend(mdat);
+            file.extend(sample_moov(chunk as u32));
+        }
+        file
+    }
+
+    /// Offset of the first chunk, and the tags under moov/udta/meta/ilst
+    fn read_back(file: &[u8]) -> (u64, Vec<(String, String)>) {
+        let top = children(file).unwrap();
+        let moov = top.iter().find(|b| &b.kind == b"moov").unwrap();
+        let mut data = &file[moov.payload..moov.end];
+        for kind in SAMPLE_TABLE_PATH.iter().chain([&b"stco"]) {
+            let b = children(data).unwrap().into_iter().find(|b| &b.kind == *kind).unwrap();
+            data = &data[b.payload..b.end];
+        }
+        let chunk = u32::from_be_bytes(data[8..12].try_into().unwrap()) as u64;
+
+        let mut data = &file[moov.payload..moov.end];
+        for kind in [b"udta", b"meta"] {
+            let b = children(data).unwrap().into_iter().find(|b| &b.kind == kind).unwrap();
+            data = &data[b.payload..b.end];
+        }
+        let ilst = children(&data[4..]).unwrap().into_iter().find(|b| &b.kind == b"ilst").unwrap();
+        let ilst = &data[4 + ilst.payload..4 + ilst.end];
+        let tags = children(ilst)
+            .unwrap()
+            .into_iter()
+            .map(|item| {
+                let item = &ilst[item.payload..item.end];
+                let (_, name) = freeform_key(item).unwrap();
+                let data = children(item).unwrap().into_iter().find(|b| &b.kind == b"data").unwrap();
+                (name, String::from_utf8_lossy(&item[data.payload + 8..data.end]).to_string())
+            })
+            .collect();
+        (chunk, tags)
+    }
+
+    fn tags(gain: &str) -> Vec<(String, String)> {
+        vec![
+            ("replaygain_track_gain".to_string(), gain.to_string()),
+            ("replaygain_track_peak".to_string(), "0.988553".to_string()),
+        ]
+    }
+
+    #[test]
+    fn tags_are_added_to_a_trailing_moov() {
+        let path = crate::test_support::temp_dir("mp4tags-trailing").join("song.m4a");
+        std::fs::write(&path, sample_file(false)).unwrap();
+        write_freeform_tags(&path, &tags("-6.20 dB")).unwrap();
+        write_freeform_tags(&path, &tags("-6.30 dB")).unwrap();
+        let file = std::fs::read(&path).unwrap();
+        let (chunk, written) = read_back(&file);
+        assert_eq!(&file[chunk as usize..chunk as usize + 8], b"audio!!!");
+        assert_eq!(written, tags("-6.30 dB"));
+    }
+
+    #[test]
+    fn chunk_offsets_follow_a_grown_leading_moov() {
+        let path = crate::test_support::temp_dir("mp4tags-faststart").join("song.m4a");
+        std::fs::write(&path, sample_file(true)).unwrap();
+        write_freeform_tags(&path, &tags("+1.50 dB")).unwrap();
+        let file = std::fs::read(&path).unwrap();
+        let (chunk, written) = read_back(&file);
+        assert_eq!(&file[chunk as usize..chunk as usize + 8], b"audio!!!");
+        assert_eq!(written, tags("+1.50 dB"));
+    }
+}
@@ -0,0 +1,144 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Pause state of the running conversion. The ffmpeg processes themselves are suspended
+/// (SIGSTOP/SIGCONT on Unix, thread suspension on Windows), so a paused encode uses no CPU.
+/// A parallel batch attaches one process per job; pausing suspends all of them.
+#[derive(Default)]
+pub struct PauseControl {
+    inner: Mutex<PauseState>,
+}
+
+#[derive(Default)]
+struct PauseState {
+    /// ffmpeg processes of the running jobs
+    pids: Vec<u32>,
+    paused_at: Option<Instant>,
+    /// Time spent paused in earlier pauses
+    paused_total: Duration,
+}
+
+/// Keeps a process attached to a `PauseControl`; detaches (resuming it if paused) when dropped
+pub struct AttachedProcess<'a> {
+    control: &'a PauseControl,
+    pid: u32,
+}
+
+impl Drop for AttachedProcess<'_> {
+    fn drop(&mut self) {
+        let mut state = self.control.inner.lock().unwrap();
+        state.pids.retain(|&pid| pid != self.pid);
+        if state.paused_at.is_some() {
+            suspend_process(self.pid, false).ok();
+        }
+        // The last process leaving ends the pause
+        if state.pids.is_empty() {
+            if let Some(paused_at) = state.paused_at.take() {
+                state.paused_total += paused_at.elapsed();
+            }
+        }
+    }
+}
+
+impl PauseControl {
+    /// Add `pid` to the processes that `pause`/`resume` act on; it is suspended right away
+    /// if the conversion is paused. Paused time keeps adding up across processes (e.g. the
+    /// passes of a two-pass encode), so jobs measure it relative to their start.
+    pub fn attach(&self, pid: u32) -> AttachedProcess<'_> {
+        let mut state = self.inner.lock().unwrap();
+        if state.paused_at.is_some() {
+            suspend_process(pid, true).ok();
+        }
+        state.pids.push(pid);
+        AttachedProcess { control: self, pid }
+    }
+
+    /// Suspend the running conversion. Pausing twice is harmless.
+    pub fn pause(&self) -> Result<(), String> {
+        let mut state = self.inner.lock().unwrap();
+        if state.pids.is_empty() {
+            return Err("No conversion is running".to_string());
+        }
+        if state.paused_at.is_none() {
+            for &pid in &state.pids {
+                suspend_process(pid, true).map_err(|e| format!("Could not pause ffmpeg: {}", e))?;
+            }
+            state.paused_at = Some(Instant::now());
+        }
+        Ok(())
+    }
+
+    /// Continue a paused conversion. Resuming one that isn't paused is harmless.
+    pub fn resume(&self) -> Result<(), String> {
+        let mut state = self.inner.lock().unwrap();
+        if state.pids.is_empty() {
+            return Err("No conversion is running".to_string());
+        }
+        if let Some(paused_at) = state.paused_at {
+            for &pid in &state.pids {
+                suspend_process(pid, false).map_err(|e| format!("Could not resume ffmpeg: {}", e))?;
+            }
+            state.paused_total += paused_at.elapsed();
+            state.paused_at = None;
+        }
+        Ok(())
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.inner.lock().unwrap().paused_at.is_some()
+    }
+
+    /// Total time spent paused since the app started, including a pause in progress
+    pub fn paused_time(&self) -> Duration {
+        let state = self.inner.lock().unwrap();
+        state.paused_total + state.paused_at.map_or(Duration::ZERO, |at| at.elapsed())
+    }
+}
+
+#[cfg(unix)]
+fn suspend_process(pid: u32, suspend: bool) -> std::io::Result<()> {
+    let signal = if suspend { libc::SIGSTOP } else { libc::SIGCONT };
+    // SAFETY: kill() only sends a signal; an invalid pid makes it fail with ESRCH
+    if unsafe { libc::kill(pid as libc::pid_t, signal) } == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+/// Windows has no SIGSTOP: suspend (or resume) every thread of the process
+#[cfg(windows)]
+fn suspend_process(pid: u32, suspend: bool) -> std::io::Result<()> {
+    use windows_sys::Win32::Foundation::{CloseHandle, INVALID_HANDLE_VALUE};
+    use windows_sys::Win32::System::Diagnostics::ToolHelp::{
+        CreateToolhelp32Snapshot, Thread32First, Thread32Next, TH32CS_SNAPTHREAD, THREADENTRY32,
+    };
+    use windows_sys::Win32::System::Threading::{OpenThread, ResumeThread, SuspendThread, THREAD_SUSPEND_RESUME};
+
+    // SAFETY: handles are checked before use and closed once; THREADENTRY32 is plain data
+    unsafe {
+        let snapshot = CreateToolhelp32Snapshot(TH32CS_SNAPTHREAD, 0);
+        if snapshot == INVALID_HANDLE_VALUE {
+            return Err(std::io::Error::last_os_error());
+        }
+        let mut entry: THREADENTRY32 = std::mem::zeroed();
+        entry.dwSize = std::mem::size_of::<THREADENTRY32>() as u32;
+        let mut more = Thread32First(snapshot, &mut entry) != 0;
+        while more {
+            if entry.th32OwnerProcessID == pid {
+                let thread = OpenThread(THREAD_SUSPEND_RESUME, 0, entry.th32ThreadID);
+                if !thread.is_null() {
+                    if suspend {
+                        SuspendThread(thread);
+                    } else {
+                        ResumeThread(thread);
+                    }
+                    CloseHandle(thread);
+                }
+            }
+            more = Thread32Next(snapshot, &mut entry) != 0;
+        }
+        CloseHandle(snapshot);
+    }
+    Ok(())
+}
@@ -0,0 +1,383 @@
+use crate::capabilities::{list_encoders, EncoderInfo};
+use crate::convert::{
+    build_ffmpeg_args, container_accepts, count_sequence_files, is_sequence_path, is_stream_url,
+    target_container, ConvertOptions,
+};
+use crate::estimate::estimate_output_size;
+use crate::probe::{probe_file_with_retry, MediaInfo};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Pass,
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PreflightCheck {
+    pub id: &'static str,
+    pub status: CheckStatus,
+    pub message: String,
+}
+
+impl PreflightCheck {
+    fn new(id: &'static str, status: CheckStatus, message: impl Into<String>) -> Self {
+        Self { id, status, message: message.into() }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PreflightReport {
+    pub checks: Vec<PreflightCheck>,
+    /// No check failed (warnings do not block a conversion)
+    pub ready: bool,
+}
+
+/// Which checks to run; all are on by default
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PreflightChecks {
+    pub input_exists: bool,
+    pub output_writable: bool,
+    pub disk_space: bool,
+    pub codec_available: bool,
+    pub container_compatible: bool,
+    pub streams_available: bool,
+}
+
+impl Default for PreflightChecks {
+    fn default() -> Self {
+        Self {
+            input_exists: true,
+            output_writable: true,
+            disk_space: true,
+            codec_available: true,
+            container_compatible: true,
+            streams_available: true,
+        }
+    }
+}
+
+pub fn check_input_exists(input_path: &str) -> PreflightCheck {
+    if is_sequence_path(input_path) {
+        return match count_sequence_files(input_path) {
+            Some(n) if n > 0 => {
+                PreflightCheck::new("input_exists", CheckStatus::Pass, format!("Image sequence of {} files", n))
+            }
+            _ => PreflightCheck::new(
+                "input_exists",
+                CheckStatus::Error,
+                format!("No files match the image sequence: {}", input_path),
+            ),
+        };
+    }
+    if Path::new(input_path).is_file() {
+        PreflightCheck::new("input_exists", CheckStatus::Pass, "Input file found")
+    } else {
+        PreflightCheck::new("input_exists", CheckStatus::Error, format!("Input file not found: {}", input_path))
+    }
+}
+
+pub fn check_output_writable(output_path: &str) -> PreflightCheck {
+    let id = "output_writable";
+    let Some(parent) = Path::new(output_path).parent().filter(|p| !p.as_os_str().is_empty()) else {
+        return PreflightCheck::new(id, CheckStatus::Pass, "Output goes to the working directory");
+    };
+    match std::fs::metadata(parent) {
+        Err(_) => PreflightCheck::new(
+            id,
+            CheckStatus::Error,
+            format!("Output directory does not exist: {}", parent.display()),
+        ),
+        Ok(meta) if meta.permissions().readonly() => PreflightCheck::new(
+            id,
+            CheckStatus::Error,
+            format!("Output directory is read-only: {}", parent.display()),
+        ),
+        Ok(_) if Path::new(output_path).exists() => PreflightCheck::new(
+            id,
+            CheckStatus::Warning,
+            "Output file already exists; a numbered name will be used",
+        ),
+        Ok(_) => PreflightCheck::new(id, CheckStatus::Pass, "Output directory is writable"),
+    }
+}
+
+/// Conservative estimate: the output needs about as much room as the input
+fn check_disk_space(input_path: &str, output_path: &str) -> PreflightCheck {
+    let id = "disk_space";
+    let dir = Path::new(output_path)
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let (Ok(available), Ok(input)) = (fs2::available_space(dir), std::fs::metadata(input_path)) else {
+        return PreflightCheck::new(id, CheckStatus::Warning, "Could not determine free disk space");
+    };
+    let needed = input.len();
+    if available < needed {
+        PreflightCheck::new(
+            id,
+            CheckStatus::Warning,
+            format!(
+                "Only {} MB free on the output volume, the input is {} MB",
+                available / 1_000_000,
+                needed / 1_000_000
+            ),
+        )
+    } else {
+        PreflightCheck::new(id, CheckStatus::Pass, format!("{} MB free", available / 1_000_000))
+    }
+}
+
+/// Free space on the output volume and the least the output is expected to need (the
+/// low end of `estimate_output_size`), when both are known
+pub fn output_space(options: &ConvertOptions, info: &MediaInfo) -> Option<(u64, u64)> {
+    let dir = Path::new(&options.output_path)
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let available = fs2::available_space(dir).ok()?;
+    let required = estimate_output_size(options, info).ok()?.min_bytes;
+    Some((required, available))
+}
+
+/// Encoders named in the arguments (`-c:v libx264`, `-c:a:1 aac`, ...), excluding stream copy
+fn requested_encoders(args: &[String]) -> Vec<&str> {
+    args.windows(2)
+        .filter(|pair| {
+            let flag = pair[0].as_str();
+            flag == "-c" || flag.starts_with("-c:") || matches!(flag, "-vcodec" | "-acodec" | "-scodec")
+        })
+        .map(|pair| pair[1].as_str())
+        .filter(|codec| *codec != "copy")
+        .collect()
+}
+
+fn check_codec_available(args: &[String], ffmpeg_sidecar: Option<&Path>) -> PreflightCheck {
+    match list_encoders(ffmpeg_sidecar) {
+        Ok(encoders) => check_encoders_listed(args, &encoders),
+        Err(e) => PreflightCheck::new(
+            "codec_available",
+            CheckStatus::Warning,
+            format!("Could not list encoders: {}", e),
+        ),
+    }
+}
+
+/// `codec_available` against the encoders an ffmpeg build reported
+fn check_encoders_listed(args: &[String], encoders: &[EncoderInfo]) -> PreflightCheck {
+    let id = "codec_available";
+    let missing: Vec<&str> = requested_encoders(args)
+        .into_iter()
+        .filter(|codec| !encoders.iter().any(|e| e.name == *codec))
+        .collect();
+    if missing.is_empty() {
+        PreflightCheck::new(id, CheckStatus::Pass, "All encoders are available")
+    } else {
+        PreflightCheck::new(
+            id,
+            CheckStatus::Error,
+            format!("This FFmpeg build lacks: {}", missing.join(", ")),
+        )
+    }
+}
+
+fn check_container_compatible(args: &[String], output_path: &str) -> PreflightCheck {
+    let id = "container_compatible";
+    let container = target_container(args, output_path);
+    let mut unknown = Vec::new();
+    for codec in requested_encoders(args) {
+        match container_accepts(&container, codec) {
+            None => {
+                return PreflightCheck::new(
+                    id,
+                    CheckStatus::Pass,
+                    format!("No compatibility data for '{}'", container),
+                )
+            }
+            Some(false) => unknown.push(codec),
+            Some(true) => {}
+        }
+    }
+    if unknown.is_empty() {
+        PreflightCheck::new(id, CheckStatus::Pass, format!("Codecs fit the '{}' container", container))
+    } else {
+        PreflightCheck::new(
+            id,
+            CheckStatus::Warning,
+            format!("'{}' may not accept: {}", container, unknown.join(", ")),
+        )
+    }
+}
+
+fn check_streams_available(options: &ConvertOptions, info: Option<&MediaInfo>) -> PreflightCheck {
+    let id = "streams_available";
+    let Some(info) = info else {
+        return PreflightCheck::new(id, CheckStatus::Error, "Input could not be read by ffprobe");
+    };
+    let selection = options.stream_selection.clone().unwrap_or_default();
+    let video = info.has_video && selection.include_video;
+    let audio = info.has_audio && selection.include_audio;
+    if !video && !audio {
+        return PreflightCheck::new(id, CheckStatus::Error, "No audio or video stream would be converted");
+    }
+    if selection.include_video && !info.has_video && selection.include_audio {
+        return PreflightCheck::new(id, CheckStatus::Warning, "Input has no video stream; output will be audio only");
+    }
+    PreflightCheck::new(id, CheckStatus::Pass, "Input streams match the selection")
+}
+
+/// Run the enabled pre-conversion checks. Later checks that need valid arguments or a
+/// readable input are skipped when those are missing.
+pub fn run_preflight(
+    options: &ConvertOptions,
+    enabled: &PreflightChecks,
+    ffmpeg_sidecar: Option<&Path>,
+    ffprobe_sidecar: Option<&Path>,
+) -> PreflightReport {
+    let mut checks = Vec::new();
+    if enabled.input_exists {
+        checks.push(check_input_exists(&options.input_path));
+    }
+    // Streaming outputs are URLs: nothing to write or fill on a local disk
+    let live = is_stream_url(&options.output_path);
+    if enabled.output_writable && !live {
+        checks.push(check_output_writable(&options.output_path));
+    }
+    if enabled.disk_space && !live {
+        checks.push(check_disk_space(&options.input_path, &options.output_path));
+    }
+    
+    let info = probe_file_with_retry(&options.input_path, ffprobe_sidecar, &options.settings.probe_retry).ok();
+    if enabled.streams_available {
+        checks.push(check_streams_available(options, info.as_ref()));
+    }
+    
+    match build_ffmpeg_args(options, info.as_ref()) {
+        Ok(args) => {
+            if enabled.codec_available {
+                checks.push(check_codec_available(&args, ffmpeg_sidecar));
+            }
+            if enabled.container_compatible {
+                checks.push(check_container_compatible(&args, &options.output_path));
+            }
+        }
+        Err(e) => checks.push(PreflightCheck::new("arguments", CheckStatus::Error, e.to_string())),
+    }
+    
+    let ready = checks.iter().all(|c| c.status != CheckStatus::Error);
+    PreflightReport { checks, ready }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capabilities::CodecKind;
+    use crate::settings::AppSettings;
+    use crate::test_support::temp_dir;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|a| a.to_string()).collect()
+    }
+
+    fn options(input_path: &str, output_path: &str) -> ConvertOptions {
+        ConvertOptions {
+            input_path: input_path.to_string(),
+            output_path: output_path.to_string(),
+            preset_id: None,
+            advanced: None,
+            stream_selection: None,
+            settings: AppSettings::default(),
+            job_id: 0,
+        }
+    }
+
+    #[test]
+    fn input_exists_checks_files() {
+        let dir = temp_dir("preflight-input");
+        let input = dir.join("clip.mp4");
+        assert_eq!(check_input_exists(&input.to_string_lossy()).status, CheckStatus::Error);
+        std::fs::write(&input, b"data").unwrap();
+        assert_eq!(check_input_exists(&input.to_string_lossy()).status, CheckStatus::Pass);
+    }
+
+    #[test]
+    fn input_exists_counts_image_sequences() {
+        let dir = temp_dir("preflight-sequence");
+        let pattern = dir.join("frame_%04d.png").to_string_lossy().to_string();
+        assert_eq!(check_input_exists(&pattern).status, CheckStatus::Error);
+        for n in 1..=3 {
+            std::fs::write(dir.join(format!("frame_{:04}.png", n)), b"png").unwrap();
+        }
+        let check = check_input_exists(&pattern);
+        assert_eq!(check.status, CheckStatus::Pass);
+        assert!(check.message.contains('3'));
+    }
+
+    #[test]
+    fn output_writable_checks_the_directory() {
+        let dir = temp_dir("preflight-output");
+        let output = dir.join("out.mp4");
+        assert_eq!(check_output_writable(&output.to_string_lossy()).status, CheckStatus::Pass);
+        std::fs::write(&output, b"old").unwrap();
+        assert_eq!(check_output_writable(&output.to_string_lossy()).status, CheckStatus::Warning);
+        let missing = dir.join("missing").join("out.mp4");
+        assert_eq!(check_output_writable(&missing.to_string_lossy()).status, CheckStatus::Error);
+    }
+
+    #[test]
+    fn disk_space_compares_with_the_input_size() {
+        let dir = temp_dir("preflight-space");
+        let input = dir.join("clip.mp4");
+        std::fs::write(&input, vec![0u8; 1024]).unwrap();
+        let output = dir.join("out.mp4");
+        let check = check_disk_space(&input.to_string_lossy(), &output.to_string_lossy());
+        assert_eq!(check.status, CheckStatus::Pass);
+        // An unreadable input leaves the requirement unknown
+        let check = check_disk_space(&dir.join("gone.mp4").to_string_lossy(), &output.to_string_lossy());
+        assert_eq!(check.status, CheckStatus::Warning);
+    }
+
+    #[test]
+    fn codec_available_reports_missing_encoders() {
+        let encoders = vec![EncoderInfo {
+            name: "libx264".to_string(),
+            kind: CodecKind::Video,
+            description: "libx264 H.264".to_string(),
+        }];
+        let check = check_encoders_listed(&args(&["-c:v", "libx264", "-c:a", "copy"]), &encoders);
+        assert_eq!(check.status, CheckStatus::Pass);
+        let check = check_encoders_listed(&args(&["-c:v", "libx264", "-c:a", "libopus"]), &encoders);
+        assert_eq!(check.status, CheckStatus::Error);
+        assert!(check.message.contains("libopus"));
+    }
+
+    #[test]
+    fn container_compatible_flags_unknown_codecs() {
+        let fits = check_container_compatible(&args(&["-c:v", "libx264", "-c:a", "aac"]), "out.mp4");
+        assert_eq!(fits.status, CheckStatus::Pass);
+        let misfit = check_container_compatible(&args(&["-c:v", "libx264", "-c:a", "libvorbis"]), "out.mp4");
+        assert_eq!(misfit.status, CheckStatus::Warning);
+        assert!(misfit.message.contains("libvorbis"));
+    }
+
+    #[test]
+    fn streams_available_follows_the_selection() {
+        let audio_only = crate::probe::parse_probe_output(
+            r#"{"streams": [{"index": 0, "codec_type": "audio", "codec_name": "flac"}], "format": {}}"#,
+            "song.flac",
+        )
+        .unwrap();
+        let mut opts = options("song.flac", "song.mp3");
+        assert_eq!(check_streams_available(&opts, None).status, CheckStatus::Error);
+        assert_eq!(check_streams_available(&opts, Some(&audio_only)).status, CheckStatus::Warning);
+        opts.stream_selection = Some(crate::convert::StreamSelection {
+            include_audio: false,
+            ..Default::default()
+        });
+        assert_eq!(check_streams_available(&opts, Some(&audio_only)).status, CheckStatus::Error);
+    }
+}
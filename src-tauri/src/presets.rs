@@ -1,4 +1,17 @@
+use crate::capabilities::{is_hardware_encoder, list_encoders, list_muxers, EncoderInfo};
+use crate::convert::{check_extra_args, run_ffmpeg, ConvertError};
+use crate::filters::{gif_palette_filter, DitherMode, GIF_DEFAULT_FPS, GIF_DEFAULT_WIDTH};
+use crate::probe::{MediaInfo, StreamType};
+use crate::settings::{load_json, save_json, AppSettings};
+use crate::temp::TempFileSet;
 use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+const CUSTOM_PRESETS_FILE: &str = "custom_presets.json";
+
+/// Version of the preset export format; bump when `Preset` changes incompatibly
+pub const PRESET_EXPORT_VERSION: u32 = 1;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Preset {
@@ -50,8 +63,8 @@ impl Preset {
     }
 }
 
-/// Get all available presets
-pub fn get_all_presets() -> Vec<Preset> {
+/// Presets shipped with the app
+fn builtin_presets() -> Vec<Preset> {
     vec![
         // ===== VIDEO PRESETS =====
         Preset {
@@ -80,6 +93,72 @@ pub fn get_all_presets() -> Vec<Preset> {
                 "-crf".to_string(), "28".to_string(),
             ],
         },
+        // Hardware encoders: only offered where `detect_hw_encoders` finds them working
+        Preset {
+            id: "mp4_h264_nvenc".to_string(),
+            name: "MP4 (H.264, NVIDIA NVENC)".to_string(),
+            category: PresetCategory::Video,
+            extension: "mp4".to_string(),
+            format: Some("mp4".to_string()),
+            video_codec: Some("h264_nvenc".to_string()),
+            audio_codec: Some("aac".to_string()),
+            extra_args: vec![
+                "-preset".to_string(), "p5".to_string(),
+                "-cq".to_string(), "23".to_string(),
+            ],
+        },
+        Preset {
+            id: "mp4_h265_nvenc".to_string(),
+            name: "MP4 (H.265, NVIDIA NVENC)".to_string(),
+            category: PresetCategory::Video,
+            extension: "mp4".to_string(),
+            format: Some("mp4".to_string()),
+            video_codec: Some("hevc_nvenc".to_string()),
+            audio_codec: Some("aac".to_string()),
+            extra_args: vec![
+                "-preset".to_string(), "p5".to_string(),
+                "-cq".to_string(), "28".to_string(),
+                "-tag:v".to_string(), "hvc1".to_string(),
+            ],
+        },
+        Preset {
+            id: "mp4_h264_qsv".to_string(),
+            name: "MP4 (H.264, Intel Quick Sync)".to_string(),
+            category: PresetCategory::Video,
+            extension: "mp4".to_string(),
+            format: Some("mp4".to_string()),
+            video_codec: Some("h264_qsv".to_string()),
+            audio_codec: Some("aac".to_string()),
+            extra_args: vec![
+                "-preset".to_string(), "medium".to_string(),
+                "-global_quality".to_string(), "23".to_string(),
+            ],
+        },
+        Preset {
+            id: "mp4_h264_videotoolbox".to_string(),
+            name: "MP4 (H.264, Apple VideoToolbox)".to_string(),
+            category: PresetCategory::Video,
+            extension: "mp4".to_string(),
+            format: Some("mp4".to_string()),
+            video_codec: Some("h264_videotoolbox".to_string()),
+            audio_codec: Some("aac".to_string()),
+            extra_args: vec![
+                "-b:v".to_string(), "6M".to_string(),
+            ],
+        },
+        Preset {
+            id: "mp4_h265_videotoolbox".to_string(),
+            name: "MP4 (H.265, Apple VideoToolbox)".to_string(),
+            category: PresetCategory::Video,
+            extension: "mp4".to_string(),
+            format: Some("mp4".to_string()),
+            video_codec: Some("hevc_videotoolbox".to_string()),
+            audio_codec: Some("aac".to_string()),
+            extra_args: vec![
+                "-b:v".to_string(), "4M".to_string(),
+                "-tag:v".to_string(), "hvc1".to_string(),
+            ],
+        },
         Preset {
             id: "webm_vp9".to_string(),
             name: "WebM (VP9)".to_string(),
@@ -140,7 +219,7 @@ pub fn get_all_presets() -> Vec<Preset> {
             audio_codec: None,
             extra_args: vec![
                 "-vf".to_string(), 
-                "fps=15,scale=480:-1:flags=lanczos,split[s0][s1];[s0]palettegen[p];[s1][p]paletteuse".to_string(),
+                gif_palette_filter(GIF_DEFAULT_FPS, GIF_DEFAULT_WIDTH, DitherMode::default()),
             ],
         },
         
@@ -247,10 +326,429 @@ pub fn get_all_presets() -> Vec<Preset> {
                 "-an".to_string(),
             ],
         },
+        // Needs an optional encoder and muxer; `available_presets` hides it on builds without.
+        // The CRF is the default for `image_quality`, which replaces it.
+        Preset {
+            id: "avif".to_string(),
+            name: "AVIF".to_string(),
+            category: PresetCategory::Image,
+            extension: "avif".to_string(),
+            format: Some("avif".to_string()),
+            video_codec: Some("libaom-av1".to_string()),
+            audio_codec: None,
+            extra_args: vec![
+                "-frames:v".to_string(), "1".to_string(),
+                "-still-picture".to_string(), "1".to_string(),
+                "-crf".to_string(), "30".to_string(),
+                "-b:v".to_string(), "0".to_string(),
+                "-an".to_string(),
+            ],
+        },
+        // Animations: no -frames:v limit, looping forever, at the GIF preset's rate and width
+        Preset {
+            id: "webp_animated".to_string(),
+            name: "WebP (Animated)".to_string(),
+            category: PresetCategory::Image,
+            extension: "webp".to_string(),
+            format: Some("webp".to_string()),
+            video_codec: Some("libwebp".to_string()),
+            audio_codec: None,
+            extra_args: vec![
+                "-vf".to_string(), animation_filter(),
+                "-quality".to_string(), "75".to_string(),
+                "-compression_level".to_string(), "4".to_string(),
+                "-loop".to_string(), "0".to_string(),
+                "-an".to_string(),
+            ],
+        },
+        Preset {
+            id: "apng".to_string(),
+            name: "APNG (Animated PNG)".to_string(),
+            category: PresetCategory::Image,
+            extension: "apng".to_string(),
+            format: Some("apng".to_string()),
+            video_codec: Some("apng".to_string()),
+            audio_codec: None,
+            extra_args: vec![
+                "-vf".to_string(), animation_filter(),
+                "-plays".to_string(), "0".to_string(),
+                "-an".to_string(),
+            ],
+        },
+    ]
+}
+
+/// User presets (imported or saved), persisted as JSON in the app config dir. Kept
+/// process-wide so `find_preset` resolves them wherever arguments are built.
+struct CustomPresets {
+    presets: Vec<Preset>,
+    config_dir: Option<PathBuf>,
+}
+
+static CUSTOM_PRESETS: Mutex<CustomPresets> = Mutex::new(CustomPresets {
+    presets: Vec::new(),
+    config_dir: None,
+});
+
+/// Load the custom presets saved in `config_dir` (called once at startup)
+pub fn init_custom_presets(config_dir: Option<PathBuf>) {
+    let mut custom = CUSTOM_PRESETS.lock().unwrap();
+    custom.presets = load_json(config_dir.as_deref(), CUSTOM_PRESETS_FILE).unwrap_or_default();
+    custom.config_dir = config_dir;
+}
+
+/// Add a custom preset and write the list to disk
+fn add_custom_preset(preset: Preset) -> Result<(), String> {
+    let mut custom = CUSTOM_PRESETS.lock().unwrap();
+    let mut updated = custom.presets.clone();
+    updated.push(preset);
+    save_json(custom.config_dir.as_deref(), CUSTOM_PRESETS_FILE, &updated)?;
+    custom.presets = updated;
+    Ok(())
+}
+
+/// Save a preset the user built, replacing their earlier preset with the same id.
+/// Built-in ids are reserved.
+pub fn save_custom_preset(preset: Preset, encoders: Option<&[EncoderInfo]>) -> Result<Preset, String> {
+    if builtin_presets().iter().any(|p| p.id == preset.id) {
+        return Err(format!("'{}' is the id of a built-in preset; choose another", preset.id));
+    }
+    if preset.video_codec.is_none() && preset.audio_codec.is_none() && preset.format.is_none() {
+        return Err("Preset needs a format or at least one codec".to_string());
+    }
+    validate_shared_preset(&preset, encoders)?;
+    
+    let mut custom = CUSTOM_PRESETS.lock().unwrap();
+    let mut updated = custom.presets.clone();
+    match updated.iter_mut().find(|p| p.id == preset.id) {
+        Some(existing) => *existing = preset.clone(),
+        None => updated.push(preset.clone()),
+    }
+    save_json(custom.config_dir.as_deref(), CUSTOM_PRESETS_FILE, &updated)?;
+    custom.presets = updated;
+    Ok(preset)
+}
+
+/// Remove a custom preset and write the list to disk
+pub fn delete_custom_preset(id: &str) -> Result<(), String> {
+    if builtin_presets().iter().any(|p| p.id == id) {
+        return Err(format!("'{}' is a built-in preset and cannot be deleted", id));
+    }
+    let mut custom = CUSTOM_PRESETS.lock().unwrap();
+    if !custom.presets.iter().any(|p| p.id == id) {
+        return Err(format!("Preset not found: {}", id));
+    }
+    let updated: Vec<Preset> = custom.presets.iter().filter(|p| p.id != id).cloned().collect();
+    save_json(custom.config_dir.as_deref(), CUSTOM_PRESETS_FILE, &updated)?;
+    custom.presets = updated;
+    Ok(())
+}
+
+/// Get all available presets: built-ins followed by the user's custom presets
+pub fn get_all_presets() -> Vec<Preset> {
+    let mut presets = builtin_presets();
+    presets.extend(CUSTOM_PRESETS.lock().unwrap().presets.iter().cloned());
+    presets
+}
+
+/// Presets usable on this machine: hardware-encoder presets are dropped unless their
+/// encoder is in `working_hw_encoders` (from `detect_hw_encoders`), and presets whose
+/// encoders or output format aren't compiled in (AVIF on many builds) are dropped when the
+/// `encoders`/`muxers` lists are known. `copy` is always available.
+pub fn available_presets(
+    working_hw_encoders: &[String],
+    encoders: Option<&[EncoderInfo]>,
+    muxers: Option<&[String]>,
+) -> Vec<Preset> {
+    get_all_presets()
+        .into_iter()
+        .filter(|preset| preset_available(preset, working_hw_encoders, encoders, muxers))
+        .collect()
+}
+
+fn preset_available(
+    preset: &Preset,
+    working_hw_encoders: &[String],
+    encoders: Option<&[EncoderInfo]>,
+    muxers: Option<&[String]>,
+) -> bool {
+    let has_encoder = |codec: &str| codec == "copy" || encoders.is_none_or(|list| list.iter().any(|e| e.name == codec));
+    let video = match preset.video_codec.as_deref() {
+        Some(codec) if is_hardware_encoder(codec) => working_hw_encoders.iter().any(|e| e == codec),
+        Some(codec) => has_encoder(codec),
+        None => true,
+    };
+    video
+        && preset.audio_codec.as_deref().is_none_or(has_encoder)
+        && preset
+            .format
+            .as_deref()
+            .is_none_or(|format| muxers.is_none_or(|list| list.iter().any(|m| m == format)))
+}
+
+/// Shareable, versioned preset file
+#[derive(Debug, Serialize, Deserialize)]
+struct PresetExport {
+    version: u32,
+    preset: Preset,
+}
+
+/// Serialize a preset (built-in or custom) for sharing
+pub fn export_preset(id: &str) -> Result<String, String> {
+    let preset = find_preset(id).ok_or_else(|| format!("Preset not found: {}", id))?;
+    serde_json::to_string_pretty(&PresetExport {
+        version: PRESET_EXPORT_VERSION,
+        preset,
+    })
+    .map_err(|e| e.to_string())
+}
+
+/// Codec/format names are plain identifiers like `libx264` or `pcm_s16le`
+fn is_identifier(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Reject shared or user-built presets that are incomplete, name codecs this ffmpeg lacks (when the
+/// encoder list is known) or carry unsafe extra arguments
+fn validate_shared_preset(preset: &Preset, encoders: Option<&[EncoderInfo]>) -> Result<(), String> {
+    if preset.id.trim().is_empty() || preset.name.trim().is_empty() {
+        return Err("Preset needs an id and a name".to_string());
+    }
+    if !is_identifier(&preset.extension) {
+        return Err(format!("Invalid file extension '{}'", preset.extension));
+    }
+    if let Some(format) = preset.format.as_deref().filter(|f| !is_identifier(f)) {
+        return Err(format!("Invalid format '{}'", format));
+    }
+    for codec in [&preset.video_codec, &preset.audio_codec].into_iter().flatten() {
+        if !is_identifier(codec) {
+            return Err(format!("Invalid codec name '{}'", codec));
+        }
+        let known = codec == "copy" || encoders.is_none_or(|list| list.iter().any(|e| e.name == *codec));
+        if !known {
+            return Err(format!("Codec '{}' is not available in this FFmpeg build", codec));
+        }
+    }
+    check_extra_args(&preset.extra_args)
+}
+
+/// Check a preset against this ffmpeg build: its codecs and format must exist, then a
+/// one-second encode of synthetic input with its arguments must succeed. Errors start
+/// with the field at fault ("video_codec: ...") and carry ffmpeg's own message.
+pub fn validate_preset_with_ffmpeg(
+    preset: &Preset,
+    settings: &AppSettings,
+    sidecar_path: Option<&std::path::Path>,
+) -> Result<(), String> {
+    validate_shared_preset(preset, None)?;
+    let encoders = list_encoders(sidecar_path)?;
+    for (field, codec) in [("video_codec", &preset.video_codec), ("audio_codec", &preset.audio_codec)] {
+        if let Some(codec) = codec.as_deref().filter(|c| *c != "copy") {
+            if !encoders.iter().any(|e| e.name == codec) {
+                return Err(format!("{}: Unknown encoder '{}' in this FFmpeg build", field, codec));
+            }
+        }
+    }
+    if let Some(format) = preset.format.as_deref() {
+        if !list_muxers(sidecar_path)?.iter().any(|m| m == format) {
+            return Err(format!("format: Unknown output format '{}' in this FFmpeg build", format));
+        }
+    }
+    
+    // Stream copy needs a real input stream of that codec; only the other options are tried
+    let mut args: Vec<String> = [
+        "-f", "lavfi", "-i", "testsrc=size=128x72:rate=25:duration=1",
+        "-f", "lavfi", "-i", "anullsrc=r=48000:cl=stereo",
+        "-t", "1",
     ]
+    .map(String::from)
+    .to_vec();
+    let mut preset_args = preset.build_args().into_iter();
+    while let Some(arg) = preset_args.next() {
+        if (arg == "-c:v" || arg == "-c:a") && preset_args.as_slice().first().is_some_and(|c| c == "copy") {
+            preset_args.next();
+            continue;
+        }
+        args.push(arg);
+    }
+    let output = TempFileSet::new(settings, "validate");
+    let output_path = format!("{}.{}", output.prefix_path().to_string_lossy(), preset.extension);
+    args.extend(["-y".to_string(), output_path]);
+    match run_ffmpeg(sidecar_path, &args) {
+        Ok(_) => Ok(()),
+        Err(ConvertError::ConversionFailed(message)) => Err(format!("extra_args: {}", message)),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Import a shared preset as a custom preset. The id gets a numeric suffix if it clashes
+/// with an existing preset. `encoders` (from `list_encoders`) enables the codec check.
+pub fn import_preset(json: &str, encoders: Option<&[EncoderInfo]>) -> Result<Preset, String> {
+    let value: serde_json::Value = serde_json::from_str(json).map_err(|e| format!("Not a preset file: {}", e))?;
+    let version = value
+        .get("version")
+        .and_then(|v| v.as_u64())
+        .ok_or_else(|| "Not a preset file: missing version".to_string())?;
+    if version > PRESET_EXPORT_VERSION as u64 {
+        return Err(format!(
+            "Preset was exported by a newer version of the app (format {}, supported up to {})",
+            version, PRESET_EXPORT_VERSION
+        ));
+    }
+    let export: PresetExport = serde_json::from_value(value).map_err(|e| format!("Invalid preset: {}", e))?;
+    let mut preset = export.preset;
+    validate_shared_preset(&preset, encoders)?;
+    
+    let taken: Vec<String> = get_all_presets().into_iter().map(|p| p.id).collect();
+    if taken.contains(&preset.id) {
+        let base = preset.id.clone();
+        preset.id = (2..)
+            .map(|n| format!("{}_{}", base, n))
+            .find(|id| !taken.contains(id))
+            .unwrap_or(base);
+    }
+    add_custom_preset(preset.clone())?;
+    Ok(preset)
+}
+
+/// Frame rate and size of the animated image presets, as for GIF
+fn animation_filter() -> String {
+    format!("fps={},scale={}:-1:flags=lanczos", GIF_DEFAULT_FPS, GIF_DEFAULT_WIDTH)
 }
 
 /// Find a preset by ID
 pub fn find_preset(id: &str) -> Option<Preset> {
     get_all_presets().into_iter().find(|p| p.id == id)
 }
+
+/// A recommended preset for an input file, with the reason shown to the user
+#[derive(Debug, Clone, Serialize)]
+pub struct PresetSuggestion {
+    pub preset_id: String,
+    pub reason: String,
+}
+
+impl PresetSuggestion {
+    fn new(preset_id: &str, reason: &str) -> Self {
+        Self {
+            preset_id: preset_id.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+}
+
+/// Recommend a sensible preset based on what the probed file contains
+pub fn suggest_preset(info: &MediaInfo) -> PresetSuggestion {
+    let video = info.base_video_stream();
+    let audio = info.streams.iter().find(|s| s.stream_type == StreamType::Audio);
+    let format = info.format.format_name.as_str();
+    let is_still_image = format == "image2" || format.ends_with("_pipe");
+    
+    if let Some(video) = video {
+        let codec = video.codec_name.as_deref().unwrap_or("");
+        if is_still_image {
+            return match codec {
+                "png" => PresetSuggestion::new("webp", "WebP keeps PNG-like quality at a fraction of the size"),
+                "mjpeg" => PresetSuggestion::new("webp", "WebP is smaller than JPEG at the same visual quality"),
+                _ => PresetSuggestion::new("png", "PNG is lossless and opens everywhere"),
+            };
+        }
+        if format == "gif" {
+            return PresetSuggestion::new("mp4_h264", "An MP4 of an animated GIF is typically 5-10x smaller");
+        }
+        return match codec {
+            "h264" => PresetSuggestion::new("mp4_h264", "Already H.264: MP4 (H.264) plays everywhere"),
+            "hevc" => PresetSuggestion::new("mp4_h265", "Already HEVC: keep the efficient modern codec"),
+            "vp9" | "av1" => PresetSuggestion::new("webm_vp9", "Already a modern web codec: WebM (VP9) keeps it efficient"),
+            _ if video.height.unwrap_or(0) >= 2160 => {
+                PresetSuggestion::new("mp4_h265", "4K video: HEVC halves the size compared to H.264")
+            }
+            _ => PresetSuggestion::new("mp4_h264", "MP4 (H.264) is the most compatible video format"),
+        };
+    }
+    
+    if let Some(audio) = audio {
+        let codec = audio.codec_name.as_deref().unwrap_or("");
+        let lossless = codec == "flac" || codec == "alac" || codec.starts_with("pcm_") || codec == "wavpack";
+        return if lossless {
+            PresetSuggestion::new("flac", "Lossless source: FLAC keeps full quality at about half the size")
+        } else {
+            PresetSuggestion::new("mp3", "MP3 plays on every device and player")
+        };
+    }
+    
+    PresetSuggestion::new("mp4_h264", "No audio or video stream detected; MP4 (H.264) is the safest default")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capabilities::CodecKind;
+
+    fn encoders(names: &[&str]) -> Vec<EncoderInfo> {
+        names
+            .iter()
+            .map(|name| EncoderInfo {
+                name: name.to_string(),
+                kind: CodecKind::Video,
+                description: String::new(),
+            })
+            .collect()
+    }
+
+    fn remux_preset() -> Preset {
+        Preset {
+            id: "mkv_remux".to_string(),
+            name: "MKV (copy)".to_string(),
+            category: PresetCategory::Video,
+            extension: "mkv".to_string(),
+            format: Some("matroska".to_string()),
+            video_codec: Some("copy".to_string()),
+            audio_codec: Some("copy".to_string()),
+            extra_args: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn copy_presets_are_always_available() {
+        let encoders = encoders(&["libx264", "aac"]);
+        let muxers = vec!["matroska".to_string()];
+        assert!(preset_available(&remux_preset(), &[], Some(&encoders), Some(&muxers)));
+    }
+
+    #[test]
+    fn avif_needs_its_encoder_and_muxer() {
+        let avif = find_preset("avif").unwrap();
+        let with_encoder = encoders(&["libaom-av1"]);
+        let without_encoder = encoders(&["libx264"]);
+        let muxers = vec!["avif".to_string()];
+        assert!(preset_available(&avif, &[], Some(&with_encoder), Some(&muxers)));
+        assert!(!preset_available(&avif, &[], Some(&without_encoder), Some(&muxers)));
+        assert!(!preset_available(&avif, &[], Some(&with_encoder), Some(&["mp4".to_string()])));
+        // Unknown lists don't hide anything
+        assert!(preset_available(&avif, &[], None, None));
+    }
+
+    #[test]
+    fn built_in_presets_pass_the_extra_args_check() {
+        for preset in builtin_presets() {
+            assert_eq!(check_extra_args(&preset.extra_args), Ok(()), "preset {}", preset.id);
+        }
+    }
+
+    #[test]
+    fn cover_art_does_not_make_an_audio_file_a_video() {
+        let json = r#"{"streams": [
+            {"index": 0, "codec_type": "audio", "codec_name": "flac"},
+            {"index": 1, "codec_type": "video", "codec_name": "mjpeg", "width": 600, "height": 600,
+             "disposition": {"attached_pic": 1}}],
+            "format": {"format_name": "flac", "duration": "200.0"}}"#;
+        let info = crate::probe::parse_probe_output(json, "song.flac").unwrap();
+        assert_eq!(suggest_preset(&info).preset_id, "flac");
+    }
+
+    #[test]
+    fn no_heic_preset_without_a_heif_muxer() {
+        assert!(find_preset("heic").is_none());
+    }
+}
@@ -0,0 +1,263 @@
+use crate::convert::{parse_timestamp, run_ffmpeg, ConvertError};
+use crate::filters::{gif_palette_filter, DitherMode};
+use crate::probe::{probe_file, StreamType};
+use serde::Serialize;
+
+/// Frame rate of hover-preview clips
+const PREVIEW_FPS: u32 = 10;
+/// Width of hover-preview clips (height follows the aspect ratio)
+const PREVIEW_WIDTH: i32 = 320;
+/// Most rows or columns a sprite sheet may have
+const MAX_SPRITE_GRID: u32 = 100;
+
+/// Render a short, small animated preview (GIF or animated WebP, from a .gif or .webp output
+/// extension) of `duration_secs` starting at `at_secs`. The window is shifted back when it
+/// would run past the end of the video.
+pub fn generate_preview_clip(
+    input_path: &str,
+    at_secs: f64,
+    duration_secs: f64,
+    output_path: &str,
+    ffmpeg_path: Option<&std::path::Path>,
+    ffprobe_path: Option<&std::path::Path>,
+) -> Result<String, ConvertError> {
+    if duration_secs <= 0.0 {
+        return Err(ConvertError::ConversionFailed("Preview duration must be positive".to_string()));
+    }
+    let is_webp = match preview_extension(output_path).as_str() {
+        "webp" => true,
+        "gif" => false,
+        _ => {
+            return Err(ConvertError::InvalidOutputPath(format!(
+                "Preview clips are GIF or WebP; use a .gif or .webp output instead of {}",
+                output_path
+            )))
+        }
+    };
+    let info = probe_file(input_path, ffprobe_path)
+        .map_err(|_| ConvertError::InputNotFound(input_path.to_string()))?;
+    if !info.has_video {
+        return Err(ConvertError::ConversionFailed("Input has no video stream".to_string()));
+    }
+    
+    // Clamp the window into [0, duration]
+    let (start, length) = match info.format.duration {
+        Some(total) => {
+            let length = duration_secs.min(total);
+            (at_secs.min(total - length).max(0.0), length)
+        }
+        None => (at_secs.max(0.0), duration_secs),
+    };
+    
+    let mut args: Vec<String> = vec![
+        "-ss".to_string(), format!("{:.3}", start),
+        "-t".to_string(), format!("{:.3}", length),
+        "-i".to_string(), input_path.to_string(),
+        "-an".to_string(),
+    ];
+    if is_webp {
+        args.extend([
+            "-vf".to_string(), format!("fps={},scale={}:-1:flags=lanczos", PREVIEW_FPS, PREVIEW_WIDTH),
+            "-c:v".to_string(), "libwebp".to_string(),
+            "-loop".to_string(), "0".to_string(),
+            "-quality".to_string(), "60".to_string(),
+        ]);
+    } else {
+        args.extend([
+            "-vf".to_string(), gif_palette_filter(PREVIEW_FPS, PREVIEW_WIDTH, DitherMode::default()),
+            "-loop".to_string(), "0".to_string(),
+        ]);
+    }
+    args.push("-y".to_string());
+    args.push(output_path.to_string());
+    
+    run_ffmpeg(ffmpeg_path, &args)?;
+    Ok(output_path.to_string())
+}
+
+fn preview_extension(output_path: &str) -> String {
+    std::path::Path::new(output_path)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+/// Where a thumbnail is taken when no timestamp is given, as a fraction of the duration
+/// (past intros and fades from black)
+const THUMBNAIL_POSITION: f64 = 0.1;
+
+/// Save a single frame as an image (format from the output extension), at `timestamp`
+/// ("HH:MM:SS", "MM:SS" or seconds) or 10% into the video, optionally scaled to `width`
+/// with the aspect ratio kept
+pub fn generate_thumbnail(
+    input_path: &str,
+    timestamp: Option<&str>,
+    output_path: &str,
+    width: Option<u32>,
+    ffmpeg_path: Option<&std::path::Path>,
+    ffprobe_path: Option<&std::path::Path>,
+) -> Result<String, ConvertError> {
+    let info = probe_file(input_path, ffprobe_path)
+        .map_err(|_| ConvertError::InputNotFound(input_path.to_string()))?;
+    if !info.has_video {
+        return Err(ConvertError::ConversionFailed("Input has no video stream".to_string()));
+    }
+    let at_secs = match timestamp {
+        Some(ts) => parse_timestamp(ts)
+            .ok_or_else(|| ConvertError::ConversionFailed(format!("Invalid timestamp '{}'", ts)))?,
+        None => info.format.duration.map_or(0.0, |d| d * THUMBNAIL_POSITION),
+    };
+    // Seeking to the very end yields no frame; stay a little before it
+    let at_secs = match info.format.duration {
+        Some(total) if at_secs >= total => (total - 0.1).max(0.0),
+        _ => at_secs,
+    };
+    
+    let mut args: Vec<String> = vec![
+        "-ss".to_string(), format!("{:.3}", at_secs),
+        "-i".to_string(), input_path.to_string(),
+        "-frames:v".to_string(), "1".to_string(),
+        "-an".to_string(),
+    ];
+    if let Some(width) = width.filter(|w| *w > 0) {
+        // -2 keeps the height even, which JPEG/YUV encoders prefer
+        args.extend(["-vf".to_string(), format!("scale={}:-2:flags=lanczos", width)]);
+    }
+    if output_path.to_lowercase().ends_with(".jpg") || output_path.to_lowercase().ends_with(".jpeg") {
+        args.extend(["-q:v".to_string(), "2".to_string()]);
+    }
+    args.push("-y".to_string());
+    args.push(output_path.to_string());
+    
+    run_ffmpeg(ffmpeg_path, &args)?;
+    Ok(output_path.to_string())
+}
+
+/// One thumbnail of a sprite sheet
+#[derive(Debug, Clone, Serialize)]
+pub struct SpriteCell {
+    pub index: u32,
+    pub row: u32,
+    pub col: u32,
+    /// Approximate time of the frame (the first frame at or after it is used)
+    pub time_secs: f64,
+}
+
+/// A sprite sheet and its index, which is also written next to the image as JSON
+#[derive(Debug, Clone, Serialize)]
+pub struct SpriteSheet {
+    pub image_path: String,
+    pub json_path: String,
+    pub rows: u32,
+    pub cols: u32,
+    pub thumb_width: u32,
+    pub thumb_height: u32,
+    pub interval_secs: f64,
+    pub cells: Vec<SpriteCell>,
+}
+
+/// Tile `rows` x `cols` frames sampled evenly across the video into one image for player
+/// scrubbing previews. Each sample sits in the middle of its slice of the duration.
+pub fn generate_sprite_sheet(
+    input_path: &str,
+    rows: u32,
+    cols: u32,
+    thumb_width: u32,
+    output_path: &str,
+    ffmpeg_path: Option<&std::path::Path>,
+    ffprobe_path: Option<&std::path::Path>,
+) -> Result<SpriteSheet, ConvertError> {
+    if rows == 0 || cols == 0 || thumb_width == 0 {
+        return Err(ConvertError::ConversionFailed(
+            "Rows, columns and thumbnail width must be positive".to_string(),
+        ));
+    }
+    if rows > MAX_SPRITE_GRID || cols > MAX_SPRITE_GRID {
+        return Err(ConvertError::ConversionFailed(format!(
+            "A sprite sheet has at most {} rows and {} columns",
+            MAX_SPRITE_GRID, MAX_SPRITE_GRID
+        )));
+    }
+    let info = probe_file(input_path, ffprobe_path)
+        .map_err(|_| ConvertError::InputNotFound(input_path.to_string()))?;
+    let video = info
+        .streams
+        .iter()
+        .find(|s| s.stream_type == StreamType::Video && !s.has_disposition("attached_pic"))
+        .ok_or_else(|| ConvertError::ConversionFailed("Input has no video stream".to_string()))?;
+    let duration = info
+        .format
+        .duration
+        .filter(|d| *d > 0.0)
+        .ok_or_else(|| ConvertError::ConversionFailed("Input duration is unknown".to_string()))?;
+    
+    let count = rows
+        .checked_mul(cols)
+        .ok_or_else(|| ConvertError::ConversionFailed("Too many sprite sheet cells".to_string()))?;
+    let interval = duration / count as f64;
+    let offset = interval / 2.0;
+    // Same rounding as scale's -2: keep the aspect ratio, even height
+    let thumb_height = match (video.width, video.height) {
+        (Some(w), Some(h)) if w > 0 => ((thumb_width as f64 * h as f64 / w as f64 / 2.0).round() as u32 * 2).max(2),
+        _ => (thumb_width * 9 / 16) & !1,
+    };
+    // Each cell's time is fixed from the start, so the picks can't drift from the cell
+    // timestamps by a frame per cell
+    let filter = format!(
+        "select='gte(t,{offset:.6}+selected_n*{interval:.6})',scale={width}:{height},tile={cols}x{rows}",
+        offset = offset,
+        interval = interval,
+        width = thumb_width,
+        height = thumb_height,
+        cols = cols,
+        rows = rows,
+    );
+    let args: Vec<String> = vec![
+        "-i".to_string(), input_path.to_string(),
+        "-an".to_string(),
+        "-vf".to_string(), filter,
+        "-frames:v".to_string(), "1".to_string(),
+        "-y".to_string(), output_path.to_string(),
+    ];
+    run_ffmpeg(ffmpeg_path, &args)?;
+    
+    let cells = (0..count)
+        .map(|index| SpriteCell {
+            index,
+            row: index / cols,
+            col: index % cols,
+            time_secs: offset + index as f64 * interval,
+        })
+        .collect();
+    let json_path = std::path::Path::new(output_path)
+        .with_extension("json")
+        .to_string_lossy()
+        .to_string();
+    let sheet = SpriteSheet {
+        image_path: output_path.to_string(),
+        json_path: json_path.clone(),
+        rows,
+        cols,
+        thumb_width,
+        thumb_height,
+        interval_secs: interval,
+        cells,
+    };
+    let json = serde_json::to_string_pretty(&sheet).map_err(|e| ConvertError::ConversionFailed(e.to_string()))?;
+    std::fs::write(&json_path, json).map_err(|e| ConvertError::ConversionFailed(e.to_string()))?;
+    Ok(sheet)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preview_clips_are_only_gif_or_webp() {
+        let result = generate_preview_clip("in.mp4", 0.0, 2.0, "preview.mp4", None, None);
+        assert!(matches!(result, Err(ConvertError::InvalidOutputPath(_))));
+        let result = generate_preview_clip("in.mp4", 0.0, 2.0, "preview", None, None);
+        assert!(matches!(result, Err(ConvertError::InvalidOutputPath(_))));
+        assert_eq!(preview_extension("clips/Preview.WEBP"), "webp");
+    }
+}
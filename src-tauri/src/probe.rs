@@ -1,5 +1,11 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use tauri::{AppHandle, Emitter};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -12,6 +18,8 @@ pub enum ProbeError {
     ParseFailed(String),
     #[error("File not found: {0}")]
     FileNotFound(String),
+    #[error("Probe cancelled")]
+    Cancelled,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -24,6 +32,113 @@ pub struct MediaInfo {
     pub has_audio: bool,
     pub has_subtitles: bool,
     pub has_data: bool,
+    /// Attached files (fonts for styled subtitles, cover images, ...)
+    pub attachments: Vec<AttachmentInfo>,
+    /// Scan limits ffprobe ran with (raised when a first probe looked incomplete)
+    #[serde(default)]
+    pub probe_limits: ProbeLimits,
+    /// ReplayGain values from the container tags, if the file has any
+    #[serde(default)]
+    pub replay_gain: Option<ReplayGain>,
+    /// Chapter markers in playback order (empty when the file has none)
+    #[serde(default)]
+    pub chapters: Vec<ChapterInfo>,
+}
+
+/// ReplayGain values: gains in dB relative to the ReplayGain 2.0 reference (-18 LUFS),
+/// peaks as linear sample amplitude (1.0 = full scale)
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub struct ReplayGain {
+    pub track_gain_db: Option<f64>,
+    pub track_peak: Option<f64>,
+    pub album_gain_db: Option<f64>,
+    pub album_peak: Option<f64>,
+}
+
+/// Tag names players read, as written by MP3 (TXXX frames), FLAC/Ogg (Vorbis comments)
+/// and M4A (iTunes freeform atoms); ffprobe reports them under these names in any case
+pub const REPLAYGAIN_TRACK_GAIN: &str = "REPLAYGAIN_TRACK_GAIN";
+pub const REPLAYGAIN_TRACK_PEAK: &str = "REPLAYGAIN_TRACK_PEAK";
+pub const REPLAYGAIN_ALBUM_GAIN: &str = "REPLAYGAIN_ALBUM_GAIN";
+pub const REPLAYGAIN_ALBUM_PEAK: &str = "REPLAYGAIN_ALBUM_PEAK";
+
+/// Read ReplayGain from container tags. Opus files use R128 gains instead (Q7.8 fixed
+/// point relative to -23 LUFS), which are converted to the -18 LUFS reference.
+pub fn replay_gain_from_tags(tags: &HashMap<String, String>) -> Option<ReplayGain> {
+    let tag = |name: &str| {
+        tags.iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    };
+    // "-6.52 dB", "+1.20 dB" or a bare number
+    let gain = |name: &str| {
+        tag(name)?
+            .trim()
+            .trim_end_matches(|c: char| c.is_ascii_alphabetic() || c.is_whitespace())
+            .parse::<f64>()
+            .ok()
+    };
+    let r128 = |name: &str| tag(name)?.trim().parse::<i32>().ok().map(|q| q as f64 / 256.0 + 5.0);
+    let peak = |name: &str| tag(name)?.trim().parse::<f64>().ok();
+    
+    let gain = ReplayGain {
+        track_gain_db: gain(REPLAYGAIN_TRACK_GAIN).or_else(|| r128("R128_TRACK_GAIN")),
+        track_peak: peak(REPLAYGAIN_TRACK_PEAK),
+        album_gain_db: gain(REPLAYGAIN_ALBUM_GAIN).or_else(|| r128("R128_ALBUM_GAIN")),
+        album_peak: peak(REPLAYGAIN_ALBUM_PEAK),
+    };
+    (gain != ReplayGain::default()).then_some(gain)
+}
+
+/// ffprobe scan limits (`-analyzeduration` in microseconds, `-probesize` in bytes).
+/// `None` keeps ffprobe's defaults (5 s / 5 MB).
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub struct ProbeLimits {
+    pub analyze_duration_us: Option<u64>,
+    pub probe_size: Option<u64>,
+}
+
+/// When and how far to re-probe files whose first probe looks incomplete, e.g. TS
+/// captures whose audio track starts after ffprobe's default scan window
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProbeRetry {
+    pub enabled: bool,
+    /// Retry when fewer streams than this were found
+    pub min_streams: usize,
+    /// Retry when the container reported no duration
+    pub retry_on_missing_duration: bool,
+    pub analyze_duration_us: u64,
+    pub probe_size: u64,
+}
+
+impl Default for ProbeRetry {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            min_streams: 1,
+            retry_on_missing_duration: true,
+            analyze_duration_us: 100_000_000,
+            probe_size: 100_000_000,
+        }
+    }
+}
+
+/// A chapter marker, times in seconds
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChapterInfo {
+    pub id: i64,
+    pub start_time: f64,
+    pub end_time: f64,
+    pub title: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttachmentInfo {
+    pub index: u32,
+    pub filename: Option<String>,
+    pub mimetype: Option<String>,
+    pub codec_name: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -33,6 +148,9 @@ pub struct FormatInfo {
     pub duration: Option<f64>,
     pub size: Option<u64>,
     pub bit_rate: Option<u64>,
+    /// Container-level metadata tags (title, artist, comment, ...)
+    #[serde(default)]
+    pub tags: HashMap<String, String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -44,15 +162,84 @@ pub struct StreamInfo {
     // Video specific
     pub width: Option<u32>,
     pub height: Option<u32>,
+    /// Raw `r_frame_rate`, e.g. "30000/1001"
     pub frame_rate: Option<String>,
+    /// `frame_rate` in frames per second; `None` when ffprobe reports 0/0
+    #[serde(default)]
+    pub frame_rate_fps: Option<f64>,
+    /// Frame count: the container's when it records one, else (video only) duration x
+    /// frame rate, which is approximate for variable frame rate content
+    #[serde(default)]
+    pub nb_frames: Option<u64>,
+    /// `nb_frames` came from the container rather than an estimate
+    #[serde(default)]
+    pub nb_frames_exact: bool,
     pub pix_fmt: Option<String>,
+    /// Bit depth of the samples (8, 10, 12), when ffprobe reports it
+    #[serde(default)]
+    pub bits_per_raw_sample: Option<u32>,
+    /// Color matrix, e.g. "bt709", "bt2020nc"
+    #[serde(default)]
+    pub color_space: Option<String>,
+    /// Transfer characteristics, e.g. "bt709", "smpte2084" (PQ), "arib-std-b67" (HLG)
+    #[serde(default)]
+    pub color_transfer: Option<String>,
+    /// Color primaries, e.g. "bt709", "bt2020"
+    #[serde(default)]
+    pub color_primaries: Option<String>,
+    /// HDR10 or HLG: PQ or HLG transfer characteristics
+    #[serde(default)]
+    pub is_hdr: bool,
+    /// Stream bitrate in bits/s, when the container records it (MKV often doesn't)
+    #[serde(default)]
+    pub bit_rate: Option<u64>,
     // Audio specific
     pub sample_rate: Option<String>,
+    /// Sample format, e.g. "s16", "s32p", "fltp"
+    pub sample_fmt: Option<String>,
     pub channels: Option<u32>,
     pub channel_layout: Option<String>,
     // Subtitle specific
     pub language: Option<String>,
     pub title: Option<String>,
+    // Dynamic HDR metadata (video)
+    /// Dolby Vision profile from the stream's DOVI configuration record
+    pub dolby_vision_profile: Option<u32>,
+    /// HDR10+ (SMPTE 2094-40) dynamic metadata found on the first frame. Only looked for
+    /// by `detect_hdr10_plus`, which plain probes skip
+    #[serde(default)]
+    pub has_hdr10_plus: bool,
+    /// Disposition flags that are set, e.g. "default", "forced", "attached_pic" (cover art)
+    #[serde(default)]
+    pub disposition: Vec<String>,
+    /// View/layer ids of multi-layer video (MV-HEVC, scalable HEVC); empty for ordinary
+    /// streams. The decoder outputs only the base layer (the first id) unless told otherwise.
+    #[serde(default)]
+    pub view_ids: Vec<u32>,
+}
+
+impl StreamInfo {
+    pub fn has_disposition(&self, flag: &str) -> bool {
+        self.disposition.iter().any(|d| d == flag)
+    }
+}
+
+impl MediaInfo {
+    /// The video stream a conversion keeps by default: the first one that is neither cover
+    /// art nor an enhancement layer that depends on another stream
+    pub fn base_video_stream(&self) -> Option<&StreamInfo> {
+        self.streams.iter().find(|s| {
+            s.stream_type == StreamType::Video && !s.has_disposition("attached_pic") && !s.has_disposition("dependent")
+        })
+    }
+
+    /// Whether some video stream is an enhancement layer stored as a stream of its own.
+    /// ffmpeg's default selection picks the largest picture, which may be that layer.
+    pub fn has_dependent_video(&self) -> bool {
+        self.streams
+            .iter()
+            .any(|s| s.stream_type == StreamType::Video && s.has_disposition("dependent"))
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -79,14 +266,60 @@ impl From<&str> for StreamType {
     }
 }
 
-// FFprobe JSON output structures
+// FFprobe JSON output structures. Parsing is deliberately lenient: exotic files
+// (layered/scalable video, odd side data) must degrade to partial info, not an error.
 #[derive(Debug, Deserialize)]
 struct FfprobeOutput {
     format: Option<FfprobeFormat>,
+    #[serde(default, deserialize_with = "deserialize_streams")]
     streams: Option<Vec<FfprobeStream>>,
+    #[serde(default)]
+    chapters: Option<Vec<FfprobeChapter>>,
 }
 
-#[derive(Debug, Deserialize)]
+/// Deserialize streams one by one; a stream with unexpected field types keeps its basic identity
+fn deserialize_streams<'de, D>(deserializer: D) -> Result<Option<Vec<FfprobeStream>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let values: Option<Vec<serde_json::Value>> = Option::deserialize(deserializer)?;
+    Ok(values.map(|values| {
+        values
+            .into_iter()
+            .map(|value| {
+                serde_json::from_value(value.clone()).unwrap_or_else(|_| {
+                    let str_field = |key: &str| value.get(key).and_then(|v| v.as_str()).map(String::from);
+                    FfprobeStream {
+                        index: value.get("index").and_then(|v| v.as_u64()).map(|i| i as u32),
+                        codec_type: str_field("codec_type"),
+                        codec_name: str_field("codec_name"),
+                        codec_long_name: str_field("codec_long_name"),
+                        ..Default::default()
+                    }
+                })
+            })
+            .collect()
+    }))
+}
+
+/// Deserialize a tag map, stringifying non-string values instead of failing
+fn deserialize_tags<'de, D>(deserializer: D) -> Result<Option<HashMap<String, String>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let values: Option<HashMap<String, serde_json::Value>> = Option::deserialize(deserializer)?;
+    Ok(values.map(|values| {
+        values
+            .into_iter()
+            .map(|(key, value)| match value {
+                serde_json::Value::String(s) => (key, s),
+                other => (key, other.to_string()),
+            })
+            .collect()
+    }))
+}
+
+#[derive(Debug, Default, Deserialize)]
 struct FfprobeFormat {
     filename: Option<String>,
     format_name: Option<String>,
@@ -94,9 +327,11 @@ struct FfprobeFormat {
     duration: Option<String>,
     size: Option<String>,
     bit_rate: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_tags")]
+    tags: Option<HashMap<String, String>>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize)]
 struct FfprobeStream {
     index: Option<u32>,
     codec_type: Option<String>,
@@ -105,17 +340,105 @@ struct FfprobeStream {
     width: Option<u32>,
     height: Option<u32>,
     r_frame_rate: Option<String>,
+    nb_frames: Option<String>,
+    duration: Option<String>,
     pix_fmt: Option<String>,
+    bits_per_raw_sample: Option<String>,
+    color_space: Option<String>,
+    color_transfer: Option<String>,
+    color_primaries: Option<String>,
+    bit_rate: Option<String>,
     sample_rate: Option<String>,
+    sample_fmt: Option<String>,
     channels: Option<u32>,
     channel_layout: Option<String>,
     tags: Option<FfprobeStreamTags>,
+    side_data_list: Option<Vec<FfprobeSideData>>,
+    /// ffprobe reports every known flag as 0/1
+    disposition: Option<HashMap<String, i64>>,
+    /// Layer ids of multi-layer video, as a list or a separated string depending on the
+    /// ffprobe version
+    view_ids_available: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FfprobeChapter {
+    id: Option<i64>,
+    start_time: Option<String>,
+    end_time: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_tags")]
+    tags: Option<HashMap<String, String>>,
 }
 
 #[derive(Debug, Deserialize)]
+struct FfprobeSideData {
+    side_data_type: Option<String>,
+    dv_profile: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FfprobeFrames {
+    frames: Option<Vec<FfprobeFrame>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FfprobeFrame {
+    side_data_list: Option<Vec<FfprobeSideData>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
 struct FfprobeStreamTags {
     language: Option<String>,
     title: Option<String>,
+    filename: Option<String>,
+    mimetype: Option<String>,
+}
+
+/// Extensions of standalone subtitle files
+const SUBTITLE_EXTENSIONS: &[&str] = &["srt", "ass", "ssa", "vtt", "sub", "lrc", "ttml"];
+
+/// Extensions of files the app can open (audio/video containers, images and subtitles)
+const MEDIA_EXTENSIONS: &[&str] = &[
+    "mp4", "m4v", "mkv", "avi", "mov", "webm", "flv", "wmv", "mpeg", "mpg", "ts", "m2ts", "mts",
+    "3gp", "ogv", "mxf", "mp3", "wav", "flac", "aac", "ogg", "oga", "opus", "m4a", "wma", "aiff",
+    "aif", "alac", "png", "jpg", "jpeg", "gif", "webp", "bmp", "tif", "tiff",
+];
+
+fn has_extension(path: &str, extensions: &[&str]) -> bool {
+    std::path::Path::new(path)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .is_some_and(|ext| extensions.contains(&ext.as_str()))
+}
+
+/// Whether the path is a standalone subtitle file (by extension)
+pub fn is_subtitle_file(path: &str) -> bool {
+    has_extension(path, SUBTITLE_EXTENSIONS)
+}
+
+/// Cheap extension-based check for files worth probing (media or subtitles)
+pub fn is_media_file(path: &str) -> bool {
+    has_extension(path, MEDIA_EXTENSIONS) || is_subtitle_file(path)
+}
+
+/// How long `is_file_stable` watches a file for changes
+const STABILITY_WINDOW: std::time::Duration = std::time::Duration::from_millis(750);
+
+/// Heuristic check that a file is no longer being written (by a recorder, a browser
+/// download, a copy): its size and modification time must not change over a short window.
+/// A writer that pauses longer than the window still looks finished, so this is a hint for
+/// the UI before probing, not a guarantee.
+pub async fn is_file_stable(path: &str) -> Result<bool, ProbeError> {
+    let snapshot = || {
+        std::fs::metadata(path)
+            .map(|m| (m.len(), m.modified().ok()))
+            .map_err(|_| ProbeError::FileNotFound(path.to_string()))
+    };
+    let before = snapshot()?;
+    tokio::time::sleep(STABILITY_WINDOW).await;
+    let after = snapshot()?;
+    // An empty file is almost always one that has just been created
+    Ok(before == after && after.0 > 0)
 }
 
 /// Check if ffprobe is available
@@ -142,6 +465,46 @@ pub fn check_ffprobe(sidecar_path: Option<&std::path::Path>) -> Result<String, P
 
 /// Probe a media file and return its info
 pub fn probe_file(path: &str, sidecar_path: Option<&std::path::Path>) -> Result<MediaInfo, ProbeError> {
+    probe_file_with_limits(path, sidecar_path, ProbeLimits::default())
+}
+
+/// Whether a probe result looks like ffprobe stopped scanning too early
+fn looks_incomplete(info: &MediaInfo, retry: &ProbeRetry) -> bool {
+    info.streams.len() < retry.min_streams
+        || (retry.retry_on_missing_duration && info.format.duration.is_none())
+        // Streams seen in the scan window but whose parameters were not found yet
+        || info.streams.iter().any(|s| {
+            matches!(s.stream_type, StreamType::Video | StreamType::Audio) && s.codec_name.is_none()
+        })
+}
+
+/// Probe a file, probing again with `retry`'s larger scan limits if the result looks incomplete.
+/// The retried result is used only if it found at least as many streams.
+pub fn probe_file_with_retry(
+    path: &str,
+    sidecar_path: Option<&std::path::Path>,
+    retry: &ProbeRetry,
+) -> Result<MediaInfo, ProbeError> {
+    let info = probe_file(path, sidecar_path)?;
+    if !retry.enabled || !looks_incomplete(&info, retry) {
+        return Ok(info);
+    }
+    let limits = ProbeLimits {
+        analyze_duration_us: Some(retry.analyze_duration_us),
+        probe_size: Some(retry.probe_size),
+    };
+    match probe_file_with_limits(path, sidecar_path, limits) {
+        Ok(retried) if retried.streams.len() >= info.streams.len() => Ok(retried),
+        _ => Ok(info),
+    }
+}
+
+/// Probe a media file with explicit ffprobe scan limits
+pub fn probe_file_with_limits(
+    path: &str,
+    sidecar_path: Option<&std::path::Path>,
+    limits: ProbeLimits,
+) -> Result<MediaInfo, ProbeError> {
     // Check if file exists
     if !std::path::Path::new(path).exists() {
         return Err(ProbeError::FileNotFound(path.to_string()));
@@ -153,51 +516,136 @@ pub fn probe_file(path: &str, sidecar_path: Option<&std::path::Path>) -> Result<
         "ffprobe".to_string()
     };
     
-    // Run ffprobe
-    let output = Command::new(&ffprobe_cmd)
-        .args([
-            "-v", "quiet",
-            "-print_format", "json",
-            "-show_format",
-            "-show_streams",
-            path,
-        ])
+    // Run ffprobe (scan limits are input options, so they go before the path)
+    let mut cmd = Command::new(&ffprobe_cmd);
+    cmd.args(["-v", "quiet", "-print_format", "json", "-show_format", "-show_streams", "-show_chapters"]);
+    if let Some(us) = limits.analyze_duration_us {
+        cmd.args(["-analyzeduration", &us.to_string()]);
+    }
+    if let Some(bytes) = limits.probe_size {
+        cmd.args(["-probesize", &bytes.to_string()]);
+    }
+    let output = cmd
+        .arg(path)
         .output()
         .map_err(|e| ProbeError::ExecutionFailed(e.to_string()))?;
     
-    if !output.status.success() {
+    // ffprobe can exit with an error after printing usable info for partly unsupported files
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if !output.status.success() && stdout.trim().is_empty() {
         let stderr = String::from_utf8_lossy(&output.stderr);
         return Err(ProbeError::ExecutionFailed(stderr.to_string()));
     }
     
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let probe_output: FfprobeOutput = serde_json::from_str(&stdout)
+    let mut info = parse_probe_output(&stdout, path)?;
+    info.probe_limits = limits;
+    
+    Ok(info)
+}
+
+/// Set `has_hdr10_plus` on the main video stream. HDR10+ lives in per-frame side data, so
+/// this decodes a frame, and only for 10-bit HEVC/AV1/VP9, the only streams that can carry it.
+pub fn detect_hdr10_plus(info: &mut MediaInfo, path: &str, sidecar_path: Option<&std::path::Path>) {
+    let ffprobe_cmd = if let Some(p) = sidecar_path {
+        p.to_string_lossy().to_string()
+    } else {
+        "ffprobe".to_string()
+    };
+    if let Some(video) = info.streams.iter_mut().find(|s| s.stream_type == StreamType::Video) {
+        let codec = video.codec_name.as_deref().unwrap_or("");
+        let ten_bit = video.pix_fmt.as_deref().is_some_and(|p| p.contains("10"));
+        if ten_bit && matches!(codec, "hevc" | "av1" | "vp9") {
+            video.has_hdr10_plus = first_frame_has_hdr10_plus(path, &ffprobe_cmd);
+        }
+    }
+}
+
+/// Build `MediaInfo` from ffprobe's `-show_format -show_streams -show_chapters` JSON
+pub(crate) fn parse_probe_output(json: &str, path: &str) -> Result<MediaInfo, ProbeError> {
+    let probe_output: FfprobeOutput = serde_json::from_str(json)
         .map_err(|e| ProbeError::ParseFailed(e.to_string()))?;
     
-    // Parse format info
-    let format = probe_output.format.ok_or_else(|| {
-        ProbeError::ParseFailed("Missing format info".to_string())
-    })?;
+    // Parse format info (some raw/exotic inputs report streams without a usable format section)
+    let format = probe_output.format.unwrap_or_default();
     
+    let replay_gain = format.tags.as_ref().and_then(replay_gain_from_tags);
     let format_info = FormatInfo {
         format_name: format.format_name.unwrap_or_default(),
         format_long_name: format.format_long_name.unwrap_or_default(),
         duration: format.duration.and_then(|d| d.parse().ok()),
         size: format.size.and_then(|s| s.parse().ok()),
         bit_rate: format.bit_rate.and_then(|b| b.parse().ok()),
+        tags: format.tags.unwrap_or_default(),
     };
     
-    // Parse streams
-    let streams: Vec<StreamInfo> = probe_output
-        .streams
+    let raw_streams = probe_output.streams.unwrap_or_default();
+    
+    // Chapters without usable times are dropped; titles are the `title` tag, any case
+    let mut chapters: Vec<ChapterInfo> = probe_output
+        .chapters
         .unwrap_or_default()
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, c)| {
+            Some(ChapterInfo {
+                id: c.id.unwrap_or(i as i64),
+                start_time: c.start_time?.parse().ok()?,
+                end_time: c.end_time?.parse().ok()?,
+                title: c.tags.and_then(|tags| {
+                    tags.into_iter()
+                        .find(|(key, _)| key.eq_ignore_ascii_case("title"))
+                        .map(|(_, title)| title)
+                }),
+            })
+        })
+        .collect();
+    chapters.sort_by(|a, b| a.start_time.total_cmp(&b.start_time));
+    
+    // Attachments carry their file name and MIME type in tags
+    let attachments: Vec<AttachmentInfo> = raw_streams
+        .iter()
+        .filter(|s| s.codec_type.as_deref() == Some("attachment"))
+        .map(|s| AttachmentInfo {
+            index: s.index.unwrap_or(0),
+            filename: s.tags.as_ref().and_then(|t| t.filename.clone()),
+            mimetype: s.tags.as_ref().and_then(|t| t.mimetype.clone()),
+            codec_name: s.codec_name.clone(),
+        })
+        .collect();
+    
+    // Parse streams
+    let streams: Vec<StreamInfo> = raw_streams
         .into_iter()
         .map(|s| {
             let stream_type = StreamType::from(s.codec_type.as_deref().unwrap_or("unknown"));
-            let tags = s.tags.unwrap_or(FfprobeStreamTags {
-                language: None,
-                title: None,
-            });
+            let tags = s.tags.unwrap_or_default();
+            let dolby_vision_profile = s
+                .side_data_list
+                .unwrap_or_default()
+                .into_iter()
+                .find(|d| d.side_data_type.as_deref() == Some("DOVI configuration record"))
+                .map(|d| d.dv_profile.unwrap_or(0));
+            let mut disposition: Vec<String> = s
+                .disposition
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|(_, set)| *set != 0)
+                .map(|(flag, _)| flag)
+                .collect();
+            disposition.sort();
+            let frame_rate_fps = s.r_frame_rate.as_deref().and_then(crate::convert::parse_frame_rate);
+            let recorded_frames = s.nb_frames.and_then(|n| n.parse::<u64>().ok()).filter(|n| *n > 0);
+            let estimated_frames = || {
+                if stream_type != StreamType::Video || disposition.iter().any(|d| d == "attached_pic") {
+                    return None;
+                }
+                let duration = s.duration.as_deref().and_then(|d| d.parse::<f64>().ok()).or(format_info.duration)?;
+                Some((duration * frame_rate_fps?).round() as u64).filter(|n| *n > 0)
+            };
+            let (nb_frames, nb_frames_exact) = match recorded_frames {
+                Some(frames) => (Some(frames), true),
+                None => (estimated_frames(), false),
+            };
             
             StreamInfo {
                 index: s.index.unwrap_or(0),
@@ -206,13 +654,28 @@ pub fn probe_file(path: &str, sidecar_path: Option<&std::path::Path>) -> Result<
                 codec_long_name: s.codec_long_name,
                 width: s.width,
                 height: s.height,
+                frame_rate_fps,
                 frame_rate: s.r_frame_rate,
+                nb_frames,
+                nb_frames_exact,
                 pix_fmt: s.pix_fmt,
+                bits_per_raw_sample: s.bits_per_raw_sample.and_then(|b| b.parse().ok()),
+                is_hdr: s.color_transfer.as_deref().is_some_and(is_hdr_transfer),
+                color_space: s.color_space,
+                color_transfer: s.color_transfer,
+                color_primaries: s.color_primaries,
+                bit_rate: s.bit_rate.and_then(|b| b.parse().ok()),
                 sample_rate: s.sample_rate,
+                sample_fmt: s.sample_fmt,
                 channels: s.channels,
-                channel_layout: s.channel_layout,
+                // Some containers (raw PCM, some WAVs) leave the layout out; assume the usual one
+                channel_layout: s.channel_layout.or_else(|| default_channel_layout(s.channels?)),
                 language: tags.language,
                 title: tags.title,
+                dolby_vision_profile,
+                has_hdr10_plus: false,
+                disposition,
+                view_ids: s.view_ids_available.as_ref().map(parse_view_ids).unwrap_or_default(),
             }
         })
         .collect();
@@ -237,5 +700,282 @@ pub fn probe_file(path: &str, sidecar_path: Option<&std::path::Path>) -> Result<
         has_audio,
         has_subtitles,
         has_data,
+        attachments,
+        probe_limits: ProbeLimits::default(),
+        replay_gain,
+        chapters,
     })
 }
+
+/// Layer ids from `view_ids_available`: `[0, 1]` or a string like "0,1" / "0 1"
+fn parse_view_ids(value: &serde_json::Value) -> Vec<u32> {
+    match value {
+        serde_json::Value::Array(ids) => ids.iter().filter_map(|id| id.as_u64()).map(|id| id as u32).collect(),
+        serde_json::Value::String(ids) => ids
+            .split(|c: char| !c.is_ascii_digit())
+            .filter_map(|id| id.parse().ok())
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// PQ (HDR10, HDR10+, most Dolby Vision) and HLG transfer functions
+fn is_hdr_transfer(transfer: &str) -> bool {
+    matches!(transfer, "smpte2084" | "arib-std-b67")
+}
+
+/// The layout ffmpeg assumes for a bare channel count
+fn default_channel_layout(channels: u32) -> Option<String> {
+    let layout = match channels {
+        1 => "mono",
+        2 => "stereo",
+        6 => "5.1",
+        8 => "7.1",
+        _ => return None,
+    };
+    Some(layout.to_string())
+}
+
+/// Whether the first video frame carries HDR10+ dynamic metadata
+fn first_frame_has_hdr10_plus(path: &str, ffprobe_cmd: &str) -> bool {
+    let Ok(output) = Command::new(ffprobe_cmd)
+        .args([
+            "-v", "quiet",
+            "-print_format", "json",
+            "-select_streams", "v:0",
+            "-read_intervals", "%+#1",
+            "-show_entries", "frame=side_data_list",
+            path,
+        ])
+        .output()
+    else {
+        return false;
+    };
+    serde_json::from_slice::<FfprobeFrames>(&output.stdout)
+        .ok()
+        .and_then(|f| f.frames)
+        .unwrap_or_default()
+        .iter()
+        .flat_map(|f| f.side_data_list.iter().flatten())
+        .filter_map(|d| d.side_data_type.as_deref())
+        .any(|t| t.contains("HDR10+") || t.contains("SMPTE2094-40"))
+}
+
+struct CachedProbe {
+    size: u64,
+    modified: Option<SystemTime>,
+    info: MediaInfo,
+}
+
+/// Cache of probe results, invalidated when a file's size or mtime changes
+#[derive(Default)]
+pub struct ProbeCache {
+    entries: Mutex<HashMap<String, CachedProbe>>,
+}
+
+impl ProbeCache {
+    /// Return the cached info for `path` if the file is unchanged, otherwise probe it
+    pub fn probe(&self, path: &str, sidecar_path: Option<&Path>, retry: &ProbeRetry) -> Result<MediaInfo, ProbeError> {
+        let meta = std::fs::metadata(path).map_err(|_| ProbeError::FileNotFound(path.to_string()))?;
+        let (size, modified) = (meta.len(), meta.modified().ok());
+        
+        if let Some(cached) = self.entries.lock().unwrap().get(path) {
+            if cached.size == size && cached.modified == modified {
+                return Ok(cached.info.clone());
+            }
+        }
+        
+        let info = probe_file_with_retry(path, sidecar_path, retry)?;
+        self.entries.lock().unwrap().insert(
+            path.to_string(),
+            CachedProbe {
+                size,
+                modified,
+                info: info.clone(),
+            },
+        );
+        Ok(info)
+    }
+}
+
+/// Deepest directory level visited by a recursive folder walk
+const MAX_WALK_DEPTH: usize = 8;
+
+/// Collect media files in `dir` (sorted), descending into subdirectories when `recursive`
+pub fn collect_media_files(dir: &Path, recursive: bool) -> Vec<String> {
+    fn walk(dir: &Path, depth: usize, recursive: bool, files: &mut Vec<String>) {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                if recursive && depth < MAX_WALK_DEPTH {
+                    walk(&path, depth + 1, recursive, files);
+                }
+            } else {
+                let path = path.to_string_lossy().to_string();
+                if is_media_file(&path) {
+                    files.push(path);
+                }
+            }
+        }
+    }
+    
+    let mut files = Vec::new();
+    walk(dir, 0, recursive, &mut files);
+    files.sort();
+    files
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProbeFolderProgress {
+    /// The scan's id for `cancel_operation`
+    pub operation_id: String,
+    pub completed: usize,
+    pub total: usize,
+    pub failed: usize,
+    pub current_path: String,
+}
+
+/// How `probe_folder` walks and probes a folder
+pub struct FolderScan {
+    pub recursive: bool,
+    /// Maximum number of ffprobe processes at once
+    pub concurrency: usize,
+    pub retry: ProbeRetry,
+    /// Id of this scan, carried by its progress events
+    pub operation_id: String,
+}
+
+/// Probe every media file in a folder with at most `scan.concurrency` ffprobe processes at once.
+/// Emits `probe-folder-progress` after each file; files that fail to probe are skipped.
+pub async fn probe_folder(
+    app_handle: AppHandle,
+    dir: PathBuf,
+    scan: FolderScan,
+    sidecar_path: Option<PathBuf>,
+    cache: Arc<ProbeCache>,
+    cancel_flag: Arc<AtomicBool>,
+) -> Result<Vec<MediaInfo>, ProbeError> {
+    let FolderScan { recursive, concurrency, retry, operation_id } = scan;
+    if !dir.is_dir() {
+        return Err(ProbeError::FileNotFound(dir.display().to_string()));
+    }
+    
+    let files = tokio::task::spawn_blocking(move || collect_media_files(&dir, recursive))
+        .await
+        .map_err(|e| ProbeError::ExecutionFailed(e.to_string()))?;
+    let total = files.len();
+    
+    let concurrency = concurrency.max(1);
+    let mut files = files.into_iter();
+    let mut tasks = tokio::task::JoinSet::new();
+    let mut results = Vec::with_capacity(total);
+    let mut completed = 0;
+    let mut failed = 0;
+    loop {
+        if cancel_flag.load(Ordering::Relaxed) {
+            tasks.abort_all();
+            return Err(ProbeError::Cancelled);
+        }
+        // Keep `concurrency` probes running, taking each result as it lands so progress
+        // is reported file by file
+        if tasks.len() < concurrency {
+            if let Some(path) = files.next() {
+                let cache = cache.clone();
+                let sidecar_path = sidecar_path.clone();
+                let retry = retry.clone();
+                tasks.spawn_blocking(move || {
+                    let result = cache.probe(&path, sidecar_path.as_deref(), &retry);
+                    (path, result)
+                });
+                continue;
+            }
+        }
+        let Some(joined) = tasks.join_next().await else {
+            break;
+        };
+        let Ok((path, result)) = joined else {
+            continue;
+        };
+        completed += 1;
+        match result {
+            Ok(info) => results.push(info),
+            Err(_) => failed += 1,
+        }
+        let _ = app_handle.emit(
+            "probe-folder-progress",
+            &ProbeFolderProgress {
+                operation_id: operation_id.clone(),
+                completed,
+                total,
+                failed,
+                current_path: path,
+            },
+        );
+    }
+    
+    results.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LAYERED_FIXTURE: &str = include_str!("../tests/fixtures/ffprobe_layered.json");
+
+    #[test]
+    fn parses_layered_fixture_format() {
+        let info = parse_probe_output(LAYERED_FIXTURE, "/media/spatial_capture.mov").unwrap();
+        assert_eq!(info.filename, "spatial_capture.mov");
+        assert_eq!(info.format.format_long_name, "QuickTime / MOV");
+        assert_eq!(info.format.duration, Some(12.012));
+        assert_eq!(info.format.size, Some(27_693_125));
+        // Non-string tag values are kept as text instead of failing the parse
+        assert_eq!(info.format.tags.get("com.apple.quicktime.spatial.format-version").map(String::as_str), Some("1"));
+    }
+
+    #[test]
+    fn parses_layered_fixture_streams() {
+        let info = parse_probe_output(LAYERED_FIXTURE, "spatial_capture.mov").unwrap();
+        assert_eq!(info.streams.len(), 3);
+        assert!(info.has_video && info.has_audio);
+
+        let base = &info.streams[0];
+        assert_eq!(base.view_ids, vec![0, 1]);
+        assert_eq!((base.width, base.height), (Some(1920), Some(1080)));
+        assert_eq!(base.nb_frames, Some(360));
+        assert!(base.nb_frames_exact);
+        assert!(info.streams[1].has_disposition("dependent"));
+
+        // "channels" as a string doesn't match the schema: the stream keeps its identity
+        let audio = &info.streams[2];
+        assert_eq!(audio.stream_type, StreamType::Audio);
+        assert_eq!(audio.codec_name.as_deref(), Some("aac"));
+        assert_eq!(audio.channels, None);
+    }
+
+    #[test]
+    fn base_layer_skips_dependent_streams() {
+        let info = parse_probe_output(LAYERED_FIXTURE, "spatial_capture.mov").unwrap();
+        assert!(info.has_dependent_video());
+        assert_eq!(info.base_video_stream().map(|s| s.index), Some(0));
+    }
+
+    #[test]
+    fn missing_format_section_is_not_an_error() {
+        let info = parse_probe_output(r#"{"streams": [{"index": 0, "codec_type": "video"}]}"#, "raw.hevc").unwrap();
+        assert_eq!(info.format.format_name, "");
+        assert_eq!(info.format.duration, None);
+        assert_eq!(info.streams.len(), 1);
+    }
+
+    #[test]
+    fn view_ids_accept_lists_and_strings() {
+        assert_eq!(parse_view_ids(&serde_json::json!([0, 1])), vec![0, 1]);
+        assert_eq!(parse_view_ids(&serde_json::json!("0 1")), vec![0, 1]);
+        assert!(parse_view_ids(&serde_json::json!(null)).is_empty());
+    }
+}
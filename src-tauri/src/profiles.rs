@@ -0,0 +1,98 @@
+use crate::convert::{AdvancedOptions, PlannedOutput, StreamSelection};
+use crate::settings::{load_json, save_json};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+const PROFILES_FILE: &str = "profiles.json";
+
+/// A saved job template: preset plus everything else a conversion needs except the input
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobProfile {
+    pub name: String,
+    pub preset_id: Option<String>,
+    pub advanced: Option<AdvancedOptions>,
+    pub stream_selection: Option<StreamSelection>,
+    /// Folder for outputs; next to the input when unset
+    pub output_dir: Option<String>,
+    /// Output file name template (see `convert::output_file_stem`); profiles saved before
+    /// it existed load with the default naming
+    #[serde(default)]
+    pub name_template: Option<String>,
+}
+
+/// A loaded profile with the output paths it gives the inputs the UI has queued
+#[derive(Debug, Clone, Serialize)]
+pub struct AppliedProfile {
+    pub profile: JobProfile,
+    pub outputs: Vec<PlannedOutput>,
+}
+
+/// Named job profiles, persisted as JSON in the app config dir
+pub struct ProfileStore {
+    profiles: Mutex<Vec<JobProfile>>,
+    config_dir: Option<PathBuf>,
+}
+
+impl ProfileStore {
+    pub fn new(config_dir: Option<PathBuf>) -> Self {
+        Self {
+            profiles: Mutex::new(load_json(config_dir.as_deref(), PROFILES_FILE).unwrap_or_default()),
+            config_dir,
+        }
+    }
+
+    pub fn list(&self) -> Vec<JobProfile> {
+        self.profiles.lock().unwrap().clone()
+    }
+
+    pub fn get(&self, name: &str) -> Option<JobProfile> {
+        self.profiles.lock().unwrap().iter().find(|p| p.name == name).cloned()
+    }
+
+    /// Add a profile, replacing any existing profile with the same name
+    pub fn save(&self, profile: JobProfile) -> Result<(), String> {
+        if profile.name.trim().is_empty() {
+            return Err("Profile name cannot be empty".to_string());
+        }
+        let mut profiles = self.profiles.lock().unwrap();
+        let mut updated = profiles.clone();
+        match updated.iter_mut().find(|p| p.name == profile.name) {
+            Some(existing) => *existing = profile,
+            None => updated.push(profile),
+        }
+        save_json(self.config_dir.as_deref(), PROFILES_FILE, &updated)?;
+        *profiles = updated;
+        Ok(())
+    }
+
+    pub fn delete(&self, name: &str) -> Result<(), String> {
+        let mut profiles = self.profiles.lock().unwrap();
+        let updated: Vec<JobProfile> = profiles.iter().filter(|p| p.name != name).cloned().collect();
+        if updated.len() == profiles.len() {
+            return Err(format!("Profile not found: {}", name));
+        }
+        save_json(self.config_dir.as_deref(), PROFILES_FILE, &updated)?;
+        *profiles = updated;
+        Ok(())
+    }
+}
+
+impl Default for ProfileStore {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profiles_without_a_name_template_still_load() {
+        let json = r#"[{"name": "web", "preset_id": "mp4_h264", "advanced": null,
+            "stream_selection": null, "output_dir": null}]"#;
+        let profiles: Vec<JobProfile> = serde_json::from_str(json).unwrap();
+        assert_eq!(profiles[0].name_template, None);
+    }
+}
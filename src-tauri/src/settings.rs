@@ -0,0 +1,99 @@
+use crate::probe::ProbeRetry;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+const SETTINGS_FILE: &str = "settings.json";
+
+/// Read a JSON file from the config dir, `None` if it is missing or invalid
+pub fn load_json<T: DeserializeOwned>(config_dir: Option<&Path>, file: &str) -> Option<T> {
+    let contents = std::fs::read_to_string(config_dir?.join(file)).ok()?;
+    serde_json::from_str(&contents).ok()
+}
+
+/// Write a value as pretty JSON into the config dir (no-op without a config dir)
+pub fn save_json<T: Serialize>(config_dir: Option<&Path>, file: &str, value: &T) -> Result<(), String> {
+    let Some(dir) = config_dir else {
+        return Ok(());
+    };
+    std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create config dir: {}", e))?;
+    let json = serde_json::to_string_pretty(value).map_err(|e| e.to_string())?;
+    std::fs::write(dir.join(file), json).map_err(|e| format!("Failed to save {}: {}", file, e))
+}
+
+/// Application-wide settings, persisted as JSON in the app config dir
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppSettings {
+    /// Never use hardware acceleration, even when a hardware encoder/decoder is
+    /// available or was requested. Software encoders (libx264, libx265, ...) give
+    /// better quality at the same bitrate; hardware encoders are several times
+    /// faster but need more bits for the same quality.
+    pub prefer_software_encoding: bool,
+    /// Seconds between progress snapshots written to the conversion log (0 = only every 10%)
+    pub progress_log_interval_secs: u64,
+    /// Directory for intermediate files (two-pass logs, concat lists); system temp dir when unset
+    pub temp_dir: Option<String>,
+    /// Re-probe with larger scan limits when a probe misses streams or the duration
+    pub probe_retry: ProbeRetry,
+    /// Round odd source dimensions down to even for encoders that need it (H.264/H.265);
+    /// off shows the encoder's own error instead
+    pub fix_odd_dimensions: bool,
+    /// Times a conversion is started over after ffmpeg fails to spawn or hits a transient
+    /// error (a briefly locked file, a dropped connection); 0 disables retrying
+    pub max_retries: u32,
+    /// Also write conversion logs to a file
+    pub file_logging: bool,
+    /// Folder for the log file; the system log folder when unset
+    pub log_dir: Option<String>,
+}
+
+impl Default for AppSettings {
+    fn default() -> Self {
+        Self {
+            prefer_software_encoding: false,
+            progress_log_interval_secs: 30,
+            temp_dir: None,
+            probe_retry: ProbeRetry::default(),
+            fix_odd_dimensions: true,
+            max_retries: 0,
+            file_logging: true,
+            log_dir: None,
+        }
+    }
+}
+
+/// Settings storage (in-memory, mirrored to disk when a config dir is known)
+pub struct SettingsStore {
+    settings: Mutex<AppSettings>,
+    config_dir: Option<PathBuf>,
+}
+
+impl SettingsStore {
+    /// Load settings from `config_dir`, falling back to defaults if the file is missing or invalid
+    pub fn new(config_dir: Option<PathBuf>) -> Self {
+        let settings = load_json(config_dir.as_deref(), SETTINGS_FILE).unwrap_or_default();
+        Self {
+            settings: Mutex::new(settings),
+            config_dir,
+        }
+    }
+
+    pub fn get(&self) -> AppSettings {
+        self.settings.lock().unwrap().clone()
+    }
+
+    /// Replace the current settings and write them to disk
+    pub fn update(&self, settings: AppSettings) -> Result<(), String> {
+        save_json(self.config_dir.as_deref(), SETTINGS_FILE, &settings)?;
+        *self.settings.lock().unwrap() = settings;
+        Ok(())
+    }
+}
+
+impl Default for SettingsStore {
+    fn default() -> Self {
+        Self::new(None) // In-memory only
+    }
+}
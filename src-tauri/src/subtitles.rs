@@ -0,0 +1,98 @@
+use crate::convert::{run_ffmpeg, ConvertError};
+use crate::probe::{is_subtitle_file, MediaInfo, StreamType};
+
+/// Map a subtitle format name or extension to the ffmpeg subtitle encoder
+fn subtitle_codec(target_format: &str) -> Option<&'static str> {
+    match target_format.to_lowercase().as_str() {
+        "srt" | "subrip" => Some("subrip"),
+        "ass" => Some("ass"),
+        "ssa" => Some("ssa"),
+        "vtt" | "webvtt" => Some("webvtt"),
+        "ttml" => Some("ttml"),
+        _ => None,
+    }
+}
+
+/// Convert a standalone subtitle file (.srt/.ass/.vtt/...) to another subtitle format.
+/// `charset` sets the input text encoding (e.g. "CP1252") for files that are not UTF-8.
+pub fn convert_subtitle_file(
+    input_path: &str,
+    output_path: &str,
+    target_format: &str,
+    charset: Option<&str>,
+    ffmpeg_path: Option<&std::path::Path>,
+) -> Result<String, ConvertError> {
+    if !std::path::Path::new(input_path).exists() {
+        return Err(ConvertError::InputNotFound(input_path.to_string()));
+    }
+    if !is_subtitle_file(input_path) {
+        return Err(ConvertError::ConversionFailed(format!(
+            "Not a subtitle file: {}",
+            input_path
+        )));
+    }
+    let codec = subtitle_codec(target_format).ok_or_else(|| {
+        ConvertError::ConversionFailed(format!("Unsupported subtitle format: {}", target_format))
+    })?;
+    
+    let mut args: Vec<String> = Vec::new();
+    if let Some(charset) = charset {
+        // Input option: must come before -i
+        args.push("-sub_charenc".to_string());
+        args.push(charset.to_string());
+    }
+    args.push("-i".to_string());
+    args.push(input_path.to_string());
+    args.push("-map".to_string());
+    args.push("0:s:0".to_string());
+    args.push("-c:s".to_string());
+    args.push(codec.to_string());
+    args.push("-y".to_string());
+    args.push(output_path.to_string());
+    
+    run_ffmpeg(ffmpeg_path, &args)?;
+    Ok(output_path.to_string())
+}
+
+/// File extension and codec for saving an embedded subtitle stream of `codec` on its own.
+/// Text formats without a standalone file type (MP4's mov_text) become SRT.
+fn extraction_format(codec: &str) -> Option<(&'static str, &'static str)> {
+    match codec {
+        "subrip" | "srt" => Some(("srt", "copy")),
+        "ass" | "ssa" => Some(("ass", "copy")),
+        "webvtt" => Some(("vtt", "copy")),
+        "mov_text" | "text" | "eia_608" => Some(("srt", "subrip")),
+        "hdmv_pgs_subtitle" => Some(("sup", "copy")),
+        _ => None,
+    }
+}
+
+/// Arguments to save subtitle stream `stream_index` (index as reported by the probe) of
+/// `info` to its own file, and the output path with the extension its format needs
+pub fn subtitle_extraction_args(
+    info: &MediaInfo,
+    stream_index: u32,
+    output_path: &str,
+) -> Result<(Vec<String>, String), ConvertError> {
+    let stream = info
+        .streams
+        .iter()
+        .find(|s| s.index == stream_index && s.stream_type == StreamType::Subtitle)
+        .ok_or_else(|| ConvertError::ConversionFailed(format!("Stream #{} is not a subtitle stream", stream_index)))?;
+    let codec = stream.codec_name.as_deref().unwrap_or("unknown");
+    let (extension, encoder) = extraction_format(codec).ok_or_else(|| {
+        ConvertError::ConversionFailed(format!("Subtitle format '{}' cannot be extracted to a file", codec))
+    })?;
+    let output_path = std::path::Path::new(output_path)
+        .with_extension(extension)
+        .to_string_lossy()
+        .to_string();
+    
+    let args = vec![
+        "-i".to_string(), info.path.clone(),
+        "-map".to_string(), format!("0:{}", stream_index),
+        "-c:s".to_string(), encoder.to_string(),
+        "-y".to_string(), output_path.clone(),
+    ];
+    Ok((args, output_path))
+}
@@ -0,0 +1,96 @@
+use crate::settings::AppSettings;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// Every intermediate file the app writes (two-pass logs, concat lists, stabilization
+/// transforms, partial outputs) is named `convertify-*` so it can be found again after a crash
+pub const TEMP_PREFIX: &str = "convertify-";
+
+/// Extensions of the intermediate files, a second guard against touching unrelated files
+const TEMP_EXTENSIONS: &[&str] = &["log", "mbtree", "cutree", "temp", "txt", "trf", "part", "tmp"];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CleanupResult {
+    pub files_removed: usize,
+    pub bytes_freed: u64,
+}
+
+/// Directory for intermediate files: the configured one, else the system temp dir
+pub fn temp_dir(settings: &AppSettings) -> PathBuf {
+    settings
+        .temp_dir
+        .as_ref()
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir)
+}
+
+/// Files of one job sharing a unique `convertify-<kind>-...` name prefix (e.g. two-pass
+/// logs, which ffmpeg names itself). Everything with the prefix is deleted on drop, so
+/// they go away however the job ends.
+pub struct TempFileSet {
+    dir: PathBuf,
+    prefix: String,
+}
+
+impl TempFileSet {
+    pub fn new(settings: &AppSettings, kind: &str) -> Self {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+        Self {
+            dir: temp_dir(settings),
+            prefix: format!("{}{}-{}-{}", TEMP_PREFIX, kind, std::process::id(), nanos),
+        }
+    }
+
+    /// Path prefix to hand to the tool writing the files
+    pub fn prefix_path(&self) -> PathBuf {
+        self.dir.join(&self.prefix)
+    }
+}
+
+impl Drop for TempFileSet {
+    fn drop(&mut self) {
+        let Ok(entries) = std::fs::read_dir(&self.dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            if entry.file_name().to_string_lossy().starts_with(&self.prefix) {
+                std::fs::remove_file(entry.path()).ok();
+            }
+        }
+    }
+}
+
+fn is_app_temp_file(path: &Path) -> bool {
+    let name = path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+    let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+    name.starts_with(TEMP_PREFIX) && TEMP_EXTENSIONS.contains(&extension.as_str())
+}
+
+/// Delete leftover intermediate files directly inside `dirs` (not recursive).
+/// Files that cannot be removed are skipped.
+pub fn cleanup_temp_files(dirs: &[PathBuf]) -> CleanupResult {
+    let mut result = CleanupResult { files_removed: 0, bytes_freed: 0 };
+    for dir in dirs {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if !metadata.is_file() || !is_app_temp_file(&path) {
+                continue;
+            }
+            if std::fs::remove_file(&path).is_ok() {
+                result.files_removed += 1;
+                result.bytes_freed += metadata.len();
+            }
+        }
+    }
+    result
+}
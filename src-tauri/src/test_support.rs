@@ -0,0 +1,11 @@
+//! Helpers shared by the unit tests
+
+use std::path::PathBuf;
+
+/// A fresh, empty directory under the system temp directory, named after the test
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("convertify-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
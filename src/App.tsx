@@ -23,6 +23,15 @@ interface StreamInfo {
   width: number | null;
   height: number | null;
   frame_rate: string | null;
+  frame_rate_fps: number | null;
+  nb_frames: number | null;
+  nb_frames_exact: boolean;
+  bits_per_raw_sample: number | null;
+  color_space: string | null;
+  color_transfer: string | null;
+  color_primaries: string | null;
+  is_hdr: boolean;
+  sample_fmt: string | null;
   sample_rate: string | null;
   channels: number | null;
   language: string | null;
@@ -44,6 +53,14 @@ interface MediaInfo {
   has_audio: boolean;
   has_subtitles: boolean;
   has_data: boolean;
+  chapters: ChapterInfo[];
+}
+
+interface ChapterInfo {
+  id: number;
+  start_time: number;
+  end_time: number;
+  title: string | null;
 }
 
 interface StreamSelection {
@@ -51,6 +68,12 @@ interface StreamSelection {
   include_audio: boolean;
   include_subtitles: boolean;
   include_data: boolean;
+  stream_map?: StreamMapEntry[];
+}
+
+interface StreamMapEntry {
+  stream_index: number;
+  include: boolean;
 }
 
 interface AdvancedOptions {
@@ -61,11 +84,24 @@ interface AdvancedOptions {
 }
 
 interface ConvertProgress {
-  percent: number;
+  job_id: number;
+  percent: number | null;
   time_secs: number;
   speed: string | null;
   bitrate: string | null;
   size_kb: number | null;
+  frame: number | null;
+  total_frames: number | null;
+  live: boolean;
+  eta_secs: number | null;
+}
+
+interface ConvertRetry {
+  job_id: number;
+  attempt: number;
+  max_retries: number;
+  delay_secs: number;
+  reason: string;
 }
 
 interface ConvertResult {
@@ -73,11 +109,14 @@ interface ConvertResult {
   output_path: string;
   duration_secs: number;
   message: string | null;
+  output_duration_secs: number | null;
+  stopped: boolean;
+  frames_written: number | null;
 }
 
 interface LogEntry {
   timestamp: string;
-  level: "Info" | "Warning" | "Error" | "Debug";
+  level: "info" | "warning" | "error" | "debug";
   message: string;
   context: string | null;
 }
@@ -170,6 +209,17 @@ const AUDIO_CODEC_OPTIONS = [
   { value: "none", label: "No audio (-an)" },
 ];
 
+// PCM formats offered on the WAV preset ("" keeps the source's sample format)
+const PCM_FORMAT_OPTIONS = [
+  { value: "", label: "Match source" },
+  { value: "pcm_u8", label: "8-bit unsigned" },
+  { value: "pcm_s16le", label: "16-bit" },
+  { value: "pcm_s24le", label: "24-bit" },
+  { value: "pcm_s32le", label: "32-bit" },
+  { value: "pcm_f32le", label: "32-bit float" },
+  { value: "pcm_f64le", label: "64-bit float" },
+];
+
 // Common extra argument presets
 const EXTRA_ARGS_OPTIONS = [
   { value: "", label: "Select preset or leave empty..." },
@@ -204,6 +254,7 @@ function App() {
   const [ffmpegError, setFfmpegError] = useState<string | null>(null);
   const [presets, setPresets] = useState<Preset[]>([]);
   const [selectedPreset, setSelectedPreset] = useState<string | null>(null);
+  const [pcmFormat, setPcmFormat] = useState("");
   
   const [inputPath, setInputPath] = useState<string | null>(null);
   const [mediaInfo, setMediaInfo] = useState<MediaInfo | null>(null);
@@ -226,6 +277,7 @@ function App() {
   
   const [isConverting, setIsConverting] = useState(false);
   const [progress, setProgress] = useState<ConvertProgress | null>(null);
+  const [retry, setRetry] = useState<ConvertRetry | null>(null);
   const [error, setError] = useState<string | null>(null);
   const [successMessage, setSuccessMessage] = useState<string | null>(null);
   const [lastOutputPath, setLastOutputPath] = useState<string | null>(null);
@@ -236,6 +288,7 @@ function App() {
   const [logs, setLogs] = useState<ConversionLog[]>([]);
   const [selectedLog, setSelectedLog] = useState<ConversionLog | null>(null);
   const [logFilePath, setLogFilePath] = useState<string | null>(null);
+  const [fileLogging, setFileLogging] = useState(false);
 
   // Initialize
   useEffect(() => {
@@ -245,11 +298,18 @@ function App() {
     // Listen for progress events
     const unlistenProgress = listen<ConvertProgress>("convert-progress", (event) => {
       setProgress(event.payload);
+      setRetry(null);
+    });
+    
+    const unlistenRetry = listen<ConvertRetry>("convert-retry", (event) => {
+      setProgress(null);
+      setRetry(event.payload);
     });
     
     const unlistenDone = listen<ConvertResult>("convert-done", (event) => {
       setIsConverting(false);
       setProgress(null);
+      setRetry(null);
       setSuccessMessage(`Conversion completed in ${event.payload.duration_secs.toFixed(1)}s`);
       setLastOutputPath(event.payload.output_path);
     });
@@ -257,6 +317,7 @@ function App() {
     const unlistenError = listen<string>("convert-error", (event) => {
       setIsConverting(false);
       setProgress(null);
+      setRetry(null);
       setError(event.payload);
     });
 
@@ -278,6 +339,7 @@ function App() {
     
     return () => {
       unlistenProgress.then(f => f());
+      unlistenRetry.then(f => f());
       unlistenDone.then(f => f());
       unlistenError.then(f => f());
       unlistenDragDrop.then(f => f());
@@ -315,7 +377,7 @@ function App() {
       const selected = await open({
         multiple: false,
         filters: [
-          { name: "Media Files", extensions: ["mp4", "mkv", "avi", "mov", "webm", "mp3", "wav", "flac", "aac", "ogg", "m4a", "png", "jpg", "jpeg", "gif", "webp"] },
+          { name: "Media Files", extensions: ["mp4", "mkv", "avi", "mov", "webm", "mp3", "wav", "flac", "aac", "ogg", "m4a", "png", "jpg", "jpeg", "gif", "webp", "srt", "ass", "vtt"] },
           { name: "All Files", extensions: ["*"] }
         ]
       });
@@ -405,7 +467,7 @@ function App() {
     setError(null);
     setSuccessMessage(null);
     setIsConverting(true);
-    setProgress({ percent: 0, time_secs: 0, speed: null, bitrate: null, size_kb: null });
+    setProgress({ percent: 0, time_secs: 0, speed: null, bitrate: null, size_kb: null, frame: null, total_frames: null, live: false, eta_secs: null });
     
     try {
       await invoke<ConvertResult>("start_convert", {
@@ -417,6 +479,12 @@ function App() {
           video_codec: advancedOptions.video_codec || null,
           audio_codec: advancedOptions.audio_codec || null,
           extra_args: advancedOptions.extra_args || null,
+        } : selectedPreset === "wav" && pcmFormat ? {
+          format: null,
+          video_codec: null,
+          audio_codec: null,
+          extra_args: null,
+          pcm_format: pcmFormat,
         } : null,
         streamSelection: {
           include_video: streamSelection.include_video,
@@ -441,6 +509,15 @@ function App() {
     }
   }
   
+  // Stop early, keeping what has been encoded so far
+  async function stopConversion() {
+    try {
+      await invoke("stop_convert");
+    } catch (e) {
+      console.error("Failed to stop:", e);
+    }
+  }
+  
   // Fetch conversion logs
   async function fetchLogs() {
     try {
@@ -466,6 +543,21 @@ function App() {
     }
   }
   
+  // Save logs as JSON for support tooling
+  async function saveLogsJson() {
+    const path = await save({
+      defaultPath: "convertify-logs.json",
+      filters: [{ name: "JSON", extensions: ["json"] }],
+    });
+    if (!path) return;
+    try {
+      await invoke("save_conversion_logs_json", { path });
+    } catch (e) {
+      console.error("Failed to save logs:", e);
+      alert("Failed to save logs: " + String(e));
+    }
+  }
+  
   // Clear all logs
   async function clearLogs() {
     try {
@@ -477,15 +569,31 @@ function App() {
     }
   }
   
-  // Open log viewer
-  async function openLogViewer() {
-    fetchLogs();
+  // Refresh the log file location and whether file logging is on
+  async function fetchLogFileState() {
     try {
       const path = await invoke<string | null>("get_log_file_path");
       setLogFilePath(path);
+      setFileLogging(await invoke<boolean>("is_file_logging_enabled"));
     } catch {
       setLogFilePath(null);
     }
+  }
+  
+  // Turn writing logs to a file on or off
+  async function toggleFileLogging(enabled: boolean) {
+    try {
+      await invoke(enabled ? "enable_file_logging" : "disable_file_logging");
+    } catch (e) {
+      alert("Failed to change file logging: " + String(e));
+    }
+    fetchLogFileState();
+  }
+  
+  // Open log viewer
+  async function openLogViewer() {
+    fetchLogs();
+    fetchLogFileState();
     setShowLogs(true);
   }
 
@@ -644,6 +752,19 @@ function App() {
                   {stream.width && stream.height && (
                     <span className="stream-detail">{stream.width}x{stream.height}</span>
                   )}
+                  {stream.frame_rate_fps && (
+                    <span className="stream-detail" title={stream.frame_rate ?? undefined}>
+                      {parseFloat(stream.frame_rate_fps.toFixed(3))} fps
+                    </span>
+                  )}
+                  {stream.bits_per_raw_sample && stream.bits_per_raw_sample > 8 && (
+                    <span className="stream-detail">{stream.bits_per_raw_sample}-bit</span>
+                  )}
+                  {stream.is_hdr && (
+                    <span className="stream-detail" title={`${stream.color_transfer} / ${stream.color_primaries}`}>
+                      HDR ({stream.color_transfer === "arib-std-b67" ? "HLG" : "PQ"})
+                    </span>
+                  )}
                   {stream.channels && (
                     <span className="stream-detail">{stream.channels}ch {stream.sample_rate}Hz</span>
                   )}
@@ -654,6 +775,20 @@ function App() {
               ))}
             </div>
 
+            {mediaInfo.chapters.length > 0 && (
+              <div className="streams">
+                <h4>Chapters</h4>
+                {mediaInfo.chapters.map((chapter, i) => (
+                  <div key={chapter.id} className="stream">
+                    <span className="stream-codec">{chapter.title || `Chapter ${i + 1}`}</span>
+                    <span className="stream-detail">
+                      {formatDuration(chapter.start_time)} – {formatDuration(chapter.end_time)}
+                    </span>
+                  </div>
+                ))}
+              </div>
+            )}
+
             <div className="stream-selection">
               <h4>Include Streams</h4>
               <div className="toggles">
@@ -758,6 +893,20 @@ function App() {
                   </button>
                 ))}
               </div>
+              {selectedPreset === "wav" && (
+                <div className="form-group">
+                  <label>PCM Format</label>
+                  <select
+                    value={pcmFormat}
+                    onChange={(e) => setPcmFormat(e.target.value)}
+                    disabled={isConverting}
+                  >
+                    {PCM_FORMAT_OPTIONS.map(opt => (
+                      <option key={opt.value} value={opt.value}>{opt.label}</option>
+                    ))}
+                  </select>
+                </div>
+              )}
             </div>
             <div className="preset-group">
               <h4>Image</h4>
@@ -879,16 +1028,28 @@ function App() {
         </div>
       )}
 
+      {isConverting && retry && (
+        <div className="progress-section">
+          <div className="progress-stats" title={retry.reason}>
+            <span>Retrying {retry.attempt}/{retry.max_retries} in {retry.delay_secs}s…</span>
+          </div>
+        </div>
+      )}
+
       {isConverting && progress && (
         <div className="progress-section">
           <div className="progress-bar">
-            <div 
-              className="progress-fill" 
-              style={{ width: `${progress.percent}%` }}
-            />
+            {progress.percent === null ? (
+              <div className="progress-fill indeterminate" />
+            ) : (
+              <div 
+                className="progress-fill" 
+                style={{ width: `${progress.percent}%` }}
+              />
+            )}
           </div>
           <div className="progress-stats">
-            <span>{progress.percent.toFixed(1)}%</span>
+            {progress.percent !== null && <span>{progress.percent.toFixed(1)}%</span>}
             {progress.speed && <span>Speed: {progress.speed}</span>}
             {progress.bitrate && <span>Bitrate: {progress.bitrate}</span>}
             {progress.size_kb && <span>Size: {formatSize(progress.size_kb * 1024)}</span>}
@@ -906,12 +1067,21 @@ function App() {
             Convert
           </button>
         ) : (
-          <button 
-            className="btn-danger btn-large"
-            onClick={cancelConversion}
-          >
-            Cancel
-          </button>
+          <>
+            <button 
+              className="btn-primary btn-large"
+              onClick={stopConversion}
+              title="Finish the file here and keep the part converted so far"
+            >
+              Stop
+            </button>
+            <button 
+              className="btn-danger btn-large"
+              onClick={cancelConversion}
+            >
+              Cancel
+            </button>
+          </>
         )}
       </div>
 
@@ -927,7 +1097,16 @@ function App() {
               <div className="log-actions">
                 <button className="btn-secondary" onClick={fetchLogs}>Refresh</button>
                 <button className="btn-secondary" onClick={exportLogs}>Copy to Clipboard</button>
+                <button className="btn-secondary" onClick={saveLogsJson}>Save as JSON</button>
                 <button className="btn-danger" onClick={clearLogs}>Clear All</button>
+                <label>
+                  <input
+                    type="checkbox"
+                    checked={fileLogging}
+                    onChange={(e) => toggleFileLogging(e.target.checked)}
+                  />
+                  Save logs to file
+                </label>
               </div>
               {logFilePath && (
                 <div className="log-file-path">